@@ -0,0 +1,1274 @@
+//! Instruction builders for every entry point of the program
+//!
+//! Builders take the accounts of the instruction, generated by anchor in `cyclos_core::accounts`,
+//! followed by the instruction arguments. Instructions reading remaining accounts take them last.
+//!
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::InstructionData;
+use cyclos_core::states::factory::{FeeDiscountTier, Role, FEE_DISCOUNT_TIER_NUM};
+use cyclos_core::states::timelock::AdminAction;
+use cyclos_core::states::tokenized_position::MintTokenizedPositionParams;
+use cyclos_core::{accounts, instruction};
+
+/// Builds an instruction for the program
+fn build(
+    accounts: impl ToAccountMetas,
+    data: impl InstructionData,
+    remaining_accounts: Vec<AccountMeta>,
+) -> Instruction {
+    let mut metas = accounts.to_account_metas(None);
+    metas.extend(remaining_accounts);
+    Instruction {
+        program_id: cyclos_core::id(),
+        accounts: metas,
+        data: data.data(),
+    }
+}
+
+/// Initializes the factory state and sets the protocol owner
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `init_factory` instruction
+///
+pub fn init_factory(accounts: accounts::Initialize) -> Instruction {
+    build(accounts, instruction::InitFactory, vec![])
+}
+
+/// Grows a factory created before roles were added, handing every role to the owner
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `migrate_factory` instruction
+///
+pub fn migrate_factory(accounts: accounts::MigrateFactory) -> Instruction {
+    build(accounts, instruction::MigrateFactory, vec![])
+}
+
+/// Proposes a new owner of the factory
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `propose_owner` instruction
+///
+pub fn propose_owner(accounts: accounts::ProposeOwner) -> Instruction {
+    build(accounts, instruction::ProposeOwner, vec![])
+}
+
+/// Makes the proposed owner the owner of the factory
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `accept_owner` instruction
+///
+pub fn accept_owner(accounts: accounts::AcceptOwner) -> Instruction {
+    build(accounts, instruction::AcceptOwner, vec![])
+}
+
+/// Hands a role of the factory to another address
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `set_role` instruction
+/// * `role` - The role to hand over
+///
+pub fn set_role(accounts: accounts::SetRole, role: Role) -> Instruction {
+    build(accounts, instruction::SetRole { role }, vec![])
+}
+
+/// Queues an admin action behind the timelock
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `queue_admin_action` instruction
+/// * `action` - The instruction to queue, along with its arguments
+///
+pub fn queue_admin_action(
+    accounts: accounts::QueueAdminAction,
+    action: AdminAction,
+) -> Instruction {
+    build(accounts, instruction::QueueAdminAction { action }, vec![])
+}
+
+/// Cancels a queued admin action
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `cancel_admin_action` instruction
+/// * `action` - The queued instruction, along with its arguments
+///
+pub fn cancel_admin_action(
+    accounts: accounts::CancelAdminAction,
+    action: AdminAction,
+) -> Instruction {
+    build(accounts, instruction::CancelAdminAction { action }, vec![])
+}
+
+/// Sets the delay between queuing an admin action and executing it
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `set_timelock_delay` instruction
+/// * `delay` - The new delay in seconds
+///
+pub fn set_timelock_delay(accounts: accounts::SetTimelockDelay, delay: u32) -> Instruction {
+    build(accounts, instruction::SetTimelockDelay { delay }, vec![])
+}
+
+/// Enables a fee amount with the given tick spacing
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `enable_fee_amount` instruction
+/// * `fee` - The fee amount to enable, in hundredths of a bip
+/// * `tick_spacing` - The spacing between ticks to be enforced for all pools created with the fee amount
+///
+pub fn enable_fee_amount(
+    accounts: accounts::EnableFeeAmount,
+    fee: u32,
+    tick_spacing: u16,
+) -> Instruction {
+    build(
+        accounts,
+        instruction::EnableFeeAmount { fee, tick_spacing },
+        vec![],
+    )
+}
+
+/// Creates a pool for the given token pair and fee, and sets the initial price
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `create_and_init_pool` instruction
+/// * `sqrt_price_x32` - The initial sqrt price of the pool, as a Q32.32
+///
+pub fn create_and_init_pool(
+    accounts: accounts::CreateAndInitPool,
+    sqrt_price_x32: u64,
+) -> Instruction {
+    build(
+        accounts,
+        instruction::CreateAndInitPool { sqrt_price_x32 },
+        vec![],
+    )
+}
+
+/// Grows a pool created before per pool protocol fees were added
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `migrate_pool` instruction
+///
+pub fn migrate_pool(accounts: accounts::MigratePool) -> Instruction {
+    build(accounts, instruction::MigratePool, vec![])
+}
+
+/// Increases the number of observations that a pool will store
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `increase_observation_cardinality_next` instruction
+/// * `observation_account_bumps` - Bumps of the observation accounts to create
+/// * `remaining_accounts` - Observation accounts to create, in order of index
+///
+pub fn increase_observation_cardinality_next(
+    accounts: accounts::IncreaseObservationCardinalityNext,
+    observation_account_bumps: Vec<u8>,
+    remaining_accounts: Vec<AccountMeta>,
+) -> Instruction {
+    build(
+        accounts,
+        instruction::IncreaseObservationCardinalityNext {
+            observation_account_bumps,
+        },
+        remaining_accounts,
+    )
+}
+
+/// Returns the cumulative tick and liquidity as of each `seconds_ago`
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `observe` instruction
+/// * `seconds_agos` - From how long ago each cumulative value should be returned
+/// * `remaining_accounts` - The observation accounts of the pool, in order of index
+///
+pub fn observe(
+    accounts: accounts::Observe,
+    seconds_agos: Vec<u32>,
+    remaining_accounts: Vec<AccountMeta>,
+) -> Instruction {
+    build(
+        accounts,
+        instruction::Observe { seconds_agos },
+        remaining_accounts,
+    )
+}
+
+/// Returns a snapshot of the cumulatives inside a tick range
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `snapshot_cumulatives_inside` instruction
+///
+pub fn snapshot_cumulatives_inside(accounts: accounts::SnapshotCumulativesInside) -> Instruction {
+    build(accounts, instruction::SnapshotCumulativesInside, vec![])
+}
+
+/// Sets the protocol's share of the fees given to new pools
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `set_fee_protocol` instruction
+/// * `fee_protocol` - The protocol fee for pools created afterwards, in hundredths of a bip
+///
+pub fn set_fee_protocol(accounts: accounts::SetFeeProtocol, fee_protocol: u32) -> Instruction {
+    build(
+        accounts,
+        instruction::SetFeeProtocol { fee_protocol },
+        vec![],
+    )
+}
+
+/// Sets the protocol fee of a pool for each token
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `set_pool_fee_protocol` instruction
+/// * `fee_protocol_0` - The protocol fee for token_0 in hundredths of a bip, or 0 to turn it off
+/// * `fee_protocol_1` - The protocol fee for token_1 in hundredths of a bip, or 0 to turn it off
+///
+pub fn set_pool_fee_protocol(
+    accounts: accounts::SetPoolFeeProtocol,
+    fee_protocol_0: u32,
+    fee_protocol_1: u32,
+) -> Instruction {
+    build(
+        accounts,
+        instruction::SetPoolFeeProtocol {
+            fee_protocol_0,
+            fee_protocol_1,
+        },
+        vec![],
+    )
+}
+
+/// Sets the swap fee discounts given to traders locking governance tokens
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `set_fee_discounts` instruction
+/// * `locker` - The locker whose escrows earn discounts, or the default address to turn them off
+/// * `fee_discount_tiers` - The least amount and duration locked for each discount
+///
+pub fn set_fee_discounts(
+    accounts: accounts::SetFeeDiscounts,
+    locker: Pubkey,
+    fee_discount_tiers: [FeeDiscountTier; FEE_DISCOUNT_TIER_NUM],
+) -> Instruction {
+    build(
+        accounts,
+        instruction::SetFeeDiscounts {
+            locker,
+            fee_discount_tiers,
+        },
+        vec![],
+    )
+}
+
+/// Sets the operations paused for every pool
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `set_pause_state` instruction
+/// * `paused` - The operations to pause as `PAUSE_*` flags, or 0 to unpause
+///
+pub fn set_pause_state(accounts: accounts::SetPauseState, paused: u8) -> Instruction {
+    build(accounts, instruction::SetPauseState { paused }, vec![])
+}
+
+/// Sets the operations paused for a pool
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `set_pool_pause_state` instruction
+/// * `paused` - The operations to pause as `PAUSE_*` flags, or 0 to unpause
+///
+pub fn set_pool_pause_state(accounts: accounts::SetPoolPauseState, paused: u8) -> Instruction {
+    build(accounts, instruction::SetPoolPauseState { paused }, vec![])
+}
+
+/// Collects the protocol fee accrued to a pool
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `collect_protocol` instruction
+/// * `amount_0_requested` - The maximum amount of token_0 to send
+/// * `amount_1_requested` - The maximum amount of token_1 to send
+///
+pub fn collect_protocol(
+    accounts: accounts::CollectProtocol,
+    amount_0_requested: u64,
+    amount_1_requested: u64,
+) -> Instruction {
+    build(
+        accounts,
+        instruction::CollectProtocol {
+            amount_0_requested,
+            amount_1_requested,
+        },
+        vec![],
+    )
+}
+
+/// Adds a reward token emitted to in range liquidity of a pool
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `initialize_reward` instruction
+/// * `reward_index` - The unused reward slot to fill
+///
+pub fn initialize_reward(accounts: accounts::InitializeReward, reward_index: u8) -> Instruction {
+    build(
+        accounts,
+        instruction::InitializeReward { reward_index },
+        vec![],
+    )
+}
+
+/// Sets the emission rate of a reward
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `set_reward_emissions` instruction
+/// * `reward_index` - The slot of the reward
+/// * `emissions_per_second_x32` - The new emission rate as a Q32.32, or 0 to stop emissions
+///
+pub fn set_reward_emissions(
+    accounts: accounts::SetRewardEmissions,
+    reward_index: u8,
+    emissions_per_second_x32: u64,
+) -> Instruction {
+    build(
+        accounts,
+        instruction::SetRewardEmissions {
+            reward_index,
+            emissions_per_second_x32,
+        },
+        vec![],
+    )
+}
+
+/// Initializes an empty program account for a tick
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `init_tick_account` instruction
+/// * `tick` - The tick for which the account is created
+///
+pub fn init_tick_account(accounts: accounts::InitTickAccount, tick: i32) -> Instruction {
+    build(accounts, instruction::InitTickAccount { tick }, vec![])
+}
+
+/// Reclaims lamports from a cleared tick account, refunded to the payer of its rent
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `close_tick_account` instruction
+///
+pub fn close_tick_account(accounts: accounts::CloseTickAccount) -> Instruction {
+    build(accounts, instruction::CloseTickAccount, vec![])
+}
+
+/// Closes cleared tick accounts in bulk, refunding each to the payer of its rent
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `sweep_tick_accounts` instruction
+/// * `remaining_accounts` - Pairs of a writable tick account and the writable payer of its rent
+///
+pub fn sweep_tick_accounts(
+    accounts: accounts::SweepTickAccounts,
+    remaining_accounts: Vec<AccountMeta>,
+) -> Instruction {
+    build(accounts, instruction::SweepTickAccounts, remaining_accounts)
+}
+
+/// Initializes an empty program account for a tick bitmap
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `init_bitmap_account` instruction
+/// * `word_pos` - The word position of the bitmap
+///
+pub fn init_bitmap_account(accounts: accounts::InitBitmapAccount, word_pos: i16) -> Instruction {
+    build(
+        accounts,
+        instruction::InitBitmapAccount { word_pos },
+        vec![],
+    )
+}
+
+/// Reclaims lamports from a bitmap account with no initialized tick, refunded to the payer of
+/// its rent
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `close_bitmap_account` instruction
+///
+pub fn close_bitmap_account(accounts: accounts::CloseBitmapAccount) -> Instruction {
+    build(accounts, instruction::CloseBitmapAccount, vec![])
+}
+
+/// Initializes an empty program account for a position
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `init_position_account` instruction
+///
+pub fn init_position_account(accounts: accounts::InitPositionAccount) -> Instruction {
+    build(accounts, instruction::InitPositionAccount, vec![])
+}
+
+/// Reclaims lamports from a position account with no liquidity and no tokens owed, refunded to
+/// the payer of its rent
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `close_position_account` instruction
+///
+pub fn close_position_account(accounts: accounts::ClosePositionAccount) -> Instruction {
+    build(accounts, instruction::ClosePositionAccount, vec![])
+}
+
+/// Grows a tick account created before rent payers were recorded
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `migrate_tick_account` instruction
+/// * `tick` - The tick of the account
+///
+pub fn migrate_tick_account(accounts: accounts::MigrateTickAccount, tick: i32) -> Instruction {
+    build(accounts, instruction::MigrateTickAccount { tick }, vec![])
+}
+
+/// Grows a bitmap account created before rent payers were recorded
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `migrate_bitmap_account` instruction
+///
+pub fn migrate_bitmap_account(accounts: accounts::MigrateBitmapAccount) -> Instruction {
+    build(accounts, instruction::MigrateBitmapAccount, vec![])
+}
+
+/// Grows a position account created before rent payers were recorded
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `migrate_position_account` instruction
+///
+pub fn migrate_position_account(accounts: accounts::MigratePositionAccount) -> Instruction {
+    build(accounts, instruction::MigratePositionAccount, vec![])
+}
+
+/// Pays the tokens owed for minting liquidity
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `mint_callback` instruction
+/// * `amount_0_owed` - The amount of token_0 due to the pool
+/// * `amount_1_owed` - The amount of token_1 due to the pool
+///
+pub fn mint_callback(
+    accounts: accounts::MintCallback,
+    amount_0_owed: u64,
+    amount_1_owed: u64,
+) -> Instruction {
+    build(
+        accounts,
+        instruction::MintCallback {
+            amount_0_owed,
+            amount_1_owed,
+        },
+        vec![],
+    )
+}
+
+/// Pays the pool the tokens owed for a swap
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `swap_callback` instruction
+/// * `amount_0_delta` - The amount of token_0 sent (negative) or owed (positive) by the pool
+/// * `amount_1_delta` - The amount of token_1 sent (negative) or owed (positive) by the pool
+///
+pub fn swap_callback(
+    accounts: accounts::SwapCallback,
+    amount_0_delta: i64,
+    amount_1_delta: i64,
+) -> Instruction {
+    build(
+        accounts,
+        instruction::SwapCallback {
+            amount_0_delta,
+            amount_1_delta,
+        },
+        vec![],
+    )
+}
+
+/// Adds liquidity to a position
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `mint` instruction
+/// * `tick_lower` - The lower tick of the position
+/// * `tick_upper` - The upper tick of the position
+/// * `amount` - The amount of liquidity to mint
+/// * `remaining_accounts` - The next observation account, if the update writes to a new one
+///
+pub fn mint(
+    accounts: accounts::MintContext,
+    tick_lower: i32,
+    tick_upper: i32,
+    amount: u64,
+    remaining_accounts: Vec<AccountMeta>,
+) -> Instruction {
+    build(
+        accounts,
+        instruction::Mint {
+            tick_lower,
+            tick_upper,
+            amount,
+        },
+        remaining_accounts,
+    )
+}
+
+/// Burns liquidity of a position and accounts the tokens owed to it
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `burn` instruction
+/// * `amount` - The amount of liquidity to burn
+/// * `remaining_accounts` - The next observation account, if the update writes to a new one
+///
+pub fn burn(
+    accounts: accounts::BurnContext,
+    amount: u64,
+    remaining_accounts: Vec<AccountMeta>,
+) -> Instruction {
+    build(accounts, instruction::Burn { amount }, remaining_accounts)
+}
+
+/// Collects tokens owed to a position
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `collect` instruction
+/// * `amount_0_requested` - The maximum amount of token_0 to collect
+/// * `amount_1_requested` - The maximum amount of token_1 to collect
+///
+pub fn collect(
+    accounts: accounts::CollectContext,
+    amount_0_requested: u64,
+    amount_1_requested: u64,
+) -> Instruction {
+    build(
+        accounts,
+        instruction::Collect {
+            amount_0_requested,
+            amount_1_requested,
+        },
+        vec![],
+    )
+}
+
+/// Collects rewards owed to a position
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `collect_reward` instruction
+/// * `reward_index` - The slot of the reward to collect
+/// * `amount_requested` - How much of the reward should be withdrawn from the rewards owed
+///
+pub fn collect_reward(
+    accounts: accounts::CollectRewardContext,
+    reward_index: u8,
+    amount_requested: u64,
+) -> Instruction {
+    build(
+        accounts,
+        instruction::CollectReward {
+            reward_index,
+            amount_requested,
+        },
+        vec![],
+    )
+}
+
+/// Swaps token_0 for token_1, or token_1 for token_0
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `swap` instruction
+/// * `amount_specified` - Exact input amount if positive, or exact output amount if negative
+/// * `sqrt_price_limit_x32` - The Q32.32 sqrt price √P limit of the swap
+/// * `remaining_accounts` - Bitmap, tick and observation accounts read by the swap, in order
+///
+pub fn swap(
+    accounts: accounts::SwapContext,
+    amount_specified: i64,
+    sqrt_price_limit_x32: u64,
+    remaining_accounts: Vec<AccountMeta>,
+) -> Instruction {
+    build(
+        accounts,
+        instruction::Swap {
+            amount_specified,
+            sqrt_price_limit_x32,
+        },
+        remaining_accounts,
+    )
+}
+
+/// Borrows tokens from a pool, to be repaid with `repay_flash`
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `flash` instruction
+/// * `amount_0` - The amount of token_0 to borrow
+/// * `amount_1` - The amount of token_1 to borrow
+///
+pub fn flash(accounts: accounts::Flash, amount_0: u64, amount_1: u64) -> Instruction {
+    build(accounts, instruction::Flash { amount_0, amount_1 }, vec![])
+}
+
+/// Repays a flash loan along with its fee
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `repay_flash` instruction
+/// * `amount_0` - The amount of token_0 borrowed
+/// * `amount_1` - The amount of token_1 borrowed
+///
+pub fn repay_flash(accounts: accounts::RepayFlash, amount_0: u64, amount_1: u64) -> Instruction {
+    build(
+        accounts,
+        instruction::RepayFlash { amount_0, amount_1 },
+        vec![],
+    )
+}
+
+/// Creates a new position wrapped in a NFT
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `mint_tokenized_position` instruction
+/// * `params` - The ticks, token amounts and deadline of the position
+/// * `remaining_accounts` - The next observation account, if the update writes to a new one
+///
+pub fn mint_tokenized_position(
+    accounts: accounts::MintTokenizedPosition,
+    params: MintTokenizedPositionParams,
+    remaining_accounts: Vec<AccountMeta>,
+) -> Instruction {
+    build(
+        accounts,
+        instruction::MintTokenizedPosition { params },
+        remaining_accounts,
+    )
+}
+
+/// Attaches metaplex metadata to a tokenized position
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `add_metaplex_metadata` instruction
+///
+pub fn add_metaplex_metadata(accounts: accounts::AddMetaplexMetadata) -> Instruction {
+    build(accounts, instruction::AddMetaplexMetadata, vec![])
+}
+
+/// Increases liquidity in a tokenized position
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `increase_liquidity` instruction
+/// * `amount_0_desired` - The desired amount of token_0 to be spent
+/// * `amount_1_desired` - The desired amount of token_1 to be spent
+/// * `amount_0_min` - The minimum amount of token_0 to spend
+/// * `amount_1_min` - The minimum amount of token_1 to spend
+/// * `deadline` - The time by which the transaction must be included
+/// * `remaining_accounts` - The next observation account, if the update writes to a new one
+///
+pub fn increase_liquidity(
+    accounts: accounts::IncreaseLiquidity,
+    amount_0_desired: u64,
+    amount_1_desired: u64,
+    amount_0_min: u64,
+    amount_1_min: u64,
+    deadline: i64,
+    remaining_accounts: Vec<AccountMeta>,
+) -> Instruction {
+    build(
+        accounts,
+        instruction::IncreaseLiquidity {
+            amount_0_desired,
+            amount_1_desired,
+            amount_0_min,
+            amount_1_min,
+            deadline,
+        },
+        remaining_accounts,
+    )
+}
+
+/// Decreases liquidity in a tokenized position
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `decrease_liquidity` instruction
+/// * `liquidity` - The amount of liquidity to remove
+/// * `amount_0_min` - The minimum amount of token_0 to account
+/// * `amount_1_min` - The minimum amount of token_1 to account
+/// * `deadline` - The time by which the transaction must be included
+/// * `remaining_accounts` - The next observation account, if the update writes to a new one
+///
+pub fn decrease_liquidity(
+    accounts: accounts::DecreaseLiquidity,
+    liquidity: u64,
+    amount_0_min: u64,
+    amount_1_min: u64,
+    deadline: i64,
+    remaining_accounts: Vec<AccountMeta>,
+) -> Instruction {
+    build(
+        accounts,
+        instruction::DecreaseLiquidity {
+            liquidity,
+            amount_0_min,
+            amount_1_min,
+            deadline,
+        },
+        remaining_accounts,
+    )
+}
+
+/// Collects tokens owed to a tokenized position
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `collect_from_tokenized` instruction
+/// * `amount_0_max` - The maximum amount of token_0 to collect
+/// * `amount_1_max` - The maximum amount of token_1 to collect
+/// * `remaining_accounts` - The next observation account, if the update writes to a new one
+///
+pub fn collect_from_tokenized(
+    accounts: accounts::CollectFromTokenized,
+    amount_0_max: u64,
+    amount_1_max: u64,
+    remaining_accounts: Vec<AccountMeta>,
+) -> Instruction {
+    build(
+        accounts,
+        instruction::CollectFromTokenized {
+            amount_0_max,
+            amount_1_max,
+        },
+        remaining_accounts,
+    )
+}
+
+/// Collects up to a maximum amount of rewards owed to a tokenized position
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `collect_reward_from_tokenized` instruction
+/// * `reward_index` - The slot of the reward to collect
+/// * `amount_max` - The maximum amount of the reward to collect
+/// * `remaining_accounts` - The next observation account, if the update writes to a new one
+///
+pub fn collect_reward_from_tokenized(
+    accounts: accounts::CollectRewardFromTokenized,
+    reward_index: u8,
+    amount_max: u64,
+    remaining_accounts: Vec<AccountMeta>,
+) -> Instruction {
+    build(
+        accounts,
+        instruction::CollectRewardFromTokenized {
+            reward_index,
+            amount_max,
+        },
+        remaining_accounts,
+    )
+}
+
+/// Burns an emptied tokenized position, refunding rent to the holder
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `burn_tokenized_position` instruction
+///
+pub fn burn_tokenized_position(accounts: accounts::BurnTokenizedPosition) -> Instruction {
+    build(accounts, instruction::BurnTokenizedPosition, vec![])
+}
+
+/// Grows a tokenized position created before rewards were added
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `migrate_tokenized_position` instruction
+///
+pub fn migrate_tokenized_position(accounts: accounts::MigrateTokenizedPosition) -> Instruction {
+    build(accounts, instruction::MigrateTokenizedPosition, vec![])
+}
+
+/// Creates an incentive rewarding positions of a pool staked between the start and end times
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `create_incentive` instruction
+/// * `start_time` - The time when the incentive program begins
+/// * `end_time` - The time when rewards stop accruing
+/// * `reward` - The amount of reward tokens to be distributed
+///
+pub fn create_incentive(
+    accounts: accounts::CreateIncentive,
+    start_time: u32,
+    end_time: u32,
+    reward: u64,
+) -> Instruction {
+    build(
+        accounts,
+        instruction::CreateIncentive {
+            start_time,
+            end_time,
+            reward,
+        },
+        vec![],
+    )
+}
+
+/// Ends an incentive without stakes past its end time, refunding the remaining rewards
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `end_incentive` instruction
+///
+pub fn end_incentive(accounts: accounts::EndIncentive) -> Instruction {
+    build(accounts, instruction::EndIncentive, vec![])
+}
+
+/// Deposits a position NFT with the program so that it can be staked
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `deposit_token` instruction
+///
+pub fn deposit_token(accounts: accounts::DepositToken) -> Instruction {
+    build(accounts, instruction::DepositToken, vec![])
+}
+
+/// Withdraws a deposited position NFT which is not staked
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `withdraw_token` instruction
+///
+pub fn withdraw_token(accounts: accounts::WithdrawToken) -> Instruction {
+    build(accounts, instruction::WithdrawToken, vec![])
+}
+
+/// Stakes a deposited position NFT in an incentive
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `stake_token` instruction
+///
+pub fn stake_token(accounts: accounts::StakeToken) -> Instruction {
+    build(accounts, instruction::StakeToken, vec![])
+}
+
+/// Unstakes a position NFT from an incentive, paying out its rewards
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `unstake_token` instruction
+///
+pub fn unstake_token(accounts: accounts::UnstakeToken) -> Instruction {
+    build(accounts, instruction::UnstakeToken, vec![])
+}
+
+/// Claims the rewards earned by a staked position NFT, keeping it staked
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `claim_reward` instruction
+///
+pub fn claim_reward(accounts: accounts::ClaimReward) -> Instruction {
+    build(accounts, instruction::ClaimReward, vec![])
+}
+
+/// Creates a distributor paying the claims of a Merkle tree of rewards
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `new_distributor` instruction
+/// * `distribution_index` - Distinguishes distributions of the same token to the same pool
+/// * `root` - The root of the Merkle tree of claims
+/// * `max_total_claim` - The maximum amount of tokens that can be claimed
+///
+pub fn new_distributor(
+    accounts: accounts::NewDistributor,
+    distribution_index: u32,
+    root: [u8; 32],
+    max_total_claim: u64,
+) -> Instruction {
+    build(
+        accounts,
+        instruction::NewDistributor {
+            distribution_index,
+            root,
+            max_total_claim,
+        },
+        vec![],
+    )
+}
+
+/// Claims tokens owed by a distributor, proving the claim against its root
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `claim` instruction
+/// * `index` - The position of the claim among the leaves
+/// * `amount` - The amount of tokens owed to the claimant
+/// * `proof` - The siblings of the nodes on the path from the leaf to the root
+///
+pub fn claim(
+    accounts: accounts::ClaimContext,
+    index: u64,
+    amount: u64,
+    proof: Vec<[u8; 32]>,
+) -> Instruction {
+    build(
+        accounts,
+        instruction::Claim {
+            index,
+            amount,
+            proof,
+        },
+        vec![],
+    )
+}
+
+/// Swaps an exact input amount in a single pool
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `exact_input_single` instruction
+/// * `deadline` - The time by which the transaction must be included
+/// * `amount_in` - The amount of input token to swap
+/// * `amount_out_minimum` - The minimum amount of output token to receive
+/// * `sqrt_price_limit_x32` - The Q32.32 sqrt price √P limit of the swap
+/// * `remaining_accounts` - Bitmap, tick and observation accounts read by the swap, in order
+///
+pub fn exact_input_single(
+    accounts: accounts::ExactInputSingle,
+    deadline: i64,
+    amount_in: u64,
+    amount_out_minimum: u64,
+    sqrt_price_limit_x32: u64,
+    remaining_accounts: Vec<AccountMeta>,
+) -> Instruction {
+    build(
+        accounts,
+        instruction::ExactInputSingle {
+            deadline,
+            amount_in,
+            amount_out_minimum,
+            sqrt_price_limit_x32,
+        },
+        remaining_accounts,
+    )
+}
+
+/// Swaps an exact input amount along a path of pools
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `exact_input` instruction
+/// * `deadline` - The time by which the transaction must be included
+/// * `amount_in` - The amount of input token to swap
+/// * `amount_out_minimum` - The minimum amount of output token to receive
+/// * `additional_accounts_per_pool` - The number of swap accounts of each pool in the path
+/// * `remaining_accounts` - Accounts of each pool in the path
+///
+pub fn exact_input(
+    accounts: accounts::ExactInput,
+    deadline: i64,
+    amount_in: u64,
+    amount_out_minimum: u64,
+    additional_accounts_per_pool: Vec<u8>,
+    remaining_accounts: Vec<AccountMeta>,
+) -> Instruction {
+    build(
+        accounts,
+        instruction::ExactInput {
+            deadline,
+            amount_in,
+            amount_out_minimum,
+            additional_accounts_per_pool,
+        },
+        remaining_accounts,
+    )
+}
+
+/// Swaps for an exact output amount in a single pool
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `exact_output_single` instruction
+/// * `deadline` - The time by which the transaction must be included
+/// * `amount_out` - The amount of output token to receive
+/// * `amount_in_maximum` - The maximum amount of input token to pay
+/// * `sqrt_price_limit_x32` - The Q32.32 sqrt price √P limit of the swap
+/// * `remaining_accounts` - Bitmap, tick and observation accounts read by the swap, in order
+///
+pub fn exact_output_single(
+    accounts: accounts::ExactOutputSingle,
+    deadline: i64,
+    amount_out: u64,
+    amount_in_maximum: u64,
+    sqrt_price_limit_x32: u64,
+    remaining_accounts: Vec<AccountMeta>,
+) -> Instruction {
+    build(
+        accounts,
+        instruction::ExactOutputSingle {
+            deadline,
+            amount_out,
+            amount_in_maximum,
+            sqrt_price_limit_x32,
+        },
+        remaining_accounts,
+    )
+}
+
+/// Swaps for an exact output amount along a reversed path of pools
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `exact_output` instruction
+/// * `deadline` - The time by which the transaction must be included
+/// * `amount_out` - The amount of output token to receive
+/// * `amount_in_maximum` - The maximum amount of input token to pay
+/// * `additional_accounts_per_pool` - The number of swap accounts of each pool in the reversed path
+/// * `remaining_accounts` - Accounts of each pool in the reversed path
+///
+pub fn exact_output(
+    accounts: accounts::ExactOutput,
+    deadline: i64,
+    amount_out: u64,
+    amount_in_maximum: u64,
+    additional_accounts_per_pool: Vec<u8>,
+    remaining_accounts: Vec<AccountMeta>,
+) -> Instruction {
+    build(
+        accounts,
+        instruction::ExactOutput {
+            deadline,
+            amount_out,
+            amount_in_maximum,
+            additional_accounts_per_pool,
+        },
+        remaining_accounts,
+    )
+}
+
+/// Quotes an exact input swap in a single pool without executing it
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `quote_exact_input_single` instruction
+/// * `amount_in` - The amount of input token to swap
+/// * `sqrt_price_limit_x32` - The Q32.32 sqrt price √P limit of the swap
+/// * `remaining_accounts` - Bitmap and tick accounts read by the swap, in order, then an
+///   optional escrow of the trader to quote the discounted fee
+///
+pub fn quote_exact_input_single(
+    accounts: accounts::QuoteSingle,
+    amount_in: u64,
+    sqrt_price_limit_x32: u64,
+    remaining_accounts: Vec<AccountMeta>,
+) -> Instruction {
+    build(
+        accounts,
+        instruction::QuoteExactInputSingle {
+            amount_in,
+            sqrt_price_limit_x32,
+        },
+        remaining_accounts,
+    )
+}
+
+/// Quotes an exact output swap in a single pool without executing it
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the `quote_exact_output_single` instruction
+/// * `amount_out` - The amount of output token to receive
+/// * `sqrt_price_limit_x32` - The Q32.32 sqrt price √P limit of the swap
+/// * `remaining_accounts` - Bitmap and tick accounts read by the swap, in order, then an
+///   optional escrow of the trader to quote the discounted fee
+///
+pub fn quote_exact_output_single(
+    accounts: accounts::QuoteSingle,
+    amount_out: u64,
+    sqrt_price_limit_x32: u64,
+    remaining_accounts: Vec<AccountMeta>,
+) -> Instruction {
+    build(
+        accounts,
+        instruction::QuoteExactOutputSingle {
+            amount_out,
+            sqrt_price_limit_x32,
+        },
+        remaining_accounts,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use anchor_lang::solana_program::hash::hash;
+
+    /// Returns the anchor discriminator of an instruction
+    fn sighash(name: &str) -> Vec<u8> {
+        hash(format!("global:{}", name).as_bytes()).to_bytes()[..8].to_vec()
+    }
+
+    #[test]
+    fn serializes_arguments_after_the_discriminator() {
+        let ix = enable_fee_amount(
+            accounts::EnableFeeAmount {
+                payer: Pubkey::new_unique(),
+                timelock_state: Pubkey::new_unique(),
+                timelock_payer: Pubkey::new_unique(),
+                fee_state: Pubkey::new_unique(),
+                system_program: Pubkey::default(),
+            },
+            500,
+            10,
+        );
+        assert_eq!(ix.program_id, cyclos_core::id());
+        assert_eq!(ix.data[..8], sighash("enable_fee_amount"));
+        assert_eq!(ix.data[8..12], 500_u32.to_le_bytes());
+        assert_eq!(ix.data[12..], 10_u16.to_le_bytes());
+    }
+
+    #[test]
+    fn appends_remaining_accounts_in_order() {
+        let signer = Pubkey::new_unique();
+        let remaining_accounts: Vec<AccountMeta> = (0..3)
+            .map(|_| AccountMeta::new(Pubkey::new_unique(), false))
+            .collect();
+        let ix = swap(
+            accounts::SwapContext {
+                signer,
+                input_token_account: Pubkey::new_unique(),
+                output_token_account: Pubkey::new_unique(),
+                input_vault: Pubkey::new_unique(),
+                output_vault: Pubkey::new_unique(),
+                token_program: anchor_spl::token::ID,
+                factory_state: Pubkey::new_unique(),
+                pool_state: Pubkey::new_unique(),
+                last_observation_state: Pubkey::new_unique(),
+                callback_handler: cyclos_core::id(),
+            },
+            -1_000,
+            0,
+            remaining_accounts.clone(),
+        );
+        assert_eq!(ix.accounts.len(), 10 + 3);
+        assert_eq!(ix.accounts[0], AccountMeta::new_readonly(signer, true));
+        assert_eq!(ix.accounts[10..], remaining_accounts[..]);
+
+        let mut data = sighash("swap");
+        data.extend_from_slice(&(-1_000_i64).to_le_bytes());
+        data.extend_from_slice(&0_u64.to_le_bytes());
+        assert_eq!(ix.data, data);
+    }
+
+    #[test]
+    fn round_trips_mint_tokenized_position_through_the_program_layout() {
+        let minter = Pubkey::new_unique();
+        let payer = Pubkey::new_unique();
+        let next_observation_state = AccountMeta::new(Pubkey::new_unique(), false);
+        let params = MintTokenizedPositionParams {
+            tick_lower: -60,
+            tick_upper: 60,
+            amount_0_desired: 1_000,
+            amount_1_desired: 2_000,
+            amount_0_min: 900,
+            amount_1_min: 1_800,
+            deadline: 1_700_000_000,
+        };
+        let accounts = accounts::MintTokenizedPosition {
+            minter,
+            payer,
+            recipient: Pubkey::new_unique(),
+            factory_state: Pubkey::new_unique(),
+            nft_mint: Pubkey::new_unique(),
+            nft_account: Pubkey::new_unique(),
+            pool_state: Pubkey::new_unique(),
+            core_position_state: Pubkey::new_unique(),
+            tick_lower_state: Pubkey::new_unique(),
+            tick_upper_state: Pubkey::new_unique(),
+            bitmap_lower_state: Pubkey::new_unique(),
+            bitmap_upper_state: Pubkey::new_unique(),
+            tokenized_position_state: Pubkey::new_unique(),
+            token_account_0: Pubkey::new_unique(),
+            token_account_1: Pubkey::new_unique(),
+            vault_0: Pubkey::new_unique(),
+            vault_1: Pubkey::new_unique(),
+            last_observation_state: Pubkey::new_unique(),
+            rent: anchor_lang::solana_program::sysvar::rent::ID,
+            core_program: cyclos_core::id(),
+            system_program: Pubkey::default(),
+            token_program: anchor_spl::token::ID,
+            associated_token_program: anchor_spl::associated_token::ID,
+        };
+        let metas = accounts.to_account_metas(None);
+        let ix = mint_tokenized_position(accounts, params, vec![next_observation_state.clone()]);
+
+        // the program reads the arguments back as a single params struct
+        assert_eq!(ix.data[..8], sighash("mint_tokenized_position"));
+        assert_eq!(ix.data.len(), 8 + 4 + 4 + 8 * 5);
+        let decoded = instruction::MintTokenizedPosition::try_from_slice(&ix.data[8..]).unwrap();
+        assert_eq!(decoded.params, params);
+
+        // accounts follow the program layout, with the rent payer signing apart from the minter
+        assert_eq!(ix.accounts.len(), metas.len() + 1);
+        assert_eq!(ix.accounts[..metas.len()], metas[..]);
+        assert_eq!(ix.accounts[0], AccountMeta::new_readonly(minter, true));
+        assert_eq!(ix.accounts[1], AccountMeta::new(payer, true));
+        assert_eq!(ix.accounts[metas.len()], next_observation_state);
+    }
+
+    #[test]
+    fn builds_instructions_without_arguments() {
+        let ix = init_factory(accounts::Initialize {
+            owner: Pubkey::new_unique(),
+            factory_state: Pubkey::new_unique(),
+            system_program: Pubkey::default(),
+        });
+        assert_eq!(ix.data, sighash("init_factory"));
+        assert_eq!(ix.accounts.len(), 3);
+        assert!(ix.accounts[0].is_signer && ix.accounts[0].is_writable);
+        assert!(!ix.accounts[1].is_signer && ix.accounts[1].is_writable);
+    }
+}
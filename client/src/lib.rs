@@ -0,0 +1,11 @@
+//! Rust client for Cykura pools
+//!
+//! Derives program addresses, builds instructions for every entry point of the program,
+//! and reads the zero-copy account states from raw account data.
+//!
+pub mod instruction;
+pub mod pda;
+pub mod resolver;
+pub mod state;
+
+pub use cyclos_core::ID;
@@ -0,0 +1,441 @@
+//! Program derived addresses of the program accounts
+//!
+//! Every finder returns the address along with its bump, as `Pubkey::find_program_address`.
+//! Pool scoped accounts are derived from the pool's token pair and fee.
+//!
+use anchor_lang::prelude::*;
+use anchor_spl::associated_token::get_associated_token_address;
+use cyclos_core::states::distributor::{CLAIM_STATUS_SEED, DISTRIBUTOR_SEED};
+use cyclos_core::states::escrow::locked_voter;
+use cyclos_core::states::fee::FEE_SEED;
+use cyclos_core::states::oracle::OBSERVATION_SEED;
+use cyclos_core::states::pool::{POOL_SEED, REWARD_VAULT_SEED};
+use cyclos_core::states::position::POSITION_SEED;
+use cyclos_core::states::staker::{DEPOSIT_SEED, INCENTIVE_SEED, STAKE_SEED};
+use cyclos_core::states::tick::TICK_SEED;
+use cyclos_core::states::tick_bitmap::BITMAP_SEED;
+use cyclos_core::states::timelock::{AdminAction, TIMELOCK_SEED};
+
+/// Returns the address of the factory account
+pub fn find_factory_address() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[], &cyclos_core::id())
+}
+
+/// Returns the address of the account storing the tick spacing of a fee tier
+///
+/// # Arguments
+///
+/// * `fee` - The fee tier, in hundredths of a bip
+///
+pub fn find_fee_address(fee: u32) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[FEE_SEED.as_bytes(), &fee.to_be_bytes()],
+        &cyclos_core::id(),
+    )
+}
+
+/// Returns the address of a pool
+///
+/// # Arguments
+///
+/// * `token_0` - The mint with the lower address
+/// * `token_1` - The mint with the higher address
+/// * `fee` - The fee tier of the pool
+///
+pub fn find_pool_address(token_0: &Pubkey, token_1: &Pubkey, fee: u32) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            POOL_SEED.as_bytes(),
+            token_0.as_ref(),
+            token_1.as_ref(),
+            &fee.to_be_bytes(),
+        ],
+        &cyclos_core::id(),
+    )
+}
+
+/// Returns the address of an observation account of a pool
+///
+/// # Arguments
+///
+/// * `token_0` - The mint with the lower address
+/// * `token_1` - The mint with the higher address
+/// * `fee` - The fee tier of the pool
+/// * `index` - The index of the observation in the oracle array
+///
+pub fn find_observation_address(
+    token_0: &Pubkey,
+    token_1: &Pubkey,
+    fee: u32,
+    index: u16,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            OBSERVATION_SEED.as_bytes(),
+            token_0.as_ref(),
+            token_1.as_ref(),
+            &fee.to_be_bytes(),
+            &index.to_be_bytes(),
+        ],
+        &cyclos_core::id(),
+    )
+}
+
+/// Returns the address of a tick account of a pool
+///
+/// # Arguments
+///
+/// * `token_0` - The mint with the lower address
+/// * `token_1` - The mint with the higher address
+/// * `fee` - The fee tier of the pool
+/// * `tick` - The tick
+///
+pub fn find_tick_address(token_0: &Pubkey, token_1: &Pubkey, fee: u32, tick: i32) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            TICK_SEED.as_bytes(),
+            token_0.as_ref(),
+            token_1.as_ref(),
+            &fee.to_be_bytes(),
+            &tick.to_be_bytes(),
+        ],
+        &cyclos_core::id(),
+    )
+}
+
+/// Returns the address of a bitmap account of a pool
+///
+/// # Arguments
+///
+/// * `token_0` - The mint with the lower address
+/// * `token_1` - The mint with the higher address
+/// * `fee` - The fee tier of the pool
+/// * `word_pos` - The word position of the bitmap
+///
+pub fn find_bitmap_address(
+    token_0: &Pubkey,
+    token_1: &Pubkey,
+    fee: u32,
+    word_pos: i16,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            BITMAP_SEED.as_bytes(),
+            token_0.as_ref(),
+            token_1.as_ref(),
+            &fee.to_be_bytes(),
+            &word_pos.to_be_bytes(),
+        ],
+        &cyclos_core::id(),
+    )
+}
+
+/// Returns the address of a position in a pool
+///
+/// The core position backing a tokenized position is owned by the factory.
+///
+/// # Arguments
+///
+/// * `token_0` - The mint with the lower address
+/// * `token_1` - The mint with the higher address
+/// * `fee` - The fee tier of the pool
+/// * `owner` - The owner of the position
+/// * `tick_lower` - The lower tick of the position
+/// * `tick_upper` - The upper tick of the position
+///
+pub fn find_position_address(
+    token_0: &Pubkey,
+    token_1: &Pubkey,
+    fee: u32,
+    owner: &Pubkey,
+    tick_lower: i32,
+    tick_upper: i32,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            POSITION_SEED.as_bytes(),
+            token_0.as_ref(),
+            token_1.as_ref(),
+            &fee.to_be_bytes(),
+            owner.as_ref(),
+            &tick_lower.to_be_bytes(),
+            &tick_upper.to_be_bytes(),
+        ],
+        &cyclos_core::id(),
+    )
+}
+
+/// Returns the address of a tokenized position
+///
+/// # Arguments
+///
+/// * `nft_mint` - The mint of the position NFT
+///
+pub fn find_tokenized_position_address(nft_mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[POSITION_SEED.as_bytes(), nft_mint.as_ref()],
+        &cyclos_core::id(),
+    )
+}
+
+/// Returns the address of the metaplex metadata account of a position NFT
+///
+/// # Arguments
+///
+/// * `nft_mint` - The mint of the position NFT
+///
+pub fn find_metadata_address(nft_mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            metaplex_token_metadata::state::PREFIX.as_bytes(),
+            metaplex_token_metadata::id().as_ref(),
+            nft_mint.as_ref(),
+        ],
+        &metaplex_token_metadata::id(),
+    )
+}
+
+/// Returns the address of the escrow holding the governance tokens locked by an owner,
+/// passed last to swaps for a fee discount
+///
+/// # Arguments
+///
+/// * `locker` - The Tribeca locker set on the factory
+/// * `owner` - The owner of the locked tokens, signing the swap
+///
+pub fn find_escrow_address(locker: &Pubkey, owner: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"Escrow", locker.as_ref(), owner.as_ref()],
+        &locked_voter::ID,
+    )
+}
+
+/// Returns the address of the timelock account queuing an admin action
+///
+/// # Arguments
+///
+/// * `action` - The queued instruction, along with its arguments
+///
+pub fn find_timelock_address(action: &AdminAction) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[TIMELOCK_SEED.as_bytes(), &action.seed()],
+        &cyclos_core::id(),
+    )
+}
+
+/// Returns the address of the token account holding the rewards of a reward token
+///
+/// # Arguments
+///
+/// * `pool` - The address of the pool emitting the reward
+/// * `reward_mint` - The reward token
+///
+pub fn find_reward_vault_address(pool: &Pubkey, reward_mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            REWARD_VAULT_SEED.as_bytes(),
+            pool.as_ref(),
+            reward_mint.as_ref(),
+        ],
+        &cyclos_core::id(),
+    )
+}
+
+/// Returns the address of an incentive
+///
+/// # Arguments
+///
+/// * `reward_token` - The token distributed as a reward
+/// * `pool` - The address of the pool whose staked positions are rewarded
+/// * `start_time` - The time when the incentive program begins
+/// * `end_time` - The time when rewards stop accruing
+/// * `refundee` - Receives the remaining rewards when the incentive is ended
+///
+pub fn find_incentive_address(
+    reward_token: &Pubkey,
+    pool: &Pubkey,
+    start_time: u32,
+    end_time: u32,
+    refundee: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            INCENTIVE_SEED.as_bytes(),
+            reward_token.as_ref(),
+            pool.as_ref(),
+            &start_time.to_be_bytes(),
+            &end_time.to_be_bytes(),
+            refundee.as_ref(),
+        ],
+        &cyclos_core::id(),
+    )
+}
+
+/// Returns the address of the deposit of a position NFT
+///
+/// # Arguments
+///
+/// * `nft_mint` - The mint of the position NFT
+///
+pub fn find_deposit_address(nft_mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[DEPOSIT_SEED.as_bytes(), nft_mint.as_ref()],
+        &cyclos_core::id(),
+    )
+}
+
+/// Returns the address of the stake of a position NFT in an incentive
+///
+/// # Arguments
+///
+/// * `incentive` - The address of the incentive
+/// * `nft_mint` - The mint of the position NFT
+///
+pub fn find_stake_address(incentive: &Pubkey, nft_mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[STAKE_SEED.as_bytes(), incentive.as_ref(), nft_mint.as_ref()],
+        &cyclos_core::id(),
+    )
+}
+
+/// Returns the address of a distributor
+///
+/// # Arguments
+///
+/// * `pool` - The address of the pool whose liquidity providers are rewarded
+/// * `reward_mint` - The token being distributed
+/// * `distribution_index` - Distinguishes distributions of the same token to the same pool
+///
+pub fn find_distributor_address(
+    pool: &Pubkey,
+    reward_mint: &Pubkey,
+    distribution_index: u32,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            DISTRIBUTOR_SEED.as_bytes(),
+            pool.as_ref(),
+            reward_mint.as_ref(),
+            &distribution_index.to_be_bytes(),
+        ],
+        &cyclos_core::id(),
+    )
+}
+
+/// Returns the address marking a leaf of a distributor as claimed
+///
+/// # Arguments
+///
+/// * `distributor` - The address of the distributor
+/// * `index` - The position of the claim among the leaves
+///
+pub fn find_claim_status_address(distributor: &Pubkey, index: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            CLAIM_STATUS_SEED.as_bytes(),
+            distributor.as_ref(),
+            &index.to_be_bytes(),
+        ],
+        &cyclos_core::id(),
+    )
+}
+
+/// Returns the pool's token account for a mint
+///
+/// # Arguments
+///
+/// * `pool` - The address of the pool
+/// * `mint` - token_0 or token_1 of the pool
+///
+pub fn get_vault_address(pool: &Pubkey, mint: &Pubkey) -> Pubkey {
+    get_associated_token_address(pool, mint)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cyclos_core::states::pool::PoolState;
+
+    const TOKEN_0: Pubkey = Pubkey::new_from_array([1; 32]);
+    const TOKEN_1: Pubkey = Pubkey::new_from_array([2; 32]);
+    const FEE: u32 = 500;
+
+    fn pool() -> PoolState {
+        PoolState {
+            token_0: TOKEN_0,
+            token_1: TOKEN_1,
+            fee: FEE,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn finds_pool_scoped_addresses_validated_by_the_program() {
+        let pool = pool();
+
+        let (address, bump) = find_observation_address(&TOKEN_0, &TOKEN_1, FEE, 3);
+        pool.validate_observation_address_at_index(&address, bump, 3)
+            .unwrap();
+
+        let (address, bump) = find_tick_address(&TOKEN_0, &TOKEN_1, FEE, -887220);
+        pool.validate_tick_address(&address, bump, -887220).unwrap();
+
+        let (address, bump) = find_bitmap_address(&TOKEN_0, &TOKEN_1, FEE, -1);
+        pool.validate_bitmap_address(&address, bump, -1).unwrap();
+
+        let owner = Pubkey::new_unique();
+        let (address, bump) = find_position_address(&TOKEN_0, &TOKEN_1, FEE, &owner, -60, 60);
+        pool.validate_position_address(&address, bump, &owner, -60, 60)
+            .unwrap();
+    }
+
+    #[test]
+    fn distinguishes_pools_by_fee() {
+        assert_ne!(
+            find_pool_address(&TOKEN_0, &TOKEN_1, 500).0,
+            find_pool_address(&TOKEN_0, &TOKEN_1, 3000).0
+        );
+        assert_ne!(find_fee_address(500).0, find_fee_address(3000).0);
+    }
+
+    #[test]
+    fn derives_tokenized_and_core_positions_separately() {
+        let nft_mint = Pubkey::new_unique();
+        let (factory, _) = find_factory_address();
+        assert_ne!(
+            find_tokenized_position_address(&nft_mint).0,
+            find_position_address(&TOKEN_0, &TOKEN_1, FEE, &factory, -60, 60).0
+        );
+    }
+
+    #[test]
+    fn distinguishes_stakes_by_incentive() {
+        let (pool, _) = find_pool_address(&TOKEN_0, &TOKEN_1, FEE);
+        let refundee = Pubkey::new_unique();
+        let (incentive_a, _) = find_incentive_address(&TOKEN_0, &pool, 100, 200, &refundee);
+        let (incentive_b, _) = find_incentive_address(&TOKEN_0, &pool, 100, 300, &refundee);
+        assert_ne!(incentive_a, incentive_b);
+
+        let nft_mint = Pubkey::new_unique();
+        assert_ne!(
+            find_stake_address(&incentive_a, &nft_mint).0,
+            find_stake_address(&incentive_b, &nft_mint).0
+        );
+        assert_ne!(
+            find_deposit_address(&nft_mint).0,
+            find_tokenized_position_address(&nft_mint).0
+        );
+    }
+
+    #[test]
+    fn distinguishes_distributions_by_index() {
+        let (pool, _) = find_pool_address(&TOKEN_0, &TOKEN_1, FEE);
+        let (distributor_a, _) = find_distributor_address(&pool, &TOKEN_0, 0);
+        let (distributor_b, _) = find_distributor_address(&pool, &TOKEN_0, 1);
+        assert_ne!(distributor_a, distributor_b);
+        assert_ne!(
+            find_claim_status_address(&distributor_a, 0).0,
+            find_claim_status_address(&distributor_b, 0).0
+        );
+    }
+}
@@ -0,0 +1,677 @@
+//! Resolves the remaining accounts of a swap
+//!
+//! A swap reads the bitmap of each word it steps through and the account of each initialized
+//! tick it crosses, in order, followed by the next observation account if it writes to a new
+//! observation. The resolver walks the fetched bitmaps of the pool in the swap direction with
+//! the program's swap engine, and lists the same accounts.
+//!
+//! The price can move between resolving accounts and executing the swap. A margin in ticks
+//! widens the walked range on both sides. The program skips bitmap and tick accounts outside
+//! the range it crosses.
+//!
+use crate::pda;
+use crate::state;
+use anchor_lang::prelude::*;
+use cyclos_core::error::ErrorCode;
+use cyclos_core::libraries::tick_math;
+use cyclos_core::states::escrow::Escrow;
+use cyclos_core::states::factory::FactoryState;
+use cyclos_core::states::pool::{PoolState, REWARD_NUM};
+use cyclos_core::states::tick::TickState;
+use cyclos_core::states::tick_bitmap::TickBitmapState;
+use cyclos_core::swap_engine::{swap_steps, NoObservation, SwapCache, SwapState, TickProvider};
+use std::collections::HashMap;
+
+/// The point at which a swap stops
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SwapTarget {
+    /// Swap until the price reaches a Q32.32 sqrt price. Only bitmaps are needed to resolve it
+    SqrtPrice(u64),
+
+    /// Swap an exact amount, positive for exact input and negative for exact output, or until
+    /// the Q32.32 sqrt price limit is reached. A limit of 0 swaps without a limit. Ticks crossed
+    /// by the swap are needed to resolve it
+    Amount {
+        amount_specified: i64,
+        sqrt_price_limit_x32: u64,
+    },
+}
+
+/// A pool along with its fetched bitmap and tick accounts
+pub struct SwapAccountResolver {
+    /// The pool to swap in
+    pub pool: PoolState,
+
+    /// Initialized bitmap accounts of the pool, keyed by word position. Bitmaps missing over the
+    /// range of a swap are read as uninitialized
+    pub bitmaps: HashMap<i16, TickBitmapState>,
+
+    /// Initialized tick accounts of the pool, keyed by tick
+    pub ticks: HashMap<i32, TickState>,
+
+    /// The swap fee charged, in hundredths of a bip. The pool fee unless discounted with
+    /// `apply_fee_discount`
+    pub fee: u32,
+}
+
+impl SwapAccountResolver {
+    /// Creates a resolver for a pool. Bitmap accounts must be added before resolving
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - The pool to swap in
+    ///
+    pub fn new(pool: PoolState) -> Self {
+        SwapAccountResolver {
+            fee: pool.fee,
+            pool,
+            bitmaps: HashMap::new(),
+            ticks: HashMap::new(),
+        }
+    }
+
+    /// Discounts the swap fee for a trader locking governance tokens, as the swap instruction
+    /// does when passed the escrow of the trader
+    ///
+    /// # Arguments
+    ///
+    /// * `factory_state` - The factory holding the locker and discount tiers
+    /// * `escrow` - The escrow of the trader
+    /// * `now` - The current time, used to tell the time left before the tokens unlock
+    ///
+    pub fn apply_fee_discount(&mut self, factory_state: &FactoryState, escrow: &Escrow, now: i64) {
+        self.fee = factory_state.discounted_fee(self.pool.fee, Some(escrow), now);
+    }
+
+    /// Adds a bitmap account from raw account data
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Data of a bitmap account of this pool, including the discriminator
+    ///
+    pub fn add_bitmap_account(&mut self, data: &[u8]) -> Result<()> {
+        let bitmap = state::bitmap(data)?;
+        self.bitmaps.insert(bitmap.word_pos, bitmap);
+        Ok(())
+    }
+
+    /// Adds a tick account from raw account data
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Data of a tick account of this pool, including the discriminator
+    ///
+    pub fn add_tick_account(&mut self, data: &[u8]) -> Result<()> {
+        let tick = state::tick(data)?;
+        self.ticks.insert(tick.tick, tick);
+        Ok(())
+    }
+
+    /// Returns the remaining accounts of a swap, in the order they are read
+    ///
+    /// Accounts are listed from `margin_ticks` ticks before the pool price up to `margin_ticks`
+    /// ticks beyond the tick where the swap ends, plus one. The next observation account is
+    /// always appended, as the program reads it only if the swap writes to a new observation.
+    ///
+    /// # Arguments
+    ///
+    /// * `zero_for_one` - The direction of the swap, true for token_0 to token_1
+    /// * `target` - The price or amount at which the swap stops
+    /// * `margin_ticks` - Allowed price movement before the swap executes, in ticks
+    ///
+    pub fn resolve(
+        &self,
+        zero_for_one: bool,
+        target: SwapTarget,
+        margin_ticks: u32,
+    ) -> Result<Vec<AccountMeta>> {
+        let pool = &self.pool;
+        let end_sqrt_price_x32 = match target {
+            SwapTarget::SqrtPrice(sqrt_price_x32) => {
+                self.check_sqrt_price_limit(zero_for_one, sqrt_price_x32)?
+            }
+            SwapTarget::Amount {
+                amount_specified,
+                sqrt_price_limit_x32,
+            } => self.end_sqrt_price(zero_for_one, amount_specified, sqrt_price_limit_x32)?,
+        };
+
+        // widen the range walked by the margin. The end is extended by one more tick, so that
+        // the walk passes the end price
+        let margin = margin_ticks.min(tick_math::MAX_TICK as u32 * 2) as i32;
+        let end_tick = tick_math::get_tick_at_sqrt_ratio(end_sqrt_price_x32)?;
+        let (start_tick, end_tick) = if zero_for_one {
+            (pool.tick + margin, end_tick - margin - 1)
+        } else {
+            (pool.tick - margin, end_tick + margin + 1)
+        };
+        let start_tick = start_tick.clamp(tick_math::MIN_TICK, tick_math::MAX_TICK);
+        let end_tick = end_tick.clamp(tick_math::MIN_TICK, tick_math::MAX_TICK);
+        let start_sqrt_price_x32 = if margin == 0 {
+            pool.sqrt_price_x32
+        } else {
+            tick_math::get_sqrt_ratio_at_tick(start_tick)?
+        };
+        let end_sqrt_price_x32 = tick_math::get_sqrt_ratio_at_tick(end_tick)?
+            .clamp(tick_math::MIN_SQRT_RATIO + 1, tick_math::MAX_SQRT_RATIO - 1);
+
+        // step through the range without liquidity, so that every step reaches its target price
+        let mut cache = SwapCache {
+            fee: self.fee,
+            fee_protocol: 0,
+            liquidity_start: 0,
+            block_timestamp: 0,
+            tick_cumulative: 0,
+            seconds_per_liquidity_cumulative_x32: 0,
+            computed_latest_observation: false,
+            reward_growths_global_x32: [0; REWARD_NUM],
+        };
+        let mut state = SwapState {
+            amount_specified_remaining: i64::MAX,
+            amount_calculated: 0,
+            sqrt_price_x32: start_sqrt_price_x32,
+            tick: if margin == 0 { pool.tick } else { start_tick },
+            fee_growth_global_x32: 0,
+            protocol_fee: 0,
+            liquidity: 0,
+        };
+        let mut tick_provider = ResolverTickProvider {
+            resolver: self,
+            accounts: Some(Vec::new()),
+        };
+        swap_steps(
+            pool,
+            &mut cache,
+            &mut state,
+            &mut tick_provider,
+            &NoObservation,
+            zero_for_one,
+            end_sqrt_price_x32,
+        )?;
+
+        let mut accounts = tick_provider.accounts.unwrap();
+        accounts.push(AccountMeta::new(
+            pda::find_observation_address(
+                &pool.token_0,
+                &pool.token_1,
+                pool.fee,
+                pool.next_observation_index(),
+            )
+            .0,
+            false,
+        ));
+        Ok(accounts)
+    }
+
+    /// Validates a price limit the same way as the `swap` instruction
+    ///
+    /// # Arguments
+    ///
+    /// * `zero_for_one` - The direction of the swap
+    /// * `sqrt_price_limit_x32` - The Q32.32 sqrt price limit
+    ///
+    fn check_sqrt_price_limit(&self, zero_for_one: bool, sqrt_price_limit_x32: u64) -> Result<u64> {
+        require!(
+            if zero_for_one {
+                sqrt_price_limit_x32 < self.pool.sqrt_price_x32
+                    && sqrt_price_limit_x32 > tick_math::MIN_SQRT_RATIO
+            } else {
+                sqrt_price_limit_x32 > self.pool.sqrt_price_x32
+                    && sqrt_price_limit_x32 < tick_math::MAX_SQRT_RATIO
+            },
+            ErrorCode::SPL
+        );
+        Ok(sqrt_price_limit_x32)
+    }
+
+    /// Returns the price at which a swap of an exact amount ends
+    ///
+    /// # Arguments
+    ///
+    /// * `zero_for_one` - The direction of the swap
+    /// * `amount_specified` - Exact input amount if positive, or exact output amount if negative
+    /// * `sqrt_price_limit_x32` - The Q32.32 sqrt price limit, or 0 to swap without a limit
+    ///
+    fn end_sqrt_price(
+        &self,
+        zero_for_one: bool,
+        amount_specified: i64,
+        sqrt_price_limit_x32: u64,
+    ) -> Result<u64> {
+        require!(amount_specified != 0, ErrorCode::AS);
+        let pool = &self.pool;
+        let sqrt_price_limit_x32 = if sqrt_price_limit_x32 == 0 {
+            if zero_for_one {
+                tick_math::MIN_SQRT_RATIO + 1
+            } else {
+                tick_math::MAX_SQRT_RATIO - 1
+            }
+        } else {
+            sqrt_price_limit_x32
+        };
+        self.check_sqrt_price_limit(zero_for_one, sqrt_price_limit_x32)?;
+
+        let mut cache = SwapCache {
+            fee: self.fee,
+            // the protocol fee is taken out of the swap fee, so it does not move the price
+            fee_protocol: 0,
+            liquidity_start: pool.liquidity,
+            block_timestamp: 0,
+            tick_cumulative: 0,
+            seconds_per_liquidity_cumulative_x32: 0,
+            computed_latest_observation: false,
+            reward_growths_global_x32: pool.reward_growths_global_x32(),
+        };
+        let mut state = SwapState {
+            amount_specified_remaining: amount_specified,
+            amount_calculated: 0,
+            sqrt_price_x32: pool.sqrt_price_x32,
+            tick: pool.tick,
+            fee_growth_global_x32: 0,
+            protocol_fee: 0,
+            liquidity: pool.liquidity,
+        };
+        swap_steps(
+            pool,
+            &mut cache,
+            &mut state,
+            &mut ResolverTickProvider {
+                resolver: self,
+                accounts: None,
+            },
+            &NoObservation,
+            zero_for_one,
+            sqrt_price_limit_x32,
+        )?;
+        Ok(state.sqrt_price_x32)
+    }
+}
+
+/// Serves the bitmaps and ticks held by a resolver
+///
+/// Walks record the accounts read and treat ticks as holding no liquidity. Otherwise the
+/// liquidity of crossed ticks is returned.
+struct ResolverTickProvider<'a> {
+    resolver: &'a SwapAccountResolver,
+    accounts: Option<Vec<AccountMeta>>,
+}
+
+impl<'a> TickProvider for ResolverTickProvider<'a> {
+    fn bitmap(&mut self, word_pos: i16) -> Result<Option<TickBitmapState>> {
+        let pool = &self.resolver.pool;
+        if let Some(accounts) = self.accounts.as_mut() {
+            let (address, _) =
+                pda::find_bitmap_address(&pool.token_0, &pool.token_1, pool.fee, word_pos);
+            accounts.push(AccountMeta::new_readonly(address, false));
+        }
+        Ok(self.resolver.bitmaps.get(&word_pos).copied())
+    }
+
+    fn cross(
+        &mut self,
+        tick: i32,
+        _fee_growth_global_0_x32: u64,
+        _fee_growth_global_1_x32: u64,
+        _cache: &SwapCache,
+    ) -> Result<i64> {
+        let pool = &self.resolver.pool;
+        if let Some(accounts) = self.accounts.as_mut() {
+            let (address, _) = pda::find_tick_address(&pool.token_0, &pool.token_1, pool.fee, tick);
+            accounts.push(AccountMeta::new(address, false));
+            return Ok(0);
+        }
+        Ok(self
+            .resolver
+            .ticks
+            .get(&tick)
+            .ok_or(ErrorCode::TNI)?
+            .liquidity_net)
+    }
+}
+
+/// Accounts of one pool in an `exact_input` path
+pub struct ExactInputHop {
+    /// The pool to swap in
+    pub pool_state: Pubkey,
+
+    /// The token account receiving the output of this hop, and paying the input of the next
+    pub output_token_account: Pubkey,
+
+    /// The vault of the pool for the input token
+    pub input_vault: Pubkey,
+
+    /// The vault of the pool for the output token
+    pub output_vault: Pubkey,
+
+    /// The observation account at the observation index of the pool
+    pub last_observation_state: Pubkey,
+
+    /// Remaining accounts of the swap in this pool, as returned by the resolver
+    pub swap_accounts: Vec<AccountMeta>,
+}
+
+/// Returns the remaining accounts and `additional_accounts_per_pool` argument of an
+/// `exact_input` instruction
+///
+/// # Arguments
+///
+/// * `hops` - The pools of the path, in order
+///
+pub fn exact_input_accounts(hops: &[ExactInputHop]) -> (Vec<AccountMeta>, Vec<u8>) {
+    let mut remaining_accounts = Vec::new();
+    let mut additional_accounts_per_pool = Vec::new();
+    for hop in hops {
+        remaining_accounts.push(AccountMeta::new(hop.pool_state, false));
+        remaining_accounts.push(AccountMeta::new(hop.output_token_account, false));
+        remaining_accounts.push(AccountMeta::new(hop.input_vault, false));
+        remaining_accounts.push(AccountMeta::new(hop.output_vault, false));
+        remaining_accounts.push(AccountMeta::new(hop.last_observation_state, false));
+        remaining_accounts.extend(hop.swap_accounts.iter().cloned());
+        additional_accounts_per_pool.push(u8::try_from(hop.swap_accounts.len()).unwrap());
+    }
+    (remaining_accounts, additional_accounts_per_pool)
+}
+
+/// Accounts of one pool in an `exact_output` path
+pub struct ExactOutputHop {
+    /// The pool to swap in
+    pub pool_state: Pubkey,
+
+    /// The vault of the pool for the input token
+    pub input_vault: Pubkey,
+
+    /// The vault of the pool for the output token
+    pub output_vault: Pubkey,
+
+    /// The observation account at the observation index of the pool
+    pub last_observation_state: Pubkey,
+
+    /// Remaining accounts of the swap in this pool, as returned by the resolver
+    pub swap_accounts: Vec<AccountMeta>,
+}
+
+/// Returns the remaining accounts and `additional_accounts_per_pool` argument of an
+/// `exact_output` instruction
+///
+/// # Arguments
+///
+/// * `hops` - The pools of the path, in reverse order
+///
+pub fn exact_output_accounts(hops: &[ExactOutputHop]) -> (Vec<AccountMeta>, Vec<u8>) {
+    let mut remaining_accounts = Vec::new();
+    let mut additional_accounts_per_pool = Vec::new();
+    for hop in hops {
+        remaining_accounts.push(AccountMeta::new(hop.pool_state, false));
+        remaining_accounts.push(AccountMeta::new(hop.input_vault, false));
+        remaining_accounts.push(AccountMeta::new(hop.output_vault, false));
+        remaining_accounts.push(AccountMeta::new(hop.last_observation_state, false));
+        remaining_accounts.extend(hop.swap_accounts.iter().cloned());
+        additional_accounts_per_pool.push(u8::try_from(hop.swap_accounts.len()).unwrap());
+    }
+    (remaining_accounts, additional_accounts_per_pool)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cyclos_core::libraries::liquidity_amounts;
+
+    const LIQUIDITY: u64 = 1 << 32;
+
+    /// Returns the address of a bitmap of the test pool
+    fn bitmap_address(pool: &PoolState, word_pos: i16) -> Pubkey {
+        pda::find_bitmap_address(&pool.token_0, &pool.token_1, pool.fee, word_pos).0
+    }
+
+    /// Returns the address of a tick of the test pool
+    fn tick_address(pool: &PoolState, tick: i32) -> Pubkey {
+        pda::find_tick_address(&pool.token_0, &pool.token_1, pool.fee, tick).0
+    }
+
+    fn keys(accounts: &[AccountMeta]) -> Vec<Pubkey> {
+        accounts.iter().map(|account| account.pubkey).collect()
+    }
+
+    /// A pool at tick 5 with spacing 10, a position between ticks -10 and 10, and another
+    /// between ticks 2560 and 2580
+    fn resolver() -> SwapAccountResolver {
+        let mut resolver = SwapAccountResolver::new(PoolState {
+            token_0: Pubkey::new_from_array([1; 32]),
+            token_1: Pubkey::new_from_array([2; 32]),
+            fee: 500,
+            tick_spacing: 10,
+            tick: 5,
+            sqrt_price_x32: tick_math::get_sqrt_ratio_at_tick(5).unwrap(),
+            liquidity: LIQUIDITY,
+            observation_cardinality: 2,
+            observation_cardinality_next: 2,
+            ..Default::default()
+        });
+
+        // tick -10 is bit 255 of word -1, tick 10 is bit 1 of word 0, and ticks 2560 and 2580
+        // are bits 0 and 2 of word 1
+        for (word_pos, bits) in [(-1, vec![255]), (0, vec![1]), (1, vec![0, 2])] {
+            let mut bitmap = TickBitmapState {
+                word_pos,
+                ..Default::default()
+            };
+            for bit_pos in bits {
+                bitmap.flip_bit(bit_pos);
+            }
+            resolver.bitmaps.insert(word_pos, bitmap);
+        }
+        for (tick, liquidity_net) in [
+            (-10, LIQUIDITY as i64),
+            (10, -(LIQUIDITY as i64)),
+            (2560, LIQUIDITY as i64),
+            (2580, -(LIQUIDITY as i64)),
+        ] {
+            let tick_state = TickState {
+                tick,
+                liquidity_net,
+                liquidity_gross: LIQUIDITY,
+                ..Default::default()
+            };
+            resolver.ticks.insert(tick, tick_state);
+        }
+        resolver
+    }
+
+    #[test]
+    fn fails_for_limit_on_the_wrong_side_of_the_price() {
+        let resolver = resolver();
+        let sqrt_price_x32 = resolver.pool.sqrt_price_x32;
+        assert!(resolver
+            .resolve(true, SwapTarget::SqrtPrice(sqrt_price_x32 + 1), 0)
+            .is_err());
+        assert!(resolver
+            .resolve(false, SwapTarget::SqrtPrice(sqrt_price_x32), 0)
+            .is_err());
+    }
+
+    #[test]
+    fn lists_bitmaps_and_ticks_up_to_a_price() {
+        let resolver = resolver();
+        let pool = &resolver.pool;
+        let limit = tick_math::get_sqrt_ratio_at_tick(2570).unwrap();
+        let accounts = resolver
+            .resolve(false, SwapTarget::SqrtPrice(limit), 0)
+            .unwrap();
+        assert_eq!(
+            keys(&accounts),
+            vec![
+                bitmap_address(pool, 0),
+                tick_address(pool, 10),
+                bitmap_address(pool, 1),
+                tick_address(pool, 2560),
+                pda::find_observation_address(&pool.token_0, &pool.token_1, pool.fee, 1).0,
+            ]
+        );
+        // crossed ticks and the observation are written
+        assert!(!accounts[0].is_writable);
+        assert!(accounts[1].is_writable && accounts[4].is_writable);
+    }
+
+    #[test]
+    fn walks_downward_for_zero_for_one() {
+        let resolver = resolver();
+        let pool = &resolver.pool;
+        let limit = tick_math::get_sqrt_ratio_at_tick(-20).unwrap();
+        let accounts = resolver
+            .resolve(true, SwapTarget::SqrtPrice(limit), 0)
+            .unwrap();
+        assert_eq!(
+            keys(&accounts[..accounts.len() - 1]),
+            vec![
+                bitmap_address(pool, 0),
+                bitmap_address(pool, -1),
+                tick_address(pool, -10),
+            ]
+        );
+    }
+
+    #[test]
+    fn stops_where_an_amount_is_used_up() {
+        let resolver = resolver();
+        let pool = &resolver.pool;
+        let accounts = resolver
+            .resolve(
+                false,
+                SwapTarget::Amount {
+                    amount_specified: 1_000,
+                    sqrt_price_limit_x32: 0,
+                },
+                0,
+            )
+            .unwrap();
+        assert_eq!(
+            keys(&accounts[..accounts.len() - 1]),
+            vec![bitmap_address(pool, 0)]
+        );
+
+        // one more than the token_0 held up to tick 10 moves the price into the next position
+        let amount_out = liquidity_amounts::get_amount_0_for_liquidity(
+            pool.sqrt_price_x32,
+            tick_math::get_sqrt_ratio_at_tick(10).unwrap(),
+            LIQUIDITY,
+        );
+        let accounts = resolver
+            .resolve(
+                false,
+                SwapTarget::Amount {
+                    amount_specified: -(amount_out as i64 + 1),
+                    sqrt_price_limit_x32: 0,
+                },
+                0,
+            )
+            .unwrap();
+        assert_eq!(
+            keys(&accounts[..accounts.len() - 1]),
+            vec![
+                bitmap_address(pool, 0),
+                tick_address(pool, 10),
+                bitmap_address(pool, 1),
+                tick_address(pool, 2560),
+            ]
+        );
+    }
+
+    #[test]
+    fn moves_the_price_further_with_a_discounted_fee() {
+        let mut resolver = resolver();
+        let sqrt_price_x32 = resolver.end_sqrt_price(false, 1_000_000, 0).unwrap();
+
+        let locker = Pubkey::new_unique();
+        let mut factory_state = FactoryState {
+            locker,
+            ..Default::default()
+        };
+        factory_state.fee_discount_tiers[0].discount = 500_000;
+        let escrow = Escrow {
+            locker,
+            escrow_ends_at: 100,
+            ..Default::default()
+        };
+        resolver.apply_fee_discount(&factory_state, &escrow, 0);
+        assert_eq!(resolver.fee, 250);
+        assert!(resolver.end_sqrt_price(false, 1_000_000, 0).unwrap() > sqrt_price_x32);
+    }
+
+    #[test]
+    fn needs_crossed_ticks_to_resolve_amounts() {
+        let mut resolver = resolver();
+        resolver.ticks.remove(&10);
+        assert!(resolver
+            .resolve(
+                false,
+                SwapTarget::Amount {
+                    amount_specified: i64::MAX,
+                    sqrt_price_limit_x32: 0,
+                },
+                0,
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn widens_both_ends_by_the_margin() {
+        let resolver = resolver();
+        let pool = &resolver.pool;
+        let limit = tick_math::get_sqrt_ratio_at_tick(2570).unwrap();
+        let accounts = resolver
+            .resolve(false, SwapTarget::SqrtPrice(limit), 20)
+            .unwrap();
+        assert_eq!(
+            keys(&accounts[..accounts.len() - 1]),
+            vec![
+                bitmap_address(pool, -1),
+                tick_address(pool, -10),
+                bitmap_address(pool, 0),
+                tick_address(pool, 10),
+                bitmap_address(pool, 1),
+                tick_address(pool, 2560),
+                tick_address(pool, 2580),
+            ]
+        );
+    }
+
+    #[test]
+    fn lists_exact_input_paths() {
+        let hop = |swap_accounts: usize| ExactInputHop {
+            pool_state: Pubkey::new_unique(),
+            output_token_account: Pubkey::new_unique(),
+            input_vault: Pubkey::new_unique(),
+            output_vault: Pubkey::new_unique(),
+            last_observation_state: Pubkey::new_unique(),
+            swap_accounts: (0..swap_accounts)
+                .map(|_| AccountMeta::new_readonly(Pubkey::new_unique(), false))
+                .collect(),
+        };
+        let hops = vec![hop(2), hop(3)];
+        let (remaining_accounts, additional_accounts_per_pool) = exact_input_accounts(&hops);
+        assert_eq!(additional_accounts_per_pool, vec![2, 3]);
+        assert_eq!(remaining_accounts.len(), 5 + 2 + 5 + 3);
+        assert_eq!(remaining_accounts[0].pubkey, hops[0].pool_state);
+        assert_eq!(remaining_accounts[7].pubkey, hops[1].pool_state);
+        assert_eq!(remaining_accounts[12..], hops[1].swap_accounts[..]);
+    }
+
+    #[test]
+    fn lists_exact_output_paths() {
+        let hop = |swap_accounts: usize| ExactOutputHop {
+            pool_state: Pubkey::new_unique(),
+            input_vault: Pubkey::new_unique(),
+            output_vault: Pubkey::new_unique(),
+            last_observation_state: Pubkey::new_unique(),
+            swap_accounts: (0..swap_accounts)
+                .map(|_| AccountMeta::new_readonly(Pubkey::new_unique(), false))
+                .collect(),
+        };
+        let hops = vec![hop(3), hop(1)];
+        let (remaining_accounts, additional_accounts_per_pool) = exact_output_accounts(&hops);
+        assert_eq!(additional_accounts_per_pool, vec![3, 1]);
+        assert_eq!(remaining_accounts.len(), 4 + 3 + 4 + 1);
+        assert_eq!(remaining_accounts[7].pubkey, hops[1].pool_state);
+        assert_eq!(remaining_accounts[11..], hops[1].swap_accounts[..]);
+    }
+}
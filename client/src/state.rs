@@ -0,0 +1,174 @@
+//! Deserializers for the zero-copy program accounts
+//!
+//! Account data is read including its discriminator, which is checked against the expected
+//! account type. States are copied out of the data, so fields of the packed structs can be read
+//! by value.
+//!
+use anchor_lang::prelude::*;
+use anchor_lang::{AccountDeserialize, ZeroCopy};
+pub use cyclos_core::states::distributor::{ClaimStatusState, DistributorState};
+pub use cyclos_core::states::factory::FactoryState;
+pub use cyclos_core::states::fee::FeeState;
+pub use cyclos_core::states::oracle::ObservationState;
+pub use cyclos_core::states::pool::PoolState;
+pub use cyclos_core::states::position::PositionState;
+pub use cyclos_core::states::staker::{DepositState, IncentiveState, StakeState};
+pub use cyclos_core::states::tick::TickState;
+pub use cyclos_core::states::tick_bitmap::TickBitmapState;
+pub use cyclos_core::states::timelock::TimelockState;
+pub use cyclos_core::states::tokenized_position::TokenizedPositionState;
+
+/// Deserializes a zero-copy program account
+///
+/// Data is checked for length first, since the zero-copy reader panics on short data.
+/// Trailing bytes are ignored, as by `AccountLoader`.
+///
+/// # Arguments
+///
+/// * `data` - Data of the account, including the discriminator
+///
+pub fn deserialize<T: ZeroCopy + AccountDeserialize>(data: &[u8]) -> Result<T> {
+    let len = 8 + std::mem::size_of::<T>();
+    require!(
+        data.len() >= len,
+        anchor_lang::error::ErrorCode::AccountDidNotDeserialize
+    );
+    T::try_deserialize(&mut &data[..len])
+}
+
+/// Deserializes the factory account
+pub fn factory(data: &[u8]) -> Result<FactoryState> {
+    deserialize(data)
+}
+
+/// Deserializes a fee account
+pub fn fee(data: &[u8]) -> Result<FeeState> {
+    deserialize(data)
+}
+
+/// Deserializes a pool account
+pub fn pool(data: &[u8]) -> Result<PoolState> {
+    deserialize(data)
+}
+
+/// Deserializes an observation account
+pub fn observation(data: &[u8]) -> Result<ObservationState> {
+    deserialize(data)
+}
+
+/// Deserializes a tick account
+pub fn tick(data: &[u8]) -> Result<TickState> {
+    deserialize(data)
+}
+
+/// Deserializes a bitmap account
+pub fn bitmap(data: &[u8]) -> Result<TickBitmapState> {
+    deserialize(data)
+}
+
+/// Deserializes a timelock account
+pub fn timelock(data: &[u8]) -> Result<TimelockState> {
+    deserialize(data)
+}
+
+/// Deserializes a position account
+pub fn position(data: &[u8]) -> Result<PositionState> {
+    deserialize(data)
+}
+
+/// Deserializes a tokenized position account
+pub fn tokenized_position(data: &[u8]) -> Result<TokenizedPositionState> {
+    deserialize(data)
+}
+
+/// Deserializes an incentive account
+pub fn incentive(data: &[u8]) -> Result<IncentiveState> {
+    deserialize(data)
+}
+
+/// Deserializes a deposit account
+pub fn deposit(data: &[u8]) -> Result<DepositState> {
+    deserialize(data)
+}
+
+/// Deserializes a stake account
+pub fn stake(data: &[u8]) -> Result<StakeState> {
+    deserialize(data)
+}
+
+/// Deserializes a distributor account
+pub fn distributor(data: &[u8]) -> Result<DistributorState> {
+    deserialize(data)
+}
+
+/// Deserializes a claim status account
+pub fn claim_status(data: &[u8]) -> Result<ClaimStatusState> {
+    deserialize(data)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use anchor_lang::__private::bytemuck::{bytes_of, Pod};
+    use anchor_lang::Discriminator;
+
+    fn serialize<T: Discriminator + Pod>(state: &T) -> Vec<u8> {
+        let mut data = T::discriminator().to_vec();
+        data.extend_from_slice(bytes_of(state));
+        data
+    }
+
+    #[test]
+    fn reads_packed_states() {
+        let pool_state = PoolState {
+            fee: 500,
+            tick_spacing: 10,
+            sqrt_price_x32: 1 << 32,
+            tick: -7,
+            liquidity: 1_000_000,
+            ..Default::default()
+        };
+        let read = pool(&serialize(&pool_state)).unwrap();
+        assert_eq!({ read.fee }, 500);
+        assert_eq!({ read.tick_spacing }, 10);
+        assert_eq!({ read.sqrt_price_x32 }, 1 << 32);
+        assert_eq!({ read.tick }, -7);
+        assert_eq!({ read.liquidity }, 1_000_000);
+
+        let tick_state = TickState {
+            tick: -887220,
+            liquidity_net: -5,
+            ..Default::default()
+        };
+        let read = tick(&serialize(&tick_state)).unwrap();
+        assert_eq!({ read.tick }, -887220);
+        assert_eq!({ read.liquidity_net }, -5);
+    }
+
+    #[test]
+    fn rejects_other_account_types() {
+        let data = serialize(&TickState::default());
+        assert!(bitmap(&data).is_err());
+        assert!(pool(&data).is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_data() {
+        let data = serialize(&PositionState::default());
+        assert!(position(&data[..data.len() - 1]).is_err());
+        assert!(position(&data[..4]).is_err());
+    }
+
+    #[test]
+    fn ignores_trailing_bytes() {
+        let mut data = serialize(&FeeState {
+            bump: 255,
+            fee: 3000,
+            tick_spacing: 60,
+        });
+        data.extend_from_slice(&[1; 8]);
+        let read = fee(&data).unwrap();
+        assert_eq!({ read.fee }, 3000);
+        assert_eq!({ read.tick_spacing }, 60);
+    }
+}
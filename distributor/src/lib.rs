@@ -0,0 +1,481 @@
+//! Off-chain reward calculator for the liquidity providers of a Cykura pool
+//!
+//! Replays the events of a pool, read from transaction log dumps, to measure the seconds each
+//! position spends in range weighted by its liquidity, per epoch. The reward of each epoch is
+//! shared in proportion to these liquidity seconds, and the amounts owed are committed to as
+//! a Merkle tree claimable from the distributor of the program.
+//!
+//! Tokenized positions are tracked by NFT mint, and paid to the holders of their NFTs.
+//!
+pub mod logs;
+pub mod merkle;
+
+use anchor_lang::prelude::*;
+use cyclos_core::libraries::big_num::U128;
+use cyclos_core::libraries::full_math::MulDiv;
+use logs::{PoolEvent, Transaction};
+use merkle::{Claim, MerkleTree};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// Identifies a position of the pool
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PositionId {
+    /// A position owned directly by an address, like the seeds of a position account
+    Core {
+        /// The owner of the position
+        owner: Pubkey,
+
+        /// The lower tick of the position
+        tick_lower: i32,
+
+        /// The upper tick of the position
+        tick_upper: i32,
+    },
+
+    /// A tokenized position, owned by the holder of its NFT
+    Tokenized {
+        /// Mint address of the tokenized position
+        token_id: Pubkey,
+    },
+}
+
+/// The range and liquidity of a tracked position
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TrackedPosition {
+    /// The lower tick of the position
+    pub tick_lower: i32,
+
+    /// The upper tick of the position
+    pub tick_upper: i32,
+
+    /// The liquidity of the position
+    pub liquidity: u64,
+}
+
+/// Measures the in-range liquidity seconds of every position of a pool, per epoch
+pub struct RewardCalculator {
+    /// The pool whose liquidity providers are rewarded
+    pub pool: Pubkey,
+
+    /// The start time of the first epoch
+    pub epoch_start: u32,
+
+    /// The duration of an epoch, in seconds
+    pub epoch_duration: u32,
+
+    /// The owner of the core positions backing tokenized positions
+    factory: Pubkey,
+
+    /// The time up to which liquidity seconds were accrued
+    time: Option<u32>,
+
+    /// The current tick of the pool, unknown until a pool creation or swap is read
+    tick: Option<i32>,
+
+    /// The positions with liquidity
+    positions: BTreeMap<PositionId, TrackedPosition>,
+
+    /// The liquidity seconds of each position, per epoch
+    liquidity_seconds: Vec<BTreeMap<PositionId, u128>>,
+}
+
+impl RewardCalculator {
+    /// Creates a calculator for consecutive epochs of equal duration
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - The pool whose liquidity providers are rewarded
+    /// * `epoch_start` - The start time of the first epoch
+    /// * `epoch_duration` - The duration of an epoch, in seconds
+    /// * `epochs` - The number of epochs
+    ///
+    pub fn new(pool: Pubkey, epoch_start: u32, epoch_duration: u32, epochs: u32) -> Self {
+        assert!(epoch_duration > 0);
+        RewardCalculator {
+            pool,
+            epoch_start,
+            epoch_duration,
+            factory: Pubkey::find_program_address(&[], &cyclos_core::id()).0,
+            time: None,
+            tick: None,
+            positions: BTreeMap::new(),
+            liquidity_seconds: vec![BTreeMap::new(); epochs as usize],
+        }
+    }
+
+    /// The end time of the last epoch
+    pub fn epoch_end(&self) -> u32 {
+        self.epoch_start + self.epoch_duration * self.liquidity_seconds.len() as u32
+    }
+
+    /// The positions of the pool with liquidity
+    pub fn positions(&self) -> &BTreeMap<PositionId, TrackedPosition> {
+        &self.positions
+    }
+
+    /// The liquidity seconds of each position, per epoch
+    pub fn liquidity_seconds(&self) -> &[BTreeMap<PositionId, u128>] {
+        &self.liquidity_seconds
+    }
+
+    /// Accrues liquidity seconds up to the time of a transaction, then applies its events.
+    /// Transactions must be processed in order
+    ///
+    /// # Arguments
+    ///
+    /// * `transaction` - The transaction to process
+    ///
+    pub fn process(&mut self, transaction: &Transaction) {
+        self.advance_to(transaction.block_time);
+
+        // Tokenized positions mint and burn on the core position owned by the factory,
+        // immediately before the event naming the NFT
+        let mut pending_range = None;
+        for event in &transaction.events {
+            match event {
+                PoolEvent::PoolCreated(event) if event.pool_state == self.pool => {
+                    self.tick = Some(event.tick);
+                }
+                PoolEvent::Swap(event) if event.pool_state == self.pool => {
+                    self.tick = Some(event.tick);
+                }
+                PoolEvent::Mint(event) if event.pool_state == self.pool => {
+                    if event.owner == self.factory {
+                        pending_range = Some((event.tick_lower, event.tick_upper));
+                    } else {
+                        self.modify_position(
+                            PositionId::Core {
+                                owner: event.owner,
+                                tick_lower: event.tick_lower,
+                                tick_upper: event.tick_upper,
+                            },
+                            event.tick_lower,
+                            event.tick_upper,
+                            event.amount as i128,
+                        );
+                    }
+                }
+                PoolEvent::Burn(event) if event.pool_state == self.pool => {
+                    if event.owner == self.factory {
+                        pending_range = Some((event.tick_lower, event.tick_upper));
+                    } else {
+                        self.modify_position(
+                            PositionId::Core {
+                                owner: event.owner,
+                                tick_lower: event.tick_lower,
+                                tick_upper: event.tick_upper,
+                            },
+                            event.tick_lower,
+                            event.tick_upper,
+                            -(event.amount as i128),
+                        );
+                    }
+                }
+                PoolEvent::IncreaseLiquidity(event) => {
+                    if let Some((tick_lower, tick_upper)) = pending_range.take() {
+                        self.modify_position(
+                            PositionId::Tokenized {
+                                token_id: event.token_id,
+                            },
+                            tick_lower,
+                            tick_upper,
+                            event.liquidity as i128,
+                        );
+                    }
+                }
+                PoolEvent::DecreaseLiquidity(event) => {
+                    if let Some((tick_lower, tick_upper)) = pending_range.take() {
+                        self.modify_position(
+                            PositionId::Tokenized {
+                                token_id: event.token_id,
+                            },
+                            tick_lower,
+                            tick_upper,
+                            -(event.liquidity as i128),
+                        );
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Accrues the liquidity seconds of positions in range, from the last processed time up
+    /// to the given time. Nothing accrues while the tick of the pool is unknown
+    ///
+    /// # Arguments
+    ///
+    /// * `time` - The time to advance to
+    ///
+    pub fn advance_to(&mut self, time: u32) {
+        let last = match self.time {
+            Some(last) if time <= last => return,
+            Some(last) => last,
+            None => {
+                self.time = Some(time);
+                return;
+            }
+        };
+        self.time = Some(time);
+
+        let tick = match self.tick {
+            Some(tick) => tick,
+            None => return,
+        };
+        for (epoch, liquidity_seconds) in self.liquidity_seconds.iter_mut().enumerate() {
+            let start = self.epoch_start + self.epoch_duration * epoch as u32;
+            let end = start + self.epoch_duration;
+            let seconds = time.min(end).saturating_sub(last.max(start));
+            if seconds == 0 {
+                continue;
+            }
+            for (id, position) in &self.positions {
+                if position.tick_lower <= tick && tick < position.tick_upper {
+                    *liquidity_seconds.entry(*id).or_default() +=
+                        position.liquidity as u128 * seconds as u128;
+                }
+            }
+        }
+    }
+
+    /// Returns the reward owed to each address, and the tokenized positions whose holder is
+    /// unknown. The reward of an epoch is shared among positions in proportion to their
+    /// liquidity seconds, and is not paid out if no liquidity was in range
+    ///
+    /// # Arguments
+    ///
+    /// * `reward_per_epoch` - The amount of reward tokens distributed per epoch
+    /// * `holders` - The holder of each position NFT, by mint address
+    ///
+    pub fn rewards(
+        &self,
+        reward_per_epoch: u64,
+        holders: &HashMap<Pubkey, Pubkey>,
+    ) -> (BTreeMap<Pubkey, u64>, BTreeSet<Pubkey>) {
+        let mut rewards = BTreeMap::new();
+        let mut unresolved = BTreeSet::new();
+        for liquidity_seconds in &self.liquidity_seconds {
+            let total: u128 = liquidity_seconds.values().sum();
+            if total == 0 {
+                continue;
+            }
+            for (id, seconds) in liquidity_seconds {
+                let owner = match id {
+                    PositionId::Core { owner, .. } => *owner,
+                    PositionId::Tokenized { token_id } => match holders.get(token_id) {
+                        Some(holder) => *holder,
+                        None => {
+                            unresolved.insert(*token_id);
+                            continue;
+                        }
+                    },
+                };
+                let reward = U128::from(reward_per_epoch)
+                    .mul_div_floor(U128::from(*seconds), U128::from(total))
+                    .unwrap()
+                    .as_u64();
+                *rewards.entry(owner).or_default() += reward;
+            }
+        }
+        (rewards, unresolved)
+    }
+
+    fn modify_position(&mut self, id: PositionId, tick_lower: i32, tick_upper: i32, delta: i128) {
+        let position = self.positions.entry(id).or_insert(TrackedPosition {
+            tick_lower,
+            tick_upper,
+            liquidity: 0,
+        });
+        position.liquidity = (position.liquidity as i128 + delta).max(0) as u64;
+        if position.liquidity == 0 {
+            self.positions.remove(&id);
+        }
+    }
+}
+
+/// Returns the claims of a distribution and their Merkle tree. Claims are indexed in the
+/// order of claimants, skipping zero amounts
+///
+/// # Arguments
+///
+/// * `rewards` - The amount owed to each claimant
+///
+pub fn build_distribution(rewards: &BTreeMap<Pubkey, u64>) -> (Vec<Claim>, MerkleTree) {
+    let claims: Vec<Claim> = rewards
+        .iter()
+        .filter(|(_, amount)| **amount > 0)
+        .enumerate()
+        .map(|(index, (claimant, amount))| Claim {
+            index: index as u64,
+            claimant: *claimant,
+            amount: *amount,
+        })
+        .collect();
+    let tree = MerkleTree::new(&claims);
+    (claims, tree)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cyclos_core::states::pool::SwapEvent;
+    use cyclos_core::states::position::{BurnEvent, MintEvent};
+    use cyclos_core::states::tokenized_position::IncreaseLiquidityEvent;
+
+    fn swap(pool: Pubkey, tick: i32) -> PoolEvent {
+        PoolEvent::Swap(SwapEvent {
+            pool_state: pool,
+            sender: Pubkey::default(),
+            token_account_0: Pubkey::default(),
+            token_account_1: Pubkey::default(),
+            amount_0: 0,
+            amount_1: 0,
+            sqrt_price_x32: 0,
+            liquidity: 0,
+            tick,
+        })
+    }
+
+    fn mint(
+        pool: Pubkey,
+        owner: Pubkey,
+        tick_lower: i32,
+        tick_upper: i32,
+        amount: u64,
+    ) -> PoolEvent {
+        PoolEvent::Mint(MintEvent {
+            pool_state: pool,
+            sender: owner,
+            owner,
+            tick_lower,
+            tick_upper,
+            amount,
+            amount_0: 0,
+            amount_1: 0,
+        })
+    }
+
+    fn burn(
+        pool: Pubkey,
+        owner: Pubkey,
+        tick_lower: i32,
+        tick_upper: i32,
+        amount: u64,
+    ) -> PoolEvent {
+        PoolEvent::Burn(BurnEvent {
+            pool_state: pool,
+            owner,
+            tick_lower,
+            tick_upper,
+            amount,
+            amount_0: 0,
+            amount_1: 0,
+        })
+    }
+
+    fn transaction(block_time: u32, events: Vec<PoolEvent>) -> Transaction {
+        Transaction {
+            slot: block_time as u64,
+            block_time,
+            events,
+        }
+    }
+
+    #[test]
+    fn accrues_liquidity_seconds_while_in_range() {
+        let pool = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let id = PositionId::Core {
+            owner,
+            tick_lower: -10,
+            tick_upper: 10,
+        };
+        let mut calculator = RewardCalculator::new(pool, 100, 100, 2);
+
+        calculator.process(&transaction(
+            50,
+            vec![swap(pool, 0), mint(pool, owner, -10, 10, 5)],
+        ));
+        // out of range from 150 to 180
+        calculator.process(&transaction(150, vec![swap(pool, 10)]));
+        calculator.process(&transaction(180, vec![swap(pool, -10)]));
+        // swaps of other pools are ignored
+        calculator.process(&transaction(190, vec![swap(Pubkey::new_unique(), 50)]));
+        calculator.process(&transaction(250, vec![burn(pool, owner, -10, 10, 5)]));
+        calculator.advance_to(calculator.epoch_end());
+
+        assert_eq!(calculator.liquidity_seconds()[0][&id], 5 * 70);
+        assert_eq!(calculator.liquidity_seconds()[1][&id], 5 * 50);
+        assert!(calculator.positions().is_empty());
+    }
+
+    #[test]
+    fn attributes_factory_liquidity_to_position_nfts() {
+        let pool = Pubkey::new_unique();
+        let factory = Pubkey::find_program_address(&[], &cyclos_core::id()).0;
+        let token_id = Pubkey::new_unique();
+        let holder = Pubkey::new_unique();
+        let mut calculator = RewardCalculator::new(pool, 0, 100, 1);
+
+        calculator.process(&transaction(
+            0,
+            vec![
+                swap(pool, 0),
+                mint(pool, factory, -60, 60, 8),
+                PoolEvent::IncreaseLiquidity(IncreaseLiquidityEvent {
+                    token_id,
+                    liquidity: 8,
+                    amount_0: 0,
+                    amount_1: 0,
+                }),
+            ],
+        ));
+        calculator.advance_to(calculator.epoch_end());
+
+        assert_eq!(
+            calculator.positions()[&PositionId::Tokenized { token_id }],
+            TrackedPosition {
+                tick_lower: -60,
+                tick_upper: 60,
+                liquidity: 8,
+            }
+        );
+        let (rewards, unresolved) = calculator.rewards(1_000, &HashMap::new());
+        assert!(rewards.is_empty());
+        assert_eq!(unresolved.into_iter().collect::<Vec<_>>(), vec![token_id]);
+
+        let holders = HashMap::from([(token_id, holder)]);
+        let (rewards, unresolved) = calculator.rewards(1_000, &holders);
+        assert_eq!(rewards[&holder], 1_000);
+        assert!(unresolved.is_empty());
+    }
+
+    #[test]
+    fn shares_epoch_rewards_by_liquidity_seconds() {
+        let pool = Pubkey::new_unique();
+        let alice = Pubkey::new_unique();
+        let bob = Pubkey::new_unique();
+        let mut calculator = RewardCalculator::new(pool, 0, 100, 3);
+
+        calculator.process(&transaction(
+            0,
+            vec![
+                swap(pool, 0),
+                mint(pool, alice, -10, 10, 1),
+                mint(pool, bob, -20, 20, 2),
+            ],
+        ));
+        // nothing is in range during the last epoch
+        calculator.process(&transaction(200, vec![swap(pool, 100)]));
+        calculator.advance_to(calculator.epoch_end());
+
+        let (rewards, _) = calculator.rewards(300, &HashMap::new());
+        assert_eq!(rewards[&alice], 200);
+        assert_eq!(rewards[&bob], 400);
+
+        let (claims, tree) = build_distribution(&rewards);
+        assert_eq!(claims.len(), 2);
+        assert_eq!(claims.iter().map(|claim| claim.amount).sum::<u64>(), 600);
+        assert_eq!(tree.proof(0).len(), 1);
+    }
+}
@@ -0,0 +1,223 @@
+//! Reads program events from transaction log dumps
+//!
+//! A dump holds one transaction per line, as the JSON returned by the `getTransaction` RPC
+//! method. Events are the base64 encoded `Program log:` lines emitted while the program is
+//! the innermost invoked program. Failed transactions are skipped.
+//!
+use anchor_lang::prelude::*;
+use anchor_lang::Discriminator;
+use cyclos_core::states::pool::{PoolCreatedAndInitialized, SwapEvent};
+use cyclos_core::states::position::{BurnEvent, MintEvent};
+use cyclos_core::states::tokenized_position::{DecreaseLiquidityEvent, IncreaseLiquidityEvent};
+use serde_json::Value;
+
+/// An event of the program read by the calculator
+pub enum PoolEvent {
+    /// A pool was created, at its initial tick
+    PoolCreated(PoolCreatedAndInitialized),
+
+    /// A swap moved the tick of a pool
+    Swap(SwapEvent),
+
+    /// Liquidity was added to a position
+    Mint(MintEvent),
+
+    /// Liquidity was removed from a position
+    Burn(BurnEvent),
+
+    /// Liquidity was added to a tokenized position, by the preceding mint
+    IncreaseLiquidity(IncreaseLiquidityEvent),
+
+    /// Liquidity was removed from a tokenized position, by the preceding burn
+    DecreaseLiquidity(DecreaseLiquidityEvent),
+}
+
+/// A successful transaction, and the events it emitted in order
+pub struct Transaction {
+    /// The slot of the transaction
+    pub slot: u64,
+
+    /// The block timestamp of the transaction
+    pub block_time: u32,
+
+    /// The events emitted by the program
+    pub events: Vec<PoolEvent>,
+}
+
+/// Decodes an event from the data of a log line, including its discriminator.
+/// Returns None for events the calculator does not read
+///
+/// # Arguments
+///
+/// * `data` - The decoded data of the log line
+///
+pub fn decode_event(data: &[u8]) -> Option<PoolEvent> {
+    if data.len() < 8 {
+        return None;
+    }
+    let (discriminator, mut data) = data.split_at(8);
+    let data = &mut data;
+    if discriminator == PoolCreatedAndInitialized::discriminator() {
+        AnchorDeserialize::deserialize(data)
+            .ok()
+            .map(PoolEvent::PoolCreated)
+    } else if discriminator == SwapEvent::discriminator() {
+        AnchorDeserialize::deserialize(data)
+            .ok()
+            .map(PoolEvent::Swap)
+    } else if discriminator == MintEvent::discriminator() {
+        AnchorDeserialize::deserialize(data)
+            .ok()
+            .map(PoolEvent::Mint)
+    } else if discriminator == BurnEvent::discriminator() {
+        AnchorDeserialize::deserialize(data)
+            .ok()
+            .map(PoolEvent::Burn)
+    } else if discriminator == IncreaseLiquidityEvent::discriminator() {
+        AnchorDeserialize::deserialize(data)
+            .ok()
+            .map(PoolEvent::IncreaseLiquidity)
+    } else if discriminator == DecreaseLiquidityEvent::discriminator() {
+        AnchorDeserialize::deserialize(data)
+            .ok()
+            .map(PoolEvent::DecreaseLiquidity)
+    } else {
+        None
+    }
+}
+
+/// Returns the events emitted by the program in the log messages of a transaction
+///
+/// # Arguments
+///
+/// * `log_messages` - The log messages of the transaction, in order
+///
+pub fn parse_logs<'a>(log_messages: impl IntoIterator<Item = &'a str>) -> Vec<PoolEvent> {
+    let program_id = cyclos_core::id().to_string();
+    let mut invoked: Vec<&str> = Vec::new();
+    let mut events = Vec::new();
+    for message in log_messages {
+        if let Some(rest) = message.strip_prefix("Program ") {
+            if let Some(data) = rest.strip_prefix("log: ") {
+                if invoked.last() == Some(&program_id.as_str()) {
+                    if let Some(event) = base64::decode(data)
+                        .ok()
+                        .and_then(|data| decode_event(&data))
+                    {
+                        events.push(event);
+                    }
+                }
+            } else if let Some((program, status)) = rest.split_once(' ') {
+                if status.starts_with("invoke [") {
+                    invoked.push(program);
+                } else if status.starts_with("success") || status.starts_with("failed") {
+                    invoked.pop();
+                }
+            }
+        }
+    }
+    events
+}
+
+/// Parses a transaction of a log dump. Returns None for failed transactions
+///
+/// # Arguments
+///
+/// * `line` - The transaction, as the JSON returned by `getTransaction`
+///
+pub fn parse_transaction(line: &str) -> serde_json::Result<Option<Transaction>> {
+    let value: Value = serde_json::from_str(line)?;
+    let meta = &value["meta"];
+    if !meta["err"].is_null() {
+        return Ok(None);
+    }
+    let log_messages = meta["logMessages"]
+        .as_array()
+        .map(|messages| {
+            messages
+                .iter()
+                .filter_map(Value::as_str)
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    Ok(Some(Transaction {
+        slot: value["slot"].as_u64().unwrap_or_default(),
+        block_time: value["blockTime"].as_u64().unwrap_or_default() as u32,
+        events: parse_logs(log_messages),
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use anchor_lang::Event;
+
+    fn swap_event(tick: i32) -> SwapEvent {
+        SwapEvent {
+            pool_state: Pubkey::new_unique(),
+            sender: Pubkey::new_unique(),
+            token_account_0: Pubkey::new_unique(),
+            token_account_1: Pubkey::new_unique(),
+            amount_0: 100,
+            amount_1: -99,
+            sqrt_price_x32: 1 << 32,
+            liquidity: 1_000,
+            tick,
+        }
+    }
+
+    fn log(event: &impl Event) -> String {
+        format!("Program log: {}", base64::encode(event.data()))
+    }
+
+    #[test]
+    fn reads_events_of_the_program_only() {
+        let program_id = cyclos_core::id().to_string();
+        let other_program = Pubkey::new_unique().to_string();
+        let logs = [
+            format!("Program {} invoke [1]", program_id),
+            "Program log: Instruction: Swap".to_string(),
+            log(&swap_event(-5)),
+            format!("Program {} invoke [2]", other_program),
+            log(&swap_event(7)),
+            format!("Program {} success", other_program),
+            log(&swap_event(9)),
+            format!("Program {} success", program_id),
+        ];
+        let events = parse_logs(logs.iter().map(String::as_str));
+
+        let ticks: Vec<i32> = events
+            .iter()
+            .map(|event| match event {
+                PoolEvent::Swap(swap) => swap.tick,
+                _ => panic!("unexpected event"),
+            })
+            .collect();
+        assert_eq!(ticks, vec![-5, 9]);
+    }
+
+    #[test]
+    fn skips_failed_transactions() {
+        let line = r#"{"slot": 5, "blockTime": 100, "meta": {"err": {"InstructionError": [0, "Custom"]}, "logMessages": []}}"#;
+        assert!(parse_transaction(line).unwrap().is_none());
+
+        let line = r#"{"slot": 5, "blockTime": 100, "meta": {"err": null, "logMessages": []}}"#;
+        let transaction = parse_transaction(line).unwrap().unwrap();
+        assert_eq!(transaction.slot, 5);
+        assert_eq!(transaction.block_time, 100);
+        assert!(transaction.events.is_empty());
+
+        assert!(parse_transaction("not json").is_err());
+    }
+
+    #[test]
+    fn ignores_other_events_and_malformed_data() {
+        assert!(decode_event(&[1, 2, 3]).is_none());
+        assert!(decode_event(&SwapEvent::discriminator()).is_none());
+
+        let mut data = swap_event(0).data();
+        data[0] ^= 1;
+        assert!(decode_event(&data).is_none());
+    }
+}
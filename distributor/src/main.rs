@@ -0,0 +1,168 @@
+//! Computes a Merkle distribution of rewards for the liquidity providers of a pool
+//!
+//! ```text
+//! cyclos-distributor --pool <address> --start <unix time> --epoch-duration <seconds>
+//!     --epochs <count> --reward-per-epoch <amount> [--holders <file>] <dump>...
+//! ```
+//!
+//! Dumps hold one `getTransaction` JSON result per line. The holders file lists the current
+//! holder of each position NFT, as `<nft mint> <holder>` lines. The distribution is printed
+//! as JSON, with the root and max total claim to pass to `new_distributor`.
+//!
+use anchor_lang::prelude::*;
+use cyclos_distributor::logs::{parse_transaction, Transaction};
+use cyclos_distributor::{build_distribution, RewardCalculator};
+use serde_json::json;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::process;
+use std::str::FromStr;
+
+const USAGE: &str = "usage: cyclos-distributor --pool <address> --start <unix time> \
+--epoch-duration <seconds> --epochs <count> --reward-per-epoch <amount> [--holders <file>] <dump>...";
+
+struct Args {
+    pool: Pubkey,
+    start: u32,
+    epoch_duration: u32,
+    epochs: u32,
+    reward_per_epoch: u64,
+    holders: Option<String>,
+    dumps: Vec<String>,
+}
+
+fn parse<T: FromStr>(name: &str, value: Option<String>) -> std::result::Result<T, String> {
+    let value = value.ok_or(format!("missing value for {}", name))?;
+    value
+        .parse()
+        .map_err(|_| format!("invalid value for {}: {}", name, value))
+}
+
+fn parse_args() -> std::result::Result<Args, String> {
+    let mut pool = None;
+    let mut start = None;
+    let mut epoch_duration = None;
+    let mut epochs = None;
+    let mut reward_per_epoch = None;
+    let mut holders = None;
+    let mut dumps = Vec::new();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--pool" => pool = Some(parse(&arg, args.next())?),
+            "--start" => start = Some(parse(&arg, args.next())?),
+            "--epoch-duration" => epoch_duration = Some(parse(&arg, args.next())?),
+            "--epochs" => epochs = Some(parse(&arg, args.next())?),
+            "--reward-per-epoch" => reward_per_epoch = Some(parse(&arg, args.next())?),
+            "--holders" => holders = Some(parse(&arg, args.next())?),
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+            _ => dumps.push(arg),
+        }
+    }
+    if dumps.is_empty() {
+        return Err("no transaction dumps given".to_string());
+    }
+
+    Ok(Args {
+        pool: pool.ok_or("missing --pool")?,
+        start: start.ok_or("missing --start")?,
+        epoch_duration: epoch_duration.ok_or("missing --epoch-duration")?,
+        epochs: epochs.ok_or("missing --epochs")?,
+        reward_per_epoch: reward_per_epoch.ok_or("missing --reward-per-epoch")?,
+        holders,
+        dumps,
+    })
+}
+
+fn read_lines(path: &str) -> std::result::Result<Vec<String>, String> {
+    let file = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+    BufReader::new(file)
+        .lines()
+        .collect::<std::io::Result<Vec<_>>>()
+        .map_err(|e| format!("{}: {}", path, e))
+}
+
+fn read_holders(path: &str) -> std::result::Result<HashMap<Pubkey, Pubkey>, String> {
+    let mut holders = HashMap::new();
+    for line in read_lines(path)? {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields[..] {
+            [] => {}
+            [mint, holder] => {
+                holders.insert(
+                    parse("holders", Some(mint.to_string()))?,
+                    parse("holders", Some(holder.to_string()))?,
+                );
+            }
+            _ => return Err(format!("{}: invalid line {}", path, line)),
+        }
+    }
+    Ok(holders)
+}
+
+fn read_transactions(paths: &[String]) -> std::result::Result<Vec<Transaction>, String> {
+    let mut transactions = Vec::new();
+    for path in paths {
+        for (number, line) in read_lines(path)?.iter().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let transaction =
+                parse_transaction(line).map_err(|e| format!("{}:{}: {}", path, number + 1, e))?;
+            transactions.extend(transaction);
+        }
+    }
+    transactions.sort_by_key(|transaction| transaction.slot);
+    Ok(transactions)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn run() -> std::result::Result<(), String> {
+    let args = parse_args()?;
+    let holders = match &args.holders {
+        Some(path) => read_holders(path)?,
+        None => HashMap::new(),
+    };
+    let transactions = read_transactions(&args.dumps)?;
+
+    let mut calculator =
+        RewardCalculator::new(args.pool, args.start, args.epoch_duration, args.epochs);
+    for transaction in &transactions {
+        calculator.process(transaction);
+    }
+    calculator.advance_to(calculator.epoch_end());
+
+    let (rewards, unresolved) = calculator.rewards(args.reward_per_epoch, &holders);
+    let (claims, tree) = build_distribution(&rewards);
+
+    let output = json!({
+        "pool": args.pool.to_string(),
+        "root": hex(&tree.root()),
+        "maxTotalClaim": claims.iter().map(|claim| claim.amount).sum::<u64>(),
+        "claims": claims.iter().map(|claim| json!({
+            "index": claim.index,
+            "claimant": claim.claimant.to_string(),
+            "amount": claim.amount,
+            "proof": tree.proof(claim.index as usize).iter().map(|node| hex(node)).collect::<Vec<_>>(),
+        })).collect::<Vec<_>>(),
+        "unresolvedTokenIds": unresolved.iter().map(Pubkey::to_string).collect::<Vec<_>>(),
+        "epochs": calculator.liquidity_seconds().iter().enumerate().map(|(epoch, liquidity_seconds)| json!({
+            "start": args.start + args.epoch_duration * epoch as u32,
+            "liquiditySeconds": liquidity_seconds.values().sum::<u128>().to_string(),
+        })).collect::<Vec<_>>(),
+    });
+    println!("{}", serde_json::to_string_pretty(&output).unwrap());
+    Ok(())
+}
+
+fn main() {
+    if let Err(error) = run() {
+        eprintln!("{}\n{}", error, USAGE);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,134 @@
+//! Merkle tree of the claims of a distribution, hashed like the distributor of the program
+//!
+use anchor_lang::prelude::*;
+use cyclos_core::states::distributor::{hash_pair, leaf};
+
+/// A claim of reward tokens, as a leaf of the tree
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Claim {
+    /// The position of the claim among the leaves
+    pub index: u64,
+
+    /// The address entitled to the claim
+    pub claimant: Pubkey,
+
+    /// The amount of tokens owed to the claimant
+    pub amount: u64,
+}
+
+/// A Merkle tree, stored layer by layer from the leaves to the root.
+/// An odd node at the end of a layer is carried up to the next layer unhashed
+pub struct MerkleTree {
+    layers: Vec<Vec<[u8; 32]>>,
+}
+
+impl MerkleTree {
+    /// Builds the tree of a list of claims, each leaf being at the index of its claim
+    ///
+    /// # Arguments
+    ///
+    /// * `claims` - The claims, ordered by index starting from 0
+    ///
+    pub fn new(claims: &[Claim]) -> MerkleTree {
+        let leaves: Vec<[u8; 32]> = claims
+            .iter()
+            .enumerate()
+            .map(|(i, claim)| {
+                assert_eq!(claim.index, i as u64);
+                leaf(claim.index, &claim.claimant, claim.amount)
+            })
+            .collect();
+
+        let mut layers = vec![leaves];
+        while layers.last().unwrap().len() > 1 {
+            let next = layers
+                .last()
+                .unwrap()
+                .chunks(2)
+                .map(|pair| match pair {
+                    [a, b] => hash_pair(a, b),
+                    [a] => *a,
+                    _ => unreachable!(),
+                })
+                .collect();
+            layers.push(next);
+        }
+        MerkleTree { layers }
+    }
+
+    /// Returns the root of the tree, or zeroes for a tree without leaves
+    pub fn root(&self) -> [u8; 32] {
+        self.layers
+            .last()
+            .and_then(|layer| layer.first().copied())
+            .unwrap_or_default()
+    }
+
+    /// Returns the proof of a leaf, as the siblings on its path to the root
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the leaf
+    ///
+    pub fn proof(&self, index: usize) -> Vec<[u8; 32]> {
+        let mut proof = Vec::new();
+        let mut index = index;
+        for layer in &self.layers[..self.layers.len() - 1] {
+            if let Some(sibling) = layer.get(index ^ 1) {
+                proof.push(*sibling);
+            }
+            index /= 2;
+        }
+        proof
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cyclos_core::states::distributor::verify_proof;
+
+    fn claims(n: u64) -> Vec<Claim> {
+        (0..n)
+            .map(|index| Claim {
+                index,
+                claimant: Pubkey::new_unique(),
+                amount: 1_000 + index,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn proves_every_leaf_on_chain() {
+        for n in 1..10 {
+            let claims = claims(n);
+            let tree = MerkleTree::new(&claims);
+            for claim in &claims {
+                let proof = tree.proof(claim.index as usize);
+                assert!(verify_proof(
+                    &proof,
+                    &tree.root(),
+                    leaf(claim.index, &claim.claimant, claim.amount)
+                ));
+                assert!(!verify_proof(
+                    &proof,
+                    &tree.root(),
+                    leaf(claim.index, &claim.claimant, claim.amount + 1)
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn root_of_a_single_leaf_is_the_leaf() {
+        let claims = claims(1);
+        let tree = MerkleTree::new(&claims);
+        assert_eq!(tree.root(), leaf(0, &claims[0].claimant, claims[0].amount));
+        assert!(tree.proof(0).is_empty());
+    }
+
+    #[test]
+    fn root_of_no_leaves_is_zero() {
+        assert_eq!(MerkleTree::new(&[]).root(), [0; 32]);
+    }
+}
@@ -1,5 +1,8 @@
 use crate::error::ErrorCode;
+use crate::states::factory::FactoryState;
+use crate::states::pool::PoolState;
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::sysvar::instructions as sysvar_instructions;
 use anchor_spl::token::TokenAccount;
 
 /// Checks whether the transaction time has not crossed the deadline
@@ -16,6 +19,25 @@ pub fn check_deadline(deadline: i64) -> Result<()> {
     Ok(())
 }
 
+/// Ensures that the current instruction is a top level instruction of the transaction, and
+/// not a cross program invocation
+///
+/// # Arguments
+///
+/// * `instructions_sysvar` - The instructions sysvar
+/// * `data` - The data of the current instruction
+///
+pub fn check_top_level(instructions_sysvar: &AccountInfo, data: &[u8]) -> Result<()> {
+    let current_index = sysvar_instructions::load_current_index_checked(instructions_sysvar)?;
+    let current_ix =
+        sysvar_instructions::load_instruction_at_checked(current_index.into(), instructions_sysvar)?;
+    require!(
+        current_ix.program_id == crate::ID && current_ix.data == data,
+        ErrorCode::FT
+    );
+    Ok(())
+}
+
 /// Ensures that the signer is the owner or a delgated authority for the position NFT
 ///
 /// # Arguments
@@ -36,3 +58,45 @@ pub fn is_authorized_for_token<'info>(
     );
     Ok(())
 }
+
+/// Ensures that an operation is paused neither globally nor for the pool
+///
+/// # Arguments
+///
+/// * `factory_state` - The factory, holding the global pause state
+/// * `pool_state` - The pool, holding its own pause state
+/// * `operation` - The pause flag of the operation, e.g. `PAUSE_SWAP`
+///
+pub fn check_not_paused(
+    factory_state: &FactoryState,
+    pool_state: &PoolState,
+    operation: u8,
+) -> Result<()> {
+    require!(
+        (factory_state.paused | pool_state.paused) & operation == 0,
+        ErrorCode::Paused
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::states::factory::{PAUSE_ALL, PAUSE_MINT, PAUSE_SWAP};
+
+    #[test]
+    fn checks_global_and_pool_pause_states() {
+        let mut factory_state = FactoryState::default();
+        let mut pool_state = PoolState::default();
+        assert!(check_not_paused(&factory_state, &pool_state, PAUSE_SWAP).is_ok());
+
+        pool_state.paused = PAUSE_SWAP;
+        assert!(check_not_paused(&factory_state, &pool_state, PAUSE_SWAP).is_err());
+        assert!(check_not_paused(&factory_state, &pool_state, PAUSE_MINT).is_ok());
+
+        pool_state.paused = 0;
+        factory_state.paused = PAUSE_ALL;
+        assert!(check_not_paused(&factory_state, &pool_state, PAUSE_SWAP).is_err());
+        assert!(check_not_paused(&factory_state, &pool_state, PAUSE_MINT).is_err());
+    }
+}
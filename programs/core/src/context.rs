@@ -1,14 +1,22 @@
 use crate::error::ErrorCode;
 use crate::program::CyclosCore;
-use crate::states::factory::FactoryState;
+use crate::states::distributor::{
+    ClaimStatusState, DistributorState, CLAIM_STATUS_SEED, DISTRIBUTOR_SEED,
+};
+use crate::states::factory::{FactoryState, FeeDiscountTier, Role, FEE_DISCOUNT_TIER_NUM};
 use crate::states::fee::{FeeState, FEE_SEED};
 use crate::states::oracle::{ObservationState, OBSERVATION_SEED};
-use crate::states::pool::{PoolState, POOL_SEED};
+use crate::states::pool::{PoolState, POOL_SEED, REWARD_VAULT_SEED};
 use crate::states::position::{PositionState, POSITION_SEED};
+use crate::states::staker::{
+    DepositState, IncentiveState, StakeState, DEPOSIT_SEED, INCENTIVE_SEED, STAKE_SEED,
+};
 use crate::states::tick::{TickState, TICK_SEED};
 use crate::states::tick_bitmap::{TickBitmapState, BITMAP_SEED};
+use crate::states::timelock::{AdminAction, TimelockState, TIMELOCK_SEED};
 use crate::states::tokenized_position::TokenizedPositionState;
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::sysvar;
 use anchor_spl::associated_token::get_associated_token_address;
 use anchor_spl::associated_token::AssociatedToken;
 use anchor_spl::token::{Mint, Token, TokenAccount};
@@ -35,23 +43,54 @@ pub struct Initialize<'info> {
 }
 
 #[derive(Accounts)]
-#[instruction(fee: u32, tick_spacing: u16)]
-pub struct EnableFeeAmount<'info> {
-    /// Valid protocol owner
-    #[account(mut, address = factory_state.load()?.owner)]
+pub struct MigrateFactory<'info> {
+    /// The protocol owner
     pub owner: Signer<'info>,
 
-    /// Factory state stores the protocol owner address
+    /// Pays rent for the space added to the factory state account
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// Factory state created before roles were added. Its owner is checked once grown
     #[account(mut)]
     pub factory_state: AccountLoader<'info, FactoryState>,
 
+    /// To pay rent for the added space
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(fee: u32, tick_spacing: u16)]
+pub struct EnableFeeAmount<'info> {
+    /// Pays to create the fee account
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// The queued action, closed once executed
+    #[account(
+        mut,
+        seeds = [
+            TIMELOCK_SEED.as_bytes(),
+            &AdminAction::EnableFeeAmount { fee, tick_spacing }.seed()
+        ],
+        bump = timelock_state.load()?.bump,
+        close = timelock_payer,
+        constraint = timelock_state.load()?.eta <= Clock::get()?.unix_timestamp @ ErrorCode::Timelocked,
+        constraint = timelock_state.load()?.payer == timelock_payer.key()
+    )]
+    pub timelock_state: AccountLoader<'info, TimelockState>,
+
+    /// The payer of the timelock account rent, refunded the reclaimed lamports
+    /// CHECK: This is not dangerous because we don't read or write from this account
+    #[account(mut)]
+    pub timelock_payer: UncheckedAccount<'info>,
+
     /// Initialize an account to store new fee tier and tick spacing
-    /// Fees are paid by owner
     #[account(
         init,
         seeds = [FEE_SEED.as_bytes(), &fee.to_be_bytes()],
         bump,
-        payer = owner,
+        payer = payer,
         space = 8 + size_of::<FeeState>()
     )]
     pub fee_state: AccountLoader<'info, FeeState>,
@@ -61,18 +100,165 @@ pub struct EnableFeeAmount<'info> {
 }
 
 #[derive(Accounts)]
-pub struct SetOwner<'info> {
-    /// Current protocol owner
-    #[account(address = factory_state.load()?.owner)]
-    pub owner: Signer<'info>,
-
-    /// Address to be designated as new protocol owner
+pub struct ProposeOwner<'info> {
+    /// Address to be proposed as new protocol owner
     /// CHECK: This is not dangerous because we don't read or write from this account
     pub new_owner: UncheckedAccount<'info>,
 
     /// Factory state stores the protocol owner address
     #[account(mut)]
     pub factory_state: AccountLoader<'info, FactoryState>,
+
+    /// The queued action, closed once executed
+    #[account(
+        mut,
+        seeds = [
+            TIMELOCK_SEED.as_bytes(),
+            &AdminAction::ProposeOwner {
+                new_owner: new_owner.key()
+            }.seed()
+        ],
+        bump = timelock_state.load()?.bump,
+        close = timelock_payer,
+        constraint = timelock_state.load()?.eta <= Clock::get()?.unix_timestamp @ ErrorCode::Timelocked,
+        constraint = timelock_state.load()?.payer == timelock_payer.key()
+    )]
+    pub timelock_state: AccountLoader<'info, TimelockState>,
+
+    /// The payer of the timelock account rent, refunded the reclaimed lamports
+    /// CHECK: This is not dangerous because we don't read or write from this account
+    #[account(mut)]
+    pub timelock_payer: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(delay: u32)]
+pub struct SetTimelockDelay<'info> {
+    /// Factory state stores the timelock delay
+    #[account(mut)]
+    pub factory_state: AccountLoader<'info, FactoryState>,
+
+    /// The queued action, closed once executed
+    #[account(
+        mut,
+        seeds = [
+            TIMELOCK_SEED.as_bytes(),
+            &AdminAction::SetTimelockDelay { delay }.seed()
+        ],
+        bump = timelock_state.load()?.bump,
+        close = timelock_payer,
+        constraint = timelock_state.load()?.eta <= Clock::get()?.unix_timestamp @ ErrorCode::Timelocked,
+        constraint = timelock_state.load()?.payer == timelock_payer.key()
+    )]
+    pub timelock_state: AccountLoader<'info, TimelockState>,
+
+    /// The payer of the timelock account rent, refunded the reclaimed lamports
+    /// CHECK: This is not dangerous because we don't read or write from this account
+    #[account(mut)]
+    pub timelock_payer: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(action: AdminAction)]
+pub struct QueueAdminAction<'info> {
+    /// The holder of the role allowed to queue the action
+    #[account(address = action.admin(&*factory_state.load()?))]
+    pub admin: Signer<'info>,
+
+    /// Pays to create the timelock account, and is refunded once the action is executed
+    /// or canceled
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// Factory state stores the role holders and the timelock delay
+    pub factory_state: AccountLoader<'info, FactoryState>,
+
+    /// Initialize an account to queue the action
+    #[account(
+        init,
+        seeds = [TIMELOCK_SEED.as_bytes(), &action.seed()],
+        bump,
+        payer = payer,
+        space = 8 + size_of::<TimelockState>()
+    )]
+    pub timelock_state: AccountLoader<'info, TimelockState>,
+
+    /// To create a new program account
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(action: AdminAction)]
+pub struct CancelAdminAction<'info> {
+    /// The protocol owner or the pause guardian
+    #[account(
+        constraint = signer.key() == factory_state.load()?.owner
+            || signer.key() == factory_state.load()?.pause_guardian
+    )]
+    pub signer: Signer<'info>,
+
+    /// Factory state stores the protocol owner and pause guardian addresses
+    pub factory_state: AccountLoader<'info, FactoryState>,
+
+    /// The queued action, closed once canceled
+    #[account(
+        mut,
+        seeds = [TIMELOCK_SEED.as_bytes(), &action.seed()],
+        bump = timelock_state.load()?.bump,
+        close = timelock_payer,
+        constraint = timelock_state.load()?.payer == timelock_payer.key()
+    )]
+    pub timelock_state: AccountLoader<'info, TimelockState>,
+
+    /// The payer of the timelock account rent, refunded the reclaimed lamports
+    /// CHECK: This is not dangerous because we don't read or write from this account
+    #[account(mut)]
+    pub timelock_payer: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptOwner<'info> {
+    /// The proposed protocol owner
+    #[account(address = factory_state.load()?.pending_owner)]
+    pub pending_owner: Signer<'info>,
+
+    /// Factory state stores the protocol owner address
+    #[account(mut)]
+    pub factory_state: AccountLoader<'info, FactoryState>,
+}
+
+#[derive(Accounts)]
+#[instruction(role: Role)]
+pub struct SetRole<'info> {
+    /// Address to be designated as new holder of the role
+    /// CHECK: This is not dangerous because we don't read or write from this account
+    pub new_holder: UncheckedAccount<'info>,
+
+    /// Factory state stores the role holders
+    #[account(mut)]
+    pub factory_state: AccountLoader<'info, FactoryState>,
+
+    /// The queued action, closed once executed
+    #[account(
+        mut,
+        seeds = [
+            TIMELOCK_SEED.as_bytes(),
+            &AdminAction::SetRole {
+                role,
+                new_holder: new_holder.key()
+            }.seed()
+        ],
+        bump = timelock_state.load()?.bump,
+        close = timelock_payer,
+        constraint = timelock_state.load()?.eta <= Clock::get()?.unix_timestamp @ ErrorCode::Timelocked,
+        constraint = timelock_state.load()?.payer == timelock_payer.key()
+    )]
+    pub timelock_state: AccountLoader<'info, TimelockState>,
+
+    /// The payer of the timelock account rent, refunded the reclaimed lamports
+    /// CHECK: This is not dangerous because we don't read or write from this account
+    #[account(mut)]
+    pub timelock_payer: UncheckedAccount<'info>,
 }
 
 #[derive(Accounts)]
@@ -92,6 +278,9 @@ pub struct CreateAndInitPool<'info> {
     /// Stores the desired fee for the pool
     pub fee_state: AccountLoader<'info, FeeState>,
 
+    /// Holds the protocol fee given to new pools
+    pub factory_state: AccountLoader<'info, FactoryState>,
+
     /// Initialize an account to store the pool state
     #[account(
         init,
@@ -130,6 +319,23 @@ pub struct CreateAndInitPool<'info> {
     pub rent: Sysvar<'info, Rent>,
 }
 
+#[derive(Accounts)]
+pub struct MigratePool<'info> {
+    /// Pays rent for the space added to the pool state account
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// Holds the protocol fee given to the pool
+    pub factory_state: AccountLoader<'info, FactoryState>,
+
+    /// Pool state created before per pool protocol fees were added
+    #[account(mut)]
+    pub pool_state: AccountLoader<'info, PoolState>,
+
+    /// To pay rent for the added space
+    pub system_program: Program<'info, System>,
+}
+
 #[derive(Accounts)]
 pub struct IncreaseObservationCardinalityNext<'info> {
     /// Pays to increase storage slots for oracle observations
@@ -144,23 +350,149 @@ pub struct IncreaseObservationCardinalityNext<'info> {
 }
 
 #[derive(Accounts)]
+pub struct Observe<'info> {
+    /// The pool to read oracle observations for
+    pub pool_state: AccountLoader<'info, PoolState>,
+}
+
+#[derive(Accounts)]
+pub struct SnapshotCumulativesInside<'info> {
+    /// The pool holding the tick range
+    pub pool_state: AccountLoader<'info, PoolState>,
+
+    /// The lower tick of the range
+    pub tick_lower_state: AccountLoader<'info, TickState>,
+
+    /// The upper tick of the range
+    #[account(
+        constraint = tick_lower_state.load()?.tick < tick_upper_state.load()?.tick @ErrorCode::TLU
+    )]
+    pub tick_upper_state: AccountLoader<'info, TickState>,
+
+    /// The program account for the most recent oracle observation, at index = pool.observation_index
+    pub latest_observation_state: AccountLoader<'info, ObservationState>,
+}
+
+#[derive(Accounts)]
+#[instruction(fee_protocol: u32)]
 pub struct SetFeeProtocol<'info> {
-    /// Valid protocol owner
-    #[account(address = factory_state.load()?.owner)]
-    pub owner: Signer<'info>,
+    /// Factory state stores the protocol fee given to new pools
+    #[account(mut)]
+    pub factory_state: AccountLoader<'info, FactoryState>,
 
-    /// Factory state stores the protocol owner address
+    /// The queued action, closed once executed
+    #[account(
+        mut,
+        seeds = [
+            TIMELOCK_SEED.as_bytes(),
+            &AdminAction::SetFeeProtocol { fee_protocol }.seed()
+        ],
+        bump = timelock_state.load()?.bump,
+        close = timelock_payer,
+        constraint = timelock_state.load()?.eta <= Clock::get()?.unix_timestamp @ ErrorCode::Timelocked,
+        constraint = timelock_state.load()?.payer == timelock_payer.key()
+    )]
+    pub timelock_state: AccountLoader<'info, TimelockState>,
+
+    /// The payer of the timelock account rent, refunded the reclaimed lamports
+    /// CHECK: This is not dangerous because we don't read or write from this account
+    #[account(mut)]
+    pub timelock_payer: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(fee_protocol_0: u32, fee_protocol_1: u32)]
+pub struct SetPoolFeeProtocol<'info> {
+    /// The pool whose protocol fee is set
+    #[account(mut)]
+    pub pool_state: AccountLoader<'info, PoolState>,
+
+    /// The queued action, closed once executed
+    #[account(
+        mut,
+        seeds = [
+            TIMELOCK_SEED.as_bytes(),
+            &AdminAction::SetPoolFeeProtocol {
+                pool: pool_state.key(),
+                fee_protocol_0,
+                fee_protocol_1
+            }.seed()
+        ],
+        bump = timelock_state.load()?.bump,
+        close = timelock_payer,
+        constraint = timelock_state.load()?.eta <= Clock::get()?.unix_timestamp @ ErrorCode::Timelocked,
+        constraint = timelock_state.load()?.payer == timelock_payer.key()
+    )]
+    pub timelock_state: AccountLoader<'info, TimelockState>,
+
+    /// The payer of the timelock account rent, refunded the reclaimed lamports
+    /// CHECK: This is not dangerous because we don't read or write from this account
+    #[account(mut)]
+    pub timelock_payer: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(locker: Pubkey, fee_discount_tiers: [FeeDiscountTier; FEE_DISCOUNT_TIER_NUM])]
+pub struct SetFeeDiscounts<'info> {
+    /// Factory state stores the fee discounts
+    #[account(mut)]
+    pub factory_state: AccountLoader<'info, FactoryState>,
+
+    /// The queued action, closed once executed
+    #[account(
+        mut,
+        seeds = [
+            TIMELOCK_SEED.as_bytes(),
+            &AdminAction::SetFeeDiscounts {
+                locker,
+                fee_discount_tiers
+            }.seed()
+        ],
+        bump = timelock_state.load()?.bump,
+        close = timelock_payer,
+        constraint = timelock_state.load()?.eta <= Clock::get()?.unix_timestamp @ ErrorCode::Timelocked,
+        constraint = timelock_state.load()?.payer == timelock_payer.key()
+    )]
+    pub timelock_state: AccountLoader<'info, TimelockState>,
+
+    /// The payer of the timelock account rent, refunded the reclaimed lamports
+    /// CHECK: This is not dangerous because we don't read or write from this account
+    #[account(mut)]
+    pub timelock_payer: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetPauseState<'info> {
+    /// Valid pause guardian
+    #[account(address = factory_state.load()?.pause_guardian)]
+    pub pause_guardian: Signer<'info>,
+
+    /// Factory state stores the pause guardian address and the global pause state
     #[account(mut)]
     pub factory_state: AccountLoader<'info, FactoryState>,
 }
 
+#[derive(Accounts)]
+pub struct SetPoolPauseState<'info> {
+    /// Valid pause guardian
+    #[account(address = factory_state.load()?.pause_guardian)]
+    pub pause_guardian: Signer<'info>,
+
+    /// Factory state stores the pause guardian address
+    pub factory_state: AccountLoader<'info, FactoryState>,
+
+    /// The pool whose operations are paused
+    #[account(mut)]
+    pub pool_state: AccountLoader<'info, PoolState>,
+}
+
 #[derive(Accounts)]
 pub struct CollectProtocol<'info> {
-    /// Valid protocol owner
-    #[account(address = factory_state.load()?.owner)]
-    pub owner: Signer<'info>,
+    /// Valid fee collector
+    #[account(address = factory_state.load()?.fee_collector)]
+    pub fee_collector: Signer<'info>,
 
-    /// Factory state stores the protocol owner address
+    /// Factory state stores the fee collector address
     #[account(mut)]
     pub factory_state: AccountLoader<'info, FactoryState>,
 
@@ -195,73 +527,221 @@ pub struct CollectProtocol<'info> {
 }
 
 #[derive(Accounts)]
-#[instruction(tick: i32)]
-pub struct InitTickAccount<'info> {
-    /// Pays to create tick account
+pub struct InitializeReward<'info> {
+    /// Valid protocol owner
+    #[account(address = factory_state.load()?.owner)]
+    pub owner: Signer<'info>,
+
+    /// Pays to create the reward vault
     #[account(mut)]
-    pub signer: Signer<'info>,
+    pub payer: Signer<'info>,
 
-    /// Create a tick account for this pool
+    /// Factory state stores the protocol owner address
+    pub factory_state: AccountLoader<'info, FactoryState>,
+
+    /// The pool emitting the reward
+    #[account(mut)]
     pub pool_state: AccountLoader<'info, PoolState>,
 
-    /// The tick account to be initialized
+    /// The reward token
+    pub reward_mint: Box<Account<'info, Mint>>,
+
+    /// The token account holding the rewards, owned by the pool
     #[account(
         init,
         seeds = [
-            TICK_SEED.as_bytes(),
-            pool_state.load()?.token_0.as_ref(),
-            pool_state.load()?.token_1.as_ref(),
-            &pool_state.load()?.fee.to_be_bytes(),
-            &tick.to_be_bytes()
+            REWARD_VAULT_SEED.as_bytes(),
+            pool_state.key().as_ref(),
+            reward_mint.key().as_ref()
         ],
         bump,
-        payer = signer,
-        space = 8 + size_of::<TickState>()
+        payer = payer,
+        token::mint = reward_mint,
+        token::authority = pool_state
     )]
-    pub tick_state: AccountLoader<'info, TickState>,
+    pub reward_vault: Box<Account<'info, TokenAccount>>,
 
-    /// Program to initialize the tick account
+    /// The address allowed to set emissions
+    /// CHECK: This is not dangerous because we don't read or write from this account
+    pub reward_authority: UncheckedAccount<'info>,
+
+    /// To create the reward vault
     pub system_program: Program<'info, System>,
+
+    /// To initialize the reward vault
+    pub token_program: Program<'info, Token>,
+
+    /// Sysvar for token account creation
+    pub rent: Sysvar<'info, Rent>,
 }
 
 #[derive(Accounts)]
-pub struct CloseTickAccount<'info> {
-    /// The tick account to be initialized
-    #[account(
-        mut,
-        close = recipient,
-        constraint = tick_state.load()?.is_clear()
-    )]
-    pub tick_state: AccountLoader<'info, TickState>,
+pub struct SetRewardEmissions<'info> {
+    /// The authority of the reward slot
+    pub reward_authority: Signer<'info>,
 
-    /// Destination for reclaimed lamports
-    /// CHECK: This is not dangerous because we don't read or write from this account
+    /// The pool emitting the reward
     #[account(mut)]
-    pub recipient: UncheckedAccount<'info>,
+    pub pool_state: AccountLoader<'info, PoolState>,
 }
 
 #[derive(Accounts)]
-#[instruction(word_pos: i16)]
-pub struct InitBitmapAccount<'info> {
-    /// Pays to create bitmap account
-    #[account(mut)]
+pub struct Flash<'info> {
+    /// The account taking the flash loan
     pub signer: Signer<'info>,
 
-    /// Create a new bitmap account for this pool
+    /// Factory state stores the global pause state
+    pub factory_state: AccountLoader<'info, FactoryState>,
+
+    /// The pool to borrow from
+    #[account(mut)]
     pub pool_state: AccountLoader<'info, PoolState>,
 
-    /// The bitmap account to be initialized
+    /// The address that holds pool tokens for token_0
     #[account(
-        init,
-        seeds = [
-            BITMAP_SEED.as_bytes(),
-            pool_state.load()?.token_0.as_ref(),
-            pool_state.load()?.token_1.as_ref(),
-            &pool_state.load()?.fee.to_be_bytes(),
-            &word_pos.to_be_bytes()
-        ],
-        bump,
-        payer = signer,
+        mut,
+        constraint = vault_0.key() == get_associated_token_address(&pool_state.key(), &pool_state.load()?.token_0),
+    )]
+    pub vault_0: Box<Account<'info, TokenAccount>>,
+
+    /// The address that holds pool tokens for token_1
+    #[account(
+        mut,
+        constraint = vault_1.key() == get_associated_token_address(&pool_state.key(), &pool_state.load()?.token_1),
+    )]
+    pub vault_1: Box<Account<'info, TokenAccount>>,
+
+    /// The address that receives the borrowed token_0
+    #[account(mut)]
+    pub recipient_wallet_0: Box<Account<'info, TokenAccount>>,
+
+    /// The address that receives the borrowed token_1
+    #[account(mut)]
+    pub recipient_wallet_1: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: Address constraint applied. Used to find the repay_flash instruction, and to
+    /// reject cross program invocations
+    #[account(address = sysvar::instructions::ID)]
+    pub instructions_sysvar: UncheckedAccount<'info>,
+
+    /// The SPL program to perform token transfers
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct RepayFlash<'info> {
+    /// The owner of the token accounts repaying the loan
+    pub signer: Signer<'info>,
+
+    /// Factory state stores the protocol fee
+    pub factory_state: AccountLoader<'info, FactoryState>,
+
+    /// The pool that was borrowed from. `flash` expects this account at index 2
+    #[account(mut)]
+    pub pool_state: AccountLoader<'info, PoolState>,
+
+    /// The address that holds pool tokens for token_0
+    #[account(
+        mut,
+        constraint = vault_0.key() == get_associated_token_address(&pool_state.key(), &pool_state.load()?.token_0),
+    )]
+    pub vault_0: Box<Account<'info, TokenAccount>>,
+
+    /// The address that holds pool tokens for token_1
+    #[account(
+        mut,
+        constraint = vault_1.key() == get_associated_token_address(&pool_state.key(), &pool_state.load()?.token_1),
+    )]
+    pub vault_1: Box<Account<'info, TokenAccount>>,
+
+    /// The token account paying back token_0 and the fee
+    #[account(mut)]
+    pub token_account_0: Box<Account<'info, TokenAccount>>,
+
+    /// The token account paying back token_1 and the fee
+    #[account(mut)]
+    pub token_account_1: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: Address constraint applied. Used to reject cross program invocations
+    #[account(address = sysvar::instructions::ID)]
+    pub instructions_sysvar: UncheckedAccount<'info>,
+
+    /// The SPL program to perform token transfers
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(tick: i32)]
+pub struct InitTickAccount<'info> {
+    /// Pays to create tick account
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    /// Create a tick account for this pool
+    pub pool_state: AccountLoader<'info, PoolState>,
+
+    /// The tick account to be initialized
+    #[account(
+        init,
+        seeds = [
+            TICK_SEED.as_bytes(),
+            pool_state.load()?.token_0.as_ref(),
+            pool_state.load()?.token_1.as_ref(),
+            &pool_state.load()?.fee.to_be_bytes(),
+            &tick.to_be_bytes()
+        ],
+        bump,
+        payer = signer,
+        space = 8 + size_of::<TickState>()
+    )]
+    pub tick_state: AccountLoader<'info, TickState>,
+
+    /// Program to initialize the tick account
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CloseTickAccount<'info> {
+    /// The tick account to be initialized
+    #[account(
+        mut,
+        close = recipient,
+        constraint = tick_state.load()?.is_clear(),
+        constraint = tick_state.load()?.payer == recipient.key()
+    )]
+    pub tick_state: AccountLoader<'info, TickState>,
+
+    /// The payer of the account rent, refunded the reclaimed lamports
+    /// CHECK: This is not dangerous because we don't read or write from this account
+    #[account(mut)]
+    pub recipient: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SweepTickAccounts {}
+
+#[derive(Accounts)]
+#[instruction(word_pos: i16)]
+pub struct InitBitmapAccount<'info> {
+    /// Pays to create bitmap account
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    /// Create a new bitmap account for this pool
+    pub pool_state: AccountLoader<'info, PoolState>,
+
+    /// The bitmap account to be initialized
+    #[account(
+        init,
+        seeds = [
+            BITMAP_SEED.as_bytes(),
+            pool_state.load()?.token_0.as_ref(),
+            pool_state.load()?.token_1.as_ref(),
+            &pool_state.load()?.fee.to_be_bytes(),
+            &word_pos.to_be_bytes()
+        ],
+        bump,
+        payer = signer,
         space = 8 + size_of::<TickBitmapState>()
     )]
     pub bitmap_state: AccountLoader<'info, TickBitmapState>,
@@ -270,6 +750,23 @@ pub struct InitBitmapAccount<'info> {
     pub system_program: Program<'info, System>,
 }
 
+#[derive(Accounts)]
+pub struct CloseBitmapAccount<'info> {
+    /// The bitmap account to be closed
+    #[account(
+        mut,
+        close = recipient,
+        constraint = bitmap_state.load()?.is_clear(),
+        constraint = bitmap_state.load()?.payer == recipient.key()
+    )]
+    pub bitmap_state: AccountLoader<'info, TickBitmapState>,
+
+    /// The payer of the account rent, refunded the reclaimed lamports
+    /// CHECK: This is not dangerous because we don't read or write from this account
+    #[account(mut)]
+    pub recipient: UncheckedAccount<'info>,
+}
+
 #[derive(Accounts)]
 pub struct InitPositionAccount<'info> {
     /// Pays to create position account
@@ -314,6 +811,152 @@ pub struct InitPositionAccount<'info> {
     pub system_program: Program<'info, System>,
 }
 
+#[derive(Accounts)]
+pub struct ClosePositionAccount<'info> {
+    /// The position account to be closed
+    #[account(
+        mut,
+        close = recipient,
+        constraint = position_state.load()?.is_clear(),
+        constraint = position_state.load()?.payer == recipient.key()
+    )]
+    pub position_state: AccountLoader<'info, PositionState>,
+
+    /// The payer of the account rent, refunded the reclaimed lamports
+    /// CHECK: This is not dangerous because we don't read or write from this account
+    #[account(mut)]
+    pub recipient: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(tick: i32)]
+pub struct MigrateTickAccount<'info> {
+    /// The protocol owner, authorizing the migration of an account shared by liquidity providers
+    #[account(address = factory_state.load()?.owner)]
+    pub owner: Signer<'info>,
+
+    /// Pays rent for the space added to the tick account, refunded when it is closed
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// Factory state stores the protocol owner address
+    pub factory_state: AccountLoader<'info, FactoryState>,
+
+    /// The pool of the tick
+    pub pool_state: AccountLoader<'info, PoolState>,
+
+    /// Tick account created before rent payers were recorded
+    #[account(
+        mut,
+        seeds = [
+            TICK_SEED.as_bytes(),
+            pool_state.load()?.token_0.as_ref(),
+            pool_state.load()?.token_1.as_ref(),
+            &pool_state.load()?.fee.to_be_bytes(),
+            &tick.to_be_bytes()
+        ],
+        bump
+    )]
+    pub tick_state: AccountLoader<'info, TickState>,
+
+    /// To pay rent for the added space
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateBitmapAccount<'info> {
+    /// The protocol owner, authorizing the migration of an account shared by liquidity providers
+    #[account(address = factory_state.load()?.owner)]
+    pub owner: Signer<'info>,
+
+    /// Pays rent for the space added to the bitmap account, refunded when it is closed
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// Factory state stores the protocol owner address
+    pub factory_state: AccountLoader<'info, FactoryState>,
+
+    /// The pool of the bitmap
+    pub pool_state: AccountLoader<'info, PoolState>,
+
+    /// Bitmap account created before rent payers were recorded. Its address is checked once grown
+    #[account(mut)]
+    pub bitmap_state: AccountLoader<'info, TickBitmapState>,
+
+    /// To pay rent for the added space
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MigratePositionAccount<'info> {
+    /// The position owner, or the protocol owner for a position held by the factory
+    #[account(
+        constraint = owner.key() == recipient.key()
+            || (recipient.key() == factory_state.key()
+                && owner.key() == factory_state.load()?.owner) @ErrorCode::NotApproved
+    )]
+    pub owner: Signer<'info>,
+
+    /// Pays rent for the space added to the position account, refunded when it is closed
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// Factory state stores the protocol owner address
+    pub factory_state: AccountLoader<'info, FactoryState>,
+
+    /// The address of the position owner
+    /// CHECK: This is not dangerous because we don't read or write from this account
+    pub recipient: UncheckedAccount<'info>,
+
+    /// The pool of the position
+    pub pool_state: AccountLoader<'info, PoolState>,
+
+    /// The lower tick boundary of the position
+    #[account(
+        seeds = [
+            TICK_SEED.as_bytes(),
+            pool_state.load()?.token_0.as_ref(),
+            pool_state.load()?.token_1.as_ref(),
+            &pool_state.load()?.fee.to_be_bytes(),
+            &tick_lower_state.load()?.tick.to_be_bytes()
+        ],
+        bump = tick_lower_state.load()?.bump
+    )]
+    pub tick_lower_state: AccountLoader<'info, TickState>,
+
+    /// The upper tick boundary of the position
+    #[account(
+        seeds = [
+            TICK_SEED.as_bytes(),
+            pool_state.load()?.token_0.as_ref(),
+            pool_state.load()?.token_1.as_ref(),
+            &pool_state.load()?.fee.to_be_bytes(),
+            &tick_upper_state.load()?.tick.to_be_bytes()
+        ],
+        bump = tick_upper_state.load()?.bump
+    )]
+    pub tick_upper_state: AccountLoader<'info, TickState>,
+
+    /// Position account created before rent payers were recorded
+    #[account(
+        mut,
+        seeds = [
+            POSITION_SEED.as_bytes(),
+            pool_state.load()?.token_0.as_ref(),
+            pool_state.load()?.token_1.as_ref(),
+            &pool_state.load()?.fee.to_be_bytes(),
+            recipient.key().as_ref(),
+            &tick_lower_state.load()?.tick.to_be_bytes(),
+            &tick_upper_state.load()?.tick.to_be_bytes(),
+        ],
+        bump
+    )]
+    pub position_state: AccountLoader<'info, PositionState>,
+
+    /// To pay rent for the added space
+    pub system_program: Program<'info, System>,
+}
+
 #[derive(Accounts)]
 pub struct MintContext<'info> {
     /// Pays to mint liquidity
@@ -341,17 +984,22 @@ pub struct MintContext<'info> {
     /// CHECK: This is not dangerous because we don't read or write from this account
     pub recipient: UncheckedAccount<'info>,
 
+    /// Factory state stores the global pause state
+    pub factory_state: AccountLoader<'info, FactoryState>,
+
     /// Mint liquidity for this pool
     #[account(mut)]
     pub pool_state: AccountLoader<'info, PoolState>,
 
-    /// The lower tick boundary of the position
+    /// The lower tick boundary of the position, created if missing
+    /// CHECK: Safety check performed inside function body
     #[account(mut)]
-    pub tick_lower_state: AccountLoader<'info, TickState>,
+    pub tick_lower_state: UncheckedAccount<'info>,
 
-    /// The upper tick boundary of the position
+    /// The upper tick boundary of the position, created if missing
+    /// CHECK: Safety check performed inside function body
     #[account(mut)]
-    pub tick_upper_state: AccountLoader<'info, TickState>,
+    pub tick_upper_state: UncheckedAccount<'info>,
 
     /// The bitmap storing initialization state of the lower tick
     /// CHECK: Safety check performed inside function body
@@ -379,6 +1027,13 @@ pub struct MintContext<'info> {
     /// Program which receives mint_callback
     /// CHECK: Allow arbitrary callback handlers
     pub callback_handler: UncheckedAccount<'info>,
+
+    /// Program to create missing tick, bitmap and position accounts
+    pub system_program: Program<'info, System>,
+
+    /// Pays rent for tick, bitmap and position accounts created on demand
+    #[account(mut)]
+    pub payer: Signer<'info>,
 }
 
 #[derive(Accounts)]
@@ -435,6 +1090,24 @@ pub struct SwapCallback<'info> {
     /// The SPL program to perform token transfers
     /// CHECK: Check applied in calling function
     pub token_program: UncheckedAccount<'info>,
+
+    /// The factory state to read the global pause state, for swaps made from the callback
+    /// CHECK: Safety check performed inside the swap function
+    pub factory_state: UncheckedAccount<'info>,
+
+    /// The program account of the pool being swapped
+    /// CHECK: Safety check performed inside the swap function
+    #[account(mut)]
+    pub pool_state: UncheckedAccount<'info>,
+
+    /// The program account for the most recent oracle observation of the pool being swapped
+    /// CHECK: Safety check performed inside the swap function
+    #[account(mut)]
+    pub last_observation_state: UncheckedAccount<'info>,
+
+    /// Program which receives swap_callback
+    /// CHECK: Allow arbitrary callback handlers
+    pub callback_handler: UncheckedAccount<'info>,
 }
 
 #[derive(Accounts)]
@@ -517,6 +1190,42 @@ pub struct CollectContext<'info> {
     pub token_program: Program<'info, Token>,
 }
 
+#[derive(Accounts)]
+pub struct CollectRewardContext<'info> {
+    /// The position owner
+    pub owner: Signer<'info>,
+
+    /// The program account for the liquidity pool emitting the reward
+    /// CHECK: Safety check performed inside function body
+    #[account(mut)]
+    pub pool_state: UncheckedAccount<'info>,
+
+    /// The lower tick of the position for which to collect rewards
+    /// CHECK: Safety check performed inside function body
+    pub tick_lower_state: UncheckedAccount<'info>,
+
+    /// The upper tick of the position for which to collect rewards
+    /// CHECK: Safety check performed inside function body
+    pub tick_upper_state: UncheckedAccount<'info>,
+
+    /// The position program account to collect rewards from
+    /// CHECK: Safety check performed inside function body
+    #[account(mut)]
+    pub position_state: UncheckedAccount<'info>,
+
+    /// The account holding the pool's rewards for the reward slot
+    #[account(mut)]
+    pub reward_vault: Box<Account<'info, TokenAccount>>,
+
+    /// The destination token account for the collected rewards
+    /// CHECK: Account validation is performed by the token program
+    #[account(mut)]
+    pub recipient_wallet: UncheckedAccount<'info>,
+
+    /// SPL program to transfer out tokens
+    pub token_program: Program<'info, Token>,
+}
+
 #[derive(Accounts)]
 pub struct SwapContext<'info> {
     /// The user performing the swap
@@ -543,7 +1252,7 @@ pub struct SwapContext<'info> {
     /// SPL program for token transfers
     pub token_program: Program<'info, Token>,
 
-    /// The factory state to read protocol fees
+    /// The factory state to read the global pause state
     /// CHECK: Safety check performed inside function body
     pub factory_state: UncheckedAccount<'info>,
 
@@ -567,9 +1276,13 @@ pub struct SwapContext<'info> {
 #[derive(Accounts)]
 pub struct MintTokenizedPosition<'info> {
     /// Pays to mint the position
-    #[account(mut)]
     pub minter: Signer<'info>,
 
+    /// Pays rent for the position NFT and state, and for tick, bitmap and position accounts
+    /// created on demand
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
     /// Receives the position NFT
     /// CHECK: This is not dangerous because we don't read or write from this account
     pub recipient: UncheckedAccount<'info>,
@@ -583,7 +1296,7 @@ pub struct MintTokenizedPosition<'info> {
         init,
         mint::decimals = 0,
         mint::authority = factory_state,
-        payer = minter
+        payer = payer
     )]
     pub nft_mint: Box<Account<'info, Mint>>,
 
@@ -592,7 +1305,7 @@ pub struct MintTokenizedPosition<'info> {
         init,
         associated_token::mint = nft_mint,
         associated_token::authority = recipient,
-        payer = minter
+        payer = payer
     )]
     pub nft_account: Box<Account<'info, TokenAccount>>,
 
@@ -631,7 +1344,7 @@ pub struct MintTokenizedPosition<'info> {
         init,
         seeds = [POSITION_SEED.as_bytes(), nft_mint.key().as_ref()],
         bump,
-        payer = minter,
+        payer = payer,
         space = 8 + size_of::<TokenizedPositionState>()
     )]
     pub tokenized_position_state: AccountLoader<'info, TokenizedPositionState>,
@@ -717,7 +1430,8 @@ pub struct AddMetaplexMetadata<'info> {
 
 #[derive(Accounts)]
 pub struct IncreaseLiquidity<'info> {
-    /// Pays to mint the position
+    /// Pays to mint the position, and rent for tick and bitmap accounts created on demand
+    #[account(mut)]
     pub payer: Signer<'info>,
 
     /// Authority PDA for the NFT mint
@@ -785,6 +1499,9 @@ pub struct IncreaseLiquidity<'info> {
 
     /// Program to create mint account and mint tokens
     pub token_program: Program<'info, Token>,
+
+    /// Program to create missing tick and bitmap accounts
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
@@ -924,12 +1641,136 @@ pub struct CollectFromTokenized<'info> {
     pub token_program: Program<'info, Token>,
 }
 
+#[derive(Accounts)]
+pub struct CollectRewardFromTokenized<'info> {
+    /// The position owner or delegated authority
+    pub owner_or_delegate: Signer<'info>,
+
+    /// The token account for the tokenized position
+    #[account(
+        constraint = nft_account.mint == tokenized_position_state.load()?.mint
+    )]
+    pub nft_account: Box<Account<'info, TokenAccount>>,
+
+    /// The program account of the NFT for which rewards are being collected
+    #[account(mut)]
+    pub tokenized_position_state: AccountLoader<'info, TokenizedPositionState>,
+
+    /// The program account acting as the core liquidity custodian for token holder
+    pub factory_state: AccountLoader<'info, FactoryState>,
+
+    /// The program account for the liquidity pool emitting the reward
+    /// CHECK: Safety check performed inside function body
+    #[account(mut)]
+    pub pool_state: UncheckedAccount<'info>,
+
+    /// The program account to access the core program position state
+    /// CHECK: Safety check performed inside function body
+    #[account(mut)]
+    pub core_position_state: UncheckedAccount<'info>,
+
+    /// The program account for the position's lower tick
+    /// CHECK: Safety check performed inside function body
+    #[account(mut)]
+    pub tick_lower_state: UncheckedAccount<'info>,
+
+    /// The program account for the position's upper tick
+    /// CHECK: Safety check performed inside function body
+    #[account(mut)]
+    pub tick_upper_state: UncheckedAccount<'info>,
+
+    /// The bitmap program account for the init state of the lower tick
+    /// CHECK: Safety check performed inside function body
+    #[account(mut)]
+    pub bitmap_lower_state: UncheckedAccount<'info>,
+
+    /// Stores init state for the upper tick
+    /// CHECK: Safety check performed inside function body
+    #[account(mut)]
+    pub bitmap_upper_state: UncheckedAccount<'info>,
+
+    /// The latest observation state
+    /// CHECK: Safety check performed inside function body
+    #[account(mut)]
+    pub last_observation_state: UncheckedAccount<'info>,
+
+    /// The account holding the pool's rewards for the reward slot
+    #[account(mut)]
+    pub reward_vault: Box<Account<'info, TokenAccount>>,
+
+    /// The destination token account for the collected rewards
+    /// CHECK: Account validation is performed by the token program
+    #[account(mut)]
+    pub recipient_wallet: UncheckedAccount<'info>,
+
+    /// The core program where liquidity is burned
+    pub core_program: Program<'info, CyclosCore>,
+
+    /// SPL program to transfer out tokens
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct BurnTokenizedPosition<'info> {
+    /// The holder of the position NFT. Receives the rent of the closed accounts
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    /// The token account holding the position NFT, closed once the NFT is burned
+    #[account(
+        mut,
+        constraint = nft_account.mint == tokenized_position_state.load()?.mint,
+        constraint = nft_account.owner == owner.key() @ErrorCode::NotApproved
+    )]
+    pub nft_account: Box<Account<'info, TokenAccount>>,
+
+    /// The mint of the position NFT, whose mint authority is revoked
+    #[account(
+        mut,
+        address = tokenized_position_state.load()?.mint
+    )]
+    pub nft_mint: Box<Account<'info, Mint>>,
+
+    /// Mint authority of the position NFT, unless revoked when adding metadata
+    pub factory_state: AccountLoader<'info, FactoryState>,
+
+    /// The tokenized position to close
+    #[account(mut, close = owner)]
+    pub tokenized_position_state: AccountLoader<'info, TokenizedPositionState>,
+
+    /// Program to burn the NFT and close its token account
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateTokenizedPosition<'info> {
+    /// Pays rent for the space added to the tokenized position account
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    /// The program account acting as the core liquidity custodian for token holder
+    pub factory_state: AccountLoader<'info, FactoryState>,
+
+    /// The pool of the position. Checked once the tokenized position is grown
+    pub pool_state: AccountLoader<'info, PoolState>,
+
+    /// The core position holding the liquidity. Checked once the tokenized position is grown
+    pub core_position_state: AccountLoader<'info, PositionState>,
+
+    /// Tokenized position created before rewards were added
+    #[account(mut)]
+    pub tokenized_position_state: AccountLoader<'info, TokenizedPositionState>,
+
+    /// To pay rent for the added space
+    pub system_program: Program<'info, System>,
+}
+
 #[derive(Accounts)]
 pub struct ExactInputSingle<'info> {
     /// The user performing the swap
     pub signer: Signer<'info>,
 
-    /// The factory state to read protocol fees
+    /// The factory state to read the global pause state
     /// CHECK: Safety check performed inside function body
     pub factory_state: UncheckedAccount<'info>,
 
@@ -968,12 +1809,72 @@ pub struct ExactInputSingle<'info> {
     pub token_program: Program<'info, Token>,
 }
 
+#[derive(Accounts)]
+pub struct ExactOutputSingle<'info> {
+    /// The user performing the swap
+    pub signer: Signer<'info>,
+
+    /// The factory state to read the global pause state
+    /// CHECK: Safety check performed inside function body
+    pub factory_state: UncheckedAccount<'info>,
+
+    /// The program account of the pool in which the swap will be performed
+    /// CHECK: Safety check performed inside function body
+    #[account(mut)]
+    pub pool_state: UncheckedAccount<'info>,
+
+    /// The user token account paying the input amount required for the output
+    /// CHECK: Account validation is performed by the token program
+    #[account(mut)]
+    pub input_token_account: UncheckedAccount<'info>,
+
+    /// The user token account receiving the exact output amount
+    /// CHECK: Account validation is performed by the token program
+    #[account(mut)]
+    pub output_token_account: UncheckedAccount<'info>,
+
+    /// The vault token account for input token
+    #[account(mut)]
+    pub input_vault: Box<Account<'info, TokenAccount>>,
+
+    /// The vault token account for output token
+    #[account(mut)]
+    pub output_vault: Box<Account<'info, TokenAccount>>,
+
+    /// The program account for the most recent oracle observation
+    /// CHECK: Safety check performed inside function body
+    #[account(mut)]
+    pub last_observation_state: UncheckedAccount<'info>,
+
+    /// The core program where swap is performed
+    pub core_program: Program<'info, CyclosCore>,
+
+    /// SPL program for token transfers
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct QuoteSingle<'info> {
+    /// The factory state to read swap fee discounts
+    pub factory_state: AccountLoader<'info, FactoryState>,
+
+    /// The pool to quote a swap for
+    pub pool_state: AccountLoader<'info, PoolState>,
+
+    /// The vault token account for input token, which sets the direction of the swap
+    #[account(
+        constraint = input_vault.key() == get_associated_token_address(&pool_state.key(), &pool_state.load()?.token_0)
+            || input_vault.key() == get_associated_token_address(&pool_state.key(), &pool_state.load()?.token_1)
+    )]
+    pub input_vault: Box<Account<'info, TokenAccount>>,
+}
+
 #[derive(Accounts)]
 pub struct ExactInput<'info> {
     /// The user performing the swap
     pub signer: Signer<'info>,
 
-    /// The factory state to read protocol fees
+    /// The factory state to read the global pause state
     /// CHECK: Safety check performed inside function body
     pub factory_state: UncheckedAccount<'info>,
 
@@ -988,3 +1889,494 @@ pub struct ExactInput<'info> {
     /// SPL program for token transfers
     pub token_program: Program<'info, Token>,
 }
+
+#[derive(Accounts)]
+pub struct ExactOutput<'info> {
+    /// The user performing the swap
+    pub signer: Signer<'info>,
+
+    /// The factory state to read the global pause state
+    /// CHECK: Safety check performed inside function body
+    pub factory_state: UncheckedAccount<'info>,
+
+    /// The token account that pays input tokens for the swap
+    #[account(mut)]
+    pub input_token_account: Box<Account<'info, TokenAccount>>,
+
+    /// The token account that receives output tokens from the swap
+    /// CHECK: Account validation is performed by the token program
+    #[account(mut)]
+    pub output_token_account: UncheckedAccount<'info>,
+
+    /// The core program where swap is performed
+    pub core_program: Program<'info, CyclosCore>,
+
+    /// SPL program for token transfers
+    pub token_program: Program<'info, Token>,
+}
+
+// Staker
+
+#[derive(Accounts)]
+#[instruction(start_time: u32, end_time: u32)]
+pub struct CreateIncentive<'info> {
+    /// Funds the rewards, and pays to create the incentive and its vault
+    #[account(mut)]
+    pub creator: Signer<'info>,
+
+    /// The pool whose staked positions are rewarded
+    pub pool_state: AccountLoader<'info, PoolState>,
+
+    /// The reward token
+    pub reward_mint: Box<Account<'info, Mint>>,
+
+    /// Receives any remaining rewards once the incentive is ended
+    /// CHECK: This is not dangerous because we don't read or write from this account
+    pub refundee: UncheckedAccount<'info>,
+
+    /// Initialize an account to store the incentive
+    #[account(
+        init,
+        seeds = [
+            INCENTIVE_SEED.as_bytes(),
+            reward_mint.key().as_ref(),
+            pool_state.key().as_ref(),
+            &start_time.to_be_bytes(),
+            &end_time.to_be_bytes(),
+            refundee.key().as_ref()
+        ],
+        bump,
+        payer = creator,
+        space = 8 + size_of::<IncentiveState>()
+    )]
+    pub incentive_state: AccountLoader<'info, IncentiveState>,
+
+    /// The token account holding the rewards, owned by the incentive
+    #[account(
+        init,
+        associated_token::mint = reward_mint,
+        associated_token::authority = incentive_state,
+        payer = creator
+    )]
+    pub reward_vault: Box<Account<'info, TokenAccount>>,
+
+    /// The token account paying the rewards
+    /// CHECK: Account validation is performed by the token program
+    #[account(mut)]
+    pub funder_wallet: UncheckedAccount<'info>,
+
+    /// Sysvar for ATA creation
+    pub rent: Sysvar<'info, Rent>,
+
+    /// To create the incentive account
+    pub system_program: Program<'info, System>,
+
+    /// Program to transfer the rewards
+    pub token_program: Program<'info, Token>,
+
+    /// Program to create the reward vault
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(Accounts)]
+pub struct EndIncentive<'info> {
+    /// The incentive to end
+    #[account(mut)]
+    pub incentive_state: AccountLoader<'info, IncentiveState>,
+
+    /// The token account holding the rewards
+    #[account(
+        mut,
+        address = incentive_state.load()?.vault
+    )]
+    pub reward_vault: Box<Account<'info, TokenAccount>>,
+
+    /// Receives the remaining rewards
+    #[account(
+        mut,
+        constraint = refundee_wallet.owner == incentive_state.load()?.refundee @ErrorCode::NotApproved
+    )]
+    pub refundee_wallet: Box<Account<'info, TokenAccount>>,
+
+    /// Program to transfer the rewards
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct DepositToken<'info> {
+    /// The holder of the position NFT, paying to create the deposit
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    /// The token account holding the position NFT
+    #[account(
+        mut,
+        constraint = nft_account.mint == nft_mint.key(),
+        constraint = nft_account.owner == owner.key() @ErrorCode::NotApproved
+    )]
+    pub nft_account: Box<Account<'info, TokenAccount>>,
+
+    /// The mint of the position NFT
+    #[account(address = tokenized_position_state.load()?.mint)]
+    pub nft_mint: Box<Account<'info, Mint>>,
+
+    /// The tokenized position to deposit
+    pub tokenized_position_state: AccountLoader<'info, TokenizedPositionState>,
+
+    /// Initialize an account to store the deposit
+    #[account(
+        init,
+        seeds = [DEPOSIT_SEED.as_bytes(), nft_mint.key().as_ref()],
+        bump,
+        payer = owner,
+        space = 8 + size_of::<DepositState>()
+    )]
+    pub deposit_state: AccountLoader<'info, DepositState>,
+
+    /// The token account holding the position NFT while deposited, owned by the deposit
+    #[account(
+        init,
+        associated_token::mint = nft_mint,
+        associated_token::authority = deposit_state,
+        payer = owner
+    )]
+    pub deposit_vault: Box<Account<'info, TokenAccount>>,
+
+    /// Sysvar for ATA creation
+    pub rent: Sysvar<'info, Rent>,
+
+    /// To create the deposit account
+    pub system_program: Program<'info, System>,
+
+    /// Program to transfer the NFT
+    pub token_program: Program<'info, Token>,
+
+    /// Program to create the deposit vault
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawToken<'info> {
+    /// The owner of the deposit. Receives the rent of the closed accounts
+    #[account(
+        mut,
+        address = deposit_state.load()?.owner @ErrorCode::NotApproved
+    )]
+    pub owner: Signer<'info>,
+
+    /// The deposit to close
+    #[account(mut, close = owner)]
+    pub deposit_state: AccountLoader<'info, DepositState>,
+
+    /// The token account holding the position NFT, closed once withdrawn
+    #[account(
+        mut,
+        address = get_associated_token_address(&deposit_state.key(), &deposit_state.load()?.mint)
+    )]
+    pub deposit_vault: Box<Account<'info, TokenAccount>>,
+
+    /// Receives the position NFT
+    /// CHECK: Account validation is performed by the token program
+    #[account(mut)]
+    pub recipient_wallet: UncheckedAccount<'info>,
+
+    /// Program to transfer the NFT and close its token account
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct StakeToken<'info> {
+    /// The owner of the deposit, paying to create the stake
+    #[account(
+        mut,
+        address = deposit_state.load()?.owner @ErrorCode::NotApproved
+    )]
+    pub owner: Signer<'info>,
+
+    /// The incentive to stake in
+    #[account(mut)]
+    pub incentive_state: AccountLoader<'info, IncentiveState>,
+
+    /// The deposit of the position NFT
+    #[account(
+        mut,
+        constraint = deposit_state.load()?.pool_id == incentive_state.load()?.pool_id
+    )]
+    pub deposit_state: AccountLoader<'info, DepositState>,
+
+    /// The deposited tokenized position
+    #[account(
+        constraint = tokenized_position_state.load()?.mint == deposit_state.load()?.mint
+    )]
+    pub tokenized_position_state: AccountLoader<'info, TokenizedPositionState>,
+
+    /// Initialize an account to store the stake
+    #[account(
+        init,
+        seeds = [
+            STAKE_SEED.as_bytes(),
+            incentive_state.key().as_ref(),
+            tokenized_position_state.load()?.mint.as_ref()
+        ],
+        bump,
+        payer = owner,
+        space = 8 + size_of::<StakeState>()
+    )]
+    pub stake_state: AccountLoader<'info, StakeState>,
+
+    /// The pool of the incentive
+    #[account(address = incentive_state.load()?.pool_id)]
+    pub pool_state: AccountLoader<'info, PoolState>,
+
+    /// The lower tick of the position
+    #[account(
+        constraint = tick_lower_state.load()?.tick == deposit_state.load()?.tick_lower
+    )]
+    pub tick_lower_state: AccountLoader<'info, TickState>,
+
+    /// The upper tick of the position
+    #[account(
+        constraint = tick_upper_state.load()?.tick == deposit_state.load()?.tick_upper
+    )]
+    pub tick_upper_state: AccountLoader<'info, TickState>,
+
+    /// The program account for the most recent oracle observation, at index = pool.observation_index
+    pub latest_observation_state: AccountLoader<'info, ObservationState>,
+
+    /// To create the stake account
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UnstakeToken<'info> {
+    /// The owner of the deposit, or anyone once the incentive has ended
+    pub signer: Signer<'info>,
+
+    /// The owner of the deposit. Receives the rent of the closed stake
+    /// CHECK: Address is checked against the deposit
+    #[account(
+        mut,
+        address = deposit_state.load()?.owner
+    )]
+    pub owner: UncheckedAccount<'info>,
+
+    /// The incentive to unstake from
+    #[account(mut)]
+    pub incentive_state: AccountLoader<'info, IncentiveState>,
+
+    /// The deposit of the position NFT
+    #[account(mut)]
+    pub deposit_state: AccountLoader<'info, DepositState>,
+
+    /// The stake to close
+    #[account(
+        mut,
+        seeds = [
+            STAKE_SEED.as_bytes(),
+            incentive_state.key().as_ref(),
+            deposit_state.load()?.mint.as_ref()
+        ],
+        bump = stake_state.load()?.bump,
+        close = owner
+    )]
+    pub stake_state: AccountLoader<'info, StakeState>,
+
+    /// The pool of the incentive
+    #[account(address = incentive_state.load()?.pool_id)]
+    pub pool_state: AccountLoader<'info, PoolState>,
+
+    /// The lower tick of the position
+    #[account(
+        constraint = tick_lower_state.load()?.tick == deposit_state.load()?.tick_lower
+    )]
+    pub tick_lower_state: AccountLoader<'info, TickState>,
+
+    /// The upper tick of the position
+    #[account(
+        constraint = tick_upper_state.load()?.tick == deposit_state.load()?.tick_upper
+    )]
+    pub tick_upper_state: AccountLoader<'info, TickState>,
+
+    /// The program account for the most recent oracle observation, at index = pool.observation_index
+    pub latest_observation_state: AccountLoader<'info, ObservationState>,
+
+    /// The token account holding the rewards
+    #[account(
+        mut,
+        address = incentive_state.load()?.vault
+    )]
+    pub reward_vault: Box<Account<'info, TokenAccount>>,
+
+    /// Receives the rewards, owned by the owner of the deposit
+    #[account(
+        mut,
+        constraint = recipient_wallet.owner == deposit_state.load()?.owner @ErrorCode::NotApproved
+    )]
+    pub recipient_wallet: Box<Account<'info, TokenAccount>>,
+
+    /// Program to transfer the rewards
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimReward<'info> {
+    /// The owner of the deposit
+    #[account(address = deposit_state.load()?.owner @ErrorCode::NotApproved)]
+    pub owner: Signer<'info>,
+
+    /// The incentive paying the rewards
+    #[account(mut)]
+    pub incentive_state: AccountLoader<'info, IncentiveState>,
+
+    /// The deposit of the position NFT
+    pub deposit_state: AccountLoader<'info, DepositState>,
+
+    /// The stake earning the rewards
+    #[account(
+        mut,
+        seeds = [
+            STAKE_SEED.as_bytes(),
+            incentive_state.key().as_ref(),
+            deposit_state.load()?.mint.as_ref()
+        ],
+        bump = stake_state.load()?.bump
+    )]
+    pub stake_state: AccountLoader<'info, StakeState>,
+
+    /// The pool of the incentive
+    #[account(address = incentive_state.load()?.pool_id)]
+    pub pool_state: AccountLoader<'info, PoolState>,
+
+    /// The lower tick of the position
+    #[account(
+        constraint = tick_lower_state.load()?.tick == deposit_state.load()?.tick_lower
+    )]
+    pub tick_lower_state: AccountLoader<'info, TickState>,
+
+    /// The upper tick of the position
+    #[account(
+        constraint = tick_upper_state.load()?.tick == deposit_state.load()?.tick_upper
+    )]
+    pub tick_upper_state: AccountLoader<'info, TickState>,
+
+    /// The program account for the most recent oracle observation, at index = pool.observation_index
+    pub latest_observation_state: AccountLoader<'info, ObservationState>,
+
+    /// The token account holding the rewards
+    #[account(
+        mut,
+        address = incentive_state.load()?.vault
+    )]
+    pub reward_vault: Box<Account<'info, TokenAccount>>,
+
+    /// Receives the rewards
+    /// CHECK: Account validation is performed by the token program
+    #[account(mut)]
+    pub recipient_wallet: UncheckedAccount<'info>,
+
+    /// Program to transfer the rewards
+    pub token_program: Program<'info, Token>,
+}
+
+// Merkle distributor
+
+#[derive(Accounts)]
+#[instruction(distribution_index: u32)]
+pub struct NewDistributor<'info> {
+    /// Valid protocol owner
+    #[account(address = factory_state.load()?.owner)]
+    pub owner: Signer<'info>,
+
+    /// Pays to create the distributor and its vault
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// Factory state stores the protocol owner address
+    pub factory_state: AccountLoader<'info, FactoryState>,
+
+    /// The pool whose liquidity providers are rewarded
+    pub pool_state: AccountLoader<'info, PoolState>,
+
+    /// The token being distributed
+    pub reward_mint: Box<Account<'info, Mint>>,
+
+    /// Initialize an account to store the distributor
+    #[account(
+        init,
+        seeds = [
+            DISTRIBUTOR_SEED.as_bytes(),
+            pool_state.key().as_ref(),
+            reward_mint.key().as_ref(),
+            &distribution_index.to_be_bytes()
+        ],
+        bump,
+        payer = payer,
+        space = 8 + size_of::<DistributorState>()
+    )]
+    pub distributor_state: AccountLoader<'info, DistributorState>,
+
+    /// The token account holding the rewards, owned by the distributor
+    #[account(
+        init,
+        associated_token::mint = reward_mint,
+        associated_token::authority = distributor_state,
+        payer = payer
+    )]
+    pub vault: Box<Account<'info, TokenAccount>>,
+
+    /// Sysvar for ATA creation
+    pub rent: Sysvar<'info, Rent>,
+
+    /// To create the distributor account
+    pub system_program: Program<'info, System>,
+
+    /// Program to initialize the vault
+    pub token_program: Program<'info, Token>,
+
+    /// Program to create the vault
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(Accounts)]
+#[instruction(index: u64)]
+pub struct ClaimContext<'info> {
+    /// The address entitled to the claim, paying to mark it as claimed
+    #[account(mut)]
+    pub claimant: Signer<'info>,
+
+    /// The distributor paying the claim
+    #[account(mut)]
+    pub distributor_state: AccountLoader<'info, DistributorState>,
+
+    /// Initialize an account to mark the leaf as claimed
+    #[account(
+        init,
+        seeds = [
+            CLAIM_STATUS_SEED.as_bytes(),
+            distributor_state.key().as_ref(),
+            &index.to_be_bytes()
+        ],
+        bump,
+        payer = claimant,
+        space = 8 + size_of::<ClaimStatusState>()
+    )]
+    pub claim_status_state: AccountLoader<'info, ClaimStatusState>,
+
+    /// The token account holding the rewards
+    #[account(
+        mut,
+        address = distributor_state.load()?.vault
+    )]
+    pub vault: Box<Account<'info, TokenAccount>>,
+
+    /// Receives the claimed tokens
+    /// CHECK: Account validation is performed by the token program
+    #[account(mut)]
+    pub recipient_wallet: UncheckedAccount<'info>,
+
+    /// To create the claim status account
+    pub system_program: Program<'info, System>,
+
+    /// Program to transfer the claimed tokens
+    pub token_program: Program<'info, Token>,
+}
@@ -94,4 +94,150 @@ pub enum ErrorCode {
     // Swap router
     #[msg("Too little received")]
     TooLittleReceived,
+
+    #[msg("Too much requested")]
+    TooMuchRequested,
+
+    // Flash loans
+
+    // Flash loans need in-range liquidity to pay the fee to
+    #[msg("L")]
+    L,
+
+    // A flash loan must be repaid later in the same transaction by a matching repay_flash
+    #[msg("FR")]
+    FR,
+
+    // Flash loans must be taken and repaid by top level instructions, not cross program invocations
+    #[msg("FT")]
+    FT,
+
+    // The pool must receive the borrowed token_0 amount plus fee
+    #[msg("F0")]
+    F0,
+
+    // The pool must receive the borrowed token_1 amount plus fee
+    #[msg("F1")]
+    F1,
+
+    // Oracle
+
+    // The oracle array must hold at least one observation
+    #[msg("I")]
+    I,
+
+    // The target timestamp must be at or after the oldest observation
+    #[msg("OLD")]
+    OLD,
+
+    // The tick must be initialized, i.e. hold liquidity
+    #[msg("TNI")]
+    TNI,
+
+    // libraries/oracle_library.rs
+
+    // The averaging window must not be empty
+    #[msg("BP")]
+    BP,
+
+    // Account initialization
+
+    // An account created on demand must be at the PDA of its seeds
+    #[msg("AD")]
+    AD,
+
+    // A tokenized position must hold no liquidity or tokens owed to be burned
+    #[msg("Not cleared")]
+    NotCleared,
+
+    // Lamports of a closed account must be refunded to the payer of its rent
+    #[msg("RP")]
+    RP,
+
+    // Pause
+
+    // The operation is paused, globally or for the pool
+    #[msg("Paused")]
+    Paused,
+
+    // Timelock
+
+    // A queued admin action can only be executed once its ETA has passed
+    #[msg("Timelocked")]
+    Timelocked,
+
+    // The arguments of an admin action are out of bounds, checked when queuing it
+    #[msg("Invalid admin action")]
+    InvalidAdminAction,
+
+    // Rewards
+
+    // The reward slot must be below REWARD_NUM, and hold a reward token unless being initialized
+    #[msg("Invalid reward index")]
+    InvalidRewardIndex,
+
+    // A reward slot holding a reward token cannot be initialized again
+    #[msg("Reward initialized")]
+    RewardInitialized,
+
+    // Staker
+
+    // An incentive must start between now and MAX_INCENTIVE_START_LEAD_TIME, and end after it starts,
+    // within MAX_INCENTIVE_DURATION
+    #[msg("Invalid incentive time")]
+    InvalidIncentiveTime,
+
+    // An incentive must distribute a non-zero reward
+    #[msg("Zero reward")]
+    ZeroReward,
+
+    // Positions can only be staked from the start of an incentive until its end
+    #[msg("Incentive not active")]
+    IncentiveNotActive,
+
+    // An incentive can only be ended after its end time
+    #[msg("Incentive not ended")]
+    IncentiveNotEnded,
+
+    // An incentive cannot be ended, nor a deposit withdrawn, while positions are staked
+    #[msg("Stakes remain")]
+    StakesRemain,
+
+    // A position must hold liquidity to be staked
+    #[msg("Zero liquidity")]
+    ZeroLiquidity,
+
+    // Merkle distributor
+
+    // The proof must lead from the leaf of the claim to the root of the distributor
+    #[msg("Invalid proof")]
+    InvalidProof,
+
+    // Claims cannot exceed the maximum total claim of the distributor
+    #[msg("Exceeded max claim")]
+    ExceededMaxClaim,
+
+    // Fee discounts
+
+    // An escrow must be an account of the locked voter program
+    #[msg("Invalid escrow")]
+    InvalidEscrow,
+
+    // An escrow only earns fee discounts for its owner
+    #[msg("Escrow not owned")]
+    EscrowNotOwned,
+
+    // Swap router
+
+    // A swap path must list the pool, vault and observation accounts of each pool, followed by
+    // as many swap accounts as given for the pool
+    #[msg("Invalid swap path")]
+    InvalidSwapPath,
+
+    // Migration
+
+    // Only accounts still holding the layout they were created with can be migrated, so that
+    // each is migrated once
+    #[msg("Already migrated")]
+    AlreadyMigrated,
 }
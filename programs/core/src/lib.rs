@@ -3,6 +3,7 @@ pub mod context;
 pub mod error;
 pub mod libraries;
 pub mod states;
+pub mod swap_engine;
 use crate::access_control::*;
 use crate::error::ErrorCode;
 use crate::libraries::liquidity_amounts;
@@ -10,16 +11,20 @@ use crate::libraries::tick_math;
 use crate::states::oracle;
 use crate::states::oracle::ObservationState;
 use crate::states::tokenized_position::{
-    CollectTokenizedEvent, DecreaseLiquidityEvent, IncreaseLiquidityEvent,
+    CollectTokenizedEvent, CollectTokenizedRewardEvent, DecreaseLiquidityEvent,
+    IncreaseLiquidityEvent, MintTokenizedPositionParams, TokenizedPositionState,
+    LEGACY_TOKENIZED_POSITION_STATE_LEN,
 };
+use crate::swap_engine::{swap_steps, AccountTickProvider, NoObservation, SwapCache, SwapState};
 use crate::{
-    libraries::{fixed_point_32, swap_math},
+    libraries::fixed_point_32,
     states::{oracle::OBSERVATION_SEED, tick_bitmap},
 };
 use anchor_lang::prelude::*;
 use anchor_lang::solana_program;
-use anchor_lang::solana_program::system_instruction::create_account;
-use anchor_lang::{solana_program::instruction::Instruction, InstructionData};
+use anchor_lang::solana_program::system_instruction::{self, create_account};
+use anchor_lang::solana_program::sysvar::instructions as sysvar_instructions;
+use anchor_lang::{solana_program::instruction::Instruction, AccountsClose, InstructionData};
 use anchor_spl::associated_token::get_associated_token_address;
 use anchor_spl::token;
 use anchor_spl::token::TokenAccount;
@@ -29,13 +34,18 @@ use libraries::liquidity_math;
 use libraries::sqrt_price_math;
 use metaplex_token_metadata::{instruction::create_metadata_accounts, state::Creator};
 use spl_token::instruction::AuthorityType;
+use states::distributor;
+use states::distributor::*;
+use states::escrow::{split_escrow, Escrow};
 use states::factory::*;
 use states::fee::*;
 use states::pool::*;
 use states::position::*;
+use states::staker::*;
 use states::tick;
 use states::tick::*;
 use states::tick_bitmap::*;
+use states::timelock::*;
 use std::collections::BTreeMap;
 use std::convert::TryFrom;
 use std::mem::size_of;
@@ -64,7 +74,12 @@ pub mod cyclos_core {
         let mut factory_state = ctx.accounts.factory_state.load_init()?;
         factory_state.bump = *ctx.bumps.get("factory_state").unwrap();
         factory_state.owner = ctx.accounts.owner.key();
-        factory_state.fee_protocol = 3; // 1/3 = 33.33%
+        factory_state.default_fee_protocol = 333_333; // 33.33%
+        factory_state.fee_tier_admin = ctx.accounts.owner.key();
+        factory_state.protocol_fee_admin = ctx.accounts.owner.key();
+        factory_state.fee_collector = ctx.accounts.owner.key();
+        factory_state.pause_guardian = ctx.accounts.owner.key();
+        factory_state.timelock_delay = MIN_TIMELOCK_DELAY;
 
         emit!(OwnerChanged {
             old_owner: Pubkey::default(),
@@ -74,20 +89,174 @@ pub mod cyclos_core {
         Ok(())
     }
 
-    /// Updates the owner of the factory
-    /// Must be called by the current owner
+    /// Grows a factory created before roles were added to the current layout
+    /// The owner is handed every role, the timelock delay is set to its minimum and the legacy
+    /// protocol fee denominator is converted to a share.
+    /// Must be called by the owner
     ///
     /// # Arguments
     ///
-    /// * `ctx`- Checks whether protocol owner has signed
+    /// * `ctx`- Checks whether the owner has signed, and grows the factory state account
     ///
-    pub fn set_owner(ctx: Context<SetOwner>) -> Result<()> {
+    pub fn migrate_factory(ctx: Context<MigrateFactory>) -> Result<()> {
+        realloc_program_account(
+            &ctx.accounts.payer.to_account_info(),
+            &ctx.accounts.factory_state.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            8 + LEGACY_FACTORY_STATE_LEN,
+            8 + size_of::<FactoryState>(),
+        )?;
+
+        // the owner is only readable once the account is grown
+        let mut factory_state = ctx.accounts.factory_state.load_mut()?;
+        require!(
+            factory_state.owner == ctx.accounts.owner.key(),
+            ErrorCode::NotApproved
+        );
+        factory_state.default_fee_protocol = factory_state.fee_protocol_share();
+        factory_state.fee_tier_admin = ctx.accounts.owner.key();
+        factory_state.protocol_fee_admin = ctx.accounts.owner.key();
+        factory_state.fee_collector = ctx.accounts.owner.key();
+        factory_state.pause_guardian = ctx.accounts.owner.key();
+        factory_state.timelock_delay = MIN_TIMELOCK_DELAY;
+
+        Ok(())
+    }
+
+    /// Proposes a new owner of the factory, who takes over by calling `accept_owner`
+    ///
+    /// The current owner queues the proposal, which can be made once past the timelock. A later
+    /// proposal replaces a pending one.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx`- Checks whether the proposal was queued and its ETA has passed
+    ///
+    pub fn propose_owner(ctx: Context<ProposeOwner>) -> Result<()> {
+        let mut factory_state = ctx.accounts.factory_state.load_mut()?;
+        factory_state.pending_owner = ctx.accounts.new_owner.key();
+
+        emit!(OwnerProposed {
+            owner: factory_state.owner,
+            pending_owner: ctx.accounts.new_owner.key(),
+        });
+        emit!(AdminActionExecuted {
+            action: AdminAction::ProposeOwner {
+                new_owner: ctx.accounts.new_owner.key()
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Makes the proposed owner the owner of the factory
+    /// Must be called by the proposed owner
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx`- Checks whether the proposed owner has signed
+    ///
+    pub fn accept_owner(ctx: Context<AcceptOwner>) -> Result<()> {
         let mut factory_state = ctx.accounts.factory_state.load_mut()?;
-        factory_state.owner = ctx.accounts.new_owner.key();
+        let old_owner = factory_state.owner;
+        factory_state.owner = ctx.accounts.pending_owner.key();
+        factory_state.pending_owner = Pubkey::default();
 
         emit!(OwnerChanged {
-            old_owner: ctx.accounts.owner.key(),
-            new_owner: ctx.accounts.new_owner.key(),
+            old_owner,
+            new_owner: ctx.accounts.pending_owner.key(),
+        });
+
+        Ok(())
+    }
+
+    /// Hands a role of the factory to another address
+    /// Executes an action queued by the owner, once past the timelock
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx`- Checks whether the action was queued and its ETA has passed
+    /// * `role` - The role to hand to `new_holder`
+    ///
+    pub fn set_role(ctx: Context<SetRole>, role: Role) -> Result<()> {
+        let mut factory_state = ctx.accounts.factory_state.load_mut()?;
+        let old_holder = factory_state.set_role(role, ctx.accounts.new_holder.key());
+
+        emit!(RoleChanged {
+            role,
+            old_holder,
+            new_holder: ctx.accounts.new_holder.key(),
+        });
+        emit!(AdminActionExecuted {
+            action: AdminAction::SetRole {
+                role,
+                new_holder: ctx.accounts.new_holder.key()
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Queues an admin action behind the timelock, to be executed once the delay has passed
+    /// Must be called by the holder of the role allowed to take the action. Actions whose
+    /// arguments are out of bounds are rejected.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx`- Checks whether the admin of the action has signed and initializes the
+    /// timelock account
+    /// * `action` - The instruction to queue, along with its arguments
+    ///
+    pub fn queue_admin_action(ctx: Context<QueueAdminAction>, action: AdminAction) -> Result<()> {
+        action.validate()?;
+        let eta =
+            Clock::get()?.unix_timestamp + ctx.accounts.factory_state.load()?.timelock_delay as i64;
+
+        let mut timelock_state = ctx.accounts.timelock_state.load_init()?;
+        timelock_state.bump = *ctx.bumps.get("timelock_state").unwrap();
+        timelock_state.eta = eta;
+        timelock_state.payer = ctx.accounts.payer.key();
+
+        emit!(AdminActionQueued { action, eta });
+
+        Ok(())
+    }
+
+    /// Cancels a queued admin action, refunding the payer of the timelock account
+    /// Must be called by the owner or the pause guardian
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx`- Checks whether the owner or the pause guardian has signed and closes the
+    /// timelock account
+    /// * `action` - The queued instruction, along with its arguments
+    ///
+    pub fn cancel_admin_action(
+        _ctx: Context<CancelAdminAction>,
+        action: AdminAction,
+    ) -> Result<()> {
+        emit!(AdminActionCanceled { action });
+
+        Ok(())
+    }
+
+    /// Sets the delay between queuing an admin action and executing it
+    /// Executes an action queued by the owner, once past the timelock
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx`- Checks whether the action was queued and its ETA has passed
+    /// * `delay` - The new delay in seconds, at least 2 days and at most 30 days
+    ///
+    pub fn set_timelock_delay(ctx: Context<SetTimelockDelay>, delay: u32) -> Result<()> {
+        assert!((MIN_TIMELOCK_DELAY..=MAX_TIMELOCK_DELAY).contains(&delay));
+        let mut factory_state = ctx.accounts.factory_state.load_mut()?;
+        let delay_old = factory_state.timelock_delay;
+        factory_state.timelock_delay = delay;
+
+        emit!(TimelockDelayChanged { delay_old, delay });
+        emit!(AdminActionExecuted {
+            action: AdminAction::SetTimelockDelay { delay }
         });
 
         Ok(())
@@ -95,10 +264,12 @@ pub mod cyclos_core {
 
     /// Enables a fee amount with the given tick_spacing
     /// Fee amounts may never be removed once enabled
+    /// Executes an action queued by the fee tier admin, once past the timelock
     ///
     /// # Arguments
     ///
-    /// * `ctx`- Checks whether protocol owner has signed and initializes the fee account
+    /// * `ctx`- Checks whether the action was queued and its ETA has passed, and initializes
+    /// the fee account
     /// * `fee_state_bump` - Bump to validate fee state address
     /// * `fee` - The fee amount to enable, denominated in hundredths of a bip (i.e. 1e-6)
     /// * `tick_spacing` - The spacing between ticks to be enforced for all pools created
@@ -122,6 +293,9 @@ pub mod cyclos_core {
         fee_state.tick_spacing = tick_spacing;
 
         emit!(FeeAmountEnabled { fee, tick_spacing });
+        emit!(AdminActionExecuted {
+            action: AdminAction::EnableFeeAmount { fee, tick_spacing }
+        });
         Ok(())
     }
 
@@ -159,6 +333,9 @@ pub mod cyclos_core {
         pool_state.unlocked = true;
         pool_state.observation_cardinality = 1;
         pool_state.observation_cardinality_next = 1;
+        let fee_protocol = ctx.accounts.factory_state.load()?.default_fee_protocol;
+        pool_state.fee_protocol_0 = fee_protocol;
+        pool_state.fee_protocol_1 = fee_protocol;
 
         let mut initial_observation_state = ctx.accounts.initial_observation_state.load_init()?;
         initial_observation_state.bump = *ctx.bumps.get("initial_observation_state").unwrap();
@@ -179,6 +356,31 @@ pub mod cyclos_core {
         Ok(())
     }
 
+    /// Grows a pool created before per pool protocol fees were added to the current layout
+    /// The protocol fee of the pool is set to the factory's, which legacy pools charged. The
+    /// pool is unpaused and emits no rewards. The factory must be migrated first
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx`- Grows the pool state account
+    ///
+    pub fn migrate_pool(ctx: Context<MigratePool>) -> Result<()> {
+        realloc_program_account(
+            &ctx.accounts.payer.to_account_info(),
+            &ctx.accounts.pool_state.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            8 + LEGACY_POOL_STATE_LEN,
+            8 + size_of::<PoolState>(),
+        )?;
+
+        let mut pool_state = ctx.accounts.pool_state.load_mut()?;
+        let fee_protocol = ctx.accounts.factory_state.load()?.default_fee_protocol;
+        pool_state.fee_protocol_0 = fee_protocol;
+        pool_state.fee_protocol_1 = fee_protocol;
+
+        Ok(())
+    }
+
     // ---------------------------------------------------------------------
     // Oracle
 
@@ -273,30 +475,246 @@ pub mod cyclos_core {
         Ok(())
     }
 
+    /// Returns the cumulative tick and liquidity as of each timestamp `seconds_ago` from the current block timestamp
+    ///
+    /// To get a time weighted average tick or liquidity-in-range, you must call this with two values, one representing
+    /// the beginning of the period and another for the end of the period. E.g., to get the last hour time-weighted average tick,
+    /// you must call it with seconds_agos = [3600, 0].
+    ///
+    /// The time weighted average tick represents the geometric time weighted average price of the pool, in
+    /// log base sqrt(1.0001) of token_1 / token_0.
+    ///
+    /// The result is set as return data, a borsh serialized `(Vec<i64>, Vec<u64>)` holding the
+    /// tick cumulatives and the seconds per liquidity cumulatives for each `seconds_agos` value.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - Holds the pool. The observation accounts of the pool are passed as remaining accounts,
+    /// ordered by index from 0 to `observation_cardinality - 1`
+    /// * `seconds_agos` - From how long ago each cumulative tick and liquidity value should be returned
+    ///
+    pub fn observe<'a, 'b, 'c, 'info>(
+        ctx: Context<'a, 'b, 'c, 'info, Observe<'info>>,
+        seconds_agos: Vec<u32>,
+    ) -> Result<()> {
+        let pool_state = ctx.accounts.pool_state.load()?;
+        let cardinality = pool_state.observation_cardinality;
+        require!(
+            ctx.remaining_accounts.len() >= cardinality as usize,
+            ErrorCode::OS
+        );
+
+        let mut observations = Vec::with_capacity(cardinality as usize);
+        for (index, account_info) in ctx.remaining_accounts[..cardinality as usize]
+            .iter()
+            .enumerate()
+        {
+            let observation_state_loader =
+                AccountLoader::<ObservationState>::try_from(account_info)?;
+            let observation_state = *observation_state_loader.load()?;
+            pool_state.validate_observation_address_at_index(
+                &observation_state_loader.key(),
+                observation_state.bump,
+                index as u16,
+            )?;
+            observations.push(observation_state);
+        }
+
+        let cumulatives = oracle::observe(
+            &observations,
+            oracle::_block_timestamp(),
+            &seconds_agos,
+            pool_state.tick,
+            pool_state.observation_index,
+            pool_state.liquidity,
+            cardinality,
+        )?;
+        solana_program::program::set_return_data(&cumulatives.try_to_vec()?);
+
+        Ok(())
+    }
+
+    /// Returns a snapshot of the tick cumulative, seconds per liquidity and seconds inside a tick range
+    ///
+    /// Snapshots must only be compared to other snapshots, taken over a period for which a position existed.
+    /// I.e., snapshots cannot be compared if a position is not held for the entire period between when the first
+    /// snapshot is taken and the second snapshot is taken.
+    ///
+    /// The snapshot is set as return data, a borsh serialized `SnapshotCumulative`.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - Holds the pool, the initialized tick accounts bounding the range and
+    /// the latest observation of the pool
+    ///
+    pub fn snapshot_cumulatives_inside(ctx: Context<SnapshotCumulativesInside>) -> Result<()> {
+        let snapshot = _snapshot_cumulatives_inside(
+            &ctx.accounts.pool_state,
+            &ctx.accounts.tick_lower_state,
+            &ctx.accounts.tick_upper_state,
+            &ctx.accounts.latest_observation_state,
+        )?;
+        solana_program::program::set_return_data(&snapshot.try_to_vec()?);
+
+        Ok(())
+    }
+
     // ---------------------------------------------------------------------
     // Pool owner instructions
 
-    /// Set the denominator of the protocol's % share of the fees.
-    ///
-    /// Unlike Uniswap, protocol fee is globally set. It can be updated by factory owner
-    /// at any time.
+    /// Set the protocol's share of the fees, given to pools created afterwards. Existing
+    /// pools keep their protocol fee, which is changed with `set_pool_fee_protocol`.
+    /// Executes an action queued by the protocol fee admin, once past the timelock.
     ///
     /// # Arguments
     ///
-    /// * `ctx` - Checks for valid owner by looking at signer and factory owner addresses.
+    /// * `ctx` - Checks whether the action was queued and its ETA has passed.
     /// Holds the Factory State account where protocol fee will be saved.
-    /// * `fee_protocol` - new protocol fee for all pools
+    /// * `fee_protocol` - new protocol fee for new pools in hundredths of a bip, or 0 to turn
+    /// it off. At most half of the swap fees.
     ///
-    pub fn set_fee_protocol(ctx: Context<SetFeeProtocol>, fee_protocol: u8) -> Result<()> {
-        assert!(fee_protocol >= 2 && fee_protocol <= 10);
+    pub fn set_fee_protocol(ctx: Context<SetFeeProtocol>, fee_protocol: u32) -> Result<()> {
+        assert!(fee_protocol <= MAX_FEE_PROTOCOL);
         let mut factory_state = ctx.accounts.factory_state.load_mut()?;
-        let fee_protocol_old = factory_state.fee_protocol;
-        factory_state.fee_protocol = fee_protocol;
+        let fee_protocol_old = factory_state.default_fee_protocol;
+        factory_state.default_fee_protocol = fee_protocol;
 
-        emit!(SetFeeProtocolEvent {
+        emit!(SetDefaultFeeProtocolEvent {
             fee_protocol_old,
             fee_protocol
         });
+        emit!(AdminActionExecuted {
+            action: AdminAction::SetFeeProtocol { fee_protocol }
+        });
+
+        Ok(())
+    }
+
+    /// Set the protocol's share of the fees of a pool, for each token
+    /// Executes an action queued by the protocol fee admin, once past the timelock.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - Checks whether the action was queued and its ETA has passed.
+    /// Holds the pool whose protocol fee is set.
+    /// * `fee_protocol_0` - new protocol fee for token_0 of the pool in hundredths of a bip,
+    /// or 0 to turn it off. At most half of the swap fees.
+    /// * `fee_protocol_1` - new protocol fee for token_1 of the pool in hundredths of a bip,
+    /// or 0 to turn it off. At most half of the swap fees.
+    ///
+    pub fn set_pool_fee_protocol(
+        ctx: Context<SetPoolFeeProtocol>,
+        fee_protocol_0: u32,
+        fee_protocol_1: u32,
+    ) -> Result<()> {
+        assert!(fee_protocol_0 <= MAX_FEE_PROTOCOL);
+        assert!(fee_protocol_1 <= MAX_FEE_PROTOCOL);
+        let mut pool_state = ctx.accounts.pool_state.load_mut()?;
+        let fee_protocol_0_old = pool_state.fee_protocol_0;
+        let fee_protocol_1_old = pool_state.fee_protocol_1;
+        pool_state.fee_protocol_0 = fee_protocol_0;
+        pool_state.fee_protocol_1 = fee_protocol_1;
+
+        emit!(SetFeeProtocolEvent {
+            pool_state: ctx.accounts.pool_state.key(),
+            fee_protocol_0_old,
+            fee_protocol_1_old,
+            fee_protocol_0,
+            fee_protocol_1
+        });
+        emit!(AdminActionExecuted {
+            action: AdminAction::SetPoolFeeProtocol {
+                pool: ctx.accounts.pool_state.key(),
+                fee_protocol_0,
+                fee_protocol_1
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Set the swap fee discounts given to traders locking governance tokens
+    /// Executes an action queued by the protocol fee admin, once past the timelock.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - Checks whether the action was queued and its ETA has passed.
+    /// Holds the Factory State account where the discounts are saved.
+    /// * `locker` - The Tribeca locker whose escrows earn discounts, or the default address to
+    /// turn discounts off
+    /// * `fee_discount_tiers` - The least amount and duration locked for each discount. Discounts
+    /// are in hundredths of a bip of the swap fee, at most half of the fee.
+    ///
+    pub fn set_fee_discounts(
+        ctx: Context<SetFeeDiscounts>,
+        locker: Pubkey,
+        fee_discount_tiers: [FeeDiscountTier; FEE_DISCOUNT_TIER_NUM],
+    ) -> Result<()> {
+        for tier in &fee_discount_tiers {
+            assert!(tier.discount <= MAX_FEE_DISCOUNT);
+        }
+        let mut factory_state = ctx.accounts.factory_state.load_mut()?;
+        factory_state.locker = locker;
+        factory_state.fee_discount_tiers = fee_discount_tiers;
+
+        emit!(SetFeeDiscountsEvent {
+            locker,
+            fee_discount_tiers
+        });
+        emit!(AdminActionExecuted {
+            action: AdminAction::SetFeeDiscounts {
+                locker,
+                fee_discount_tiers
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Pause operations for every pool. Burns and collects are never paused, so that
+    /// liquidity providers can always exit
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - Checks for valid pause guardian by looking at signer and factory addresses.
+    /// Holds the Factory State account where the pause state is saved.
+    /// * `paused` - The operations to pause as `PAUSE_*` flags, or 0 to unpause
+    ///
+    pub fn set_pause_state(ctx: Context<SetPauseState>, paused: u8) -> Result<()> {
+        assert!(paused & !PAUSE_ALL == 0);
+        let mut factory_state = ctx.accounts.factory_state.load_mut()?;
+        let paused_old = factory_state.paused;
+        factory_state.paused = paused;
+
+        emit!(PauseStateChanged {
+            pool_state: Pubkey::default(),
+            paused_old,
+            paused
+        });
+
+        Ok(())
+    }
+
+    /// Pause operations for a pool, on top of those paused globally. Burns and collects
+    /// are never paused, so that liquidity providers can always exit
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - Checks for valid pause guardian by looking at signer and factory addresses.
+    /// Holds the pool whose operations are paused.
+    /// * `paused` - The operations to pause as `PAUSE_*` flags, or 0 to unpause
+    ///
+    pub fn set_pool_pause_state(ctx: Context<SetPoolPauseState>, paused: u8) -> Result<()> {
+        assert!(paused & !PAUSE_ALL == 0);
+        let mut pool_state = ctx.accounts.pool_state.load_mut()?;
+        let paused_old = pool_state.paused;
+        pool_state.paused = paused;
+
+        emit!(PauseStateChanged {
+            pool_state: ctx.accounts.pool_state.key(),
+            paused_old,
+            paused
+        });
 
         Ok(())
     }
@@ -305,7 +723,7 @@ pub mod cyclos_core {
     ///
     /// # Arguments
     ///
-    /// * `ctx` - Checks for valid owner by looking at signer and factory owner addresses.
+    /// * `ctx` - Checks for valid fee collector by looking at signer and factory addresses.
     /// Holds the Pool State account where accrued protocol fee is saved, and token accounts to perform
     /// transfer.
     /// * `amount_0_requested` - The maximum amount of token_0 to send, can be 0 to collect fees in only token_1
@@ -366,7 +784,7 @@ pub mod cyclos_core {
 
         emit!(CollectProtocolEvent {
             pool_state: ctx.accounts.pool_state.key(),
-            sender: ctx.accounts.owner.key(),
+            sender: ctx.accounts.fee_collector.key(),
             recipient_wallet_0: ctx.accounts.recipient_wallet_0.key(),
             recipient_wallet_1: ctx.accounts.recipient_wallet_1.key(),
             amount_0,
@@ -378,6 +796,80 @@ pub mod cyclos_core {
         Ok(())
     }
 
+    /// Adds a reward token emitted to in range liquidity of a pool. Emissions start at zero,
+    /// and are set by the reward authority once the vault is funded
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - Checks for valid owner by looking at signer and factory addresses. Holds the
+    /// pool, the reward token and the vault created to hold rewards.
+    /// * `reward_index` - The unused reward slot to fill
+    ///
+    pub fn initialize_reward(ctx: Context<InitializeReward>, reward_index: u8) -> Result<()> {
+        require!(
+            (reward_index as usize) < REWARD_NUM,
+            ErrorCode::InvalidRewardIndex
+        );
+        let mut pool_state = ctx.accounts.pool_state.load_mut()?;
+        let reward_info = &mut pool_state.reward_infos[reward_index as usize];
+        require!(!reward_info.initialized(), ErrorCode::RewardInitialized);
+
+        reward_info.mint = ctx.accounts.reward_mint.key();
+        reward_info.vault = ctx.accounts.reward_vault.key();
+        reward_info.authority = ctx.accounts.reward_authority.key();
+        reward_info.last_update_time = oracle::_block_timestamp();
+
+        emit!(RewardInitialized {
+            pool_state: ctx.accounts.pool_state.key(),
+            reward_index,
+            mint: ctx.accounts.reward_mint.key(),
+            vault: ctx.accounts.reward_vault.key(),
+            authority: ctx.accounts.reward_authority.key(),
+        });
+
+        Ok(())
+    }
+
+    /// Sets the emission rate of a reward. Rewards emitted so far are accrued at the old rate
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - Holds the reward authority and the pool emitting the reward
+    /// * `reward_index` - The slot of the reward
+    /// * `emissions_per_second_x32` - The new emission rate as a Q32.32, or 0 to stop emissions
+    ///
+    pub fn set_reward_emissions(
+        ctx: Context<SetRewardEmissions>,
+        reward_index: u8,
+        emissions_per_second_x32: u64,
+    ) -> Result<()> {
+        let mut pool_state = ctx.accounts.pool_state.load_mut()?;
+        require!(
+            (reward_index as usize) < REWARD_NUM
+                && pool_state.reward_infos[reward_index as usize].initialized(),
+            ErrorCode::InvalidRewardIndex
+        );
+        require!(
+            pool_state.reward_infos[reward_index as usize].authority
+                == ctx.accounts.reward_authority.key(),
+            ErrorCode::NotApproved
+        );
+
+        pool_state.update_reward_infos(oracle::_block_timestamp());
+        let reward_info = &mut pool_state.reward_infos[reward_index as usize];
+        let emissions_per_second_x32_old = reward_info.emissions_per_second_x32;
+        reward_info.emissions_per_second_x32 = emissions_per_second_x32;
+
+        emit!(SetRewardEmissionsEvent {
+            pool_state: ctx.accounts.pool_state.key(),
+            reward_index,
+            emissions_per_second_x32_old,
+            emissions_per_second_x32,
+        });
+
+        Ok(())
+    }
+
     /// ---------------------------------------------------------------------
     /// Account init instructions
     ///
@@ -399,10 +891,11 @@ pub mod cyclos_core {
         let mut tick_state = ctx.accounts.tick_state.load_init()?;
         tick_state.bump = *ctx.bumps.get("tick_state").unwrap();
         tick_state.tick = tick;
+        tick_state.payer = ctx.accounts.signer.key();
         Ok(())
     }
 
-    /// Reclaims lamports from a cleared tick account
+    /// Reclaims lamports from a cleared tick account, refunded to the payer of its rent
     ///
     /// # Arguments
     ///
@@ -412,6 +905,34 @@ pub mod cyclos_core {
         Ok(())
     }
 
+    /// Closes cleared tick accounts in bulk, refunding each to the payer of its rent. Permissionless
+    /// to call.
+    ///
+    /// Ticks holding liquidity are skipped, so that a sweep does not fail if a listed tick was
+    /// initialized again, even by another payer.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - Remaining accounts hold pairs of a tick account and the payer of its rent
+    ///
+    pub fn sweep_tick_accounts<'a, 'b, 'c, 'info>(
+        ctx: Context<'a, 'b, 'c, 'info, SweepTickAccounts>,
+    ) -> Result<()> {
+        for pair in ctx.remaining_accounts.chunks(2) {
+            let tick_state = AccountLoader::<TickState>::try_from(&pair[0])?;
+            let payer = pair
+                .get(1)
+                .ok_or(anchor_lang::error::ErrorCode::AccountNotEnoughKeys)?;
+            let tick = *tick_state.load()?.deref();
+            if !tick.is_clear() {
+                continue;
+            }
+            require!(tick.payer == payer.key(), ErrorCode::RP);
+            tick_state.close(payer.clone())?;
+        }
+        Ok(())
+    }
+
     /// Initializes an empty program account for a tick bitmap
     ///
     /// # Arguments
@@ -432,6 +953,20 @@ pub mod cyclos_core {
         let mut bitmap_account = ctx.accounts.bitmap_state.load_init()?;
         bitmap_account.bump = *ctx.bumps.get("bitmap_state").unwrap();
         bitmap_account.word_pos = word_pos;
+        bitmap_account.payer = ctx.accounts.signer.key();
+        Ok(())
+    }
+
+    /// Reclaims lamports from a bitmap account with no initialized tick, refunded to the payer
+    /// of its rent
+    ///
+    /// The swap reads a closed bitmap as uninitialized, and `mint` creates it again as needed.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - Holds bitmap and recipient accounts with validation and closure code
+    ///
+    pub fn close_bitmap_account(_ctx: Context<CloseBitmapAccount>) -> Result<()> {
         Ok(())
     }
 
@@ -447,28 +982,139 @@ pub mod cyclos_core {
     pub fn init_position_account(ctx: Context<InitPositionAccount>) -> Result<()> {
         let mut position_account = ctx.accounts.position_state.load_init()?;
         position_account.bump = *ctx.bumps.get("position_state").unwrap();
+        position_account.payer = ctx.accounts.signer.key();
         Ok(())
     }
 
-    // ---------------------------------------------------------------------
-    // Position instructions
+    /// Reclaims lamports from a position account with no liquidity and no tokens owed, refunded
+    /// to the payer of its rent
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - Holds position and recipient accounts with validation and closure code
+    ///
+    pub fn close_position_account(_ctx: Context<ClosePositionAccount>) -> Result<()> {
+        Ok(())
+    }
 
-    /// Callback to pay tokens for creating or adding liquidity to a position
+    /// Grows a tick account created before rent payers were recorded to the current layout
+    /// Must be authorized by the protocol owner, as the liquidity providers who paid for a
+    /// tick are not known. The payer pays rent for the added space, and is refunded the
+    /// whole rent once the cleared tick is closed. The pool must be migrated first
     ///
-    /// Callback function lies in core program instead of non_fungible_position_manager since
-    /// reentrancy is disallowed in Solana. Integrators can use a second program to handle callbacks.
+    /// # Arguments
+    ///
+    /// * `ctx` - Validates the tick account PDA and grows the account
+    /// * `tick` - The tick of the account
+    ///
+    pub fn migrate_tick_account(ctx: Context<MigrateTickAccount>, tick: i32) -> Result<()> {
+        realloc_program_account(
+            &ctx.accounts.payer.to_account_info(),
+            &ctx.accounts.tick_state.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            8 + LEGACY_TICK_STATE_LEN,
+            8 + size_of::<TickState>(),
+        )?;
+
+        let pool_state = ctx.accounts.pool_state.load()?;
+        let mut tick_state = ctx.accounts.tick_state.load_mut()?;
+        tick_state.payer = ctx.accounts.payer.key();
+        // as when a tick is initialized, all reward growth before the migration is assumed to
+        // have happened below the tick
+        if tick_state.liquidity_gross > 0 && tick <= pool_state.tick {
+            tick_state.reward_growths_outside_x32 = pool_state.reward_growths_global_x32();
+        }
+        Ok(())
+    }
+
+    /// Grows a bitmap account created before rent payers were recorded to the current layout
+    /// Must be authorized by the protocol owner, as the liquidity providers who paid for a
+    /// bitmap are not known. The payer pays rent for the added space, and is refunded the
+    /// whole rent once the empty bitmap is closed
     ///
     /// # Arguments
     ///
-    /// * `amount_0_owed` - The amount of token_0 due to the pool for the minted liquidity
-    /// * `amount_1_owed` - The amount of token_1 due to the pool for the minted liquidity
+    /// * `ctx` - Grows the bitmap account and validates its PDA
     ///
-    pub fn mint_callback(
-        ctx: Context<MintCallback>,
-        amount_0_owed: u64,
-        amount_1_owed: u64,
-    ) -> Result<()> {
-        if amount_0_owed > 0 {
+    pub fn migrate_bitmap_account(ctx: Context<MigrateBitmapAccount>) -> Result<()> {
+        realloc_program_account(
+            &ctx.accounts.payer.to_account_info(),
+            &ctx.accounts.bitmap_state.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            8 + LEGACY_TICK_BITMAP_STATE_LEN,
+            8 + size_of::<TickBitmapState>(),
+        )?;
+
+        // the bump and word position are only readable once the account is grown
+        let mut bitmap_state = ctx.accounts.bitmap_state.load_mut()?;
+        ctx.accounts.pool_state.load()?.validate_bitmap_address(
+            &ctx.accounts.bitmap_state.key(),
+            bitmap_state.bump,
+            bitmap_state.word_pos,
+        )?;
+        bitmap_state.payer = ctx.accounts.payer.key();
+        Ok(())
+    }
+
+    /// Grows a position account created before rent payers were recorded to the current layout
+    /// Must be authorized by the position owner, or by the protocol owner for positions held
+    /// by the factory for tokenized positions. The payer pays rent for the added space, and is
+    /// refunded the whole rent once the empty position is closed. Its ticks must be migrated
+    /// first
+    ///
+    /// The position earns rewards from the migration on, as if it was created then.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - Validates the position account PDA and grows the account
+    ///
+    pub fn migrate_position_account(ctx: Context<MigratePositionAccount>) -> Result<()> {
+        realloc_program_account(
+            &ctx.accounts.payer.to_account_info(),
+            &ctx.accounts.position_state.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            8 + LEGACY_POSITION_STATE_LEN,
+            8 + size_of::<PositionState>(),
+        )?;
+
+        let pool_state = ctx.accounts.pool_state.load()?;
+        let reward_growths_inside_x32 = tick::get_reward_growths_inside(
+            ctx.accounts.tick_lower_state.load()?.deref(),
+            ctx.accounts.tick_upper_state.load()?.deref(),
+            pool_state.tick,
+            pool_state.reward_growths_global_x32(),
+        );
+        let mut position_state = ctx.accounts.position_state.load_mut()?;
+        position_state.payer = ctx.accounts.payer.key();
+        for (reward_info, growth_inside_x32) in position_state
+            .reward_infos
+            .iter_mut()
+            .zip(reward_growths_inside_x32)
+        {
+            reward_info.growth_inside_last_x32 = growth_inside_x32;
+        }
+        Ok(())
+    }
+
+    // ---------------------------------------------------------------------
+    // Position instructions
+
+    /// Callback to pay tokens for creating or adding liquidity to a position
+    ///
+    /// Callback function lies in core program instead of non_fungible_position_manager since
+    /// reentrancy is disallowed in Solana. Integrators can use a second program to handle callbacks.
+    ///
+    /// # Arguments
+    ///
+    /// * `amount_0_owed` - The amount of token_0 due to the pool for the minted liquidity
+    /// * `amount_1_owed` - The amount of token_1 due to the pool for the minted liquidity
+    ///
+    pub fn mint_callback(
+        ctx: Context<MintCallback>,
+        amount_0_owed: u64,
+        amount_1_owed: u64,
+    ) -> Result<()> {
+        if amount_0_owed > 0 {
             token::transfer(
                 CpiContext::new(
                     ctx.accounts.token_program.to_account_info(),
@@ -501,6 +1147,9 @@ pub mod cyclos_core {
     /// The caller of this method must be checked to be the core program.
     /// amount_0_delta and amount_1_delta can both be 0 if no tokens were swapped.
     ///
+    /// Swaps made by the core with itself as the callback handler are paid without a cross
+    /// program invocation, in `pay_swap`. This instruction pays from the signer alike.
+    ///
     /// # Arguments
     ///
     /// * `ctx` - Token accounts for payment
@@ -514,47 +1163,42 @@ pub mod cyclos_core {
         amount_0_delta: i64,
         amount_1_delta: i64,
     ) -> Result<()> {
-        let (exact_input, amount_to_pay) = if amount_0_delta > 0 {
-            (
-                ctx.accounts.input_vault.mint < ctx.accounts.output_vault.mint,
-                amount_0_delta as u64,
-            )
-        } else {
-            (
-                ctx.accounts.output_vault.mint < ctx.accounts.input_vault.mint,
-                amount_1_delta as u64,
-            )
-        };
-        if exact_input {
-            msg!("amount to pay {}, delta 0 {}, delta 1 {}", amount_to_pay, amount_0_delta, amount_1_delta);
-            token::transfer(
-                CpiContext::new(
-                    ctx.accounts.token_program.to_account_info(),
-                    token::Transfer {
-                        from: ctx.accounts.input_token_account.to_account_info(),
-                        to: ctx.accounts.input_vault.to_account_info(),
-                        authority: ctx.accounts.signer.to_account_info(),
-                    },
-                ),
-                amount_to_pay,
-            )?;
+        let amount_to_pay = if amount_0_delta > 0 {
+            amount_0_delta as u64
         } else {
-            msg!("exact output not implemented");
+            amount_1_delta as u64
         };
-
-        Ok(())
+        msg!("amount to pay {}, delta 0 {}, delta 1 {}", amount_to_pay, amount_0_delta, amount_1_delta);
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.input_token_account.to_account_info(),
+                    to: ctx.accounts.input_vault.to_account_info(),
+                    authority: ctx.accounts.signer.to_account_info(),
+                },
+            ),
+            amount_to_pay,
+        )
     }
 
     /// Adds liquidity for the given pool/recipient/tickLower/tickUpper position
     ///
+    /// Tick, bitmap and position accounts which do not exist yet are created, with rent
+    /// paid by the payer.
+    ///
     /// # Arguments
     ///
     /// * `ctx` - Holds the recipient's address and program accounts for
     /// pool, position and ticks.
+    /// * `tick_lower` - The lower tick of the position in which to add liquidity
+    /// * `tick_upper` - The upper tick of the position in which to add liquidity
     /// * `amount` - The amount of liquidity to mint
     ///
     pub fn mint<'a, 'b, 'c, 'info>(
         ctx: Context<'a, 'b, 'c, 'info, MintContext<'info>>,
+        tick_lower: i32,
+        tick_upper: i32,
         amount: u64,
     ) -> Result<()> {
         let mut pool = ctx.accounts.pool_state.load_mut()?;
@@ -567,18 +1211,24 @@ pub mod cyclos_core {
             ctx.accounts.vault_1.key()
                 == get_associated_token_address(&ctx.accounts.pool_state.key(), &pool.token_1)
         );
-        let tick_lower = *ctx.accounts.tick_lower_state.load()?.deref();
+        check_not_paused(&*ctx.accounts.factory_state.load()?, &pool, PAUSE_MINT)?;
+        check_ticks(tick_lower, tick_upper)?;
+        init_mint_accounts(ctx.accounts, &pool, tick_lower, tick_upper)?;
+
+        let tick_lower_state =
+            AccountLoader::<TickState>::try_from(&ctx.accounts.tick_lower_state.to_account_info())?;
         pool.validate_tick_address(
             &ctx.accounts.tick_lower_state.key(),
-            tick_lower.bump,
-            tick_lower.tick,
+            tick_lower_state.load()?.bump,
+            tick_lower,
         )?;
 
-        let tick_upper = *ctx.accounts.tick_upper_state.load()?.deref();
+        let tick_upper_state =
+            AccountLoader::<TickState>::try_from(&ctx.accounts.tick_upper_state.to_account_info())?;
         pool.validate_tick_address(
             &ctx.accounts.tick_upper_state.key(),
-            tick_upper.bump,
-            tick_upper.tick,
+            tick_upper_state.load()?.bump,
+            tick_upper,
         )?;
 
         let bitmap_lower_state = AccountLoader::<TickBitmapState>::try_from(
@@ -587,7 +1237,7 @@ pub mod cyclos_core {
         pool.validate_bitmap_address(
             &ctx.accounts.bitmap_lower_state.key(),
             bitmap_lower_state.load()?.bump,
-            tick_bitmap::position(tick_lower.tick / pool.tick_spacing as i32).word_pos,
+            tick_bitmap::position(tick_lower / pool.tick_spacing as i32).word_pos,
         )?;
         let bitmap_upper_state = AccountLoader::<TickBitmapState>::try_from(
             &ctx.accounts.bitmap_upper_state.to_account_info(),
@@ -595,7 +1245,7 @@ pub mod cyclos_core {
         pool.validate_bitmap_address(
             &ctx.accounts.bitmap_upper_state.key(),
             bitmap_upper_state.load()?.bump,
-            tick_bitmap::position(tick_upper.tick / pool.tick_spacing as i32).word_pos,
+            tick_bitmap::position(tick_upper / pool.tick_spacing as i32).word_pos,
         )?;
 
         let position_state = AccountLoader::<PositionState>::try_from(
@@ -605,8 +1255,8 @@ pub mod cyclos_core {
             &ctx.accounts.position_state.key(),
             position_state.load()?.bump,
             &ctx.accounts.recipient.key(),
-            tick_lower.tick,
-            tick_upper.tick,
+            tick_lower,
+            tick_upper,
         )?;
 
         let last_observation_state = AccountLoader::<ObservationState>::try_from(
@@ -626,11 +1276,13 @@ pub mod cyclos_core {
         let (amount_0_int, amount_1_int) = _modify_position(
             i64::try_from(amount).unwrap(),
             pool.deref_mut(),
-            &position_state,
-            &ctx.accounts.tick_lower_state,
-            &ctx.accounts.tick_upper_state,
-            &bitmap_lower_state,
-            &bitmap_upper_state,
+            &PositionAccounts {
+                position_state: &position_state,
+                tick_lower_state: &tick_lower_state,
+                tick_upper_state: &tick_upper_state,
+                bitmap_lower: &bitmap_lower_state,
+                bitmap_upper: &bitmap_upper_state,
+            },
             &last_observation_state,
             ctx.remaining_accounts,
         )?;
@@ -682,8 +1334,8 @@ pub mod cyclos_core {
             pool_state: ctx.accounts.pool_state.key(),
             sender: ctx.accounts.minter.key(),
             owner: ctx.accounts.recipient.key(),
-            tick_lower: tick_lower.tick,
-            tick_upper: tick_upper.tick,
+            tick_lower,
+            tick_upper,
             amount,
             amount_0,
             amount_1
@@ -773,11 +1425,13 @@ pub mod cyclos_core {
         let (amount_0_int, amount_1_int) = _modify_position(
             -i64::try_from(amount).unwrap(),
             pool.deref_mut(),
-            &ctx.accounts.position_state,
-            &tick_lower_state,
-            &tick_upper_state,
-            &bitmap_lower_state,
-            &bitmap_upper_state,
+            &PositionAccounts {
+                position_state: &ctx.accounts.position_state,
+                tick_lower_state: &tick_lower_state,
+                tick_upper_state: &tick_upper_state,
+                bitmap_lower: &bitmap_lower_state,
+                bitmap_upper: &bitmap_upper_state,
+            },
             &last_observation_state,
             ctx.remaining_accounts,
         )?;
@@ -794,7 +1448,7 @@ pub mod cyclos_core {
             pool_state: ctx.accounts.pool_state.key(),
             owner: ctx.accounts.owner.key(),
             tick_lower: tick_lower.tick,
-            tick_upper: tick_lower.tick,
+            tick_upper: tick_upper.tick,
             amount,
             amount_0,
             amount_1,
@@ -915,584 +1569,402 @@ pub mod cyclos_core {
         Ok(())
     }
 
-    // ---------------------------------------------------------------------
-    // 4. Swap instructions
-
-    pub struct SwapCache {
-        // the protocol fee for the input token
-        pub fee_protocol: u8,
-        // liquidity at the beginning of the swap
-        pub liquidity_start: u64,
-        // the timestamp of the current block
-        pub block_timestamp: u32,
-        // the current value of the tick accumulator, computed only if we cross an initialized tick
-        pub tick_cumulative: i64,
-        // the current value of seconds per liquidity accumulator, computed only if we cross an initialized tick
-        pub seconds_per_liquidity_cumulative_x32: u64,
-        // whether we've computed and cached the above two accumulators
-        pub computed_latest_observation: bool,
-    }
-
-    // the top level state of the swap, the results of which are recorded in storage at the end
-    #[derive(Debug)]
-    pub struct SwapState {
-        // the amount remaining to be swapped in/out of the input/output asset
-        pub amount_specified_remaining: i64,
-        // the amount already swapped out/in of the output/input asset
-        pub amount_calculated: i64,
-        // current sqrt(price)
-        pub sqrt_price_x32: u64,
-        // the tick associated with the current price
-        pub tick: i32,
-        // the global fee growth of the input token
-        pub fee_growth_global_x32: u64,
-        // amount of input token paid as protocol fee
-        pub protocol_fee: u64,
-        // the current liquidity in range
-        pub liquidity: u64,
-    }
-
-    #[derive(Default)]
-    struct StepComputations {
-        // the price at the beginning of the step
-        sqrt_price_start_x32: u64,
-        // the next tick to swap to from the current tick in the swap direction
-        tick_next: i32,
-        // whether tick_next is initialized or not
-        initialized: bool,
-        // sqrt(price) for the next tick (1/0)
-        sqrt_price_next_x32: u64,
-        // how much is being swapped in in this step
-        amount_in: u64,
-        // how much is being swapped out
-        amount_out: u64,
-        // how much fee is being paid in
-        fee_amount: u64,
-    }
-
-    /// Swap token_0 for token_1, or token_1 for token_0
+    /// Collects rewards owed to a position, up to the balance of the reward vault.
     ///
-    /// Outstanding tokens must be paid in #swap_callback
+    /// Does not recompute rewards earned, which must be done either via mint or burn of any amount of liquidity.
+    /// Collect must be called by the position owner.
     ///
     /// # Arguments
     ///
-    /// * `ctx` - Accounts required for the swap. Remaining accounts should contain each bitmap leading to
-    /// the end tick, and each tick being flipped
-    /// account leading to the destination tick
-    /// * `deadline` - The time by which the transaction must be included to effect the change
-    /// * `amount_specified` - The amount of the swap, which implicitly configures the swap as exact input (positive),
-    /// or exact output (negative)
-    /// * `sqrt_price_limit` - The Q32.32 sqrt price √P limit. If zero for one, the price cannot
-    /// be less than this value after the swap.  If one for zero, the price cannot be greater than
-    /// this value after the swap.
+    /// * `reward_index` - The slot of the reward to collect
+    /// * `amount_requested` - How much of the reward should be withdrawn from the rewards owed
     ///
-    pub fn swap(
-        ctx: Context<SwapContext>,
-        amount_specified: i64,
-        sqrt_price_limit_x32: u64,
+    pub fn collect_reward(
+        ctx: Context<CollectRewardContext>,
+        reward_index: u8,
+        amount_requested: u64,
     ) -> Result<()> {
-        require!(amount_specified != 0, ErrorCode::AS);
+        let pool_state =
+            AccountLoader::<PoolState>::try_from(&ctx.accounts.pool_state.to_account_info())?;
+        let mut pool = pool_state.load_mut()?;
 
-        let factory_state =
-            AccountLoader::<FactoryState>::try_from(&ctx.accounts.factory_state.to_account_info())?;
+        let tick_lower_state =
+            AccountLoader::<TickState>::try_from(&ctx.accounts.tick_lower_state.to_account_info())?;
+        let tick_lower = *tick_lower_state.load()?.deref();
+        pool.validate_tick_address(
+            &ctx.accounts.tick_lower_state.key(),
+            tick_lower.bump,
+            tick_lower.tick,
+        )?;
 
-        let pool_loader =
-            AccountLoader::<PoolState>::try_from(&ctx.accounts.pool_state.to_account_info())?;
-        let mut pool = pool_loader.load_mut()?;
-
-        let input_token_account =
-            Account::<TokenAccount>::try_from(&ctx.accounts.input_token_account)?;
-        let output_token_account =
-            Account::<TokenAccount>::try_from(&ctx.accounts.output_token_account)?;
-
-        let zero_for_one = ctx.accounts.input_vault.mint == pool.token_0;
-
-        let (token_account_0, token_account_1, mut vault_0, mut vault_1) = if zero_for_one {
-            (
-                input_token_account,
-                output_token_account,
-                ctx.accounts.input_vault.clone(),
-                ctx.accounts.output_vault.clone(),
-            )
-        } else {
-            (
-                output_token_account,
-                input_token_account,
-                ctx.accounts.output_vault.clone(),
-                ctx.accounts.input_vault.clone(),
-            )
-        };
-        assert!(vault_0.key() == get_associated_token_address(&pool_loader.key(), &pool.token_0));
-        assert!(vault_1.key() == get_associated_token_address(&pool_loader.key(), &pool.token_1));
+        let tick_upper_state =
+            AccountLoader::<TickState>::try_from(&ctx.accounts.tick_upper_state.to_account_info())?;
+        let tick_upper = *tick_upper_state.load()?.deref();
+        pool.validate_tick_address(
+            &ctx.accounts.tick_upper_state.key(),
+            tick_upper.bump,
+            tick_upper.tick,
+        )?;
 
-        let last_observation_state = AccountLoader::<ObservationState>::try_from(
-            &ctx.accounts.last_observation_state.to_account_info(),
+        let position_state = AccountLoader::<PositionState>::try_from(
+            &ctx.accounts.position_state.to_account_info(),
         )?;
-        pool.validate_observation_address(
-            &ctx.accounts.last_observation_state.key(),
-            last_observation_state.load()?.bump,
-            false,
+        pool.validate_position_address(
+            &ctx.accounts.position_state.key(),
+            position_state.load()?.bump,
+            &ctx.accounts.owner.key(),
+            tick_lower.tick,
+            tick_upper.tick,
         )?;
 
-        require!(pool.unlocked, ErrorCode::LOK);
         require!(
-            if zero_for_one {
-                sqrt_price_limit_x32 < pool.sqrt_price_x32
-                    && sqrt_price_limit_x32 > tick_math::MIN_SQRT_RATIO
-            } else {
-                sqrt_price_limit_x32 > pool.sqrt_price_x32
-                    && sqrt_price_limit_x32 < tick_math::MAX_SQRT_RATIO
-            },
-            ErrorCode::SPL
+            (reward_index as usize) < REWARD_NUM
+                && pool.reward_infos[reward_index as usize].initialized(),
+            ErrorCode::InvalidRewardIndex
         );
+        assert!(ctx.accounts.reward_vault.key() == pool.reward_infos[reward_index as usize].vault);
 
+        require!(pool.unlocked, ErrorCode::LOK);
         pool.unlocked = false;
-        let mut cache = SwapCache {
-            liquidity_start: pool.liquidity,
-            block_timestamp: oracle::_block_timestamp(),
-            fee_protocol: factory_state.load()?.fee_protocol,
-            seconds_per_liquidity_cumulative_x32: 0,
-            tick_cumulative: 0,
-            computed_latest_observation: false,
-        };
-
-        let exact_input = amount_specified > 0;
-
-        let mut state = SwapState {
-            amount_specified_remaining: amount_specified,
-            amount_calculated: 0,
-            sqrt_price_x32: pool.sqrt_price_x32,
-            tick: pool.tick,
-            fee_growth_global_x32: if zero_for_one {
-                pool.fee_growth_global_0_x32
-            } else {
-                pool.fee_growth_global_1_x32
-            },
-            protocol_fee: 0,
-            liquidity: cache.liquidity_start,
-        };
-
-        let latest_observation = last_observation_state.load_mut()?;
-        let mut remaining_accounts = ctx.remaining_accounts.iter();
-
-        // cache for the current bitmap account. Cache is cleared on bitmap transitions
-        let mut bitmap_cache: Option<TickBitmapState> = None;
-
-        // continue swapping as long as we haven't used the entire input/output and haven't
-        // reached the price limit
-        while state.amount_specified_remaining != 0 && state.sqrt_price_x32 != sqrt_price_limit_x32
-        {
-            let mut step = StepComputations::default();
-            step.sqrt_price_start_x32 = state.sqrt_price_x32;
-
-            let mut compressed = state.tick / pool.tick_spacing as i32;
-
-            // state.tick is the starting tick for the transition
-            if state.tick < 0 && state.tick % pool.tick_spacing as i32 != 0 {
-                compressed -= 1; // round towards negative infinity
-            }
-            // The current tick is not considered in greater than or equal to (lte = false, i.e one for zero) case
-            if !zero_for_one {
-                compressed += 1;
-            }
-
-            let Position { word_pos, bit_pos } = tick_bitmap::position(compressed);
-
-            // load the next bitmap account if cache is empty (first loop instance), or if we have
-            // crossed out of this bitmap
-            if bitmap_cache.is_none() || bitmap_cache.unwrap().word_pos != word_pos {
-                let bitmap_account = remaining_accounts.next().unwrap();
-                msg!("check bitmap {}", word_pos);
-                // ensure this is a valid PDA, even if account is not initialized
-                assert!(
-                    bitmap_account.key()
-                        == Pubkey::find_program_address(
-                            &[
-                                BITMAP_SEED.as_bytes(),
-                                pool.token_0.as_ref(),
-                                pool.token_1.as_ref(),
-                                &pool.fee.to_be_bytes(),
-                                &word_pos.to_be_bytes(),
-                            ],
-                            &cyclos_core::id()
-                        )
-                        .0
-                );
-
-                msg!("check if bitmap is initialized");
-                // read from bitmap if account is initialized, else use default values for next initialized bit
-                if let Ok(bitmap_loader) =
-                    AccountLoader::<TickBitmapState>::try_from(bitmap_account)
-                {
-                    msg!("bitmap is initialized, loading");
-                    let bitmap_state = bitmap_loader.load()?;
-                    msg!("bitmap loaded");
-                    bitmap_cache = Some(*bitmap_state.deref());
-                } else {
-                    // clear cache if the bitmap account was uninitialized. This way default uninitialized
-                    // values will be returned for the next bit
-                    msg!("cache cleared");
-                    bitmap_cache = None;
-                }
-            }
-
-            // what if bitmap_cache is not updated since next account is not initialized?
-            // default values for the next initialized bit if the bitmap account is not initialized
-            let next_initialized_bit = if let Some(bitmap) = bitmap_cache {
-                bitmap.next_initialized_bit(bit_pos, zero_for_one)
-            } else {
-                NextBit {
-                    next: if zero_for_one { 0 } else { 255 },
-                    initialized: false,
-                }
-            };
-
-            step.tick_next = (((word_pos as i32) << 8) + next_initialized_bit.next as i32)
-                * pool.tick_spacing as i32; // convert relative to absolute
-            step.initialized = next_initialized_bit.initialized;
-
-            // ensure that we do not overshoot the min/max tick, as the tick bitmap is not aware of these bounds
-            if step.tick_next < tick_math::MIN_TICK {
-                step.tick_next = tick_math::MIN_TICK;
-            } else if step.tick_next > tick_math::MAX_TICK {
-                step.tick_next = tick_math::MAX_TICK;
-            }
-
-            step.sqrt_price_next_x32 = tick_math::get_sqrt_ratio_at_tick(step.tick_next)?;
-
-            let target_price = if (zero_for_one && step.sqrt_price_next_x32 < sqrt_price_limit_x32)
-                || (!zero_for_one && step.sqrt_price_next_x32 > sqrt_price_limit_x32)
-            {
-                sqrt_price_limit_x32
-            } else {
-                step.sqrt_price_next_x32
-            };
-            let swap_step = swap_math::compute_swap_step(
-                state.sqrt_price_x32,
-                target_price,
-                state.liquidity,
-                state.amount_specified_remaining,
-                pool.fee,
-            );
-            state.sqrt_price_x32 = swap_step.sqrt_ratio_next_x32;
-            step.amount_in = swap_step.amount_in;
-            step.amount_out = swap_step.amount_out;
-            step.fee_amount = swap_step.fee_amount;
-
-            if exact_input {
-                state.amount_specified_remaining -=
-                    i64::try_from(step.amount_in + step.fee_amount).unwrap();
-                state.amount_calculated = state
-                    .amount_calculated
-                    .checked_sub(i64::try_from(step.amount_out).unwrap())
-                    .unwrap();
-            } else {
-                state.amount_specified_remaining += i64::try_from(step.amount_out).unwrap();
-                state.amount_calculated = state
-                    .amount_calculated
-                    .checked_add(i64::try_from(step.amount_in + step.fee_amount).unwrap())
-                    .unwrap();
-            }
-
-            // if the protocol fee is on, calculate how much is owed, decrement fee_amount, and increment protocol_fee
-            if cache.fee_protocol > 0 {
-                let delta = step.fee_amount / cache.fee_protocol as u64;
-                step.fee_amount -= delta;
-                state.protocol_fee += delta;
-            }
-
-            // update global fee tracker
-            if state.liquidity > 0 {
-                state.fee_growth_global_x32 += step
-                    .fee_amount
-                    .mul_div_floor(fixed_point_32::Q32, state.liquidity)
-                    .unwrap();
-            }
-
-            // shift tick if we reached the next price
-            if state.sqrt_price_x32 == step.sqrt_price_next_x32 {
-                msg!("reached next price, transitioning");
-                // if the tick is initialized, run the tick transition
-                if step.initialized {
-                    // check for the placeholder value for the oracle observation, which we replace with the
-                    // actual value the first time the swap crosses an initialized tick
-                    if !cache.computed_latest_observation {
-                        let new_observation = latest_observation.observe_latest(
-                            cache.block_timestamp,
-                            pool.tick,
-                            pool.liquidity,
-                        );
-                        cache.tick_cumulative = new_observation.0;
-                        cache.seconds_per_liquidity_cumulative_x32 = new_observation.1;
-                        cache.computed_latest_observation = true;
-                    }
-
-                    msg!("loading tick {}", step.tick_next);
-                    let tick_loader =
-                        AccountLoader::<TickState>::try_from(remaining_accounts.next().unwrap())?;
-                    let mut tick_state = tick_loader.load_mut()?;
-                    pool.validate_tick_address(
-                        &tick_loader.key(),
-                        tick_state.bump,
-                        step.tick_next,
-                    )?;
-                    let mut liquidity_net = tick_state.deref_mut().cross(
-                        if zero_for_one {
-                            state.fee_growth_global_x32
-                        } else {
-                            pool.fee_growth_global_0_x32
-                        },
-                        if zero_for_one {
-                            pool.fee_growth_global_1_x32
-                        } else {
-                            state.fee_growth_global_x32
-                        },
-                        cache.seconds_per_liquidity_cumulative_x32,
-                        cache.tick_cumulative,
-                        cache.block_timestamp,
-                    );
-
-                    // if we're moving leftward, we interpret liquidity_net as the opposite sign
-                    // safe because liquidity_net cannot be i64::MIN
-                    if zero_for_one {
-                        liquidity_net = liquidity_net.neg();
-                    }
-
-                    state.liquidity = liquidity_math::add_delta(state.liquidity, liquidity_net)?;
-                } else {
-                    msg!("step not initialized");
-                }
-
-                state.tick = if zero_for_one {
-                    step.tick_next - 1
-                } else {
-                    step.tick_next
-                };
-            } else if state.sqrt_price_x32 != step.sqrt_price_start_x32 {
-                msg!("not at next or start price");
-                // recompute unless we're on a lower tick boundary (i.e. already transitioned ticks), and haven't moved
-                state.tick = tick_math::get_tick_at_sqrt_ratio(state.sqrt_price_x32)?;
-            }
-        }
-        let partition_current_timestamp = cache.block_timestamp / 14;
-        let partition_last_timestamp = latest_observation.block_timestamp / 14;
-        drop(latest_observation);
-
-        // update tick and write an oracle entry if the tick changes
-        if state.tick != pool.tick {
-            // use the next observation account and update pool observation index if block time falls
-            // in another partition
-            let next_observation_state;
-            let mut next_observation = if partition_current_timestamp > partition_last_timestamp {
-                msg!("loading observation");
-                next_observation_state = AccountLoader::<ObservationState>::try_from(
-                    &remaining_accounts.next().unwrap(),
-                )?;
-                let next_observation = next_observation_state.load_mut()?;
-                msg!("observation loaded");
-
-                pool.validate_observation_address(
-                    &next_observation_state.key(),
-                    next_observation.bump,
-                    true,
-                )?;
-
-                next_observation
-            } else {
-                last_observation_state.load_mut()?
-            };
-            pool.tick = state.tick;
-            pool.observation_cardinality_next = next_observation.update(
-                cache.block_timestamp,
-                pool.tick,
-                cache.liquidity_start,
-                pool.observation_cardinality,
-                pool.observation_cardinality_next,
-            );
-        }
-        pool.sqrt_price_x32 = state.sqrt_price_x32;
-
-        // update liquidity if it changed
-        if cache.liquidity_start != state.liquidity {
-            pool.liquidity = state.liquidity;
-        }
 
-        // update fee growth global and, if necessary, protocol fees
-        // overflow is acceptable, protocol has to withdraw before it hit u64::MAX fees
-        if zero_for_one {
-            pool.fee_growth_global_0_x32 = state.fee_growth_global_x32;
-            if state.protocol_fee > 0 {
-                pool.protocol_fees_token_0 += state.protocol_fee;
-            }
-        } else {
-            pool.fee_growth_global_1_x32 = state.fee_growth_global_x32;
-            if state.protocol_fee > 0 {
-                pool.protocol_fees_token_1 += state.protocol_fee;
-            }
-        }
+        let mut position = position_state.load_mut()?;
+        let reward_info = &mut position.reward_infos[reward_index as usize];
 
-        let (amount_0, amount_1) = if zero_for_one == exact_input {
-            (
-                amount_specified - state.amount_specified_remaining,
-                state.amount_calculated,
-            )
-        } else {
-            (
-                state.amount_calculated,
-                amount_specified - state.amount_specified_remaining,
-            )
-        };
+        let amount = amount_requested
+            .min(reward_info.reward_amount_owed)
+            .min(ctx.accounts.reward_vault.amount);
 
-        // do the transfers and collect payment
         let pool_state_seeds = [
-            &POOL_SEED.as_bytes(),
+            POOL_SEED.as_bytes(),
             &pool.token_0.to_bytes() as &[u8],
             &pool.token_1.to_bytes() as &[u8],
             &pool.fee.to_be_bytes(),
             &[pool.bump],
         ];
-        drop(pool);
-
-        msg!("vault balances {} {}", vault_0.amount, vault_1.amount);
 
-        if zero_for_one {
-            if amount_1 < 0 {
-                msg!("paying {}", amount_1.neg());
-                token::transfer(
-                    CpiContext::new_with_signer(
-                        ctx.accounts.token_program.to_account_info().clone(),
-                        token::Transfer {
-                            from: vault_1.to_account_info().clone(),
-                            to: token_account_1.to_account_info().clone(),
-                            authority: ctx.accounts.pool_state.to_account_info().clone(),
-                        },
-                        &[&pool_state_seeds[..]],
-                    ),
-                    amount_1.neg() as u64,
-                )?;
-            }
-            let balance_0_before = vault_0.amount;
-
-            // transfer tokens to pool in callback
-            let swap_callback_ix = cyclos_core::instruction::SwapCallback {
-                amount_0_delta: amount_0,
-                amount_1_delta: amount_1,
-            };
-            let ix = Instruction::new_with_bytes(
-                ctx.accounts.callback_handler.key(),
-                &swap_callback_ix.data(),
-                ctx.accounts.to_account_metas(None),
-            );
-            solana_program::program::invoke(&ix, &ctx.accounts.to_account_infos())?;
-            vault_0.reload()?;
-            require!(
-                balance_0_before.checked_add(amount_0 as u64).unwrap() <= vault_0.amount,
-                ErrorCode::IIA
-            );
-        } else {
-            if amount_0 < 0 {
-                msg!("paying {}", amount_0.neg());
-                token::transfer(
-                    CpiContext::new_with_signer(
-                        ctx.accounts.token_program.to_account_info().clone(),
-                        token::Transfer {
-                            from: vault_0.to_account_info().clone(),
-                            to: token_account_0.to_account_info().clone(),
-                            authority: ctx.accounts.pool_state.to_account_info().clone(),
-                        },
-                        &[&pool_state_seeds[..]],
-                    ),
-                    amount_0.neg() as u64,
-                )?;
-            }
-            let balance_1_before = vault_1.amount;
-            // transfer tokens to pool in callback
-            let swap_callback_ix = cyclos_core::instruction::SwapCallback {
-                amount_0_delta: amount_0,
-                amount_1_delta: amount_1,
-            };
-            let ix = Instruction::new_with_bytes(
-                ctx.accounts.callback_handler.key(),
-                &swap_callback_ix.data(),
-                ctx.accounts.to_account_metas(None),
-            );
-            solana_program::program::invoke(&ix, &ctx.accounts.to_account_infos())?;
-            vault_1.reload()?;
-            require!(
-                balance_1_before.checked_add(amount_1 as u64).unwrap() <= vault_1.amount,
-                ErrorCode::IIA
-            );
+        drop(pool);
+        if amount > 0 {
+            reward_info.reward_amount_owed -= amount;
+            token::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info().clone(),
+                    token::Transfer {
+                        from: ctx.accounts.reward_vault.to_account_info().clone(),
+                        to: ctx.accounts.recipient_wallet.to_account_info().clone(),
+                        authority: pool_state.to_account_info().clone(),
+                    },
+                    &[&pool_state_seeds[..]],
+                ),
+                amount,
+            )?;
         }
 
-        emit!(SwapEvent {
-            pool_state: pool_loader.key(),
-            sender: ctx.accounts.signer.key(),
-            token_account_0: token_account_0.key(),
-            token_account_1: token_account_1.key(),
-            amount_0,
-            amount_1,
-            sqrt_price_x32: state.sqrt_price_x32,
-            liquidity: state.liquidity,
-            tick: state.tick
+        emit!(CollectRewardEvent {
+            pool_state: pool_state.key(),
+            owner: ctx.accounts.owner.key(),
+            tick_lower: tick_lower.tick,
+            tick_upper: tick_upper.tick,
+            reward_index,
+            amount,
         });
-        pool_loader.load_mut()?.unlocked = true;
 
+        pool_state.load_mut()?.unlocked = true;
         Ok(())
     }
 
-    // /// Component function for flash swaps
-    // ///
-    // /// Donate given liquidity to in-range positions then make callback
-    // /// Only callable by a smart contract which implements uniswapV3FlashCallback(),
-    // /// where profitability check can be performed
-    // ///
-    // /// Flash swaps is an advanced feature for developers, not directly available for UI based traders.
-    // /// Periphery does not provide an implementation, but a sample is provided
-    // /// Ref- https://github.com/Uniswap/v3-periphery/blob/main/contracts/examples/PairFlash.sol
-    // ///
-    // ///
-    // /// Flow
-    // /// 1. FlashDapp.initFlash()
-    // /// 2. Core.flash()
-    // /// 3. FlashDapp.uniswapV3FlashCallback()
-    // ///
-    // /// @param amount_0 Amount of token 0 to donate
-    // /// @param amount_1 Amount of token 1 to donate
-    // pub fn flash(ctx: Context<SetFeeProtocol>, amount_0: u64, amount_1: u64) -> Result<()> {
-    //     todo!()
-    // }
-
-    // Non fungible position manager
+    // ---------------------------------------------------------------------
+    // 4. Swap instructions
 
-    /// Creates a new position wrapped in a NFT
+    /// Swap token_0 for token_1, or token_1 for token_0
+    ///
+    /// Outstanding tokens must be paid in #swap_callback
     ///
     /// # Arguments
     ///
-    /// * `ctx` - Holds pool, tick, bitmap, position and token accounts
-    /// * `amount_0_desired` - Desired amount of token_0 to be spent
-    /// * `amount_1_desired` - Desired amount of token_1 to be spent
-    /// * `amount_0_min` - The minimum amount of token_0 to spend, which serves as a slippage check
-    /// * `amount_1_min` - The minimum amount of token_1 to spend, which serves as a slippage check
+    /// * `ctx` - Accounts required for the swap. Remaining accounts should contain each bitmap leading to
+    /// the end tick, and each tick being flipped
+    /// account leading to the destination tick. Bitmaps and ticks beyond the range crossed, as
+    /// listed with a price margin, are skipped. The next observation account follows them.
+    /// Governance tokens locked by the signer can be passed last, for a swap fee discount
     /// * `deadline` - The time by which the transaction must be included to effect the change
+    /// * `amount_specified` - The amount of the swap, which implicitly configures the swap as exact input (positive),
+    /// or exact output (negative)
+    /// * `sqrt_price_limit` - The Q32.32 sqrt price √P limit. If zero for one, the price cannot
+    /// be less than this value after the swap.  If one for zero, the price cannot be greater than
+    /// this value after the swap.
     ///
-    #[access_control(check_deadline(deadline))]
+    pub fn swap<'a, 'b, 'c, 'info>(
+        mut ctx: Context<'a, 'b, 'c, 'info, SwapContext<'info>>,
+        amount_specified: i64,
+        sqrt_price_limit_x32: u64,
+    ) -> Result<()> {
+        let (remaining_accounts, escrow) = split_escrow(ctx.remaining_accounts);
+        ctx.remaining_accounts = remaining_accounts;
+        swap_internal(ctx, amount_specified, sqrt_price_limit_x32, escrow, &[], &[])
+    }
+
+    /// Borrow token_0 and / or token_1 from the pool, to be repaid along with a fee
+    /// later in the same transaction
+    ///
+    /// Solana programs cannot be re-entered with a callback the way Uniswap's
+    /// `uniswapV3FlashCallback()` works, so a flash loan is an instruction pair.
+    /// `flash` transfers the borrowed amounts out, records them on the pool and locks
+    /// it. It only succeeds if a top level `repay_flash` instruction for the same pool
+    /// and the same amounts is present later in the transaction, which is verified
+    /// through the instructions sysvar. Since transactions are atomic, the loan is
+    /// either repaid or the borrow is reverted. Both instructions must be called at the
+    /// top level, so that a program cannot borrow again by repaying through a cross
+    /// program invocation.
+    ///
+    /// Any instructions between the pair, for example swaps on other pools, can
+    /// be used to perform the profitability check. Instructions on the locked pool fail.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - Accounts for the flash loan
+    /// * `amount_0` - The amount of token_0 to borrow
+    /// * `amount_1` - The amount of token_1 to borrow
+    ///
+    pub fn flash(ctx: Context<Flash>, amount_0: u64, amount_1: u64) -> Result<()> {
+        let instructions_sysvar = ctx.accounts.instructions_sysvar.to_account_info();
+        check_top_level(
+            &instructions_sysvar,
+            &crate::instruction::Flash { amount_0, amount_1 }.data(),
+        )?;
+
+        let mut pool_state = ctx.accounts.pool_state.load_mut()?;
+        check_not_paused(
+            &*ctx.accounts.factory_state.load()?,
+            &pool_state,
+            PAUSE_SWAP,
+        )?;
+        require!(pool_state.unlocked, ErrorCode::LOK);
+        require!(pool_state.liquidity > 0, ErrorCode::L);
+
+        // the first repay_flash for this pool after the current instruction must
+        // return the borrowed amounts
+        let repay_data = crate::instruction::RepayFlash { amount_0, amount_1 }.data();
+        let mut index =
+            sysvar_instructions::load_current_index_checked(&instructions_sysvar)? as usize + 1;
+        let mut repaid = false;
+        while let Ok(ix) =
+            sysvar_instructions::load_instruction_at_checked(index, &instructions_sysvar)
+        {
+            if ix.program_id == crate::ID
+                && ix.data.get(..8) == Some(&repay_data[..8])
+                && ix.accounts.get(2).map(|meta| meta.pubkey) == Some(ctx.accounts.pool_state.key())
+            {
+                repaid = ix.data == repay_data;
+                break;
+            }
+            index += 1;
+        }
+        require!(repaid, ErrorCode::FR);
+
+        // the pool stays locked until repay_flash returns the recorded amounts
+        pool_state.unlocked = false;
+        pool_state.flash_amount_0 = amount_0;
+        pool_state.flash_amount_1 = amount_1;
+
+        let pool_state_seeds = [
+            POOL_SEED.as_bytes(),
+            &pool_state.token_0.to_bytes() as &[u8],
+            &pool_state.token_1.to_bytes() as &[u8],
+            &pool_state.fee.to_be_bytes(),
+            &[pool_state.bump],
+        ];
+        drop(pool_state);
+
+        if amount_0 > 0 {
+            token::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info().clone(),
+                    token::Transfer {
+                        from: ctx.accounts.vault_0.to_account_info().clone(),
+                        to: ctx.accounts.recipient_wallet_0.to_account_info().clone(),
+                        authority: ctx.accounts.pool_state.to_account_info().clone(),
+                    },
+                    &[&pool_state_seeds[..]],
+                ),
+                amount_0,
+            )?;
+        }
+        if amount_1 > 0 {
+            token::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info().clone(),
+                    token::Transfer {
+                        from: ctx.accounts.vault_1.to_account_info().clone(),
+                        to: ctx.accounts.recipient_wallet_1.to_account_info().clone(),
+                        authority: ctx.accounts.pool_state.to_account_info().clone(),
+                    },
+                    &[&pool_state_seeds[..]],
+                ),
+                amount_1,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Repay a flash loan taken with `flash` in the same transaction
+    ///
+    /// The borrowed amounts plus a fee at the pool's fee tier are transferred back
+    /// to the vaults. The fee is donated to in-range liquidity, less the protocol share.
+    /// The amounts must be those recorded by `flash`, and the instruction must be called
+    /// at the top level.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - Accounts for the repayment
+    /// * `amount_0` - The amount of token_0 that was borrowed
+    /// * `amount_1` - The amount of token_1 that was borrowed
+    ///
+    pub fn repay_flash(ctx: Context<RepayFlash>, amount_0: u64, amount_1: u64) -> Result<()> {
+        check_top_level(
+            &ctx.accounts.instructions_sysvar.to_account_info(),
+            &crate::instruction::RepayFlash { amount_0, amount_1 }.data(),
+        )?;
+
+        let mut pool_state = ctx.accounts.pool_state.load_mut()?;
+        // only an outstanding flash loan leaves the pool locked between instructions, and it
+        // must be repaid in full
+        require!(!pool_state.unlocked, ErrorCode::FR);
+        require!(
+            pool_state.flash_amount_0 == amount_0 && pool_state.flash_amount_1 == amount_1,
+            ErrorCode::FR
+        );
+
+        let fee_0 = amount_0
+            .mul_div_ceil(pool_state.fee as u64, 1_000_000)
+            .unwrap();
+        let fee_1 = amount_1
+            .mul_div_ceil(pool_state.fee as u64, 1_000_000)
+            .unwrap();
+        let owed_0 = amount_0.checked_add(fee_0).ok_or(ErrorCode::F0)?;
+        let owed_1 = amount_1.checked_add(fee_1).ok_or(ErrorCode::F1)?;
+
+        let balance_0_before = ctx.accounts.vault_0.amount;
+        let balance_1_before = ctx.accounts.vault_1.amount;
+
+        if owed_0 > 0 {
+            token::transfer(
+                CpiContext::new(
+                    ctx.accounts.token_program.to_account_info().clone(),
+                    token::Transfer {
+                        from: ctx.accounts.token_account_0.to_account_info().clone(),
+                        to: ctx.accounts.vault_0.to_account_info().clone(),
+                        authority: ctx.accounts.signer.to_account_info().clone(),
+                    },
+                ),
+                owed_0,
+            )?;
+        }
+        if owed_1 > 0 {
+            token::transfer(
+                CpiContext::new(
+                    ctx.accounts.token_program.to_account_info().clone(),
+                    token::Transfer {
+                        from: ctx.accounts.token_account_1.to_account_info().clone(),
+                        to: ctx.accounts.vault_1.to_account_info().clone(),
+                        authority: ctx.accounts.signer.to_account_info().clone(),
+                    },
+                ),
+                owed_1,
+            )?;
+        }
+
+        ctx.accounts.vault_0.reload()?;
+        ctx.accounts.vault_1.reload()?;
+        require!(
+            balance_0_before.checked_add(owed_0).ok_or(ErrorCode::F0)?
+                <= ctx.accounts.vault_0.amount,
+            ErrorCode::F0
+        );
+        require!(
+            balance_1_before.checked_add(owed_1).ok_or(ErrorCode::F1)?
+                <= ctx.accounts.vault_1.amount,
+            ErrorCode::F1
+        );
+
+        // sub is safe because we know balance_after is gt balance_before by at least fee
+        let paid_0 = ctx.accounts.vault_0.amount - balance_0_before - amount_0;
+        let paid_1 = ctx.accounts.vault_1.amount - balance_1_before - amount_1;
+
+        if paid_0 > 0 {
+            let fees_0 = paid_0
+                .mul_div_floor(
+                    pool_state.fee_protocol_0.into(),
+                    FEE_PROTOCOL_DENOMINATOR.into(),
+                )
+                .unwrap();
+            if fees_0 > 0 {
+                pool_state.protocol_fees_token_0 += fees_0;
+            }
+            pool_state.fee_growth_global_0_x32 += (paid_0 - fees_0)
+                .mul_div_floor(fixed_point_32::Q32, pool_state.liquidity)
+                .unwrap();
+        }
+        if paid_1 > 0 {
+            let fees_1 = paid_1
+                .mul_div_floor(
+                    pool_state.fee_protocol_1.into(),
+                    FEE_PROTOCOL_DENOMINATOR.into(),
+                )
+                .unwrap();
+            if fees_1 > 0 {
+                pool_state.protocol_fees_token_1 += fees_1;
+            }
+            pool_state.fee_growth_global_1_x32 += (paid_1 - fees_1)
+                .mul_div_floor(fixed_point_32::Q32, pool_state.liquidity)
+                .unwrap();
+        }
+        pool_state.flash_amount_0 = 0;
+        pool_state.flash_amount_1 = 0;
+        pool_state.unlocked = true;
+
+        emit!(FlashEvent {
+            pool_state: ctx.accounts.pool_state.key(),
+            sender: ctx.accounts.signer.key(),
+            amount_0,
+            amount_1,
+            paid_0,
+            paid_1,
+        });
+
+        Ok(())
+    }
+
+    // Non fungible position manager
+
+    /// Creates a new position wrapped in a NFT
+    ///
+    /// Tick, bitmap and core position accounts which do not exist yet are created, with rent
+    /// paid by the minter.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - Holds pool, tick, bitmap, position and token accounts
+    /// * `params` - The ticks, token amounts and deadline of the position
+    ///
+    #[access_control(check_deadline(params.deadline))]
     pub fn mint_tokenized_position<'a, 'b, 'c, 'info>(
         ctx: Context<'a, 'b, 'c, 'info, MintTokenizedPosition<'info>>,
-        amount_0_desired: u64,
-        amount_1_desired: u64,
-        amount_0_min: u64,
-        amount_1_min: u64,
-        deadline: i64,
+        params: MintTokenizedPositionParams,
     ) -> Result<()> {
+        let MintTokenizedPositionParams {
+            tick_lower,
+            tick_upper,
+            amount_0_desired,
+            amount_1_desired,
+            amount_0_min,
+            amount_1_min,
+            ..
+        } = params;
+
         // Validate addresses manually, as constraint checks are not applied to internal calls
         let pool_state =
             AccountLoader::<PoolState>::try_from(&ctx.accounts.pool_state.to_account_info())?;
-        let tick_lower_state =
-            AccountLoader::<TickState>::try_from(&ctx.accounts.tick_lower_state.to_account_info())?;
-        let tick_lower = tick_lower_state.load()?.tick;
-        let tick_upper_state =
-            AccountLoader::<TickState>::try_from(&ctx.accounts.tick_upper_state.to_account_info())?;
-        let tick_upper = tick_upper_state.load()?.tick;
 
         let mut accs = MintContext {
             minter: ctx.accounts.minter.clone(),
@@ -1501,9 +1973,10 @@ pub mod cyclos_core {
             vault_0: ctx.accounts.vault_0.clone(),
             vault_1: ctx.accounts.vault_1.clone(),
             recipient: UncheckedAccount::try_from(ctx.accounts.factory_state.to_account_info()),
+            factory_state: ctx.accounts.factory_state.clone(),
             pool_state,
-            tick_lower_state,
-            tick_upper_state,
+            tick_lower_state: ctx.accounts.tick_lower_state.clone(),
+            tick_upper_state: ctx.accounts.tick_upper_state.clone(),
             bitmap_lower_state: ctx.accounts.bitmap_lower_state.clone(),
             bitmap_upper_state: ctx.accounts.bitmap_upper_state.clone(),
             position_state: ctx.accounts.core_position_state.clone(),
@@ -1512,17 +1985,21 @@ pub mod cyclos_core {
             callback_handler: UncheckedAccount::try_from(
                 ctx.accounts.core_program.to_account_info(),
             ),
+            system_program: ctx.accounts.system_program.clone(),
+            payer: ctx.accounts.payer.clone(),
         };
 
         let (liquidity, amount_0, amount_1) = add_liquidity(
             &mut accs,
             ctx.remaining_accounts,
-            amount_0_desired,
-            amount_1_desired,
-            amount_0_min,
-            amount_1_min,
-            tick_lower,
-            tick_upper,
+            &AddLiquidityParams {
+                tick_lower,
+                tick_upper,
+                amount_0_desired,
+                amount_1_desired,
+                amount_0_min,
+                amount_1_min,
+            },
         )?;
 
         // Mint the NFT
@@ -1547,17 +2024,17 @@ pub mod cyclos_core {
 
         tokenized_position.tick_lower = tick_lower; // can read from core position
         tokenized_position.tick_upper = tick_upper;
-        tokenized_position.liquidity = liquidity;
-        tokenized_position.fee_growth_inside_0_last_x32 = AccountLoader::<PositionState>::try_from(
-            &ctx.accounts.core_position_state.to_account_info(),
-        )?
-        .load()?
-        .fee_growth_inside_0_last_x32;
-        tokenized_position.fee_growth_inside_1_last_x32 = AccountLoader::<PositionState>::try_from(
+        let core_position = *AccountLoader::<PositionState>::try_from(
             &ctx.accounts.core_position_state.to_account_info(),
         )?
-        .load()?
-        .fee_growth_inside_1_last_x32;
+        .load()?;
+        tokenized_position.fee_growth_inside_0_last_x32 =
+            core_position.fee_growth_inside_0_last_x32;
+        tokenized_position.fee_growth_inside_1_last_x32 =
+            core_position.fee_growth_inside_1_last_x32;
+        // snapshot reward growths, crediting nothing as the position held no liquidity
+        tokenized_position.update_rewards(&core_position);
+        tokenized_position.liquidity = liquidity;
 
         emit!(IncreaseLiquidityEvent {
             token_id: ctx.accounts.nft_mint.key(),
@@ -1652,13 +2129,8 @@ pub mod cyclos_core {
     ) -> Result<()> {
         let pool_state =
             AccountLoader::<PoolState>::try_from(&ctx.accounts.pool_state.to_account_info())?;
-        let tick_lower_state =
-            AccountLoader::<TickState>::try_from(&ctx.accounts.tick_lower_state.to_account_info())?;
-        let tick_lower = tick_lower_state.load()?.tick;
-
-        let tick_upper_state =
-            AccountLoader::<TickState>::try_from(&ctx.accounts.tick_upper_state.to_account_info())?;
-        let tick_upper = tick_upper_state.load()?.tick;
+        let tick_lower = ctx.accounts.tokenized_position_state.load()?.tick_lower;
+        let tick_upper = ctx.accounts.tokenized_position_state.load()?.tick_upper;
 
         let mut accs = MintContext {
             minter: ctx.accounts.payer.clone(),
@@ -1667,9 +2139,10 @@ pub mod cyclos_core {
             vault_0: ctx.accounts.vault_0.clone(),
             vault_1: ctx.accounts.vault_1.clone(),
             recipient: UncheckedAccount::try_from(ctx.accounts.factory_state.to_account_info()),
+            factory_state: ctx.accounts.factory_state.clone(),
             pool_state,
-            tick_lower_state,
-            tick_upper_state,
+            tick_lower_state: ctx.accounts.tick_lower_state.clone(),
+            tick_upper_state: ctx.accounts.tick_upper_state.clone(),
             bitmap_lower_state: ctx.accounts.bitmap_lower_state.clone(),
             bitmap_upper_state: ctx.accounts.bitmap_upper_state.clone(),
             position_state: ctx.accounts.core_position_state.clone(),
@@ -1678,17 +2151,21 @@ pub mod cyclos_core {
             callback_handler: UncheckedAccount::try_from(
                 ctx.accounts.core_program.to_account_info(),
             ),
+            system_program: ctx.accounts.system_program.clone(),
+            payer: ctx.accounts.payer.clone(),
         };
 
         let (liquidity, amount_0, amount_1) = add_liquidity(
             &mut accs,
             ctx.remaining_accounts,
-            amount_0_desired,
-            amount_1_desired,
-            amount_0_min,
-            amount_1_min,
-            tick_lower,
-            tick_upper,
+            &AddLiquidityParams {
+                tick_lower,
+                tick_upper,
+                amount_0_desired,
+                amount_1_desired,
+                amount_0_min,
+                amount_1_min,
+            },
         )?;
 
         let core_position_state = AccountLoader::<PositionState>::try_from(
@@ -1711,6 +2188,7 @@ pub mod cyclos_core {
 
         position.fee_growth_inside_0_last_x32 = fee_growth_inside_0_last_x32;
         position.fee_growth_inside_1_last_x32 = fee_growth_inside_1_last_x32;
+        position.update_rewards(&*core_position_state.load()?);
         position.liquidity += liquidity;
 
         emit!(IncreaseLiquidityEvent {
@@ -1796,6 +2274,7 @@ pub mod cyclos_core {
 
         tokenized_position.fee_growth_inside_0_last_x32 = fee_growth_inside_0_last_x32;
         tokenized_position.fee_growth_inside_1_last_x32 = fee_growth_inside_1_last_x32;
+        tokenized_position.update_rewards(&updated_core_position);
         tokenized_position.liquidity -= liquidity;
 
         emit!(DecreaseLiquidityEvent {
@@ -1872,6 +2351,7 @@ pub mod cyclos_core {
                 core_position.fee_growth_inside_0_last_x32;
             tokenized_position.fee_growth_inside_1_last_x32 =
                 core_position.fee_growth_inside_1_last_x32;
+            tokenized_position.update_rewards(&core_position);
         }
 
         // adjust amounts to the max for the position
@@ -1919,168 +2399,1558 @@ pub mod cyclos_core {
         Ok(())
     }
 
-    /// Swaps `amount_in` of one token for as much as possible of another token,
-    /// across a single pool
+    /// Collects up to a maximum amount of rewards owed to a specific tokenized position to the recipient
     ///
     /// # Arguments
     ///
-    /// * `ctx` - Accounts required for the swap
-    /// * `deadline` - The time by which the transaction must be included to effect the change
-    /// * `amount_in` - Token amount to be swapped in
-    /// * `amount_out_minimum` - The minimum amount to swap out, which serves as a slippage check
-    /// * `sqrt_price_limit` - The Q32.32 sqrt price √P limit. If zero for one, the price cannot
-    /// be less than this value after the swap.  If one for zero, the price cannot be greater than
-    /// this value after the swap.
+    /// * `ctx` - Validated addresses of the tokenized position, the reward vault and the recipient
+    /// token account. Rewards can be sent to third parties
+    /// * `reward_index` - The slot of the reward to collect
+    /// * `amount_max` - The maximum amount of the reward to collect
     ///
-    #[access_control(check_deadline(deadline))]
-    pub fn exact_input_single<'a, 'b, 'c, 'info>(
-        ctx: Context<'a, 'b, 'c, 'info, ExactInputSingle<'info>>,
-        deadline: i64,
-        amount_in: u64,
-        amount_out_minimum: u64,
-        sqrt_price_limit_x32: u64,
+    #[access_control(is_authorized_for_token(&ctx.accounts.owner_or_delegate, &ctx.accounts.nft_account))]
+    pub fn collect_reward_from_tokenized<'a, 'b, 'c, 'info>(
+        ctx: Context<'a, 'b, 'c, 'info, CollectRewardFromTokenized<'info>>,
+        reward_index: u8,
+        amount_max: u64,
     ) -> Result<()> {
-        let amount_out = exact_input_internal(
-            &mut SwapContext {
-                signer: ctx.accounts.signer.clone(),
-                factory_state: ctx.accounts.factory_state.clone(),
-                input_token_account: ctx.accounts.input_token_account.clone(),
-                output_token_account: ctx.accounts.output_token_account.clone(),
-                input_vault: ctx.accounts.input_vault.clone(),
-                output_vault: ctx.accounts.output_vault.clone(),
-                token_program: ctx.accounts.token_program.clone(),
-                pool_state: ctx.accounts.pool_state.clone(),
-                last_observation_state: ctx.accounts.last_observation_state.clone(),
-                // next_observation_state: ctx.accounts.next_observation_state.clone(),
-                callback_handler: UncheckedAccount::try_from(
-                    ctx.accounts.core_program.to_account_info(),
-                ),
-            },
-            ctx.remaining_accounts,
-            amount_in,
-            sqrt_price_limit_x32,
-        )?;
+        assert!(amount_max > 0);
         require!(
-            amount_out >= amount_out_minimum,
-            ErrorCode::TooLittleReceived
+            (reward_index as usize) < REWARD_NUM,
+            ErrorCode::InvalidRewardIndex
         );
-        Ok(())
-    }
 
-    /// Swaps `amount_in` of one token for as much as possible of another token,
-    /// across the path provided
-    ///
-    /// # Arguments
-    ///
-    /// * `ctx` - Accounts for token transfer and swap route
-    /// * `deadline` - Swap should if fail if past deadline
-    /// * `amount_in` - Token amount to be swapped in
-    /// * `amount_out_minimum` - Panic if output amount is below minimum amount. For slippage.
-    /// * `additional_accounts_per_pool` - Additional observation, bitmap and tick accounts per pool
-    ///
-    #[access_control(check_deadline(deadline))]
-    pub fn exact_input<'a, 'b, 'c, 'info>(
-        ctx: Context<'a, 'b, 'c, 'info, ExactInput<'info>>,
-        deadline: i64,
-        amount_in: u64,
-        amount_out_minimum: u64,
-        additional_accounts_per_pool: Vec<u8>,
-    ) -> Result<()> {
-        let mut remaining_accounts = ctx.remaining_accounts.iter();
+        let mut tokenized_position = ctx.accounts.tokenized_position_state.load_mut()?;
 
-        let mut amount_in_internal = amount_in;
-        let mut input_token_account = ctx.accounts.input_token_account.clone();
-        for i in 0..additional_accounts_per_pool.len() {
-            let pool_state = UncheckedAccount::try_from(remaining_accounts.next().unwrap().clone());
-            let output_token_account =
-                UncheckedAccount::try_from(remaining_accounts.next().unwrap().clone());
-            let input_vault = Box::new(Account::<TokenAccount>::try_from(
-                remaining_accounts.next().unwrap(),
-            )?);
-            let output_vault = Box::new(Account::<TokenAccount>::try_from(
-                remaining_accounts.next().unwrap(),
-            )?);
+        let position_state = AccountLoader::<PositionState>::try_from(
+            &ctx.accounts.core_position_state.to_account_info(),
+        )?;
 
-            amount_in_internal = exact_input_internal(
-                &mut SwapContext {
-                    signer: ctx.accounts.signer.clone(),
-                    factory_state: ctx.accounts.factory_state.clone(),
-                    input_token_account: input_token_account.clone(),
+        // trigger an update of the position rewards owed and reward growth snapshots if it has any liquidity
+        if tokenized_position.liquidity > 0 {
+            let mut core_position_owner = ctx.accounts.factory_state.to_account_info();
+            core_position_owner.is_signer = true;
+            let mut burn_accounts = BurnContext {
+                owner: Signer::try_from(&core_position_owner)?,
+                pool_state: ctx.accounts.pool_state.clone(),
+                tick_lower_state: ctx.accounts.tick_lower_state.clone(),
+                tick_upper_state: ctx.accounts.tick_upper_state.clone(),
+                bitmap_lower_state: ctx.accounts.bitmap_lower_state.clone(),
+                bitmap_upper_state: ctx.accounts.bitmap_upper_state.clone(),
+                position_state,
+                last_observation_state: ctx.accounts.last_observation_state.clone(),
+            };
+            burn(
+                Context::new(
+                    &ID,
+                    &mut burn_accounts,
+                    ctx.remaining_accounts,
+                    BTreeMap::default(),
+                ),
+                0,
+            )?;
+
+            let core_position = *burn_accounts.position_state.load()?.deref();
+            tokenized_position.update_rewards(&core_position);
+        }
+
+        // adjust the amount to the max for the position and the rewards left in the vault
+        let reward_info = &mut tokenized_position.reward_infos[reward_index as usize];
+        let amount = amount_max
+            .min(reward_info.reward_amount_owed)
+            .min(ctx.accounts.reward_vault.amount);
+
+        let mut core_position_owner = ctx.accounts.factory_state.to_account_info().clone();
+        core_position_owner.is_signer = true;
+
+        let mut accounts = CollectRewardContext {
+            owner: Signer::try_from(&core_position_owner)?,
+            pool_state: ctx.accounts.pool_state.clone(),
+            tick_lower_state: ctx.accounts.tick_lower_state.clone(),
+            tick_upper_state: ctx.accounts.tick_upper_state.clone(),
+            position_state: ctx.accounts.core_position_state.clone(),
+            reward_vault: ctx.accounts.reward_vault.clone(),
+            recipient_wallet: ctx.accounts.recipient_wallet.clone(),
+            token_program: ctx.accounts.token_program.clone(),
+        };
+        collect_reward(
+            Context::new(&ID, &mut accounts, &[], BTreeMap::default()),
+            reward_index,
+            amount,
+        )?;
+
+        // the core position may be owed a few less tokens than expected due to rounding down, but
+        // we just subtract the full amount expected so we can burn the token
+        reward_info.reward_amount_owed -= amount;
+
+        emit!(CollectTokenizedRewardEvent {
+            token_id: tokenized_position.mint,
+            reward_index,
+            recipient_wallet: ctx.accounts.recipient_wallet.key(),
+            amount
+        });
+
+        Ok(())
+    }
+
+    /// Burns a tokenized position NFT, and closes its token account and the tokenized position
+    /// account with rent refunded to the holder. The position must hold no liquidity and no tokens
+    /// owed.
+    ///
+    /// The mint authority of the NFT is revoked, so that the emptied mint can never be used
+    /// again. The mint and metaplex metadata stay allocated, as the SPL token program cannot
+    /// close mints and the metadata program cannot close metadata accounts.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - Holds the tokenized position, the NFT mint and the holder's token account
+    ///
+    pub fn burn_tokenized_position(ctx: Context<BurnTokenizedPosition>) -> Result<()> {
+        let tokenized_position = ctx.accounts.tokenized_position_state.load()?;
+        require!(
+            tokenized_position.liquidity == 0
+                && tokenized_position.tokens_owed_0 == 0
+                && tokenized_position.tokens_owed_1 == 0
+                && tokenized_position
+                    .reward_infos
+                    .iter()
+                    .all(|reward_info| reward_info.reward_amount_owed == 0),
+            ErrorCode::NotCleared
+        );
+        drop(tokenized_position);
+
+        token::burn(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Burn {
+                    mint: ctx.accounts.nft_mint.to_account_info(),
+                    to: ctx.accounts.nft_account.to_account_info(),
+                    authority: ctx.accounts.owner.to_account_info(),
+                },
+            ),
+            1,
+        )?;
+        token::close_account(CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            token::CloseAccount {
+                account: ctx.accounts.nft_account.to_account_info(),
+                destination: ctx.accounts.owner.to_account_info(),
+                authority: ctx.accounts.owner.to_account_info(),
+            },
+        ))?;
+
+        // minting is already disabled for NFTs given metadata
+        if ctx.accounts.nft_mint.mint_authority.is_some() {
+            token::set_authority(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    token::SetAuthority {
+                        current_authority: ctx.accounts.factory_state.to_account_info(),
+                        account_or_mint: ctx.accounts.nft_mint.to_account_info(),
+                    },
+                    &[&[&[ctx.accounts.factory_state.load()?.bump] as &[u8]]],
+                ),
+                AuthorityType::MintTokens,
+                None,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Grows a tokenized position created before rewards were added to the current layout
+    /// The signer pays rent for the added space. Its core position must be migrated first
+    ///
+    /// The tokenized position earns rewards from the migration on, as of the reward growth
+    /// snapshots of its core position.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - Grows the tokenized position account, and validates its pool and core position
+    ///
+    pub fn migrate_tokenized_position(ctx: Context<MigrateTokenizedPosition>) -> Result<()> {
+        realloc_program_account(
+            &ctx.accounts.signer.to_account_info(),
+            &ctx.accounts.tokenized_position_state.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            8 + LEGACY_TOKENIZED_POSITION_STATE_LEN,
+            8 + size_of::<TokenizedPositionState>(),
+        )?;
+
+        // the pool and ticks of the position are only readable once the account is grown
+        let mut tokenized_position = ctx.accounts.tokenized_position_state.load_mut()?;
+        let pool_state = ctx.accounts.pool_state.load()?;
+        let core_position = ctx.accounts.core_position_state.load()?;
+        require!(
+            tokenized_position.pool_id == ctx.accounts.pool_state.key(),
+            anchor_lang::error::ErrorCode::ConstraintRaw
+        );
+        let factory_state = ctx.accounts.factory_state.key();
+        let core_position_seeds = [
+            POSITION_SEED.as_bytes(),
+            pool_state.token_0.as_ref(),
+            pool_state.token_1.as_ref(),
+            &pool_state.fee.to_be_bytes(),
+            factory_state.as_ref(),
+            &tokenized_position.tick_lower.to_be_bytes(),
+            &tokenized_position.tick_upper.to_be_bytes(),
+            &[core_position.bump],
+        ];
+        require!(
+            Pubkey::create_program_address(&core_position_seeds, &ID)
+                == Ok(ctx.accounts.core_position_state.key()),
+            anchor_lang::error::ErrorCode::ConstraintSeeds
+        );
+
+        for (reward_info, core_reward_info) in tokenized_position
+            .reward_infos
+            .iter_mut()
+            .zip(core_position.reward_infos)
+        {
+            reward_info.growth_inside_last_x32 = core_reward_info.growth_inside_last_x32;
+        }
+        Ok(())
+    }
+
+    // ---------------------------------------------------------------------
+    // Staker
+    // Position NFTs are deposited with the program and staked in incentives, earning
+    // rewards in proportion to the seconds their liquidity spends in range
+
+    /// Creates an incentive distributing `reward` tokens to positions of a pool staked
+    /// between `start_time` and `end_time`. The rewards are transferred from the creator.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - Holds the pool, the reward token and the accounts created to store the
+    /// incentive and its rewards
+    /// * `start_time` - The time when the incentive program begins
+    /// * `end_time` - The time when rewards stop accruing
+    /// * `reward` - The amount of reward tokens to be distributed
+    ///
+    pub fn create_incentive(
+        ctx: Context<CreateIncentive>,
+        start_time: u32,
+        end_time: u32,
+        reward: u64,
+    ) -> Result<()> {
+        require!(reward > 0, ErrorCode::ZeroReward);
+        let time = oracle::_block_timestamp();
+        require!(
+            time <= start_time
+                && start_time - time <= MAX_INCENTIVE_START_LEAD_TIME
+                && start_time < end_time
+                && end_time - start_time <= MAX_INCENTIVE_DURATION,
+            ErrorCode::InvalidIncentiveTime
+        );
+
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.funder_wallet.to_account_info(),
+                    to: ctx.accounts.reward_vault.to_account_info(),
+                    authority: ctx.accounts.creator.to_account_info(),
+                },
+            ),
+            reward,
+        )?;
+
+        let mut incentive = ctx.accounts.incentive_state.load_init()?;
+        incentive.bump = *ctx.bumps.get("incentive_state").unwrap();
+        incentive.reward_token = ctx.accounts.reward_mint.key();
+        incentive.pool_id = ctx.accounts.pool_state.key();
+        incentive.start_time = start_time;
+        incentive.end_time = end_time;
+        incentive.refundee = ctx.accounts.refundee.key();
+        incentive.vault = ctx.accounts.reward_vault.key();
+        incentive.total_reward_unclaimed = reward;
+
+        emit!(IncentiveCreated {
+            reward_token: ctx.accounts.reward_mint.key(),
+            pool_state: ctx.accounts.pool_state.key(),
+            start_time,
+            end_time,
+            refundee: ctx.accounts.refundee.key(),
+            reward,
+        });
+
+        Ok(())
+    }
+
+    /// Ends an incentive once its end time has passed and every stake was withdrawn, and
+    /// refunds the remaining rewards to the refundee. Can be called by anyone
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - Holds the incentive, its reward vault and the token account of the refundee
+    ///
+    pub fn end_incentive(ctx: Context<EndIncentive>) -> Result<()> {
+        let mut incentive = ctx.accounts.incentive_state.load_mut()?;
+        require!(
+            oracle::_block_timestamp() >= incentive.end_time,
+            ErrorCode::IncentiveNotEnded
+        );
+        require!(incentive.number_of_stakes == 0, ErrorCode::StakesRemain);
+
+        // unclaimed rewards along with any rounding dust belong to the refundee
+        let refund = ctx.accounts.reward_vault.amount;
+        incentive.total_reward_unclaimed = 0;
+
+        let incentive_state_seeds = [
+            INCENTIVE_SEED.as_bytes(),
+            &incentive.reward_token.to_bytes() as &[u8],
+            &incentive.pool_id.to_bytes() as &[u8],
+            &incentive.start_time.to_be_bytes(),
+            &incentive.end_time.to_be_bytes(),
+            &incentive.refundee.to_bytes() as &[u8],
+            &[incentive.bump],
+        ];
+        drop(incentive);
+
+        if refund > 0 {
+            token::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    token::Transfer {
+                        from: ctx.accounts.reward_vault.to_account_info(),
+                        to: ctx.accounts.refundee_wallet.to_account_info(),
+                        authority: ctx.accounts.incentive_state.to_account_info(),
+                    },
+                    &[&incentive_state_seeds[..]],
+                ),
+                refund,
+            )?;
+        }
+
+        emit!(IncentiveEnded {
+            incentive_state: ctx.accounts.incentive_state.key(),
+            refund,
+        });
+
+        Ok(())
+    }
+
+    /// Deposits a position NFT with the program, so that it can be staked. The liquidity
+    /// of the position cannot be decreased, nor its fees collected, until it is withdrawn
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - Holds the NFT and the accounts created to store the deposit and the NFT
+    ///
+    pub fn deposit_token(ctx: Context<DepositToken>) -> Result<()> {
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.nft_account.to_account_info(),
+                    to: ctx.accounts.deposit_vault.to_account_info(),
+                    authority: ctx.accounts.owner.to_account_info(),
+                },
+            ),
+            1,
+        )?;
+
+        let tokenized_position = ctx.accounts.tokenized_position_state.load()?;
+        let mut deposit = ctx.accounts.deposit_state.load_init()?;
+        deposit.bump = *ctx.bumps.get("deposit_state").unwrap();
+        deposit.owner = ctx.accounts.owner.key();
+        deposit.mint = tokenized_position.mint;
+        deposit.pool_id = tokenized_position.pool_id;
+        deposit.tick_lower = tokenized_position.tick_lower;
+        deposit.tick_upper = tokenized_position.tick_upper;
+
+        emit!(DepositTransferred {
+            token_id: tokenized_position.mint,
+            old_owner: Pubkey::default(),
+            new_owner: ctx.accounts.owner.key(),
+        });
+
+        Ok(())
+    }
+
+    /// Withdraws a deposited position NFT which is not staked, closing the deposit
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - Holds the deposit, the NFT and the token account receiving it
+    ///
+    pub fn withdraw_token(ctx: Context<WithdrawToken>) -> Result<()> {
+        let deposit = ctx.accounts.deposit_state.load()?;
+        require!(deposit.number_of_stakes == 0, ErrorCode::StakesRemain);
+        let mint = deposit.mint;
+
+        let deposit_state_seeds = [
+            DEPOSIT_SEED.as_bytes(),
+            &deposit.mint.to_bytes() as &[u8],
+            &[deposit.bump],
+        ];
+        drop(deposit);
+
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.deposit_vault.to_account_info(),
+                    to: ctx.accounts.recipient_wallet.to_account_info(),
+                    authority: ctx.accounts.deposit_state.to_account_info(),
+                },
+                &[&deposit_state_seeds[..]],
+            ),
+            1,
+        )?;
+        token::close_account(CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            token::CloseAccount {
+                account: ctx.accounts.deposit_vault.to_account_info(),
+                destination: ctx.accounts.owner.to_account_info(),
+                authority: ctx.accounts.deposit_state.to_account_info(),
+            },
+            &[&deposit_state_seeds[..]],
+        ))?;
+
+        emit!(DepositTransferred {
+            token_id: mint,
+            old_owner: ctx.accounts.owner.key(),
+            new_owner: Pubkey::default(),
+        });
+
+        Ok(())
+    }
+
+    /// Stakes a deposited position NFT in an active incentive of its pool
+    ///
+    /// The liquidity of the position is recorded, along with a snapshot of the seconds per
+    /// liquidity inside its range from which rewards accrue.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - Holds the incentive, the deposit, the accounts of the position's range and
+    /// the account created to store the stake
+    ///
+    pub fn stake_token(ctx: Context<StakeToken>) -> Result<()> {
+        let time = oracle::_block_timestamp();
+        let mut incentive = ctx.accounts.incentive_state.load_mut()?;
+        require!(
+            time >= incentive.start_time && time < incentive.end_time,
+            ErrorCode::IncentiveNotActive
+        );
+
+        let liquidity = ctx.accounts.tokenized_position_state.load()?.liquidity;
+        require!(liquidity > 0, ErrorCode::ZeroLiquidity);
+
+        let snapshot = _snapshot_cumulatives_inside(
+            &ctx.accounts.pool_state,
+            &ctx.accounts.tick_lower_state,
+            &ctx.accounts.tick_upper_state,
+            &ctx.accounts.latest_observation_state,
+        )?;
+
+        let mut stake = ctx.accounts.stake_state.load_init()?;
+        stake.bump = *ctx.bumps.get("stake_state").unwrap();
+        stake.seconds_per_liquidity_inside_initial_x32 = snapshot.seconds_per_liquidity_inside_x32;
+        stake.liquidity = liquidity;
+
+        incentive.number_of_stakes += 1;
+        let mut deposit = ctx.accounts.deposit_state.load_mut()?;
+        deposit.number_of_stakes += 1;
+
+        emit!(TokenStaked {
+            token_id: deposit.mint,
+            incentive_state: ctx.accounts.incentive_state.key(),
+            liquidity,
+        });
+
+        Ok(())
+    }
+
+    /// Unstakes a position NFT from an incentive, paying out the rewards it earned to its owner
+    ///
+    /// Must be called by the owner of the deposit before the incentive ends. Anyone can unstake
+    /// once it has ended, so that the incentive can be ended and its remaining rewards refunded.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - Holds the incentive, the deposit, the stake to close, the accounts of the
+    /// position's range and the token account of the owner receiving rewards
+    ///
+    pub fn unstake_token(ctx: Context<UnstakeToken>) -> Result<()> {
+        let mut deposit = ctx.accounts.deposit_state.load_mut()?;
+        require!(
+            ctx.accounts.signer.key() == deposit.owner
+                || oracle::_block_timestamp() >= ctx.accounts.incentive_state.load()?.end_time,
+            ErrorCode::NotApproved
+        );
+
+        let snapshot = _snapshot_cumulatives_inside(
+            &ctx.accounts.pool_state,
+            &ctx.accounts.tick_lower_state,
+            &ctx.accounts.tick_upper_state,
+            &ctx.accounts.latest_observation_state,
+        )?;
+        let amount = _claim_stake_reward(
+            &ctx.accounts.incentive_state,
+            &*ctx.accounts.stake_state.load()?,
+            snapshot.seconds_per_liquidity_inside_x32,
+            &ctx.accounts.reward_vault.to_account_info(),
+            &ctx.accounts.recipient_wallet.to_account_info(),
+            &ctx.accounts.token_program.to_account_info(),
+        )?;
+
+        ctx.accounts.incentive_state.load_mut()?.number_of_stakes -= 1;
+        deposit.number_of_stakes -= 1;
+
+        emit!(RewardClaimed {
+            token_id: deposit.mint,
+            incentive_state: ctx.accounts.incentive_state.key(),
+            recipient_wallet: ctx.accounts.recipient_wallet.key(),
+            amount,
+        });
+        emit!(TokenUnstaked {
+            token_id: deposit.mint,
+            incentive_state: ctx.accounts.incentive_state.key(),
+        });
+
+        Ok(())
+    }
+
+    /// Claims the rewards earned by a staked position NFT so far, keeping it staked
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - Holds the incentive, the deposit, the stake, the accounts of the position's
+    /// range and the token account receiving rewards
+    ///
+    pub fn claim_reward(ctx: Context<ClaimReward>) -> Result<()> {
+        let snapshot = _snapshot_cumulatives_inside(
+            &ctx.accounts.pool_state,
+            &ctx.accounts.tick_lower_state,
+            &ctx.accounts.tick_upper_state,
+            &ctx.accounts.latest_observation_state,
+        )?;
+
+        let mut stake = ctx.accounts.stake_state.load_mut()?;
+        let amount = _claim_stake_reward(
+            &ctx.accounts.incentive_state,
+            &stake,
+            snapshot.seconds_per_liquidity_inside_x32,
+            &ctx.accounts.reward_vault.to_account_info(),
+            &ctx.accounts.recipient_wallet.to_account_info(),
+            &ctx.accounts.token_program.to_account_info(),
+        )?;
+        stake.seconds_per_liquidity_inside_initial_x32 = snapshot.seconds_per_liquidity_inside_x32;
+
+        emit!(RewardClaimed {
+            token_id: ctx.accounts.deposit_state.load()?.mint,
+            incentive_state: ctx.accounts.incentive_state.key(),
+            recipient_wallet: ctx.accounts.recipient_wallet.key(),
+            amount,
+        });
+
+        Ok(())
+    }
+
+    // ---------------------------------------------------------------------
+    // Merkle distributor
+    // Rewards computed off-chain are committed to as a Merkle root, and claimed with proofs
+
+    /// Creates a distributor of reward tokens to the liquidity providers of a pool. Claims
+    /// are paid from the vault of the distributor, funded separately
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - Checks for valid owner by looking at signer and factory addresses. Holds the
+    /// pool, the reward token and the accounts created to store the distributor and its rewards.
+    /// * `distribution_index` - Distinguishes distributions of the same token to the same pool
+    /// * `root` - The root of the Merkle tree of claims
+    /// * `max_total_claim` - The maximum amount of tokens that can be claimed
+    ///
+    pub fn new_distributor(
+        ctx: Context<NewDistributor>,
+        distribution_index: u32,
+        root: [u8; 32],
+        max_total_claim: u64,
+    ) -> Result<()> {
+        let mut distributor = ctx.accounts.distributor_state.load_init()?;
+        distributor.bump = *ctx.bumps.get("distributor_state").unwrap();
+        distributor.pool_id = ctx.accounts.pool_state.key();
+        distributor.reward_mint = ctx.accounts.reward_mint.key();
+        distributor.distribution_index = distribution_index;
+        distributor.vault = ctx.accounts.vault.key();
+        distributor.root = root;
+        distributor.max_total_claim = max_total_claim;
+
+        emit!(DistributorCreated {
+            distributor_state: ctx.accounts.distributor_state.key(),
+            pool_state: ctx.accounts.pool_state.key(),
+            reward_mint: ctx.accounts.reward_mint.key(),
+            distribution_index,
+            root,
+            max_total_claim,
+        });
+
+        Ok(())
+    }
+
+    /// Claims tokens owed to the signer by a distributor, proving the claim against its root.
+    /// Each leaf can only be claimed once
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - Holds the distributor, its vault and the account created to mark the leaf
+    /// as claimed
+    /// * `index` - The position of the claim among the leaves
+    /// * `amount` - The amount of tokens owed to the claimant
+    /// * `proof` - The siblings of the nodes on the path from the leaf to the root
+    ///
+    pub fn claim(
+        ctx: Context<ClaimContext>,
+        index: u64,
+        amount: u64,
+        proof: Vec<[u8; 32]>,
+    ) -> Result<()> {
+        let mut distributor = ctx.accounts.distributor_state.load_mut()?;
+        require!(
+            distributor::verify_proof(
+                &proof,
+                &distributor.root,
+                distributor::leaf(index, &ctx.accounts.claimant.key(), amount),
+            ),
+            ErrorCode::InvalidProof
+        );
+
+        let mut claim_status = ctx.accounts.claim_status_state.load_init()?;
+        claim_status.bump = *ctx.bumps.get("claim_status_state").unwrap();
+        claim_status.claimant = ctx.accounts.claimant.key();
+        claim_status.amount = amount;
+
+        distributor.total_amount_claimed = distributor
+            .total_amount_claimed
+            .checked_add(amount)
+            .ok_or(ErrorCode::ExceededMaxClaim)?;
+        require!(
+            distributor.total_amount_claimed <= distributor.max_total_claim,
+            ErrorCode::ExceededMaxClaim
+        );
+        distributor.num_nodes_claimed += 1;
+
+        let distributor_state_seeds = [
+            DISTRIBUTOR_SEED.as_bytes(),
+            &distributor.pool_id.to_bytes() as &[u8],
+            &distributor.reward_mint.to_bytes() as &[u8],
+            &distributor.distribution_index.to_be_bytes(),
+            &[distributor.bump],
+        ];
+        drop(distributor);
+
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.vault.to_account_info(),
+                    to: ctx.accounts.recipient_wallet.to_account_info(),
+                    authority: ctx.accounts.distributor_state.to_account_info(),
+                },
+                &[&distributor_state_seeds[..]],
+            ),
+            amount,
+        )?;
+
+        emit!(ClaimEvent {
+            distributor_state: ctx.accounts.distributor_state.key(),
+            index,
+            claimant: ctx.accounts.claimant.key(),
+            amount,
+        });
+
+        Ok(())
+    }
+
+    /// Swaps `amount_in` of one token for as much as possible of another token,
+    /// across a single pool
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - Accounts required for the swap. Governance tokens locked by the signer can be
+    /// passed last, for a swap fee discount
+    /// * `deadline` - The time by which the transaction must be included to effect the change
+    /// * `amount_in` - Token amount to be swapped in
+    /// * `amount_out_minimum` - The minimum amount to swap out, which serves as a slippage check
+    /// * `sqrt_price_limit` - The Q32.32 sqrt price √P limit. If zero for one, the price cannot
+    /// be less than this value after the swap.  If one for zero, the price cannot be greater than
+    /// this value after the swap.
+    ///
+    #[access_control(check_deadline(deadline))]
+    pub fn exact_input_single<'a, 'b, 'c, 'info>(
+        ctx: Context<'a, 'b, 'c, 'info, ExactInputSingle<'info>>,
+        deadline: i64,
+        amount_in: u64,
+        amount_out_minimum: u64,
+        sqrt_price_limit_x32: u64,
+    ) -> Result<()> {
+        let (remaining_accounts, escrow) = split_escrow(ctx.remaining_accounts);
+        let amount_out = exact_input_internal(
+            &mut SwapContext {
+                signer: ctx.accounts.signer.clone(),
+                factory_state: ctx.accounts.factory_state.clone(),
+                input_token_account: ctx.accounts.input_token_account.clone(),
+                output_token_account: ctx.accounts.output_token_account.clone(),
+                input_vault: ctx.accounts.input_vault.clone(),
+                output_vault: ctx.accounts.output_vault.clone(),
+                token_program: ctx.accounts.token_program.clone(),
+                pool_state: ctx.accounts.pool_state.clone(),
+                last_observation_state: ctx.accounts.last_observation_state.clone(),
+                // next_observation_state: ctx.accounts.next_observation_state.clone(),
+                callback_handler: UncheckedAccount::try_from(
+                    ctx.accounts.core_program.to_account_info(),
+                ),
+            },
+            remaining_accounts,
+            amount_in,
+            sqrt_price_limit_x32,
+            escrow,
+        )?;
+        require!(
+            amount_out >= amount_out_minimum,
+            ErrorCode::TooLittleReceived
+        );
+        Ok(())
+    }
+
+    /// Swaps `amount_in` of one token for as much as possible of another token,
+    /// across the path provided
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - Accounts for token transfer and swap route
+    /// * `deadline` - Swap should if fail if past deadline
+    /// * `amount_in` - Token amount to be swapped in
+    /// * `amount_out_minimum` - Panic if output amount is below minimum amount. For slippage.
+    /// * `additional_accounts_per_pool` - Additional observation, bitmap and tick accounts per pool.
+    /// Each pool is listed as its pool, output token account, input vault, output vault and latest
+    /// observation accounts, followed by this many accounts. Governance tokens locked by the
+    /// signer can be passed after the last pool, for a swap fee discount
+    ///
+    #[access_control(check_deadline(deadline))]
+    pub fn exact_input<'a, 'b, 'c, 'info>(
+        ctx: Context<'a, 'b, 'c, 'info, ExactInput<'info>>,
+        deadline: i64,
+        amount_in: u64,
+        amount_out_minimum: u64,
+        additional_accounts_per_pool: Vec<u8>,
+    ) -> Result<()> {
+        let (remaining_accounts, escrow) = split_escrow(ctx.remaining_accounts);
+        let mut remaining_accounts = remaining_accounts.iter();
+
+        let mut amount_in_internal = amount_in;
+        let mut input_token_account = ctx.accounts.input_token_account.clone();
+        for additional_accounts in additional_accounts_per_pool {
+            let pool_state = UncheckedAccount::try_from(
+                remaining_accounts
+                    .next()
+                    .ok_or(ErrorCode::InvalidSwapPath)?
+                    .clone(),
+            );
+            let output_token_account = UncheckedAccount::try_from(
+                remaining_accounts
+                    .next()
+                    .ok_or(ErrorCode::InvalidSwapPath)?
+                    .clone(),
+            );
+            let input_vault = Box::new(Account::<TokenAccount>::try_from(
+                remaining_accounts
+                    .next()
+                    .ok_or(ErrorCode::InvalidSwapPath)?,
+            )?);
+            let output_vault = Box::new(Account::<TokenAccount>::try_from(
+                remaining_accounts
+                    .next()
+                    .ok_or(ErrorCode::InvalidSwapPath)?,
+            )?);
+            let last_observation_state = UncheckedAccount::try_from(
+                remaining_accounts
+                    .next()
+                    .ok_or(ErrorCode::InvalidSwapPath)?
+                    .clone(),
+            );
+            let (swap_accounts, path) =
+                split_path(remaining_accounts.as_slice(), additional_accounts)?;
+            remaining_accounts = path.iter();
+
+            amount_in_internal = exact_input_internal(
+                &mut SwapContext {
+                    signer: ctx.accounts.signer.clone(),
+                    factory_state: ctx.accounts.factory_state.clone(),
+                    input_token_account: input_token_account.clone(),
                     pool_state,
                     output_token_account: output_token_account.clone(),
                     input_vault,
                     output_vault,
-                    last_observation_state: UncheckedAccount::try_from(
-                        remaining_accounts.next().unwrap().clone(),
-                    ),
+                    last_observation_state,
                     token_program: ctx.accounts.token_program.clone(),
                     callback_handler: UncheckedAccount::try_from(
                         ctx.accounts.core_program.to_account_info(),
                     ),
                 },
-                remaining_accounts.as_slice(),
+                swap_accounts,
                 amount_in_internal,
                 0,
+                escrow,
+            )?;
+
+            // output token account is the new input
+            input_token_account = output_token_account;
+        }
+        require!(
+            amount_in_internal >= amount_out_minimum,
+            ErrorCode::TooLittleReceived
+        );
+
+        Ok(())
+    }
+
+    /// Swaps as little as possible of one token for `amount_out` of another token,
+    /// across a single pool
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - Token and pool accounts for swap. Governance tokens locked by the signer can be
+    /// passed last, for a swap fee discount
+    /// * `deadline` - Swap should if fail if past deadline
+    /// * `amount_out` - Token amount to be swapped out
+    /// * `amount_in_maximum` - For slippage. Panic if required input exceeds max limit.
+    /// * `sqrt_price_limit` - Limit price √P for slippage
+    ///
+    #[access_control(check_deadline(deadline))]
+    pub fn exact_output_single<'a, 'b, 'c, 'info>(
+        ctx: Context<'a, 'b, 'c, 'info, ExactOutputSingle<'info>>,
+        deadline: i64,
+        amount_out: u64,
+        amount_in_maximum: u64,
+        sqrt_price_limit_x32: u64,
+    ) -> Result<()> {
+        let (remaining_accounts, escrow) = split_escrow(ctx.remaining_accounts);
+        let amount_in = exact_output_internal(
+            &mut SwapContext {
+                signer: ctx.accounts.signer.clone(),
+                factory_state: ctx.accounts.factory_state.clone(),
+                input_token_account: ctx.accounts.input_token_account.clone(),
+                output_token_account: ctx.accounts.output_token_account.clone(),
+                input_vault: ctx.accounts.input_vault.clone(),
+                output_vault: ctx.accounts.output_vault.clone(),
+                token_program: ctx.accounts.token_program.clone(),
+                pool_state: ctx.accounts.pool_state.clone(),
+                last_observation_state: ctx.accounts.last_observation_state.clone(),
+                callback_handler: UncheckedAccount::try_from(
+                    ctx.accounts.core_program.to_account_info(),
+                ),
+            },
+            remaining_accounts,
+            amount_out,
+            sqrt_price_limit_x32,
+            escrow,
+            &[],
+            &[],
+        )?;
+        require!(amount_in <= amount_in_maximum, ErrorCode::TooMuchRequested);
+        Ok(())
+    }
+
+    /// Swaps as little as possible of one token for `amount_out` of another
+    /// along the specified path (reversed)
+    ///
+    /// The pool paying out to `output_token_account` is swapped first. The amount it is owed is
+    /// sourced in its swap callback from the next pool, and so on until the last pool, which
+    /// is paid from `input_token_account`.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - Accounts for token transfer and swap route. Remaining accounts should contain
+    /// the pool, input vault, output vault and latest observation accounts for each pool, in
+    /// reverse order of the path, each followed by the bitmap, tick and observation accounts
+    /// needed to swap it. Governance tokens locked by the signer can be passed after the last
+    /// pool, for a swap fee discount on every pool
+    /// * `deadline` - Swap should if fail if past deadline
+    /// * `amount_out` - Token amount to be swapped out
+    /// * `amount_in_maximum` - For slippage. Panic if required input exceeds max limit.
+    /// * `additional_accounts_per_pool` - Bitmap, tick and observation accounts of each pool, in
+    /// reverse order of the path
+    ///
+    #[access_control(check_deadline(deadline))]
+    pub fn exact_output<'a, 'b, 'c, 'info>(
+        ctx: Context<'a, 'b, 'c, 'info, ExactOutput<'info>>,
+        deadline: i64,
+        amount_out: u64,
+        amount_in_maximum: u64,
+        additional_accounts_per_pool: Vec<u8>,
+    ) -> Result<()> {
+        let (remaining_accounts, escrow) = split_escrow(ctx.remaining_accounts);
+        let hop = ExactOutputHop::split(remaining_accounts, &additional_accounts_per_pool)?;
+
+        let balance_before = ctx.accounts.input_token_account.amount;
+        exact_output_internal(
+            &mut SwapContext {
+                signer: ctx.accounts.signer.clone(),
+                factory_state: ctx.accounts.factory_state.clone(),
+                input_token_account: UncheckedAccount::try_from(
+                    ctx.accounts.input_token_account.to_account_info(),
+                ),
+                output_token_account: ctx.accounts.output_token_account.clone(),
+                input_vault: hop.input_vault,
+                output_vault: hop.output_vault,
+                token_program: ctx.accounts.token_program.clone(),
+                pool_state: hop.pool_state,
+                last_observation_state: hop.last_observation_state,
+                callback_handler: UncheckedAccount::try_from(
+                    ctx.accounts.core_program.to_account_info(),
+                ),
+            },
+            hop.swap_accounts,
+            amount_out,
+            0,
+            escrow,
+            hop.path,
+            &additional_accounts_per_pool[1..],
+        )?;
+
+        // the input is paid by the last pool of the path, when paying the swap before it
+        ctx.accounts.input_token_account.reload()?;
+        let amount_in = balance_before - ctx.accounts.input_token_account.amount;
+        require!(amount_in <= amount_in_maximum, ErrorCode::TooMuchRequested);
+
+        Ok(())
+    }
+
+    /// Returns the amount out received for a given exact input swap without executing the swap
+    ///
+    /// Runs the swap loop as a dry run, without token transfers or state writes. Meant to be
+    /// called through `simulateTransaction`. The result is set as return data, a borsh serialized
+    /// `(amount_in, amount_out, sqrt_price_after, ticks_crossed)` tuple of type `(u64, u64, u64, u32)`.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - Holds the pool and its input vault. Remaining accounts are the bitmap and tick
+    /// accounts of the swap, as in `exact_input_single`. An escrow passed last quotes the discounted fee
+    /// * `amount_in` - Token amount to be swapped
+    /// * `sqrt_price_limit_x32` - The Q32.32 price limit. Pass 0 to swap without a limit
+    ///
+    pub fn quote_exact_input_single<'a, 'b, 'c, 'info>(
+        ctx: Context<'a, 'b, 'c, 'info, QuoteSingle<'info>>,
+        amount_in: u64,
+        sqrt_price_limit_x32: u64,
+    ) -> Result<()> {
+        let (remaining_accounts, escrow) = split_escrow(ctx.remaining_accounts);
+        let quote = quote_internal(
+            ctx.accounts,
+            remaining_accounts,
+            i64::try_from(amount_in).unwrap(),
+            sqrt_price_limit_x32,
+            escrow,
+        )?;
+        solana_program::program::set_return_data(&quote.try_to_vec()?);
+        Ok(())
+    }
+
+    /// Returns the amount in required to receive a given exact output amount without executing the swap
+    ///
+    /// Runs the swap loop as a dry run, without token transfers or state writes. Meant to be
+    /// called through `simulateTransaction`. The result is set as return data, a borsh serialized
+    /// `(amount_in, amount_out, sqrt_price_after, ticks_crossed)` tuple of type `(u64, u64, u64, u32)`.
+    /// `amount_out` falls short of the requested amount if the price limit is reached.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - Holds the pool and its input vault. Remaining accounts are the bitmap and tick
+    /// accounts of the swap, as in `exact_output_single`. An escrow passed last quotes the discounted fee
+    /// * `amount_out` - The desired output amount
+    /// * `sqrt_price_limit_x32` - The Q32.32 price limit. Pass 0 to swap without a limit
+    ///
+    pub fn quote_exact_output_single<'a, 'b, 'c, 'info>(
+        ctx: Context<'a, 'b, 'c, 'info, QuoteSingle<'info>>,
+        amount_out: u64,
+        sqrt_price_limit_x32: u64,
+    ) -> Result<()> {
+        let (remaining_accounts, escrow) = split_escrow(ctx.remaining_accounts);
+        let quote = quote_internal(
+            ctx.accounts,
+            remaining_accounts,
+            -i64::try_from(amount_out).unwrap(),
+            sqrt_price_limit_x32,
+            escrow,
+        )?;
+        solana_program::program::set_return_data(&quote.try_to_vec()?);
+        Ok(())
+    }
+}
+
+/// Quotes a swap on a single pool with a dry run of the swap loop
+///
+/// Returns `(amount_in, amount_out, sqrt_price_after, ticks_crossed)`
+///
+/// # Arguments
+///
+/// * `accounts` - Holds the factory, the pool and its input vault
+/// * `remaining_accounts` - Bitmap and tick accounts of the swap
+/// * `amount_specified` - The amount of the swap, which implicitly configures the swap as exact input (positive), or exact output (negative)
+/// * `sqrt_price_limit_x32` - The Q32.32 price limit. Pass 0 to swap without a limit
+/// * `escrow` - Governance tokens locked by the trader, quoting the fee discounted as in a swap
+///
+pub fn quote_internal<'info>(
+    accounts: &QuoteSingle<'info>,
+    remaining_accounts: &[AccountInfo<'info>],
+    amount_specified: i64,
+    sqrt_price_limit_x32: u64,
+    escrow: Option<&AccountInfo<'info>>,
+) -> Result<(u64, u64, u64, u32)> {
+    require!(amount_specified != 0, ErrorCode::AS);
+
+    let pool = accounts.pool_state.load()?;
+    let zero_for_one = accounts.input_vault.mint == pool.token_0;
+    let sqrt_price_limit_x32 = if sqrt_price_limit_x32 == 0 {
+        if zero_for_one {
+            tick_math::MIN_SQRT_RATIO + 1
+        } else {
+            tick_math::MAX_SQRT_RATIO - 1
+        }
+    } else {
+        sqrt_price_limit_x32
+    };
+
+    require!(pool.unlocked, ErrorCode::LOK);
+    require!(
+        if zero_for_one {
+            sqrt_price_limit_x32 < pool.sqrt_price_x32
+                && sqrt_price_limit_x32 > tick_math::MIN_SQRT_RATIO
+        } else {
+            sqrt_price_limit_x32 > pool.sqrt_price_x32
+                && sqrt_price_limit_x32 < tick_math::MAX_SQRT_RATIO
+        },
+        ErrorCode::SPL
+    );
+
+    // no signer to own the escrow, so the quote trusts the trader passing it
+    let escrow = escrow.map(Escrow::load).transpose()?;
+    let fee = accounts.factory_state.load()?.discounted_fee(
+        pool.fee,
+        escrow.as_ref(),
+        Clock::get()?.unix_timestamp,
+    );
+
+    let mut cache = SwapCache {
+        fee,
+        liquidity_start: pool.liquidity,
+        block_timestamp: oracle::_block_timestamp(),
+        // the protocol fee is taken out of the swap fee, so it does not change swap amounts
+        fee_protocol: 0,
+        seconds_per_liquidity_cumulative_x32: 0,
+        tick_cumulative: 0,
+        computed_latest_observation: false,
+        reward_growths_global_x32: pool.reward_growths_global_x32(),
+    };
+    let mut state = SwapState {
+        amount_specified_remaining: amount_specified,
+        amount_calculated: 0,
+        sqrt_price_x32: pool.sqrt_price_x32,
+        tick: pool.tick,
+        fee_growth_global_x32: if zero_for_one {
+            pool.fee_growth_global_0_x32
+        } else {
+            pool.fee_growth_global_1_x32
+        },
+        protocol_fee: 0,
+        liquidity: cache.liquidity_start,
+    };
+
+    let ticks_crossed = swap_steps(
+        &pool,
+        &mut cache,
+        &mut state,
+        &mut AccountTickProvider::new(&pool, &mut remaining_accounts.iter(), false),
+        &NoObservation,
+        zero_for_one,
+        sqrt_price_limit_x32,
+    )?;
+
+    let amount_specified_used = amount_specified - state.amount_specified_remaining;
+    let (amount_in, amount_out) = if amount_specified > 0 {
+        (amount_specified_used, state.amount_calculated.neg())
+    } else {
+        (state.amount_calculated, amount_specified_used.neg())
+    };
+
+    Ok((
+        amount_in as u64,
+        amount_out as u64,
+        state.sqrt_price_x32,
+        ticks_crossed,
+    ))
+}
+
+/// Swap token_0 for token_1, or token_1 for token_0
+///
+/// Implements the `swap` instruction. Exact output paths routed by the core pass the accounts
+/// of the pools left to swap, which pay for this swap.
+///
+/// # Arguments
+///
+/// * `ctx` - Accounts required for the swap, as in `swap`
+/// * `amount_specified` - The amount of the swap, which implicitly configures the swap as exact input (positive),
+///   or exact output (negative)
+/// * `sqrt_price_limit_x32` - The Q32.32 sqrt price √P limit
+/// * `escrow` - Governance tokens locked by the signer, earning a swap fee discount
+/// * `path` - Accounts of the pools left in a reversed exact output path
+/// * `additional_accounts_per_pool` - Bitmap, tick and observation account count of each pool
+///   left in the path
+///
+pub fn swap_internal<'info>(
+    ctx: Context<'_, '_, '_, 'info, SwapContext<'info>>,
+    amount_specified: i64,
+    sqrt_price_limit_x32: u64,
+    escrow: Option<&AccountInfo<'info>>,
+    path: &[AccountInfo<'info>],
+    additional_accounts_per_pool: &[u8],
+) -> Result<()> {
+    require!(amount_specified != 0, ErrorCode::AS);
+
+    let pool_loader =
+        AccountLoader::<PoolState>::try_from(&ctx.accounts.pool_state.to_account_info())?;
+    let mut pool = pool_loader.load_mut()?;
+
+    let factory_state =
+        AccountLoader::<FactoryState>::try_from(&ctx.accounts.factory_state.to_account_info())?;
+    check_not_paused(&*factory_state.load()?, &pool, PAUSE_SWAP)?;
+
+    // traders locking governance tokens pay a discounted fee, shrinking the protocol fee alike
+    let escrow_account = escrow;
+    let escrow = escrow_account.map(Escrow::load).transpose()?;
+    if let Some(escrow) = &escrow {
+        require!(
+            escrow.owner == ctx.accounts.signer.key(),
+            ErrorCode::EscrowNotOwned
+        );
+    }
+    let fee = factory_state.load()?.discounted_fee(
+        pool.fee,
+        escrow.as_ref(),
+        Clock::get()?.unix_timestamp,
+    );
+
+    let input_token_account =
+        Account::<TokenAccount>::try_from(&ctx.accounts.input_token_account)?;
+    let output_token_account =
+        Account::<TokenAccount>::try_from(&ctx.accounts.output_token_account)?;
+
+    let zero_for_one = ctx.accounts.input_vault.mint == pool.token_0;
+
+    let (token_account_0, token_account_1, mut vault_0, mut vault_1) = if zero_for_one {
+        (
+            input_token_account,
+            output_token_account,
+            ctx.accounts.input_vault.clone(),
+            ctx.accounts.output_vault.clone(),
+        )
+    } else {
+        (
+            output_token_account,
+            input_token_account,
+            ctx.accounts.output_vault.clone(),
+            ctx.accounts.input_vault.clone(),
+        )
+    };
+    assert!(vault_0.key() == get_associated_token_address(&pool_loader.key(), &pool.token_0));
+    assert!(vault_1.key() == get_associated_token_address(&pool_loader.key(), &pool.token_1));
+
+    let last_observation_state = AccountLoader::<ObservationState>::try_from(
+        &ctx.accounts.last_observation_state.to_account_info(),
+    )?;
+    pool.validate_observation_address(
+        &ctx.accounts.last_observation_state.key(),
+        last_observation_state.load()?.bump,
+        false,
+    )?;
+
+    require!(pool.unlocked, ErrorCode::LOK);
+    require!(
+        if zero_for_one {
+            sqrt_price_limit_x32 < pool.sqrt_price_x32
+                && sqrt_price_limit_x32 > tick_math::MIN_SQRT_RATIO
+        } else {
+            sqrt_price_limit_x32 > pool.sqrt_price_x32
+                && sqrt_price_limit_x32 < tick_math::MAX_SQRT_RATIO
+        },
+        ErrorCode::SPL
+    );
+
+    pool.unlocked = false;
+    let block_timestamp = oracle::_block_timestamp();
+    pool.update_reward_infos(block_timestamp);
+    let mut cache = SwapCache {
+        fee,
+        liquidity_start: pool.liquidity,
+        block_timestamp,
+        fee_protocol: if zero_for_one {
+            pool.fee_protocol_0
+        } else {
+            pool.fee_protocol_1
+        },
+        seconds_per_liquidity_cumulative_x32: 0,
+        tick_cumulative: 0,
+        computed_latest_observation: false,
+        reward_growths_global_x32: pool.reward_growths_global_x32(),
+    };
+
+    let exact_input = amount_specified > 0;
+
+    let mut state = SwapState {
+        amount_specified_remaining: amount_specified,
+        amount_calculated: 0,
+        sqrt_price_x32: pool.sqrt_price_x32,
+        tick: pool.tick,
+        fee_growth_global_x32: if zero_for_one {
+            pool.fee_growth_global_0_x32
+        } else {
+            pool.fee_growth_global_1_x32
+        },
+        protocol_fee: 0,
+        liquidity: cache.liquidity_start,
+    };
+
+    let latest_observation = last_observation_state.load_mut()?;
+    let mut remaining_accounts = ctx.remaining_accounts.iter();
+
+    swap_steps(
+        &pool,
+        &mut cache,
+        &mut state,
+        &mut AccountTickProvider::new(&pool, &mut remaining_accounts, true),
+        &*latest_observation,
+        zero_for_one,
+        sqrt_price_limit_x32,
+    )?;
+
+    let partition_current_timestamp = cache.block_timestamp / 14;
+    let partition_last_timestamp = latest_observation.block_timestamp / 14;
+    drop(latest_observation);
+
+    // update tick and write an oracle entry if the tick changes
+    if state.tick != pool.tick {
+        // use the next observation account and update pool observation index if block time falls
+        // in another partition
+        let next_observation_state;
+        let mut next_observation = if partition_current_timestamp > partition_last_timestamp {
+            msg!("loading observation");
+            // bitmap and tick accounts left unread by the swap come first
+            next_observation_state = remaining_accounts
+                .find_map(|account| AccountLoader::<ObservationState>::try_from(account).ok())
+                .ok_or(anchor_lang::error::ErrorCode::AccountNotEnoughKeys)?;
+            let next_observation = next_observation_state.load_mut()?;
+            msg!("observation loaded");
+
+            pool.validate_observation_address(
+                &next_observation_state.key(),
+                next_observation.bump,
+                true,
             )?;
 
-            if i < additional_accounts_per_pool.len() - 1 {
-                // reach accounts needed for the next swap
-                for _j in 0..additional_accounts_per_pool[i] {
-                    remaining_accounts.next();
-                }
-                // output token account is the new input
-                input_token_account = output_token_account;
-            }
+            next_observation
+        } else {
+            last_observation_state.load_mut()?
+        };
+        pool.tick = state.tick;
+        pool.observation_cardinality_next = next_observation.update(
+            cache.block_timestamp,
+            pool.tick,
+            cache.liquidity_start,
+            pool.observation_cardinality,
+            pool.observation_cardinality_next,
+        );
+    }
+    pool.sqrt_price_x32 = state.sqrt_price_x32;
+
+    // update liquidity if it changed
+    if cache.liquidity_start != state.liquidity {
+        pool.liquidity = state.liquidity;
+    }
+
+    // update fee growth global and, if necessary, protocol fees
+    // overflow is acceptable, protocol has to withdraw before it hit u64::MAX fees
+    if zero_for_one {
+        pool.fee_growth_global_0_x32 = state.fee_growth_global_x32;
+        if state.protocol_fee > 0 {
+            pool.protocol_fees_token_0 += state.protocol_fee;
+        }
+    } else {
+        pool.fee_growth_global_1_x32 = state.fee_growth_global_x32;
+        if state.protocol_fee > 0 {
+            pool.protocol_fees_token_1 += state.protocol_fee;
+        }
+    }
+
+    let (amount_0, amount_1) = if zero_for_one == exact_input {
+        (
+            amount_specified - state.amount_specified_remaining,
+            state.amount_calculated,
+        )
+    } else {
+        (
+            state.amount_calculated,
+            amount_specified - state.amount_specified_remaining,
+        )
+    };
+
+    // do the transfers and collect payment
+    let pool_state_seeds = [
+        POOL_SEED.as_bytes(),
+        &pool.token_0.to_bytes() as &[u8],
+        &pool.token_1.to_bytes() as &[u8],
+        &pool.fee.to_be_bytes(),
+        &[pool.bump],
+    ];
+    drop(pool);
+
+    msg!("vault balances {} {}", vault_0.amount, vault_1.amount);
+
+    if zero_for_one {
+        if amount_1 < 0 {
+            msg!("paying {}", amount_1.neg());
+            token::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info().clone(),
+                    token::Transfer {
+                        from: vault_1.to_account_info().clone(),
+                        to: token_account_1.to_account_info().clone(),
+                        authority: ctx.accounts.pool_state.to_account_info().clone(),
+                    },
+                    &[&pool_state_seeds[..]],
+                ),
+                amount_1.neg() as u64,
+            )?;
+        }
+        let balance_0_before = vault_0.amount;
+
+        // transfer tokens to pool in callback
+        pay_swap(
+            ctx.accounts,
+            amount_0,
+            amount_1,
+            escrow_account,
+            path,
+            additional_accounts_per_pool,
+        )?;
+        vault_0.reload()?;
+        require!(
+            balance_0_before.checked_add(amount_0 as u64).unwrap() <= vault_0.amount,
+            ErrorCode::IIA
+        );
+    } else {
+        if amount_0 < 0 {
+            msg!("paying {}", amount_0.neg());
+            token::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info().clone(),
+                    token::Transfer {
+                        from: vault_0.to_account_info().clone(),
+                        to: token_account_0.to_account_info().clone(),
+                        authority: ctx.accounts.pool_state.to_account_info().clone(),
+                    },
+                    &[&pool_state_seeds[..]],
+                ),
+                amount_0.neg() as u64,
+            )?;
         }
+        let balance_1_before = vault_1.amount;
+        // transfer tokens to pool in callback
+        pay_swap(
+            ctx.accounts,
+            amount_0,
+            amount_1,
+            escrow_account,
+            path,
+            additional_accounts_per_pool,
+        )?;
+        vault_1.reload()?;
         require!(
-            amount_in_internal >= amount_out_minimum,
-            ErrorCode::TooLittleReceived
+            balance_1_before.checked_add(amount_1 as u64).unwrap() <= vault_1.amount,
+            ErrorCode::IIA
         );
+    }
 
-        Ok(())
+    emit!(SwapEvent {
+        pool_state: pool_loader.key(),
+        sender: ctx.accounts.signer.key(),
+        token_account_0: token_account_0.key(),
+        token_account_1: token_account_1.key(),
+        amount_0,
+        amount_1,
+        sqrt_price_x32: state.sqrt_price_x32,
+        liquidity: state.liquidity,
+        tick: state.tick
+    });
+    pool_loader.load_mut()?.unlocked = true;
+
+    Ok(())
+}
+
+/// Pays the tokens owed to a pool for a swap
+///
+/// Swaps with the core as their callback handler are paid without a cross program invocation.
+/// The amount owed is sourced by swapping the next pool of an exact output path, or paid by
+/// the signer once the path is exhausted. Other callback handlers are sent `swap_callback`.
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the swap being paid
+/// * `amount_0_delta` - The amount of token_0 owed to the pool, if positive
+/// * `amount_1_delta` - The amount of token_1 owed to the pool, if positive
+/// * `escrow` - Governance tokens locked by the signer, discounting the swaps of the path
+/// * `path` - Accounts of the pools left in a reversed exact output path
+/// * `additional_accounts_per_pool` - Bitmap, tick and observation account count of each pool
+///   left in the path
+///
+pub fn pay_swap<'info>(
+    accounts: &SwapContext<'info>,
+    amount_0_delta: i64,
+    amount_1_delta: i64,
+    escrow: Option<&AccountInfo<'info>>,
+    path: &[AccountInfo<'info>],
+    additional_accounts_per_pool: &[u8],
+) -> Result<()> {
+    if accounts.callback_handler.key() != crate::ID {
+        let swap_callback_ix = instruction::SwapCallback {
+            amount_0_delta,
+            amount_1_delta,
+        };
+        let ix = Instruction::new_with_bytes(
+            accounts.callback_handler.key(),
+            &swap_callback_ix.data(),
+            accounts.to_account_metas(None),
+        );
+        solana_program::program::invoke(&ix, &accounts.to_account_infos())?;
+        return Ok(());
+    }
+
+    let amount_to_pay = if amount_0_delta > 0 {
+        amount_0_delta as u64
+    } else {
+        amount_1_delta as u64
+    };
+    if additional_accounts_per_pool.is_empty() {
+        msg!("amount to pay {}, delta 0 {}, delta 1 {}", amount_to_pay, amount_0_delta, amount_1_delta);
+        return token::transfer(
+            CpiContext::new(
+                accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: accounts.input_token_account.to_account_info(),
+                    to: accounts.input_vault.to_account_info(),
+                    authority: accounts.signer.to_account_info(),
+                },
+            ),
+            amount_to_pay,
+        );
+    }
+
+    let hop = ExactOutputHop::split(path, additional_accounts_per_pool)?;
+    exact_output_internal(
+        &mut SwapContext {
+            signer: accounts.signer.clone(),
+            factory_state: accounts.factory_state.clone(),
+            input_token_account: accounts.input_token_account.clone(),
+            // output of the next pool is paid straight into this pool
+            output_token_account: UncheckedAccount::try_from(accounts.input_vault.to_account_info()),
+            input_vault: hop.input_vault,
+            output_vault: hop.output_vault,
+            token_program: accounts.token_program.clone(),
+            pool_state: hop.pool_state,
+            last_observation_state: hop.last_observation_state,
+            callback_handler: accounts.callback_handler.clone(),
+        },
+        hop.swap_accounts,
+        amount_to_pay,
+        0,
+        escrow,
+        hop.path,
+        &additional_accounts_per_pool[1..],
+    )?;
+    Ok(())
+}
+
+/// Accounts of the next pool in a reversed exact output path
+pub struct ExactOutputHop<'c, 'info> {
+    /// The pool to swap
+    pub pool_state: UncheckedAccount<'info>,
+
+    /// The vault of the pool for the input token
+    pub input_vault: Box<Account<'info, TokenAccount>>,
+
+    /// The vault of the pool for the output token
+    pub output_vault: Box<Account<'info, TokenAccount>>,
+
+    /// The observation account at the observation index of the pool
+    pub last_observation_state: UncheckedAccount<'info>,
+
+    /// Bitmap, tick and observation accounts of the swap
+    pub swap_accounts: &'c [AccountInfo<'info>],
+
+    /// Accounts of the pools after this one
+    pub path: &'c [AccountInfo<'info>],
+}
+
+impl<'c, 'info> ExactOutputHop<'c, 'info> {
+    /// Splits the accounts of the first pool off a reversed exact output path
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The pool, input vault, output vault and latest observation accounts of each
+    ///   pool, each followed by the bitmap, tick and observation accounts of its swap
+    /// * `additional_accounts_per_pool` - Bitmap, tick and observation account count of each pool
+    ///
+    pub fn split(
+        path: &'c [AccountInfo<'info>],
+        additional_accounts_per_pool: &[u8],
+    ) -> Result<Self> {
+        let additional_accounts = *additional_accounts_per_pool
+            .first()
+            .ok_or(ErrorCode::InvalidSwapPath)?;
+        let mut accounts = path.iter();
+        let pool_state =
+            UncheckedAccount::try_from(accounts.next().ok_or(ErrorCode::InvalidSwapPath)?.clone());
+        let input_vault = Box::new(Account::<TokenAccount>::try_from(
+            accounts.next().ok_or(ErrorCode::InvalidSwapPath)?,
+        )?);
+        let output_vault = Box::new(Account::<TokenAccount>::try_from(
+            accounts.next().ok_or(ErrorCode::InvalidSwapPath)?,
+        )?);
+        let last_observation_state =
+            UncheckedAccount::try_from(accounts.next().ok_or(ErrorCode::InvalidSwapPath)?.clone());
+        let (swap_accounts, path) = split_path(accounts.as_slice(), additional_accounts)?;
+
+        Ok(ExactOutputHop {
+            pool_state,
+            input_vault,
+            output_vault,
+            last_observation_state,
+            swap_accounts,
+            path,
+        })
     }
+}
 
-    //  /// Swaps as little as possible of one token for `amount_out` of another token,
-    // /// across a single pool
-    // ///
-    // /// # Arguments
-    // ///
-    // /// * `ctx` - Token and pool accounts for swap
-    // /// * `zero_for_one` - Direction of swap. Swap token_0 for token_1 if true
-    // /// * `deadline` - Swap should if fail if past deadline
-    // /// * `amount_out` - Token amount to be swapped out
-    // /// * `amount_in_maximum` - For slippage. Panic if required input exceeds max limit.
-    // /// * `sqrt_price_limit` - Limit price √P for slippage
-    // ///
-    // pub fn exact_output_single(
-    //     ctx: Context<ExactInputSingle>,
-    //     zero_for_one: bool,
-    //     deadline: u64,
-    //     amount_out: u64,
-    //     amount_in_maximum: u64,
-    //     sqrt_price_limit_x32: u64,
-    // ) -> Result<()> {
-    //     todo!()
-    // }
-
-    // /// Swaps as little as possible of one token for `amount_out` of another
-    // /// along the specified path (reversed)
-    // ///
-    // /// # Arguments
-    // ///
-    // /// * `ctx` - Accounts for token transfer and swap route
-    // /// * `deadline` - Swap should if fail if past deadline
-    // /// * `amount_out` - Token amount to be swapped out
-    // /// * `amount_in_maximum` - For slippage. Panic if required input exceeds max limit.
-    // ///
-    // pub fn exact_output(
-    //     ctx: Context<ExactInput>,
-    //     deadline: u64,
-    //     amount_out: u64,
-    //     amount_out_maximum: u64,
-    // ) -> Result<()> {
-    //     todo!()
-    // }
+/// Splits the bitmap, tick and observation accounts of a pool off a swap path
+///
+/// # Arguments
+///
+/// * `path` - Accounts of the path, beginning with those of the pool
+/// * `additional_accounts` - The number of accounts of the pool
+///
+pub fn split_path<'c, 'info>(
+    path: &'c [AccountInfo<'info>],
+    additional_accounts: u8,
+) -> Result<(&'c [AccountInfo<'info>], &'c [AccountInfo<'info>])> {
+    require!(
+        path.len() >= usize::from(additional_accounts),
+        ErrorCode::InvalidSwapPath
+    );
+    Ok(path.split_at(usize::from(additional_accounts)))
 }
 
 /// Performs a single exact input swap
@@ -2089,12 +3959,13 @@ pub fn exact_input_internal<'info>(
     remaining_accounts: &[AccountInfo<'info>],
     amount_in: u64,
     sqrt_price_limit_x32: u64,
+    escrow: Option<&AccountInfo<'info>>,
 ) -> Result<u64> {
     let pool_state = AccountLoader::<PoolState>::try_from(&accounts.pool_state)?;
     let zero_for_one = accounts.input_vault.mint == pool_state.load()?.token_0;
 
     let balance_before = accounts.input_vault.amount;
-    swap(
+    swap_internal(
         Context::new(&ID, accounts, remaining_accounts, BTreeMap::default()),
         i64::try_from(amount_in).unwrap(),
         if sqrt_price_limit_x32 == 0 {
@@ -2106,12 +3977,62 @@ pub fn exact_input_internal<'info>(
         } else {
             sqrt_price_limit_x32
         },
+        escrow,
+        &[],
+        &[],
     )?;
 
     accounts.input_vault.reload()?;
     Ok(accounts.input_vault.amount - balance_before)
 }
 
+/// Performs a single exact output swap. The pool is paid by swapping the next pool of `path`,
+/// or by the signer if the path is empty
+pub fn exact_output_internal<'info>(
+    accounts: &mut SwapContext<'info>,
+    remaining_accounts: &[AccountInfo<'info>],
+    amount_out: u64,
+    sqrt_price_limit_x32: u64,
+    escrow: Option<&AccountInfo<'info>>,
+    path: &[AccountInfo<'info>],
+    additional_accounts_per_pool: &[u8],
+) -> Result<u64> {
+    let pool_state = AccountLoader::<PoolState>::try_from(&accounts.pool_state)?;
+    let zero_for_one = accounts.input_vault.mint == pool_state.load()?.token_0;
+
+    let input_balance_before = accounts.input_vault.amount;
+    let output_balance_before = accounts.output_vault.amount;
+    swap_internal(
+        Context::new(&ID, accounts, remaining_accounts, BTreeMap::default()),
+        -i64::try_from(amount_out).unwrap(),
+        if sqrt_price_limit_x32 == 0 {
+            if zero_for_one {
+                tick_math::MIN_SQRT_RATIO + 1
+            } else {
+                tick_math::MAX_SQRT_RATIO - 1
+            }
+        } else {
+            sqrt_price_limit_x32
+        },
+        escrow,
+        path,
+        additional_accounts_per_pool,
+    )?;
+
+    accounts.input_vault.reload()?;
+    accounts.output_vault.reload()?;
+
+    // it's technically possible to not receive the full output amount,
+    // so if no price limit has been specified, require this possibility away
+    if sqrt_price_limit_x32 == 0 {
+        require!(
+            output_balance_before - accounts.output_vault.amount == amount_out,
+            ErrorCode::TooLittleReceived
+        );
+    }
+    Ok(accounts.input_vault.amount - input_balance_before)
+}
+
 /// Common checks for a valid tick input.
 /// A tick is valid iff it lies within tick boundaries and it is a multiple
 /// of tick spacing.
@@ -2139,6 +4060,328 @@ pub fn check_ticks(tick_lower: i32, tick_upper: i32) -> Result<()> {
     Ok(())
 }
 
+/// Returns a snapshot of the tick cumulative, seconds per liquidity and seconds inside a tick range,
+/// after validating the tick and observation accounts against the pool
+///
+/// # Arguments
+///
+/// * `pool_state` - The pool holding the tick range
+/// * `tick_lower_state` - The lower tick of the range, which must be initialized
+/// * `tick_upper_state` - The upper tick of the range, which must be initialized
+/// * `latest_observation_state` - The most recent oracle observation of the pool
+///
+pub fn _snapshot_cumulatives_inside<'info>(
+    pool_state: &AccountLoader<'info, PoolState>,
+    tick_lower_state: &AccountLoader<'info, TickState>,
+    tick_upper_state: &AccountLoader<'info, TickState>,
+    latest_observation_state: &AccountLoader<'info, ObservationState>,
+) -> Result<SnapshotCumulative> {
+    let pool_state = pool_state.load()?;
+
+    let tick_lower = *tick_lower_state.load()?.deref();
+    pool_state.validate_tick_address(&tick_lower_state.key(), tick_lower.bump, tick_lower.tick)?;
+    let tick_upper = *tick_upper_state.load()?.deref();
+    pool_state.validate_tick_address(&tick_upper_state.key(), tick_upper.bump, tick_upper.tick)?;
+    require!(tick_lower.liquidity_gross > 0, ErrorCode::TNI);
+    require!(tick_upper.liquidity_gross > 0, ErrorCode::TNI);
+
+    let latest_observation = *latest_observation_state.load()?.deref();
+    pool_state.validate_observation_address(
+        &latest_observation_state.key(),
+        latest_observation.bump,
+        false,
+    )?;
+
+    Ok(pool_state.snapshot_cumulatives_inside(&tick_lower, &tick_upper, &latest_observation))
+}
+
+/// Credits the rewards earned by a stake since its last claim to the claimed totals of
+/// the incentive, and transfers them out of the incentive vault
+///
+/// Returns the amount of rewards claimed
+///
+/// # Arguments
+///
+/// * `incentive_state` - The incentive paying the rewards
+/// * `stake` - The stake earning the rewards
+/// * `seconds_per_liquidity_inside_x32` - The current seconds per liquidity inside the range
+///   of the staked position
+/// * `reward_vault` - The token account of the incentive holding the rewards
+/// * `recipient_wallet` - Receives the rewards
+/// * `token_program` - Program to transfer the rewards
+///
+pub fn _claim_stake_reward<'info>(
+    incentive_state: &AccountLoader<'info, IncentiveState>,
+    stake: &StakeState,
+    seconds_per_liquidity_inside_x32: u64,
+    reward_vault: &AccountInfo<'info>,
+    recipient_wallet: &AccountInfo<'info>,
+    token_program: &AccountInfo<'info>,
+) -> Result<u64> {
+    let mut incentive = incentive_state.load_mut()?;
+    let (amount, seconds_inside_x32) = incentive.compute_reward_amount(
+        stake,
+        seconds_per_liquidity_inside_x32,
+        oracle::_block_timestamp(),
+    );
+    incentive.total_seconds_claimed_x32 += seconds_inside_x32;
+    incentive.total_reward_unclaimed -= amount;
+
+    let incentive_state_seeds = [
+        INCENTIVE_SEED.as_bytes(),
+        &incentive.reward_token.to_bytes() as &[u8],
+        &incentive.pool_id.to_bytes() as &[u8],
+        &incentive.start_time.to_be_bytes(),
+        &incentive.end_time.to_be_bytes(),
+        &incentive.refundee.to_bytes() as &[u8],
+        &[incentive.bump],
+    ];
+    drop(incentive);
+
+    if amount > 0 {
+        token::transfer(
+            CpiContext::new_with_signer(
+                token_program.clone(),
+                token::Transfer {
+                    from: reward_vault.clone(),
+                    to: recipient_wallet.clone(),
+                    authority: incentive_state.to_account_info(),
+                },
+                &[&incentive_state_seeds[..]],
+            ),
+            amount,
+        )?;
+    }
+    Ok(amount)
+}
+
+/// Creates a program account at a PDA, with rent paid by the payer
+///
+/// Returns the bump of the PDA
+///
+/// # Arguments
+///
+/// * `payer` - Pays rent for the account
+/// * `account` - The account to create
+/// * `system_program` - The system program
+/// * `space` - Size of the account data, including the discriminator
+/// * `seeds` - Seeds of the PDA, without the bump
+///
+pub fn create_program_account<'info>(
+    payer: &AccountInfo<'info>,
+    account: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    space: usize,
+    seeds: &[&[u8]],
+) -> Result<u8> {
+    let (address, bump) = Pubkey::find_program_address(seeds, &ID);
+    require!(account.key() == address, ErrorCode::AD);
+    let bump_seed = [bump];
+    let signer_seeds = [seeds, &[&bump_seed[..]]].concat();
+
+    let lamports = Rent::get()?.minimum_balance(space);
+    if account.lamports() == 0 {
+        solana_program::program::invoke_signed(
+            &create_account(payer.key, account.key, lamports, space as u64, &ID),
+            &[payer.clone(), account.clone(), system_program.clone()],
+            &[&signer_seeds[..]],
+        )?;
+    } else {
+        // create_account fails for an address already holding lamports, so the account is topped
+        // up, allocated and assigned instead
+        let top_up = lamports.saturating_sub(account.lamports());
+        if top_up > 0 {
+            solana_program::program::invoke(
+                &system_instruction::transfer(payer.key, account.key, top_up),
+                &[payer.clone(), account.clone(), system_program.clone()],
+            )?;
+        }
+        solana_program::program::invoke_signed(
+            &system_instruction::allocate(account.key, space as u64),
+            &[account.clone(), system_program.clone()],
+            &[&signer_seeds[..]],
+        )?;
+        solana_program::program::invoke_signed(
+            &system_instruction::assign(account.key, &ID),
+            &[account.clone(), system_program.clone()],
+            &[&signer_seeds[..]],
+        )?;
+    }
+    Ok(bump)
+}
+
+/// Grows a program account holding an older layout of its state to the current layout. Rent
+/// for the added space is paid by the payer, and the added space is zeroed
+///
+/// # Arguments
+///
+/// * `payer` - Pays rent for the added space
+/// * `account` - The account to grow
+/// * `system_program` - The system program
+/// * `legacy_space` - Size of the account data in the older layout, including the discriminator
+/// * `space` - Size of the account data in the current layout, including the discriminator
+///
+pub fn realloc_program_account<'info>(
+    payer: &AccountInfo<'info>,
+    account: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    legacy_space: usize,
+    space: usize,
+) -> Result<()> {
+    require!(
+        account.data_len() == legacy_space,
+        ErrorCode::AlreadyMigrated
+    );
+
+    let top_up = Rent::get()?
+        .minimum_balance(space)
+        .saturating_sub(account.lamports());
+    if top_up > 0 {
+        solana_program::program::invoke(
+            &system_instruction::transfer(payer.key, account.key, top_up),
+            &[payer.clone(), account.clone(), system_program.clone()],
+        )?;
+    }
+
+    // The runtime reads the length of the account data back from the serialized input, where
+    // it precedes the data. Up to MAX_PERMITTED_DATA_INCREASE bytes are reserved after the data
+    unsafe {
+        let data_ptr = account.try_borrow_mut_data()?.as_mut_ptr();
+        *(data_ptr.offset(-8) as *mut u64) = space as u64;
+        *account.data.borrow_mut() = std::slice::from_raw_parts_mut(data_ptr, space);
+    }
+    account.try_borrow_mut_data()?[legacy_space..].fill(0);
+    Ok(())
+}
+
+/// Creates the tick, bitmap and position accounts of a mint which do not exist yet, with rent paid
+/// by the payer. Existing accounts are left for `mint` to validate
+///
+/// # Arguments
+///
+/// * `accounts` - Accounts of the mint
+/// * `pool` - The pool in which liquidity is minted
+/// * `tick_lower` - The lower tick of the position
+/// * `tick_upper` - The upper tick of the position
+///
+pub fn init_mint_accounts<'info>(
+    accounts: &MintContext<'info>,
+    pool: &PoolState,
+    tick_lower: i32,
+    tick_upper: i32,
+) -> Result<()> {
+    let payer = accounts.payer.to_account_info();
+    let system_program = accounts.system_program.to_account_info();
+
+    for (tick_state, tick) in [
+        (accounts.tick_lower_state.to_account_info(), tick_lower),
+        (accounts.tick_upper_state.to_account_info(), tick_upper),
+    ] {
+        if tick_state.owner == &ID {
+            continue;
+        }
+        check_tick(tick, pool.tick_spacing)?;
+        let bump = create_program_account(
+            &payer,
+            &tick_state,
+            &system_program,
+            8 + size_of::<TickState>(),
+            &[
+                TICK_SEED.as_bytes(),
+                pool.token_0.as_ref(),
+                pool.token_1.as_ref(),
+                &pool.fee.to_be_bytes(),
+                &tick.to_be_bytes(),
+            ],
+        )?;
+        let tick_loader = AccountLoader::<TickState>::try_from_unchecked(&ID, &tick_state)?;
+        let mut tick_account = tick_loader.load_init()?;
+        tick_account.bump = bump;
+        tick_account.tick = tick;
+        tick_account.payer = payer.key();
+        drop(tick_account);
+        tick_loader.exit(&ID)?;
+    }
+
+    // both ticks can share a bitmap, which is then created once
+    for (bitmap_state, tick) in [
+        (accounts.bitmap_lower_state.to_account_info(), tick_lower),
+        (accounts.bitmap_upper_state.to_account_info(), tick_upper),
+    ] {
+        if bitmap_state.owner == &ID {
+            continue;
+        }
+        let word_pos = tick_bitmap::position(tick / pool.tick_spacing as i32).word_pos;
+        let bump = create_program_account(
+            &payer,
+            &bitmap_state,
+            &system_program,
+            8 + size_of::<TickBitmapState>(),
+            &[
+                BITMAP_SEED.as_bytes(),
+                pool.token_0.as_ref(),
+                pool.token_1.as_ref(),
+                &pool.fee.to_be_bytes(),
+                &word_pos.to_be_bytes(),
+            ],
+        )?;
+        let bitmap_loader =
+            AccountLoader::<TickBitmapState>::try_from_unchecked(&ID, &bitmap_state)?;
+        let mut bitmap_account = bitmap_loader.load_init()?;
+        bitmap_account.bump = bump;
+        bitmap_account.word_pos = word_pos;
+        bitmap_account.payer = payer.key();
+        drop(bitmap_account);
+        bitmap_loader.exit(&ID)?;
+    }
+
+    let position_state = accounts.position_state.to_account_info();
+    if position_state.owner != &ID {
+        let bump = create_program_account(
+            &payer,
+            &position_state,
+            &system_program,
+            8 + size_of::<PositionState>(),
+            &[
+                POSITION_SEED.as_bytes(),
+                pool.token_0.as_ref(),
+                pool.token_1.as_ref(),
+                &pool.fee.to_be_bytes(),
+                accounts.recipient.key.as_ref(),
+                &tick_lower.to_be_bytes(),
+                &tick_upper.to_be_bytes(),
+            ],
+        )?;
+        let position_loader =
+            AccountLoader::<PositionState>::try_from_unchecked(&ID, &position_state)?;
+        let mut position_account = position_loader.load_init()?;
+        position_account.bump = bump;
+        position_account.payer = payer.key();
+        drop(position_account);
+        position_loader.exit(&ID)?;
+    }
+    Ok(())
+}
+
+/// Accounts of a position whose liquidity is modified
+#[derive(Clone, Copy)]
+pub struct PositionAccounts<'a, 'info> {
+    /// Effect change to this position
+    pub position_state: &'a AccountLoader<'info, PositionState>,
+
+    /// Program account for the lower tick boundary
+    pub tick_lower_state: &'a AccountLoader<'info, TickState>,
+
+    /// Program account for the upper tick boundary
+    pub tick_upper_state: &'a AccountLoader<'info, TickState>,
+
+    /// Holds the initialization state of the lower tick
+    pub bitmap_lower: &'a AccountLoader<'info, TickBitmapState>,
+
+    /// Holds the initialization state of the upper tick. Can be `bitmap_lower`
+    pub bitmap_upper: &'a AccountLoader<'info, TickBitmapState>,
+}
+
 /// Credit or debit liquidity to a position, and find the amount of token_0 and token_1
 /// required to produce this change.
 /// Returns amount of token_0 and token_1 owed to the pool, negative if the pool should
@@ -2146,11 +4389,7 @@ pub fn check_ticks(tick_lower: i32, tick_upper: i32) -> Result<()> {
 ///
 /// # Arguments
 ///
-/// * `position_state` - Effect change to this position
-/// * `tick_lower_state`- Program account for the lower tick boundary
-/// * `tick_upper_state`- Program account for the upper tick boundary
-/// * `bitmap_lower` - Holds the initialization state of the lower tick
-/// * `bitmap_upper` - Holds the initialization state of the upper tick
+/// * `accounts` - The position, its tick and its bitmap accounts
 /// * `last_observation_state` - The last written oracle observation, having index = pool.observation_index.
 /// This condition must be externally tracked.
 /// * `next_observation_state` - The observation account following `last_observation_state`. Becomes equal
@@ -2162,27 +4401,24 @@ pub fn check_ticks(tick_lower: i32, tick_upper: i32) -> Result<()> {
 pub fn _modify_position<'info>(
     liquidity_delta: i64,
     pool_state: &mut PoolState,
-    position_state: &AccountLoader<'info, PositionState>,
-    tick_lower_state: &AccountLoader<'info, TickState>,
-    tick_upper_state: &AccountLoader<'info, TickState>,
-    bitmap_lower: &AccountLoader<'info, TickBitmapState>,
-    bitmap_upper: &AccountLoader<'info, TickBitmapState>,
+    accounts: &PositionAccounts<'_, 'info>,
     last_observation_state: &AccountLoader<'info, ObservationState>,
     remaining_accounts: &[AccountInfo<'info>],
 ) -> Result<(i64, i64)> {
+    let tick_lower_state = accounts.tick_lower_state;
+    let tick_upper_state = accounts.tick_upper_state;
     check_ticks(tick_lower_state.load()?.tick, tick_upper_state.load()?.tick)?;
 
     let latest_observation = last_observation_state.load()?;
 
+    // accrue rewards to the liquidity in range before it changes
+    pool_state.update_reward_infos(oracle::_block_timestamp());
+
     _update_position(
         liquidity_delta,
         pool_state.deref(),
         latest_observation.deref(),
-        position_state,
-        tick_lower_state,
-        tick_upper_state,
-        bitmap_lower,
-        bitmap_upper,
+        accounts,
     )?;
 
     let mut amount_0 = 0;
@@ -2266,26 +4502,25 @@ pub fn _modify_position<'info>(
 /// # Arguments
 ///
 /// * `pool_state` - Current pool state
-/// * `position_state` - Effect change to this position
-/// * `tick_lower_state`- Program account for the lower tick boundary
-/// * `tick_upper_state`- Program account for the upper tick boundary
-/// * `bitmap_lower` - Bitmap account for the lower tick
-/// * `bitmap_upper` - Bitmap account for the upper tick, if it is different from
-/// `bitmap_lower`
+/// * `last_observation_state` - The last written oracle observation
+/// * `accounts` - The position, its tick and its bitmap accounts
 /// * `lamport_destination` - Destination account for freed lamports when a tick state is
 /// un-initialized
 /// * `liquidity_delta` - The change in liquidity. Can be 0 to perform a poke.
 ///
-pub fn _update_position<'info>(
+pub fn _update_position(
     liquidity_delta: i64,
     pool_state: &PoolState,
     last_observation_state: &ObservationState,
-    position_state: &AccountLoader<'info, PositionState>,
-    tick_lower_state: &AccountLoader<'info, TickState>,
-    tick_upper_state: &AccountLoader<'info, TickState>,
-    bitmap_lower: &AccountLoader<'info, TickBitmapState>,
-    bitmap_upper: &AccountLoader<'info, TickBitmapState>,
+    accounts: &PositionAccounts,
 ) -> Result<()> {
+    let PositionAccounts {
+        position_state,
+        tick_lower_state,
+        tick_upper_state,
+        bitmap_lower,
+        bitmap_upper,
+    } = *accounts;
     let mut tick_lower = tick_lower_state.load_mut()?;
     let mut tick_upper = tick_upper_state.load_mut()?;
 
@@ -2301,26 +4536,27 @@ pub fn _update_position<'info>(
         let max_liquidity_per_tick =
             tick_spacing_to_max_liquidity_per_tick(pool_state.tick_spacing as i32);
 
+        let globals = GlobalGrowths {
+            fee_growth_global_0_x32: pool_state.fee_growth_global_0_x32,
+            fee_growth_global_1_x32: pool_state.fee_growth_global_1_x32,
+            seconds_per_liquidity_cumulative_x32,
+            tick_cumulative,
+            time,
+            reward_growths_global_x32: pool_state.reward_growths_global_x32(),
+        };
+
         // Update tick state and find if tick is flipped
         flipped_lower = tick_lower.update(
             pool_state.tick,
             liquidity_delta,
-            pool_state.fee_growth_global_0_x32,
-            pool_state.fee_growth_global_1_x32,
-            seconds_per_liquidity_cumulative_x32,
-            tick_cumulative,
-            time,
+            &globals,
             false,
             max_liquidity_per_tick,
         )?;
         flipped_upper = tick_upper.update(
             pool_state.tick,
             liquidity_delta,
-            pool_state.fee_growth_global_0_x32,
-            pool_state.fee_growth_global_1_x32,
-            seconds_per_liquidity_cumulative_x32,
-            tick_cumulative,
-            time,
+            &globals,
             true,
             max_liquidity_per_tick,
         )?;
@@ -2338,7 +4574,7 @@ pub fn _update_position<'info>(
             }
         }
     }
-    // Update fees accrued to the position
+    // Update fees and rewards accrued to the position
     let (fee_growth_inside_0_x32, fee_growth_inside_1_x32) = tick::get_fee_growth_inside(
         tick_lower.deref(),
         tick_upper.deref(),
@@ -2346,10 +4582,17 @@ pub fn _update_position<'info>(
         pool_state.fee_growth_global_0_x32,
         pool_state.fee_growth_global_1_x32,
     );
+    let reward_growths_inside_x32 = tick::get_reward_growths_inside(
+        tick_lower.deref(),
+        tick_upper.deref(),
+        pool_state.tick,
+        pool_state.reward_growths_global_x32(),
+    );
     position_state.load_mut()?.update(
         liquidity_delta,
         fee_growth_inside_0_x32,
         fee_growth_inside_1_x32,
+        reward_growths_inside_x32,
     )?;
 
     // Deallocate the tick accounts if they get un-initialized
@@ -2365,28 +4608,49 @@ pub fn _update_position<'info>(
     Ok(())
 }
 
+/// Tick range and token amounts of liquidity to add
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AddLiquidityParams {
+    /// The lower tick bound for the position
+    pub tick_lower: i32,
+
+    /// The upper tick bound for the position
+    pub tick_upper: i32,
+
+    /// Desired amount of token_0 to be spent
+    pub amount_0_desired: u64,
+
+    /// Desired amount of token_1 to be spent
+    pub amount_1_desired: u64,
+
+    /// The minimum amount of token_0 to spend, which serves as a slippage check
+    pub amount_0_min: u64,
+
+    /// The minimum amount of token_1 to spend, which serves as a slippage check
+    pub amount_1_min: u64,
+}
+
 /// Add liquidity to an initialized pool
 ///
 /// # Arguments
 ///
 /// * `accounts` - Accounts to mint core liquidity
-/// * `amount_0_desired` - Desired amount of token_0 to be spent
-/// * `amount_1_desired` - Desired amount of token_1 to be spent
-/// * `amount_0_min` - The minimum amount of token_0 to spend, which serves as a slippage check
-/// * `amount_1_min` - The minimum amount of token_1 to spend, which serves as a slippage check
-/// * `tick_lower` - The lower tick bound for the position
-/// * `tick_upper` - The upper tick bound for the position
+/// * `remaining_accounts` - The next observation account, if the update writes to a new one
+/// * `params` - The tick range and token amounts to add
 ///
 pub fn add_liquidity<'info>(
     accounts: &mut MintContext<'info>,
     remaining_accounts: &[AccountInfo<'info>],
-    amount_0_desired: u64,
-    amount_1_desired: u64,
-    amount_0_min: u64,
-    amount_1_min: u64,
-    tick_lower: i32,
-    tick_upper: i32,
+    params: &AddLiquidityParams,
 ) -> Result<(u64, u64, u64)> {
+    let AddLiquidityParams {
+        tick_lower,
+        tick_upper,
+        amount_0_desired,
+        amount_1_desired,
+        amount_0_min,
+        amount_1_min,
+    } = *params;
     let sqrt_price_x32 = accounts.pool_state.load()?.sqrt_price_x32;
 
     let sqrt_ratio_a_x32 = tick_math::get_sqrt_ratio_at_tick(tick_lower)?;
@@ -2404,6 +4668,8 @@ pub fn add_liquidity<'info>(
 
     mint(
         Context::new(&ID, accounts, remaining_accounts, BTreeMap::default()),
+        tick_lower,
+        tick_upper,
         liquidity,
     )?;
 
@@ -4,6 +4,7 @@ pub mod fixed_point_32;
 pub mod full_math;
 pub mod liquidity_amounts;
 pub mod liquidity_math;
+pub mod oracle_library;
 pub mod sqrt_price_math;
 pub mod swap_math;
 #[cfg(test)]
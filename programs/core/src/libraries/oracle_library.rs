@@ -0,0 +1,203 @@
+//! Oracle library
+//! Provides functions to integrate with the pool oracle
+//!
+//! Functions operate on the cumulatives returned by the `observe` instruction, so they can
+//! be used inside the program as well as by off-chain clients.
+//!
+//! Ref- https://github.com/Uniswap/v3-periphery/blob/main/contracts/libraries/OracleLibrary.sol
+//!
+use super::big_num::U128;
+use super::full_math::MulDiv;
+use super::tick_math;
+use crate::error::ErrorCode;
+use anchor_lang::prelude::*;
+
+/// Calculates the time weighted means of tick and liquidity over a window
+///
+/// Returns `(arithmetic_mean_tick, harmonic_mean_liquidity)`. The arithmetic mean tick
+/// is rounded toward negative infinity, and its price is the geometric mean price of the window.
+/// The harmonic mean liquidity saturates at u64::MAX if no seconds per liquidity accrued.
+///
+/// # Arguments
+///
+/// * `tick_cumulatives` - Tick cumulatives as of `[seconds_ago, 0]` seconds ago
+/// * `seconds_per_liquidity_cumulative_x32s` - Seconds per liquidity cumulatives as of `[seconds_ago, 0]` seconds ago
+/// * `seconds_ago` - Length of the window in seconds
+///
+pub fn consult(
+    tick_cumulatives: [i64; 2],
+    seconds_per_liquidity_cumulative_x32s: [u64; 2],
+    seconds_ago: u32,
+) -> Result<(i32, u64)> {
+    require!(seconds_ago != 0, ErrorCode::BP);
+
+    let tick_cumulatives_delta = tick_cumulatives[1] - tick_cumulatives[0];
+    let mut arithmetic_mean_tick = (tick_cumulatives_delta / seconds_ago as i64) as i32;
+    // Always round to negative infinity
+    if tick_cumulatives_delta < 0 && tick_cumulatives_delta % seconds_ago as i64 != 0 {
+        arithmetic_mean_tick -= 1;
+    }
+
+    // Δ(seconds per liquidity) = seconds_ago * 2^32 / liquidity
+    let seconds_per_liquidity_cumulatives_delta = seconds_per_liquidity_cumulative_x32s[1]
+        .wrapping_sub(seconds_per_liquidity_cumulative_x32s[0]);
+    let harmonic_mean_liquidity = if seconds_per_liquidity_cumulatives_delta == 0 {
+        u64::MAX
+    } else {
+        (((seconds_ago as u128) << 32) / seconds_per_liquidity_cumulatives_delta as u128)
+            .min(u64::MAX as u128) as u64
+    };
+
+    Ok((arithmetic_mean_tick, harmonic_mean_liquidity))
+}
+
+/// Given a tick and a token amount, calculates the amount of token received in exchange
+///
+/// The quote is returned as a u128 since it can exceed the range of token amounts at extreme ticks.
+///
+/// # Arguments
+///
+/// * `tick` - Tick value used to calculate the quote
+/// * `base_amount` - Amount of token to be converted
+/// * `base_token` - Address of the token being quoted
+/// * `quote_token` - Address of the token used to denominate the quote
+///
+pub fn get_quote_at_tick(
+    tick: i32,
+    base_amount: u64,
+    base_token: &Pubkey,
+    quote_token: &Pubkey,
+) -> Result<u128> {
+    let sqrt_ratio_x32 = U128::from(tick_math::get_sqrt_ratio_at_tick(tick)?);
+
+    // sqrt_ratio_x32 is below 2^49, so the square fits in 128 bits
+    let ratio_x64 = sqrt_ratio_x32 * sqrt_ratio_x32;
+    let q64 = U128::from(1) << 64;
+    let quote_amount = if base_token < quote_token {
+        ratio_x64.mul_div_floor(U128::from(base_amount), q64)
+    } else {
+        q64.mul_div_floor(U128::from(base_amount), ratio_x64)
+    }
+    .unwrap();
+
+    Ok(quote_amount.as_u128())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Returns the name of the program error
+    fn error_name(error: Error) -> String {
+        match error {
+            Error::AnchorError(error) => error.error_name,
+            Error::ProgramError(error) => error.to_string(),
+        }
+    }
+
+    mod consult {
+        use super::*;
+
+        #[test]
+        fn fails_if_seconds_ago_is_zero() {
+            assert_eq!(error_name(consult([0, 0], [0, 0], 0).unwrap_err()), "BP");
+        }
+
+        #[test]
+        fn returns_mean_tick_and_liquidity_for_constant_values() {
+            // 10 seconds at tick 100 with liquidity 2^10
+            let seconds_per_liquidity_delta = (10 << 32) / (1 << 10);
+            let (arithmetic_mean_tick, harmonic_mean_liquidity) =
+                consult([5_000, 6_000], [7, 7 + seconds_per_liquidity_delta], 10).unwrap();
+            assert_eq!(arithmetic_mean_tick, 100);
+            assert_eq!(harmonic_mean_liquidity, 1 << 10);
+        }
+
+        #[test]
+        fn rounds_positive_mean_tick_down() {
+            let (arithmetic_mean_tick, _) = consult([0, 7], [0, 1], 2).unwrap();
+            assert_eq!(arithmetic_mean_tick, 3);
+        }
+
+        #[test]
+        fn rounds_negative_mean_tick_toward_negative_infinity() {
+            let (arithmetic_mean_tick, _) = consult([0, -7], [0, 1], 2).unwrap();
+            assert_eq!(arithmetic_mean_tick, -4);
+
+            let (arithmetic_mean_tick, _) = consult([0, -8], [0, 1], 2).unwrap();
+            assert_eq!(arithmetic_mean_tick, -4);
+        }
+
+        #[test]
+        fn handles_seconds_per_liquidity_overflow() {
+            let (_, harmonic_mean_liquidity) =
+                consult([0, 0], [u64::MAX, (10 << 32) / 5 - 1], 10).unwrap();
+            assert_eq!(harmonic_mean_liquidity, 5);
+        }
+
+        #[test]
+        fn saturates_harmonic_mean_liquidity() {
+            let (_, harmonic_mean_liquidity) = consult([0, 0], [3, 3], 10).unwrap();
+            assert_eq!(harmonic_mean_liquidity, u64::MAX);
+        }
+    }
+
+    mod get_quote_at_tick {
+        use super::*;
+
+        const TOKEN_0: Pubkey = Pubkey::new_from_array([0; 32]);
+        const TOKEN_1: Pubkey = Pubkey::new_from_array([1; 32]);
+
+        #[test]
+        fn fails_for_tick_out_of_range() {
+            assert_eq!(
+                error_name(
+                    get_quote_at_tick(tick_math::MAX_TICK + 1, 1, &TOKEN_0, &TOKEN_1).unwrap_err()
+                ),
+                "T"
+            );
+        }
+
+        #[test]
+        fn is_one_to_one_at_tick_zero() {
+            assert_eq!(
+                get_quote_at_tick(0, 1_000_000, &TOKEN_0, &TOKEN_1).unwrap(),
+                1_000_000
+            );
+            assert_eq!(
+                get_quote_at_tick(0, 1_000_000, &TOKEN_1, &TOKEN_0).unwrap(),
+                1_000_000
+            );
+        }
+
+        #[test]
+        fn quotes_token_0_in_token_1() {
+            // 1.0001^10000 = 2.71814...
+            let quote = get_quote_at_tick(10_000, 1_000_000, &TOKEN_0, &TOKEN_1).unwrap();
+            assert!(quote > 2_718_000 && quote < 2_718_200);
+        }
+
+        #[test]
+        fn quotes_token_1_in_token_0() {
+            // 1 / 1.0001^10000 = 0.367897...
+            let quote = get_quote_at_tick(10_000, 1_000_000, &TOKEN_1, &TOKEN_0).unwrap();
+            assert!(quote > 367_850 && quote < 367_950);
+        }
+
+        #[test]
+        fn quotes_at_extreme_ticks_without_overflow() {
+            let max_quote =
+                get_quote_at_tick(tick_math::MAX_TICK, u64::MAX, &TOKEN_0, &TOKEN_1).unwrap();
+            assert!(max_quote > u64::MAX as u128);
+
+            let min_quote =
+                get_quote_at_tick(tick_math::MIN_TICK, u64::MAX, &TOKEN_1, &TOKEN_0).unwrap();
+            assert!(min_quote > u64::MAX as u128);
+
+            assert_eq!(
+                get_quote_at_tick(tick_math::MIN_TICK, 1, &TOKEN_0, &TOKEN_1).unwrap(),
+                0
+            );
+        }
+    }
+}
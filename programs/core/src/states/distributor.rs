@@ -0,0 +1,199 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::hash::hashv;
+
+/// Seed to derive account address and signature
+pub const DISTRIBUTOR_SEED: &str = "md";
+
+/// Seed to derive account address and signature
+pub const CLAIM_STATUS_SEED: &str = "cs";
+
+/// A distribution of reward tokens to the liquidity providers of a pool, committed to as the
+/// root of a Merkle tree of `(index, claimant, amount)` leaves computed off-chain
+///
+/// PDA of `[DISTRIBUTOR_SEED, pool_id, reward_mint, distribution_index]`
+///
+#[account(zero_copy)]
+#[derive(Default)]
+#[repr(packed)]
+pub struct DistributorState {
+    /// Bump to identify PDA
+    pub bump: u8,
+
+    /// The pool whose liquidity providers are rewarded
+    pub pool_id: Pubkey,
+
+    /// The token being distributed
+    pub reward_mint: Pubkey,
+
+    /// Distinguishes distributions of the same token to the same pool
+    pub distribution_index: u32,
+
+    /// The associated token account of the distributor holding the rewards
+    pub vault: Pubkey,
+
+    /// The root of the Merkle tree of claims
+    pub root: [u8; 32],
+
+    /// The maximum amount of tokens that can be claimed
+    pub max_total_claim: u64,
+
+    /// The amount of tokens claimed so far
+    pub total_amount_claimed: u64,
+
+    /// The number of leaves claimed so far
+    pub num_nodes_claimed: u64,
+}
+
+/// Marks a leaf of a distributor as claimed
+///
+/// PDA of `[CLAIM_STATUS_SEED, distributor, index]`
+///
+#[account(zero_copy)]
+#[derive(Default)]
+#[repr(packed)]
+pub struct ClaimStatusState {
+    /// Bump to identify PDA
+    pub bump: u8,
+
+    /// The address which claimed the leaf
+    pub claimant: Pubkey,
+
+    /// The amount of tokens claimed
+    pub amount: u64,
+}
+
+/// Returns the leaf of a claim in the Merkle tree of a distributor
+///
+/// # Arguments
+///
+/// * `index` - The position of the claim among the leaves
+/// * `claimant` - The address entitled to the claim
+/// * `amount` - The amount of tokens owed to the claimant
+///
+pub fn leaf(index: u64, claimant: &Pubkey, amount: u64) -> [u8; 32] {
+    hashv(&[
+        &index.to_le_bytes(),
+        claimant.as_ref(),
+        &amount.to_le_bytes(),
+    ])
+    .to_bytes()
+}
+
+/// Returns the parent of two nodes of a Merkle tree. Nodes are hashed in sorted order, so that
+/// proofs need not record whether a sibling is on the left or right
+///
+/// # Arguments
+///
+/// * `a` - A node
+/// * `b` - Its sibling
+///
+pub fn hash_pair(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
+    if a <= b {
+        hashv(&[a, b]).to_bytes()
+    } else {
+        hashv(&[b, a]).to_bytes()
+    }
+}
+
+/// Returns true if a leaf belongs to the Merkle tree with the given root
+///
+/// # Arguments
+///
+/// * `proof` - The siblings of the nodes on the path from the leaf to the root
+/// * `root` - The root of the tree
+/// * `leaf` - The leaf to verify
+///
+pub fn verify_proof(proof: &[[u8; 32]], root: &[u8; 32], leaf: [u8; 32]) -> bool {
+    proof
+        .iter()
+        .fold(leaf, |node, sibling| hash_pair(&node, sibling))
+        == *root
+}
+
+/// Emitted when a distributor is created
+#[event]
+pub struct DistributorCreated {
+    /// The created distributor
+    #[index]
+    pub distributor_state: Pubkey,
+
+    /// The pool whose liquidity providers are rewarded
+    #[index]
+    pub pool_state: Pubkey,
+
+    /// The token being distributed
+    pub reward_mint: Pubkey,
+
+    /// Distinguishes distributions of the same token to the same pool
+    pub distribution_index: u32,
+
+    /// The root of the Merkle tree of claims
+    pub root: [u8; 32],
+
+    /// The maximum amount of tokens that can be claimed
+    pub max_total_claim: u64,
+}
+
+/// Emitted when tokens are claimed from a distributor
+#[event]
+pub struct ClaimEvent {
+    /// The distributor paying the claim
+    #[index]
+    pub distributor_state: Pubkey,
+
+    /// The position of the claim among the leaves
+    pub index: u64,
+
+    /// The address which claimed the tokens
+    #[index]
+    pub claimant: Pubkey,
+
+    /// The amount of tokens claimed
+    pub amount: u64,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn verifies_proofs_of_every_leaf() {
+        let claimants: Vec<Pubkey> = (0..3).map(|_| Pubkey::new_unique()).collect();
+        let leaves: Vec<[u8; 32]> = claimants
+            .iter()
+            .enumerate()
+            .map(|(i, claimant)| leaf(i as u64, claimant, 100 * (i as u64 + 1)))
+            .collect();
+        // the odd leaf is carried up to the next layer
+        let node_01 = hash_pair(&leaves[0], &leaves[1]);
+        let root = hash_pair(&node_01, &leaves[2]);
+
+        assert!(verify_proof(&[leaves[1], leaves[2]], &root, leaves[0]));
+        assert!(verify_proof(&[leaves[0], leaves[2]], &root, leaves[1]));
+        assert!(verify_proof(&[node_01], &root, leaves[2]));
+    }
+
+    #[test]
+    fn rejects_altered_claims() {
+        let claimant = Pubkey::new_unique();
+        let other = leaf(1, &Pubkey::new_unique(), 50);
+        let root = hash_pair(&leaf(0, &claimant, 100), &other);
+
+        assert!(verify_proof(&[other], &root, leaf(0, &claimant, 100)));
+        assert!(!verify_proof(&[other], &root, leaf(0, &claimant, 101)));
+        assert!(!verify_proof(&[other], &root, leaf(2, &claimant, 100)));
+        assert!(!verify_proof(
+            &[other],
+            &root,
+            leaf(0, &Pubkey::new_unique(), 100)
+        ));
+    }
+
+    #[test]
+    fn hashes_pairs_in_sorted_order() {
+        let a = [1; 32];
+        let b = [2; 32];
+        assert_eq!(hash_pair(&a, &b), hash_pair(&b, &a));
+        assert_ne!(hash_pair(&a, &b), hash_pair(&a, &a));
+    }
+}
@@ -0,0 +1,201 @@
+use crate::error::ErrorCode;
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::hash::hash;
+
+/// The Tribeca locked voter program, which holds the escrows of governance token lockers
+pub mod locked_voter {
+    use anchor_lang::prelude::*;
+
+    declare_id!("LocktDzaV1W2Bm9DeZeiyz4J9zs4fRqNiYqQyracRXw");
+}
+
+/// Governance tokens locked by an owner in a Tribeca locker, mirroring the layout of the
+/// `Escrow` account of the locked voter program
+///
+/// PDA of `["Escrow", locker, owner]` for the locked voter program
+///
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct Escrow {
+    /// The locker holding the escrow
+    pub locker: Pubkey,
+
+    /// The address owning the locked tokens
+    pub owner: Pubkey,
+
+    /// Bump to identify PDA
+    pub bump: u8,
+
+    /// The token account holding the locked tokens
+    pub tokens: Pubkey,
+
+    /// The amount of governance tokens locked
+    pub amount: u64,
+
+    /// The time when the tokens were locked
+    pub escrow_started_at: i64,
+
+    /// The time when the tokens unlock
+    pub escrow_ends_at: i64,
+
+    /// The address voting on behalf of the owner
+    pub vote_delegate: Pubkey,
+}
+
+/// Splits off the escrow optionally passed after the remaining accounts of a swap, earning a
+/// swap fee discount. It is told apart from bitmap, tick and observation accounts by its owner
+///
+/// # Arguments
+///
+/// * `remaining_accounts` - Remaining accounts of the swap
+///
+pub fn split_escrow<'c, 'info>(
+    remaining_accounts: &'c [AccountInfo<'info>],
+) -> (&'c [AccountInfo<'info>], Option<&'c AccountInfo<'info>>) {
+    match remaining_accounts.split_last() {
+        Some((last, accounts)) if *last.owner == locked_voter::ID => (accounts, Some(last)),
+        _ => (remaining_accounts, None),
+    }
+}
+
+impl Escrow {
+    /// Returns the anchor discriminator of escrow accounts
+    pub fn discriminator() -> [u8; 8] {
+        let mut discriminator = [0; 8];
+        discriminator.copy_from_slice(&hash(b"account:Escrow").to_bytes()[..8]);
+        discriminator
+    }
+
+    /// Reads an escrow account passed to a swap
+    ///
+    /// # Arguments
+    ///
+    /// * `account` - The escrow account
+    ///
+    pub fn load(account: &AccountInfo) -> Result<Escrow> {
+        require!(
+            *account.owner == locked_voter::ID,
+            ErrorCode::InvalidEscrow
+        );
+        let data = account.try_borrow_data()?;
+        require!(
+            data.len() >= 8 && data[..8] == Escrow::discriminator(),
+            ErrorCode::InvalidEscrow
+        );
+        let escrow = Escrow::deserialize(&mut &data[8..]).map_err(|_| ErrorCode::InvalidEscrow)?;
+        Ok(escrow)
+    }
+
+    /// Returns the time left before the tokens unlock, or zero once unlocked
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The current time
+    ///
+    pub fn duration_left(&self, now: i64) -> i64 {
+        (self.escrow_ends_at - now).max(0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn account_data(escrow: &Escrow) -> Vec<u8> {
+        let mut data = Escrow::discriminator().to_vec();
+        data.extend(escrow.try_to_vec().unwrap());
+        data
+    }
+
+    #[test]
+    fn reads_escrows_of_the_locked_voter_program() {
+        let key = Pubkey::new_unique();
+        let escrow = Escrow {
+            locker: Pubkey::new_unique(),
+            owner: Pubkey::new_unique(),
+            amount: 1_000,
+            escrow_ends_at: 500,
+            ..Default::default()
+        };
+        let mut data = account_data(&escrow);
+        let mut lamports = 0;
+        let account = AccountInfo::new(
+            &key,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            &locked_voter::ID,
+            false,
+            0,
+        );
+        assert_eq!(Escrow::load(&account).unwrap(), escrow);
+    }
+
+    #[test]
+    fn rejects_accounts_of_other_programs() {
+        let key = Pubkey::new_unique();
+        let mut data = account_data(&Escrow::default());
+        let mut lamports = 0;
+        let owner = Pubkey::new_unique();
+        let account = AccountInfo::new(
+            &key,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            &owner,
+            false,
+            0,
+        );
+        assert!(Escrow::load(&account).is_err());
+    }
+
+    #[test]
+    fn splits_off_a_trailing_escrow() {
+        let keys = [Pubkey::new_unique(), Pubkey::new_unique()];
+        let (mut lamports_0, mut lamports_1) = (0, 0);
+        let (mut data_0, mut data_1) = (vec![], vec![]);
+        let tick = AccountInfo::new(
+            &keys[0],
+            false,
+            true,
+            &mut lamports_0,
+            &mut data_0,
+            &crate::ID,
+            false,
+            0,
+        );
+        let escrow = AccountInfo::new(
+            &keys[1],
+            false,
+            false,
+            &mut lamports_1,
+            &mut data_1,
+            &locked_voter::ID,
+            false,
+            0,
+        );
+
+        let accounts = [tick.clone(), escrow];
+        let (swap_accounts, escrow) = split_escrow(&accounts);
+        assert_eq!(swap_accounts.len(), 1);
+        assert_eq!(escrow.unwrap().key(), keys[1]);
+
+        let accounts = [tick];
+        let (swap_accounts, escrow) = split_escrow(&accounts);
+        assert_eq!(swap_accounts.len(), 1);
+        assert!(escrow.is_none());
+        assert!(split_escrow(&[]).1.is_none());
+    }
+
+    #[test]
+    fn counts_no_duration_once_unlocked() {
+        let escrow = Escrow {
+            escrow_ends_at: 500,
+            ..Default::default()
+        };
+        assert_eq!(escrow.duration_left(200), 300);
+        assert_eq!(escrow.duration_left(500), 0);
+        assert_eq!(escrow.duration_left(800), 0);
+    }
+}
@@ -1,12 +1,32 @@
+use crate::states::escrow::Escrow;
 use anchor_lang::prelude::*;
 
-/// Holds the current owner of the factory
+/// Holds the current owner of the factory, and the holders of its roles
 ///
 /// # The owner can
 ///
-/// 1. Set and collect a pool's protocol fees
-/// 2. Enable a new fee amount for pool creation
-/// 3. Set another address as an owner
+/// 1. Propose another address as an owner, who takes over once accepting
+/// 2. Hand each role to another address
+/// 3. Set the timelock delay
+///
+/// Proposing an owner, handing over a role, enabling a fee amount, setting the protocol fee
+/// for new pools or for a pool, setting the swap fee discounts and setting the timelock delay
+/// are queued behind the timelock.
+///
+/// # Roles
+///
+/// 1. The fee tier admin enables new fee amounts for pool creation
+/// 2. The protocol fee admin sets the protocol fee, for new pools and for each pool
+/// 3. The fee collector collects a pool's protocol fees
+/// 4. The pause guardian pauses the program during an incident
+///
+/// The protocol fee admin also sets the swap fee discounts given to governance token lockers.
+///
+/// # Governance
+///
+/// The owner and role holders only ever sign, and rent is paid by separate payer accounts.
+/// They can therefore be PDAs of other programs, such as a Goki smart wallet executing the
+/// proposals passed by a Tribeca governor.
 ///
 /// PDA of `[]`
 ///
@@ -20,8 +40,165 @@ pub struct FactoryState {
     /// Address of the protocol owner
     pub owner: Pubkey,
 
-    /// The global protocol fee
+    /// The denominator of the protocol's share of the swap fees given to new pools, e.g. 3
+    /// for 1/3, held by factories created before `default_fee_protocol`. Only read by
+    /// `migrate_factory`, with `fee_protocol_share()`
     pub fee_protocol: u8,
+
+    /// The protocol's share of the swap fees given to new pools, in hundredths of a bip,
+    /// or 0 if the protocol fee is off
+    pub default_fee_protocol: u32,
+
+    /// Address proposed as the next owner, or the default address if no transfer is pending
+    pub pending_owner: Pubkey,
+
+    /// Address allowed to enable fee amounts
+    pub fee_tier_admin: Pubkey,
+
+    /// Address allowed to set protocol fees
+    pub protocol_fee_admin: Pubkey,
+
+    /// Address allowed to collect protocol fees
+    pub fee_collector: Pubkey,
+
+    /// Address allowed to pause the program
+    pub pause_guardian: Pubkey,
+
+    /// Operations paused for every pool, as `PAUSE_*` flags
+    pub paused: u8,
+    /// Delay in seconds between queuing an admin action and executing it
+    pub timelock_delay: u32,
+
+    /// The Tribeca locker whose escrows earn swap fee discounts, or the default address
+    /// if discounts are off
+    pub locker: Pubkey,
+
+    /// Swap fee discounts by amount and duration locked. The largest discount among the
+    /// tiers met by an escrow applies
+    pub fee_discount_tiers: [FeeDiscountTier; FEE_DISCOUNT_TIER_NUM],
+}
+
+/// Size of factory accounts created before roles were added, without the discriminator.
+/// These end after the legacy `fee_protocol`, and are grown by `migrate_factory`
+pub const LEGACY_FACTORY_STATE_LEN: usize = 34;
+
+/// The number of swap fee discount tiers
+pub const FEE_DISCOUNT_TIER_NUM: usize = 4;
+
+/// The largest swap fee discount, half of the swap fee
+pub const MAX_FEE_DISCOUNT: u32 = 500_000;
+
+/// A swap fee discount for traders locking governance tokens
+#[zero_copy]
+#[derive(Default, Debug, PartialEq, Eq, AnchorSerialize, AnchorDeserialize)]
+pub struct FeeDiscountTier {
+    /// The least amount of governance tokens locked
+    pub min_amount: u64,
+
+    /// The least time left before the tokens unlock, in seconds
+    pub min_duration: u32,
+
+    /// The discount on the swap fee, in hundredths of a bip of the fee. Zero for an unused tier
+    pub discount: u32,
+}
+
+/// Pauses swaps and flash loans
+pub const PAUSE_SWAP: u8 = 1 << 0;
+
+/// Pauses minting liquidity
+pub const PAUSE_MINT: u8 = 1 << 1;
+
+/// Pauses every operation but burns and collects, so that liquidity providers can always exit
+pub const PAUSE_ALL: u8 = PAUSE_SWAP | PAUSE_MINT;
+
+/// Powers of the factory held apart from ownership
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    FeeTierAdmin,
+    ProtocolFeeAdmin,
+    FeeCollector,
+    PauseGuardian,
+}
+
+/// The denominator of protocol fee shares, which are in hundredths of a bip
+pub const FEE_PROTOCOL_DENOMINATOR: u32 = 1_000_000;
+
+/// The largest protocol fee share, half of the swap fees
+pub const MAX_FEE_PROTOCOL: u32 = 500_000;
+
+impl FactoryState {
+    /// Returns the legacy protocol fee denominator as a share, in hundredths of a bip
+    ///
+    pub fn fee_protocol_share(&self) -> u32 {
+        match self.fee_protocol {
+            0 => 0,
+            fee_protocol => FEE_PROTOCOL_DENOMINATOR / fee_protocol as u32,
+        }
+    }
+
+    /// Returns the discount on swap fees for an escrow, in hundredths of a bip of the fee.
+    /// Escrows of other lockers and unlocked escrows get no discount
+    ///
+    /// # Arguments
+    ///
+    /// * `escrow` - The escrow of the trader
+    /// * `now` - The current time
+    ///
+    pub fn fee_discount(&self, escrow: &Escrow, now: i64) -> u32 {
+        let duration_left = escrow.duration_left(now);
+        if self.locker == Pubkey::default() || escrow.locker != self.locker || duration_left == 0 {
+            return 0;
+        }
+        let fee_discount_tiers = self.fee_discount_tiers;
+        fee_discount_tiers
+            .iter()
+            .filter(|tier| {
+                escrow.amount >= tier.min_amount && duration_left >= tier.min_duration as i64
+            })
+            .map(|tier| tier.discount)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Returns the swap fee of a pool after the discount of an escrow, in hundredths of a bip
+    ///
+    /// # Arguments
+    ///
+    /// * `fee` - The fee of the pool
+    /// * `escrow` - The escrow of the trader, if any
+    /// * `now` - The current time
+    ///
+    pub fn discounted_fee(&self, fee: u32, escrow: Option<&Escrow>, now: i64) -> u32 {
+        let discount = match escrow {
+            Some(escrow) => self.fee_discount(escrow, now),
+            None => return fee,
+        };
+        // the discount is rounded down, in favor of liquidity providers
+        fee - (fee as u64 * discount as u64 / FEE_PROTOCOL_DENOMINATOR as u64) as u32
+    }
+
+    /// Hands a role to another address, returning the previous holder
+    ///
+    /// # Arguments
+    ///
+    /// * `role` - The role to hand over
+    /// * `holder` - The new holder of the role
+    ///
+    pub fn set_role(&mut self, role: Role, holder: Pubkey) -> Pubkey {
+        let holder_old = match role {
+            Role::FeeTierAdmin => self.fee_tier_admin,
+            Role::ProtocolFeeAdmin => self.protocol_fee_admin,
+            Role::FeeCollector => self.fee_collector,
+            Role::PauseGuardian => self.pause_guardian,
+        };
+        match role {
+            Role::FeeTierAdmin => self.fee_tier_admin = holder,
+            Role::ProtocolFeeAdmin => self.protocol_fee_admin = holder,
+            Role::FeeCollector => self.fee_collector = holder,
+            Role::PauseGuardian => self.pause_guardian = holder,
+        }
+        holder_old
+    }
 }
 
 /// Emitted when the owner of the factory is changed
@@ -36,12 +213,206 @@ pub struct OwnerChanged {
     pub new_owner: Pubkey,
 }
 
+/// Emitted when an address is proposed as the next owner of the factory
+#[event]
+pub struct OwnerProposed {
+    /// The current owner
+    #[index]
+    pub owner: Pubkey,
+
+    /// The address that becomes owner once accepting
+    #[index]
+    pub pending_owner: Pubkey,
+}
+
+/// Emitted when a role of the factory is handed to another address
+#[event]
+pub struct RoleChanged {
+    /// The role handed over
+    pub role: Role,
+
+    /// The holder before the role was changed
+    #[index]
+    pub old_holder: Pubkey,
+
+    /// The holder after the role was changed
+    #[index]
+    pub new_holder: Pubkey,
+}
+
+/// Emitted when the pause guardian changes the operations paused, globally or for a pool
+#[event]
+pub struct PauseStateChanged {
+    /// The pool whose operations are paused, or the default address if paused globally
+    #[index]
+    pub pool_state: Pubkey,
+
+    /// The paused operations before the change
+    pub paused_old: u8,
+
+    /// The paused operations after the change
+    pub paused: u8,
+}
+
+/// Emitted when the swap fee discounts for governance token lockers are changed
+#[event]
+pub struct SetFeeDiscountsEvent {
+    /// The locker whose escrows earn discounts
+    pub locker: Pubkey,
+
+    /// The updated discount tiers
+    pub fee_discount_tiers: [FeeDiscountTier; FEE_DISCOUNT_TIER_NUM],
+}
+
+/// Emitted when the protocol fee given to new pools is changed
+#[event]
+pub struct SetDefaultFeeProtocolEvent {
+    /// The previous value of the protocol fee, in hundredths of a bip
+    pub fee_protocol_old: u32,
+
+    /// The updated value of the protocol fee, in hundredths of a bip
+    pub fee_protocol: u32,
+}
+
 /// Emitted when the protocol fee is changed for a pool
 #[event]
 pub struct SetFeeProtocolEvent {
-    /// The previous value of the protocol fee
-    pub fee_protocol_old: u8,
+    /// The pool whose protocol fee is changed
+    #[index]
+    pub pool_state: Pubkey,
 
-    /// The updated value of the protocol fee
-    pub fee_protocol: u8,
+    /// The previous value of the token_0 protocol fee, in hundredths of a bip
+    pub fee_protocol_0_old: u32,
+
+    /// The previous value of the token_1 protocol fee, in hundredths of a bip
+    pub fee_protocol_1_old: u32,
+
+    /// The updated value of the token_0 protocol fee, in hundredths of a bip
+    pub fee_protocol_0: u32,
+
+    /// The updated value of the token_1 protocol fee, in hundredths of a bip
+    pub fee_protocol_1: u32,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn appends_fields_after_the_legacy_layout() {
+        let factory_state = FactoryState::default();
+        let start = std::ptr::addr_of!(factory_state) as usize;
+        assert_eq!(
+            std::ptr::addr_of!(factory_state.default_fee_protocol) as usize - start,
+            LEGACY_FACTORY_STATE_LEN
+        );
+    }
+
+    #[test]
+    fn reads_the_legacy_denominator_as_a_share() {
+        let mut factory_state = FactoryState::default();
+        assert_eq!(factory_state.fee_protocol_share(), 0);
+
+        factory_state.fee_protocol = 3;
+        assert_eq!(factory_state.fee_protocol_share(), 333_333);
+
+        factory_state.fee_protocol = 2;
+        assert_eq!(factory_state.fee_protocol_share(), MAX_FEE_PROTOCOL);
+    }
+
+    fn discounting_factory(locker: Pubkey) -> FactoryState {
+        let mut factory_state = FactoryState {
+            locker,
+            ..Default::default()
+        };
+        factory_state.fee_discount_tiers[0] = FeeDiscountTier {
+            min_amount: 1_000,
+            min_duration: 100,
+            discount: 100_000,
+        };
+        factory_state.fee_discount_tiers[1] = FeeDiscountTier {
+            min_amount: 10_000,
+            min_duration: 1_000,
+            discount: 250_000,
+        };
+        factory_state
+    }
+
+    #[test]
+    fn applies_the_largest_discount_met() {
+        let locker = Pubkey::new_unique();
+        let factory_state = discounting_factory(locker);
+        let escrow = |amount, escrow_ends_at| Escrow {
+            locker,
+            amount,
+            escrow_ends_at,
+            ..Default::default()
+        };
+
+        assert_eq!(factory_state.fee_discount(&escrow(999, 1_000), 0), 0);
+        assert_eq!(factory_state.fee_discount(&escrow(1_000, 99), 0), 0);
+        assert_eq!(factory_state.fee_discount(&escrow(1_000, 100), 0), 100_000);
+        // a large amount locked briefly only meets the lower tier
+        assert_eq!(factory_state.fee_discount(&escrow(50_000, 500), 0), 100_000);
+        assert_eq!(factory_state.fee_discount(&escrow(50_000, 1_500), 0), 250_000);
+        // the duration is counted from now
+        assert_eq!(factory_state.fee_discount(&escrow(50_000, 1_500), 1_000), 100_000);
+        assert_eq!(factory_state.fee_discount(&escrow(50_000, 1_500), 1_500), 0);
+    }
+
+    #[test]
+    fn ignores_escrows_of_other_lockers() {
+        let escrow = Escrow {
+            locker: Pubkey::new_unique(),
+            amount: 50_000,
+            escrow_ends_at: 5_000,
+            ..Default::default()
+        };
+        assert_eq!(
+            discounting_factory(Pubkey::new_unique()).fee_discount(&escrow, 0),
+            0
+        );
+        // discounts are off until a locker is set
+        let mut factory_state = discounting_factory(Pubkey::default());
+        assert_eq!(factory_state.fee_discount(&Escrow::default(), 0), 0);
+
+        factory_state.locker = escrow.locker;
+        assert_eq!(factory_state.fee_discount(&escrow, 0), 250_000);
+    }
+
+    #[test]
+    fn discounts_the_pool_fee() {
+        let locker = Pubkey::new_unique();
+        let factory_state = discounting_factory(locker);
+        let escrow = Escrow {
+            locker,
+            amount: 50_000,
+            escrow_ends_at: 5_000,
+            ..Default::default()
+        };
+        assert_eq!(factory_state.discounted_fee(3_000, None, 0), 3_000);
+        assert_eq!(factory_state.discounted_fee(3_000, Some(&escrow), 0), 2_250);
+        // rounded in favor of liquidity providers
+        assert_eq!(factory_state.discounted_fee(1, Some(&escrow), 0), 1);
+    }
+
+    #[test]
+    fn sets_each_role_apart() {
+        let mut factory_state = FactoryState::default();
+        let fee_collector = Pubkey::new_unique();
+
+        assert_eq!(
+            factory_state.set_role(Role::FeeCollector, fee_collector),
+            Pubkey::default()
+        );
+        assert_eq!({ factory_state.fee_collector }, fee_collector);
+        assert_eq!({ factory_state.fee_tier_admin }, Pubkey::default());
+        assert_eq!({ factory_state.protocol_fee_admin }, Pubkey::default());
+        assert_eq!({ factory_state.pause_guardian }, Pubkey::default());
+
+        assert_eq!(
+            factory_state.set_role(Role::FeeCollector, Pubkey::default()),
+            fee_collector
+        );
+    }
 }
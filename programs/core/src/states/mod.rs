@@ -1,3 +1,4 @@
+pub mod escrow;
 pub mod factory;
 pub mod fee;
 pub mod oracle;
@@ -5,6 +6,7 @@ pub mod pool;
 pub mod position;
 pub mod tick;
 pub mod tick_bitmap;
+pub mod timelock;
 
 // Non fungible position manager
 pub mod position_manager;
@@ -12,3 +14,9 @@ pub mod tokenized_position;
 
 // Swap router
 pub mod swap_router;
+
+// Staker
+pub mod staker;
+
+// Merkle distributor
+pub mod distributor;
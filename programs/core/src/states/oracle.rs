@@ -12,6 +12,7 @@
 /// The most recent observation is available, independent of the length of the oracle array,
 /// by passing 0 as the index seed.
 ///
+use crate::error::ErrorCode;
 use anchor_lang::prelude::*;
 
 /// Seed to derive account address and signature
@@ -126,6 +127,277 @@ impl ObservationState {
     }
 }
 
+/// Comparator for 32-bit timestamps, safe for 0 or 1 overflows
+///
+/// Returns true if `a` is chronologically less than or equal to `b`
+///
+/// # Arguments
+///
+/// * `time` - A timestamp truncated to 32 bits
+/// * `a` - A comparison timestamp from which to determine the relative position of `time`
+/// * `b` - From which to determine the relative position of `time`
+///
+pub fn lte(time: u32, a: u32, b: u32) -> bool {
+    // if there hasn't been overflow, no need to adjust
+    if a <= time && b <= time {
+        return a <= b;
+    }
+
+    let a_adjusted = if a > time {
+        a as u64
+    } else {
+        a as u64 + (1 << 32)
+    };
+    let b_adjusted = if b > time {
+        b as u64
+    } else {
+        b as u64 + (1 << 32)
+    };
+
+    a_adjusted <= b_adjusted
+}
+
+/// Fetches the observations `before_or_at` and `at_or_after` a target, i.e. where
+/// [before_or_at, at_or_after] is satisfied.
+///
+/// The result may be the same observation, or adjacent observations.
+/// The answer must be contained in the array, used when the target is located within the
+/// stored observation boundaries: older than the most recent observation and younger, or the
+/// same age as, the oldest observation
+///
+/// # Arguments
+///
+/// * `observations` - The oracle array, ordered by observation index
+/// * `time` - The current block.timestamp
+/// * `target` - The timestamp at which the reserved observation should be for
+/// * `index` - The index of the observation that was most recently written to the observations array
+/// * `cardinality` - The number of populated elements in the oracle array
+///
+pub fn binary_search(
+    observations: &[ObservationState],
+    time: u32,
+    target: u32,
+    index: u16,
+    cardinality: u16,
+) -> (ObservationState, ObservationState) {
+    let cardinality = cardinality as usize;
+    let mut l = (index as usize + 1) % cardinality; // oldest observation
+    let mut r = l + cardinality - 1; // newest observation
+    loop {
+        let i = (l + r) / 2;
+        let before_or_at = observations[i % cardinality];
+
+        // we've landed on an uninitialized observation, keep searching higher (more recently)
+        if !before_or_at.initialized {
+            l = i + 1;
+            continue;
+        }
+
+        let at_or_after = observations[(i + 1) % cardinality];
+
+        let target_at_or_after = lte(time, before_or_at.block_timestamp, target);
+
+        // check if we've found the answer!
+        if target_at_or_after && lte(time, target, at_or_after.block_timestamp) {
+            return (before_or_at, at_or_after);
+        }
+
+        if !target_at_or_after {
+            r = i - 1;
+        } else {
+            l = i + 1;
+        }
+    }
+}
+
+/// Fetches the observations `before_or_at` and `at_or_after` a given target, i.e. where
+/// [before_or_at, at_or_after] is satisfied
+///
+/// Assumes there is at least 1 initialized observation.
+/// Used by `observe_single()` to compute the counterfactual accumulator values as of a
+/// given block timestamp.
+///
+/// # Arguments
+///
+/// * `observations` - The oracle array, ordered by observation index
+/// * `time` - The current block.timestamp
+/// * `target` - The timestamp at which the reserved observation should be for
+/// * `tick` - The active tick at the time of the returned or simulated observation
+/// * `index` - The index of the observation that was most recently written to the observations array
+/// * `liquidity` - The total pool liquidity at the time of the call
+/// * `cardinality` - The number of populated elements in the oracle array
+///
+pub fn get_surrounding_observations(
+    observations: &[ObservationState],
+    time: u32,
+    target: u32,
+    tick: i32,
+    index: u16,
+    liquidity: u64,
+    cardinality: u16,
+) -> Result<(ObservationState, ObservationState)> {
+    // optimistically set before to the newest observation
+    let before_or_at = observations[index as usize];
+
+    // if the target is chronologically at or after the newest observation, we can early return
+    if lte(time, before_or_at.block_timestamp, target) {
+        return Ok(if before_or_at.block_timestamp == target {
+            // if newest observation equals target, we're in the same block, so we can ignore at_or_after
+            (before_or_at, before_or_at)
+        } else {
+            // otherwise, we need to transform
+            (
+                before_or_at,
+                before_or_at.transform(target, tick, liquidity),
+            )
+        });
+    }
+
+    // now, set before to the oldest observation
+    let mut before_or_at = observations[(index as usize + 1) % cardinality as usize];
+    if !before_or_at.initialized {
+        before_or_at = observations[0];
+    }
+
+    // ensure that the target is chronologically at or after the oldest observation
+    require!(
+        lte(time, before_or_at.block_timestamp, target),
+        ErrorCode::OLD
+    );
+
+    // if we've reached this point, we have to binary search
+    Ok(binary_search(
+        observations,
+        time,
+        target,
+        index,
+        cardinality,
+    ))
+}
+
+/// Returns the accumulator values as of `seconds_ago` seconds from the given time
+///
+/// Reverts if an observation at or before the desired observation timestamp does not exist.
+/// 0 may be passed as `seconds_ago` to return the current cumulative values.
+/// If called with a timestamp falling between two observations, returns the counterfactual
+/// accumulator values at exactly the timestamp between the two observations.
+///
+/// # Arguments
+///
+/// * `observations` - The oracle array, ordered by observation index
+/// * `time` - The current block timestamp
+/// * `seconds_ago` - The amount of time to look back, in seconds, at which point to return an observation
+/// * `tick` - The current tick
+/// * `index` - The index of the observation that was most recently written to the observations array
+/// * `liquidity` - The current in-range pool liquidity
+/// * `cardinality` - The number of populated elements in the oracle array
+///
+pub fn observe_single(
+    observations: &[ObservationState],
+    time: u32,
+    seconds_ago: u32,
+    tick: i32,
+    index: u16,
+    liquidity: u64,
+    cardinality: u16,
+) -> Result<(i64, u64)> {
+    if seconds_ago == 0 {
+        return Ok(observations[index as usize].observe_latest(time, tick, liquidity));
+    }
+
+    let target = time.wrapping_sub(seconds_ago);
+
+    let (before_or_at, at_or_after) = get_surrounding_observations(
+        observations,
+        time,
+        target,
+        tick,
+        index,
+        liquidity,
+        cardinality,
+    )?;
+
+    Ok(if target == before_or_at.block_timestamp {
+        // we're at the left boundary
+        (
+            before_or_at.tick_cumulative,
+            before_or_at.seconds_per_liquidity_cumulative_x32,
+        )
+    } else if target == at_or_after.block_timestamp {
+        // we're at the right boundary
+        (
+            at_or_after.tick_cumulative,
+            at_or_after.seconds_per_liquidity_cumulative_x32,
+        )
+    } else {
+        // we're in the middle
+        let observation_time_delta = at_or_after
+            .block_timestamp
+            .wrapping_sub(before_or_at.block_timestamp);
+        let target_delta = target.wrapping_sub(before_or_at.block_timestamp);
+        (
+            before_or_at.tick_cumulative
+                + (at_or_after.tick_cumulative - before_or_at.tick_cumulative)
+                    / observation_time_delta as i64
+                    * target_delta as i64,
+            before_or_at
+                .seconds_per_liquidity_cumulative_x32
+                .wrapping_add(
+                    (at_or_after
+                        .seconds_per_liquidity_cumulative_x32
+                        .wrapping_sub(before_or_at.seconds_per_liquidity_cumulative_x32)
+                        as u128
+                        * target_delta as u128
+                        / observation_time_delta as u128) as u64,
+                ),
+        )
+    })
+}
+
+/// Returns the accumulator values as of each time seconds ago from the given time
+/// in the array of `seconds_agos`
+///
+/// Reverts if `seconds_agos` > oldest observation
+///
+/// # Arguments
+///
+/// * `observations` - The oracle array, ordered by observation index
+/// * `time` - The current block timestamp
+/// * `seconds_agos` - Each amount of time to look back, in seconds, at which point to return an observation
+/// * `tick` - The current tick
+/// * `index` - The index of the observation that was most recently written to the observations array
+/// * `liquidity` - The current in-range pool liquidity
+/// * `cardinality` - The number of populated elements in the oracle array
+///
+pub fn observe(
+    observations: &[ObservationState],
+    time: u32,
+    seconds_agos: &[u32],
+    tick: i32,
+    index: u16,
+    liquidity: u64,
+    cardinality: u16,
+) -> Result<(Vec<i64>, Vec<u64>)> {
+    require!(cardinality > 0, ErrorCode::I);
+
+    let mut tick_cumulatives = Vec::with_capacity(seconds_agos.len());
+    let mut seconds_per_liquidity_cumulative_x32s = Vec::with_capacity(seconds_agos.len());
+    for seconds_ago in seconds_agos {
+        let (tick_cumulative, seconds_per_liquidity_cumulative_x32) = observe_single(
+            observations,
+            time,
+            *seconds_ago,
+            tick,
+            index,
+            liquidity,
+            cardinality,
+        )?;
+        tick_cumulatives.push(tick_cumulative);
+        seconds_per_liquidity_cumulative_x32s.push(seconds_per_liquidity_cumulative_x32);
+    }
+    Ok((tick_cumulatives, seconds_per_liquidity_cumulative_x32s))
+}
+
 /// Returns the block timestamp truncated to 32 bits, i.e. mod 2**32
 ///
 pub fn _block_timestamp() -> u32 {
@@ -145,3 +417,153 @@ pub struct IncreaseObservationCardinalityNext {
     /// The updated value of the next observation cardinality
     pub observation_cardinality_next_new: u16,
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Returns the name of the program error
+    fn error_name(error: Error) -> String {
+        match error {
+            Error::AnchorError(error) => error.error_name,
+            Error::ProgramError(error) => error.to_string(),
+        }
+    }
+
+    /// Builds an oracle array by writing one observation per entry in `writes`, each
+    /// being `(seconds elapsed since the previous write, tick, liquidity)`
+    fn oracle_array(
+        initialize_time: u32,
+        cardinality: u16,
+        writes: &[(u32, i32, u64)],
+    ) -> (Vec<ObservationState>, u16, u32) {
+        let mut observations = vec![ObservationState::default(); cardinality as usize];
+        for (i, observation) in observations.iter_mut().enumerate() {
+            observation.index = i as u16;
+        }
+        observations[0].block_timestamp = initialize_time;
+        observations[0].initialized = true;
+
+        let mut index = 0;
+        let mut time = initialize_time;
+        for (elapsed, tick, liquidity) in writes {
+            time += elapsed;
+            let last = observations[index as usize];
+            index = (index + 1) % cardinality;
+            let mut next = last.transform(time, *tick, *liquidity);
+            next.index = index;
+            observations[index as usize] = next;
+        }
+        (observations, index, time)
+    }
+
+    mod lte {
+        use super::*;
+
+        #[test]
+        fn compares_without_overflow() {
+            assert!(lte(100, 10, 20));
+            assert!(lte(100, 20, 20));
+            assert!(!lte(100, 30, 20));
+        }
+
+        #[test]
+        fn compares_across_overflow() {
+            // `a` was written before the timestamp overflowed, `b` after
+            assert!(lte(5, u32::MAX - 5, 2));
+            assert!(!lte(5, 2, u32::MAX - 5));
+        }
+    }
+
+    mod observe {
+        use super::*;
+
+        #[test]
+        fn fails_if_cardinality_is_zero() {
+            assert!(observe(&[], 5, &[0], 0, 0, 0, 0).is_err());
+        }
+
+        #[test]
+        fn single_observation_at_current_time() {
+            let (observations, index, time) = oracle_array(5, 1, &[]);
+            let (tick_cumulatives, seconds_per_liquidity_cumulative_x32s) =
+                observe(&observations, time, &[0], 2, index, 4, 1).unwrap();
+            assert_eq!(tick_cumulatives, vec![0]);
+            assert_eq!(seconds_per_liquidity_cumulative_x32s, vec![0]);
+        }
+
+        #[test]
+        fn single_observation_in_recent_past_counterfactual() {
+            let (observations, index, _) = oracle_array(5, 1, &[]);
+            let (tick_cumulatives, seconds_per_liquidity_cumulative_x32s) =
+                observe(&observations, 8, &[1], 2, index, 4, 1).unwrap();
+            // 2 seconds have passed at tick 2 since the observation
+            assert_eq!(tick_cumulatives, vec![4]);
+            assert_eq!(seconds_per_liquidity_cumulative_x32s, vec![(2 << 32) / 4]);
+        }
+
+        #[test]
+        fn fails_for_single_observation_older_than_oldest() {
+            let (observations, index, _) = oracle_array(5, 1, &[]);
+            let error = observe(&observations, 8, &[4], 2, index, 4, 1).unwrap_err();
+            assert_eq!(error_name(error), "OLD");
+        }
+
+        #[test]
+        fn interpolates_between_two_observations() {
+            // written at 5 and 9, 4 seconds at tick 3 with liquidity 2
+            let (observations, index, time) = oracle_array(5, 2, &[(4, 3, 2)]);
+            assert_eq!(time, 9);
+
+            let (tick_cumulatives, seconds_per_liquidity_cumulative_x32s) =
+                observe(&observations, time, &[1], 7, index, 100, 2).unwrap();
+            // target 8 lies 3 seconds into the 4 second interval
+            assert_eq!(tick_cumulatives, vec![9]);
+            assert_eq!(
+                seconds_per_liquidity_cumulative_x32s,
+                vec![(((4_u64 << 32) / 2) as u128 * 3 / 4) as u64]
+            );
+        }
+
+        #[test]
+        fn returns_boundary_observations() {
+            let (observations, index, time) = oracle_array(5, 2, &[(4, 3, 2)]);
+            let (tick_cumulatives, _) =
+                observe(&observations, time, &[4, 0], 7, index, 100, 2).unwrap();
+            assert_eq!(tick_cumulatives, vec![0, 12]);
+        }
+
+        #[test]
+        fn binary_searches_a_full_ring() {
+            // 5 writes into 4 slots overwrite the first observation
+            let writes = [(2, 1, 1), (3, 2, 1), (4, -1, 1), (1, 5, 1), (2, 0, 1)];
+            let (observations, index, time) = oracle_array(0, 4, &writes);
+            assert_eq!(index, 1);
+            assert_eq!(time, 12);
+
+            // the oldest stored observation is at time 5 (index 2)
+            assert_eq!(
+                error_name(observe(&observations, time, &[8], 0, index, 1, 4).unwrap_err()),
+                "OLD"
+            );
+
+            // observations: t=5 => 2 + 6 = 8, t=9 => 8 - 4 = 4, t=10 => 4 + 5 = 9, t=12 => 9
+            let (tick_cumulatives, _) =
+                observe(&observations, time, &[7, 5, 4, 3, 0], 0, index, 1, 4).unwrap();
+            // t=7 and t=8 interpolate between t=5 and t=9 with slope -1
+            assert_eq!(tick_cumulatives, vec![8, 6, 5, 4, 9]);
+        }
+
+        #[test]
+        fn skips_uninitialized_observations() {
+            // cardinality grew to 4 but only two observations have been written
+            let (observations, index, time) = oracle_array(5, 4, &[(4, 3, 2)]);
+            let (tick_cumulatives, _) = observe(&observations, time, &[2], 3, index, 2, 4).unwrap();
+            assert_eq!(tick_cumulatives, vec![6]);
+            assert_eq!(
+                error_name(observe(&observations, time, &[5], 3, index, 2, 4).unwrap_err()),
+                "OLD"
+            );
+        }
+    }
+}
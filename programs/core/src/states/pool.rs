@@ -1,6 +1,7 @@
 use anchor_lang::prelude::*;
 
 use crate::{
+    libraries::big_num::U128,
     program::CyclosCore,
     states::{
         oracle::{self, OBSERVATION_SEED},
@@ -15,6 +16,16 @@ use super::{oracle::ObservationState, tick::TickState};
 /// Seed to derive account address and signature
 pub const POOL_SEED: &str = "p";
 
+/// Seed to derive the address of a reward vault
+pub const REWARD_VAULT_SEED: &str = "rv";
+
+/// The number of reward tokens a pool can emit to liquidity providers
+pub const REWARD_NUM: usize = 3;
+
+/// Size of pool accounts created before per pool protocol fees were added, without the
+/// discriminator. These end after `unlocked`, and are grown by `migrate_pool`
+pub const LEGACY_POOL_STATE_LEN: usize = 130;
+
 /// The pool state
 ///
 /// PDA of `[POOL_SEED, token_0, token_1, fee]`
@@ -71,9 +82,96 @@ pub struct PoolState {
 
     /// Whether the pool is currently locked to reentrancy
     pub unlocked: bool,
+
+    /// The protocol's share of the swap fees in token_0 and token_1, in hundredths of a bip,
+    /// or 0 if the protocol fee is off. Set to the factory's fee_protocol on pool creation
+    /// or migration
+    pub fee_protocol_0: u32,
+    pub fee_protocol_1: u32,
+
+    /// Operations paused for this pool alone, as `PAUSE_*` flags
+    pub paused: u8,
+
+    /// Reward tokens emitted to in range liquidity, by slot
+    pub reward_infos: [RewardInfo; REWARD_NUM],
+
+    /// The amounts of token_0 and token_1 lent by the outstanding flash loan, to be returned
+    /// by `repay_flash`
+    pub flash_amount_0: u64,
+    pub flash_amount_1: u64,
+}
+
+/// A reward token emitted to in range liquidity of a pool
+#[zero_copy]
+#[derive(Default, Debug)]
+#[repr(packed)]
+pub struct RewardInfo {
+    /// The reward token, or the default address if the slot is unused
+    pub mint: Pubkey,
+
+    /// The pool owned token account holding the rewards
+    ///
+    /// PDA of `[REWARD_VAULT_SEED, pool, mint]`
+    ///
+    pub vault: Pubkey,
+
+    /// The address allowed to set emissions
+    pub authority: Pubkey,
+
+    /// Rewards emitted per second, as a Q32.32
+    pub emissions_per_second_x32: u64,
+
+    /// The reward growth as a Q32.32 number, i.e. rewards emitted per unit of liquidity
+    /// for the entire life of the slot. This value can overflow u64
+    pub reward_growth_global_x32: u64,
+
+    /// The time up to which reward growth is accounted
+    pub last_update_time: u32,
+}
+
+impl RewardInfo {
+    /// Whether the slot holds a reward token
+    pub fn initialized(&self) -> bool {
+        self.mint != Pubkey::default()
+    }
 }
 
 impl PoolState {
+    /// Accrues rewards emitted since the last update to in range liquidity. Must be called
+    /// before the pool liquidity or an emission rate changes
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The pool emitting rewards
+    /// * `time` - The current block timestamp
+    ///
+    pub fn update_reward_infos(&mut self, time: u32) {
+        let liquidity = self.liquidity;
+        for reward_info in self.reward_infos.iter_mut() {
+            if !reward_info.initialized() || time <= reward_info.last_update_time {
+                continue;
+            }
+            if liquidity > 0 {
+                let growth_delta_x32 = U128::from(reward_info.emissions_per_second_x32)
+                    * U128::from(time - reward_info.last_update_time)
+                    / U128::from(liquidity);
+                reward_info.reward_growth_global_x32 = reward_info
+                    .reward_growth_global_x32
+                    .wrapping_add(growth_delta_x32.low_u64());
+            }
+            reward_info.last_update_time = time;
+        }
+    }
+
+    /// Returns the reward growth of each slot, per unit of liquidity
+    pub fn reward_growths_global_x32(&self) -> [u64; REWARD_NUM] {
+        let mut reward_growths_global_x32 = [0; REWARD_NUM];
+        for (i, reward_info) in self.reward_infos.iter().enumerate() {
+            reward_growths_global_x32[i] = reward_info.reward_growth_global_x32;
+        }
+        reward_growths_global_x32
+    }
+
     /// Returns the observation index after the currently active one in a liquidity pool
     ///
     /// # Arguments
@@ -98,6 +196,24 @@ impl PoolState {
         } else {
             self.observation_index
         };
+        self.validate_observation_address_at_index(key, bump, index)
+    }
+
+    /// Validates the public key of the observation account at a given index of the oracle array
+    ///
+    /// # Arguments
+    ///
+    /// * `self`- The pool to which the account belongs
+    /// * `key` - The address to validated
+    /// * `bump` - The PDA bump for the address
+    /// * `index` - The index of the observation in the oracle array
+    ///
+    pub fn validate_observation_address_at_index(
+        self,
+        key: &Pubkey,
+        bump: u8,
+        index: u16,
+    ) -> Result<()> {
         let seeds = [
             &OBSERVATION_SEED.as_bytes(),
             self.token_0.as_ref(),
@@ -260,6 +376,7 @@ impl PoolState {
 }
 
 /// A snapshot of the tick cumulative, seconds per liquidity and seconds inside a tick range
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq)]
 pub struct SnapshotCumulative {
     /// The snapshot of the tick accumulator for the range.
     pub tick_cumulative_inside: i64,
@@ -360,3 +477,121 @@ pub struct SwapEvent {
     /// The log base 1.0001 of price of the pool after the swap
     pub tick: i32,
 }
+
+/// Emitted by the pool for any flashes of token_0 or token_1
+#[event]
+pub struct FlashEvent {
+    /// The pool that was borrowed from
+    #[index]
+    pub pool_state: Pubkey,
+
+    /// The address that repaid the flash loan
+    #[index]
+    pub sender: Pubkey,
+
+    /// The amount of token_0 that was flashed
+    pub amount_0: u64,
+
+    /// The amount of token_1 that was flashed
+    pub amount_1: u64,
+
+    /// The amount of token_0 paid as fee, which could exceed the required fee
+    pub paid_0: u64,
+
+    /// The amount of token_1 paid as fee, which could exceed the required fee
+    pub paid_1: u64,
+}
+
+/// Emitted when a reward token is added to a slot of a pool
+#[event]
+pub struct RewardInitialized {
+    /// The pool emitting the reward
+    #[index]
+    pub pool_state: Pubkey,
+
+    /// The slot of the reward
+    pub reward_index: u8,
+
+    /// The reward token
+    pub mint: Pubkey,
+
+    /// The token account holding the rewards
+    pub vault: Pubkey,
+
+    /// The address allowed to set emissions
+    pub authority: Pubkey,
+}
+
+/// Emitted when the emission rate of a reward is changed
+#[event]
+pub struct SetRewardEmissionsEvent {
+    /// The pool emitting the reward
+    #[index]
+    pub pool_state: Pubkey,
+
+    /// The slot of the reward
+    pub reward_index: u8,
+
+    /// The emission rate before the change, as a Q32.32
+    pub emissions_per_second_x32_old: u64,
+
+    /// The emission rate after the change, as a Q32.32
+    pub emissions_per_second_x32: u64,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn appends_fields_after_the_legacy_layout() {
+        let pool_state = PoolState::default();
+        let start = std::ptr::addr_of!(pool_state) as usize;
+        assert_eq!(
+            std::ptr::addr_of!(pool_state.fee_protocol_0) as usize - start,
+            LEGACY_POOL_STATE_LEN
+        );
+    }
+
+    fn pool_with_reward(liquidity: u64, emissions_per_second_x32: u64) -> PoolState {
+        let mut pool_state = PoolState {
+            liquidity,
+            ..Default::default()
+        };
+        pool_state.reward_infos[0] = RewardInfo {
+            mint: Pubkey::new_unique(),
+            emissions_per_second_x32,
+            last_update_time: 100,
+            ..Default::default()
+        };
+        pool_state
+    }
+
+    #[test]
+    fn accrues_emissions_per_unit_of_liquidity() {
+        let mut pool_state = pool_with_reward(1000, 50 << 32);
+        pool_state.update_reward_infos(120);
+        // 20 seconds at 50 per second, shared by 1000 units of liquidity
+        assert_eq!(pool_state.reward_growths_global_x32()[0], 1 << 32);
+        assert_eq!({ pool_state.reward_infos[0].last_update_time }, 120);
+
+        pool_state.update_reward_infos(120);
+        assert_eq!(pool_state.reward_growths_global_x32()[0], 1 << 32);
+    }
+
+    #[test]
+    fn skips_emissions_without_liquidity() {
+        let mut pool_state = pool_with_reward(0, 50 << 32);
+        pool_state.update_reward_infos(120);
+        assert_eq!(pool_state.reward_growths_global_x32()[0], 0);
+        assert_eq!({ pool_state.reward_infos[0].last_update_time }, 120);
+    }
+
+    #[test]
+    fn ignores_unused_slots() {
+        let mut pool_state = pool_with_reward(1000, 50 << 32);
+        pool_state.update_reward_infos(120);
+        assert_eq!({ pool_state.reward_infos[1].last_update_time }, 0);
+        assert_eq!(pool_state.reward_growths_global_x32()[1], 0);
+    }
+}
@@ -2,6 +2,7 @@ use crate::libraries::full_math::MulDiv;
 use crate::{
     error::ErrorCode,
     libraries::{fixed_point_32, liquidity_math},
+    states::pool::REWARD_NUM,
 };
 ///! Positions represent an owner address' liquidity between a lower and upper tick boundary
 ///! Positions store additional state for tracking fees owed to the position
@@ -11,6 +12,10 @@ use anchor_lang::prelude::*;
 /// Seed to derive account address and signature
 pub const POSITION_SEED: &str = "ps";
 
+/// Size of position accounts created before rent payers were recorded, without the
+/// discriminator. These end after `tokens_owed_1`, and are grown by `migrate_position_account`
+pub const LEGACY_POSITION_STATE_LEN: usize = 41;
+
 /// Info stored for each user's position
 ///
 /// PDA of `[POSITION_SEED, token_0, token_1, fee, owner, tick_lower, tick_upper]`
@@ -36,6 +41,47 @@ pub struct PositionState {
 
     /// The fees owed to the position owner in token_1
     pub tokens_owed_1: u64,
+
+    /// Paid rent for the account, and is refunded when the empty position is closed
+    pub payer: Pubkey,
+
+    /// Rewards earned by the position, for each reward slot of the pool
+    pub reward_infos: [PositionRewardInfo; REWARD_NUM],
+}
+
+/// Rewards earned by a position from a reward slot of its pool
+#[zero_copy]
+#[derive(Default, Debug)]
+#[repr(packed)]
+pub struct PositionRewardInfo {
+    /// The reward growth per unit of liquidity inside the position's tick boundaries as of the
+    /// last update to liquidity or rewards owed
+    pub growth_inside_last_x32: u64,
+
+    /// The rewards owed to the position owner
+    pub reward_amount_owed: u64,
+}
+
+impl PositionRewardInfo {
+    /// Credits rewards accumulated by a liquidity amount since the last snapshot, and
+    /// snapshots the current reward growth inside
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The reward info to update
+    /// * `liquidity` - The liquidity that earned the rewards
+    /// * `growth_inside_x32` - The all-time reward growth, per unit of liquidity, inside the
+    ///   position's tick boundaries
+    ///
+    pub fn update(&mut self, liquidity: u64, growth_inside_x32: u64) {
+        let reward_amount = growth_inside_x32
+            .wrapping_sub(self.growth_inside_last_x32)
+            .mul_div_floor(liquidity, fixed_point_32::Q32)
+            .unwrap();
+        // overflow is acceptable, have to withdraw before you hit u64::MAX rewards
+        self.reward_amount_owed = self.reward_amount_owed.wrapping_add(reward_amount);
+        self.growth_inside_last_x32 = growth_inside_x32;
+    }
 }
 
 impl PositionState {
@@ -49,12 +95,15 @@ impl PositionState {
     /// inside the position's tick boundaries
     /// * `fee_growth_inside_1_x32` - The all-time fee growth in token_1, per unit of liquidity,
     /// inside the position's tick boundaries
+    /// * `reward_growths_inside_x32` - The all-time reward growth of each reward slot, per unit
+    ///   of liquidity, inside the position's tick boundaries
     ///
     pub fn update(
         &mut self,
         liquidity_delta: i64,
         fee_growth_inside_0_x32: u64,
         fee_growth_inside_1_x32: u64,
+        reward_growths_inside_x32: [u64; REWARD_NUM],
     ) -> Result<()> {
         let liquidity_next = if liquidity_delta == 0 {
             require!(self.liquidity > 0, ErrorCode::NP); // disallow pokes for 0 liquidity positions
@@ -71,6 +120,12 @@ impl PositionState {
             .mul_div_floor(self.liquidity as u64, fixed_point_32::Q32)
             .unwrap();
 
+        // calculate accumulated rewards
+        let liquidity = self.liquidity;
+        for (i, reward_info) in self.reward_infos.iter_mut().enumerate() {
+            reward_info.update(liquidity, reward_growths_inside_x32[i]);
+        }
+
         // Update the position
         if liquidity_delta != 0 {
             self.liquidity = liquidity_next;
@@ -85,6 +140,18 @@ impl PositionState {
 
         Ok(())
     }
+
+    /// Whether the position holds no liquidity, no tokens owed and no rewards owed, so that its
+    /// account can be closed
+    pub fn is_clear(self) -> bool {
+        self.liquidity == 0
+            && self.tokens_owed_0 == 0
+            && self.tokens_owed_1 == 0
+            && self
+                .reward_infos
+                .iter()
+                .all(|reward_info| reward_info.reward_amount_owed == 0)
+    }
 }
 
 /// Emitted when liquidity is minted for a given position
@@ -172,3 +239,43 @@ pub struct CollectEvent {
     /// The amount of token_1 fees collected
     pub amount_1: u64,
 }
+
+/// Emitted when rewards are collected by the owner of a position
+#[event]
+pub struct CollectRewardEvent {
+    /// The pool from which rewards are collected
+    #[index]
+    pub pool_state: Pubkey,
+
+    /// The owner of the position for which rewards are collected
+    pub owner: Pubkey,
+
+    /// The lower tick of the position
+    #[index]
+    pub tick_lower: i32,
+
+    /// The upper tick of the position
+    #[index]
+    pub tick_upper: i32,
+
+    /// The slot of the collected reward
+    pub reward_index: u8,
+
+    /// The amount of rewards collected
+    pub amount: u64,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn appends_fields_after_the_legacy_layout() {
+        let position_state = PositionState::default();
+        let start = std::ptr::addr_of!(position_state) as usize;
+        assert_eq!(
+            std::ptr::addr_of!(position_state.payer) as usize - start,
+            LEGACY_POSITION_STATE_LEN
+        );
+    }
+}
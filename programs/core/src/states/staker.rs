@@ -0,0 +1,326 @@
+use crate::libraries::big_num::U128;
+use crate::libraries::full_math::MulDiv;
+use anchor_lang::prelude::*;
+
+/// Seed to derive account address and signature
+pub const INCENTIVE_SEED: &str = "in";
+
+/// Seed to derive account address and signature
+pub const DEPOSIT_SEED: &str = "dp";
+
+/// Seed to derive account address and signature
+pub const STAKE_SEED: &str = "sk";
+
+/// The longest time ahead of its start an incentive can be created, 30 days
+pub const MAX_INCENTIVE_START_LEAD_TIME: u32 = 30 * 24 * 60 * 60;
+
+/// The longest duration of an incentive, 2 years
+pub const MAX_INCENTIVE_DURATION: u32 = 2 * 365 * 24 * 60 * 60;
+
+/// A program of rewards for staked position NFTs of a pool, distributed over a period in
+/// proportion to the seconds each stake spent in range, weighted by its liquidity
+///
+/// PDA of `[INCENTIVE_SEED, reward_token, pool_id, start_time, end_time, refundee]`
+///
+#[account(zero_copy)]
+#[derive(Default)]
+#[repr(packed)]
+pub struct IncentiveState {
+    /// Bump to identify PDA
+    pub bump: u8,
+
+    /// The token being distributed as a reward
+    pub reward_token: Pubkey,
+
+    /// The pool whose staked positions are rewarded
+    pub pool_id: Pubkey,
+
+    /// The time when the incentive program begins
+    pub start_time: u32,
+
+    /// The time when rewards stop accruing
+    pub end_time: u32,
+
+    /// The address which receives any remaining reward tokens when the incentive is ended
+    pub refundee: Pubkey,
+
+    /// The associated token account of the incentive holding the rewards
+    pub vault: Pubkey,
+
+    /// The amount of reward tokens not yet claimed by stakes
+    pub total_reward_unclaimed: u64,
+
+    /// The seconds inside the range, weighted by liquidity, for which rewards were claimed,
+    /// as a Q96.32
+    pub total_seconds_claimed_x32: u128,
+
+    /// The number of position NFTs staked in the incentive
+    pub number_of_stakes: u32,
+}
+
+impl IncentiveState {
+    /// Returns the rewards owed to a stake, and the seconds inside the range it is credited
+    /// with as a Q96.32
+    ///
+    /// Unclaimed rewards are shared among the unclaimed seconds of the incentive. Seconds keep
+    /// accruing past the end time, so that stakes withdrawn late get a smaller share.
+    ///
+    /// # Arguments
+    ///
+    /// * `stake` - The stake for which rewards are computed
+    /// * `seconds_per_liquidity_inside_x32` - The seconds per liquidity inside the range of
+    ///   the position, as of the current time
+    /// * `current_time` - The current time, at or after the start of the incentive
+    ///
+    pub fn compute_reward_amount(
+        &self,
+        stake: &StakeState,
+        seconds_per_liquidity_inside_x32: u64,
+        current_time: u32,
+    ) -> (u64, u128) {
+        assert!(current_time >= self.start_time);
+
+        let seconds_inside_x32 = seconds_per_liquidity_inside_x32
+            .wrapping_sub(stake.seconds_per_liquidity_inside_initial_x32)
+            as u128
+            * stake.liquidity as u128;
+        if seconds_inside_x32 == 0 {
+            return (0, 0);
+        }
+
+        let total_seconds_unclaimed_x32 =
+            (((self.end_time.max(current_time) - self.start_time) as u128) << 32)
+                - self.total_seconds_claimed_x32;
+
+        let reward = U128::from(self.total_reward_unclaimed)
+            .mul_div_floor(
+                U128::from(seconds_inside_x32),
+                U128::from(total_seconds_unclaimed_x32),
+            )
+            .unwrap()
+            .as_u64();
+
+        (reward, seconds_inside_x32)
+    }
+}
+
+/// A position NFT held by the program on behalf of its owner, so that it can be staked
+///
+/// PDA of `[DEPOSIT_SEED, mint_address]`
+///
+#[account(zero_copy)]
+#[derive(Default)]
+#[repr(packed)]
+pub struct DepositState {
+    /// Bump to identify PDA
+    pub bump: u8,
+
+    /// The address which deposited the NFT, and can stake or withdraw it
+    pub owner: Pubkey,
+
+    /// Mint address of the tokenized position
+    pub mint: Pubkey,
+
+    /// The pool of the position
+    pub pool_id: Pubkey,
+
+    /// The lower bound tick of the position
+    pub tick_lower: i32,
+
+    /// The upper bound tick of the position
+    pub tick_upper: i32,
+
+    /// The number of incentives the NFT is staked in
+    pub number_of_stakes: u32,
+}
+
+/// A deposited position NFT staked in an incentive
+///
+/// PDA of `[STAKE_SEED, incentive_address, mint_address]`
+///
+#[account(zero_copy)]
+#[derive(Default)]
+#[repr(packed)]
+pub struct StakeState {
+    /// Bump to identify PDA
+    pub bump: u8,
+
+    /// The seconds per liquidity inside the range of the position, as of the last claim
+    pub seconds_per_liquidity_inside_initial_x32: u64,
+
+    /// The liquidity of the position when it was staked
+    pub liquidity: u64,
+}
+
+/// Emitted when an incentive is created
+#[event]
+pub struct IncentiveCreated {
+    /// The token being distributed as a reward
+    #[index]
+    pub reward_token: Pubkey,
+
+    /// The pool whose staked positions are rewarded
+    #[index]
+    pub pool_state: Pubkey,
+
+    /// The time when the incentive program begins
+    pub start_time: u32,
+
+    /// The time when rewards stop accruing
+    pub end_time: u32,
+
+    /// The address which receives any remaining reward tokens when the incentive is ended
+    pub refundee: Pubkey,
+
+    /// The amount of reward tokens to be distributed
+    pub reward: u64,
+}
+
+/// Emitted when an incentive is ended
+#[event]
+pub struct IncentiveEnded {
+    /// The ended incentive
+    #[index]
+    pub incentive_state: Pubkey,
+
+    /// The amount of reward tokens refunded
+    pub refund: u64,
+}
+
+/// Emitted when ownership of a deposit changes, on deposit and withdrawal of a position NFT
+#[event]
+pub struct DepositTransferred {
+    /// The ID of the deposited token
+    #[index]
+    pub token_id: Pubkey,
+
+    /// The owner before the deposit was transferred, or the default key on deposit
+    pub old_owner: Pubkey,
+
+    /// The owner after the deposit was transferred, or the default key on withdrawal
+    pub new_owner: Pubkey,
+}
+
+/// Emitted when a position NFT is staked in an incentive
+#[event]
+pub struct TokenStaked {
+    /// The ID of the staked token
+    #[index]
+    pub token_id: Pubkey,
+
+    /// The incentive in which the token is staked
+    #[index]
+    pub incentive_state: Pubkey,
+
+    /// The amount of liquidity staked
+    pub liquidity: u64,
+}
+
+/// Emitted when a position NFT is unstaked from an incentive
+#[event]
+pub struct TokenUnstaked {
+    /// The ID of the unstaked token
+    #[index]
+    pub token_id: Pubkey,
+
+    /// The incentive in which the token was staked
+    #[index]
+    pub incentive_state: Pubkey,
+}
+
+/// Emitted when rewards of a stake are claimed, including on unstake
+#[event]
+pub struct RewardClaimed {
+    /// The ID of the staked token
+    #[index]
+    pub token_id: Pubkey,
+
+    /// The incentive paying the rewards
+    #[index]
+    pub incentive_state: Pubkey,
+
+    /// The token account that received the rewards
+    pub recipient_wallet: Pubkey,
+
+    /// The amount of reward tokens claimed
+    pub amount: u64,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn incentive() -> IncentiveState {
+        IncentiveState {
+            start_time: 100,
+            end_time: 200,
+            total_reward_unclaimed: 1_000,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn shares_rewards_by_seconds_inside_weighted_by_liquidity() {
+        let stake = StakeState {
+            seconds_per_liquidity_inside_initial_x32: 0,
+            liquidity: 10,
+            ..Default::default()
+        };
+        // in range for 25 of the 100 seconds, as the only liquidity
+        let (reward, seconds_inside_x32) =
+            incentive().compute_reward_amount(&stake, (25 << 32) / 10, 150);
+        assert_eq!(reward, 250);
+        assert_eq!(seconds_inside_x32, ((25_u64 << 32) / 10) as u128 * 10);
+    }
+
+    #[test]
+    fn shares_remaining_rewards_among_unclaimed_seconds() {
+        let mut incentive = incentive();
+        incentive.total_reward_unclaimed = 500;
+        incentive.total_seconds_claimed_x32 = 50 << 32;
+
+        let stake = StakeState {
+            seconds_per_liquidity_inside_initial_x32: 0,
+            liquidity: 1,
+            ..Default::default()
+        };
+        let (reward, _) = incentive.compute_reward_amount(&stake, 50 << 32, 200);
+        assert_eq!(reward, 500);
+    }
+
+    #[test]
+    fn dilutes_rewards_claimed_after_the_end() {
+        let stake = StakeState {
+            seconds_per_liquidity_inside_initial_x32: 0,
+            liquidity: 1,
+            ..Default::default()
+        };
+        let (reward, _) = incentive().compute_reward_amount(&stake, 100 << 32, 400);
+        assert_eq!(reward, 333);
+    }
+
+    #[test]
+    fn owes_nothing_without_seconds_inside() {
+        let stake = StakeState {
+            seconds_per_liquidity_inside_initial_x32: 7 << 32,
+            liquidity: 1_000,
+            ..Default::default()
+        };
+        assert_eq!(
+            incentive().compute_reward_amount(&stake, 7 << 32, 100),
+            (0, 0)
+        );
+    }
+
+    #[test]
+    fn handles_wrapping_seconds_per_liquidity() {
+        let stake = StakeState {
+            seconds_per_liquidity_inside_initial_x32: u64::MAX - (5 << 32) + 1,
+            liquidity: 1,
+            ..Default::default()
+        };
+        let (reward, seconds_inside_x32) = incentive().compute_reward_amount(&stake, 5 << 32, 200);
+        assert_eq!(seconds_inside_x32, 10 << 32);
+        assert_eq!(reward, 100);
+    }
+}
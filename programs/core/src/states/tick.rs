@@ -2,11 +2,16 @@
 ///!
 use crate::error::ErrorCode;
 use crate::libraries::{liquidity_math, tick_math};
+use crate::states::pool::REWARD_NUM;
 use anchor_lang::prelude::*;
 
 /// Seed to derive account address and signature
 pub const TICK_SEED: &str = "t";
 
+/// Size of tick accounts created before rent payers were recorded, without the discriminator.
+/// These end after `seconds_outside`, and are grown by `migrate_tick_account`
+pub const LEGACY_TICK_STATE_LEN: usize = 57;
+
 /// Account storing info for a price tick
 ///
 /// PDA of `[TICK_SEED, token_0, token_1, fee, tick]`
@@ -42,6 +47,36 @@ pub struct TickState {
     /// The seconds spent on the other side of the tick (relative to the current tick)
     /// only has relative meaning, not absolute — the value depends on when the tick is initialized
     pub seconds_outside: u32,
+
+    /// Paid rent for the account, and is refunded when the cleared tick is closed
+    pub payer: Pubkey,
+
+    /// Reward growth per unit of liquidity on the _other_ side of this tick (relative to the current tick),
+    /// for each reward slot of the pool. Only has relative meaning, like fee growth outside
+    pub reward_growths_outside_x32: [u64; REWARD_NUM],
+}
+
+/// The all-time growths of a pool at the current block time, recorded outside a tick as it is
+/// initialized
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GlobalGrowths {
+    /// The all-time global fee growth, per unit of liquidity, in token_0
+    pub fee_growth_global_0_x32: u64,
+
+    /// The all-time global fee growth, per unit of liquidity, in token_1
+    pub fee_growth_global_1_x32: u64,
+
+    /// The all-time seconds per max(1, liquidity) of the pool
+    pub seconds_per_liquidity_cumulative_x32: u64,
+
+    /// The tick * time elapsed since the pool was first initialized
+    pub tick_cumulative: i64,
+
+    /// The current block timestamp cast to a u32
+    pub time: u32,
+
+    /// The all-time reward growth of each reward slot, per unit of liquidity
+    pub reward_growths_global_x32: [u64; REWARD_NUM],
 }
 
 impl TickState {
@@ -53,11 +88,8 @@ impl TickState {
     /// * `tick_current` - The current tick
     /// * `liquidity_delta` - A new amount of liquidity to be added (subtracted) when tick is crossed
     /// from left to right (right to left)
-    /// * `fee_growth_global_0_x32` - The all-time global fee growth, per unit of liquidity, in token_0
-    /// * `fee_growth_global_1_x32` - The all-time global fee growth, per unit of liquidity, in token_1
-    /// * `seconds_per_liquidity_cumulative_x32` - The all-time seconds per max(1, liquidity) of the pool
-    /// * `tick_cumulative` - The tick * time elapsed since the pool was first initialized
-    /// * `time` - The current block timestamp cast to a u32
+    /// * `globals` - The all-time growths of the pool, recorded outside the tick if it is
+    ///   initialized at or below the current tick
     /// * `upper` - true for updating a position's upper tick, or false for updating a position's lower tick
     /// * `max_liquidity` - The maximum liquidity allocation for a single tick
     ///
@@ -65,11 +97,7 @@ impl TickState {
         &mut self,
         tick_current: i32,
         liquidity_delta: i64,
-        fee_growth_global_0_x32: u64,
-        fee_growth_global_1_x32: u64,
-        seconds_per_liquidity_cumulative_x32: u64,
-        tick_cumulative: i64,
-        time: u32,
+        globals: &GlobalGrowths,
         upper: bool,
         max_liquidity: u64,
     ) -> Result<bool> {
@@ -88,11 +116,13 @@ impl TickState {
         if liquidity_gross_before == 0 {
             // by convention, we assume that all growth before a tick was initialized happened _below_ the tick
             if self.tick <= tick_current {
-                self.fee_growth_outside_0_x32 = fee_growth_global_0_x32;
-                self.fee_growth_outside_1_x32 = fee_growth_global_1_x32;
-                self.seconds_per_liquidity_outside_x32 = seconds_per_liquidity_cumulative_x32;
-                self.tick_cumulative_outside = tick_cumulative;
-                self.seconds_outside = time;
+                self.fee_growth_outside_0_x32 = globals.fee_growth_global_0_x32;
+                self.fee_growth_outside_1_x32 = globals.fee_growth_global_1_x32;
+                self.seconds_per_liquidity_outside_x32 =
+                    globals.seconds_per_liquidity_cumulative_x32;
+                self.tick_cumulative_outside = globals.tick_cumulative;
+                self.seconds_outside = globals.time;
+                self.reward_growths_outside_x32 = globals.reward_growths_global_x32;
             }
         }
 
@@ -121,6 +151,7 @@ impl TickState {
     /// * `seconds_per_liquidity_cumulative_x32` - The current seconds per liquidity
     /// * `tick_cumulative` - The tick * time elapsed since the pool was first initialized
     /// * `time` - The current block timestamp
+    /// * `reward_growths_global_x32` - The all-time reward growth of each reward slot, per unit of liquidity
     ///
     pub fn cross(
         &mut self,
@@ -129,6 +160,7 @@ impl TickState {
         seconds_per_liquidity_cumulative_x32: u64,
         tick_cumulative: i64,
         time: u32,
+        reward_growths_global_x32: [u64; REWARD_NUM],
     ) -> i64 {
         self.fee_growth_outside_0_x32 = fee_growth_global_0_x32 - self.fee_growth_outside_0_x32;
         self.fee_growth_outside_1_x32 = fee_growth_global_1_x32 - self.fee_growth_outside_1_x32;
@@ -137,6 +169,13 @@ impl TickState {
         self.tick_cumulative_outside = tick_cumulative - self.tick_cumulative_outside;
         self.seconds_outside = time - self.seconds_outside;
 
+        let mut reward_growths_outside_x32 = self.reward_growths_outside_x32;
+        for (i, reward_growth_outside_x32) in reward_growths_outside_x32.iter_mut().enumerate() {
+            *reward_growth_outside_x32 =
+                reward_growths_global_x32[i].wrapping_sub(*reward_growth_outside_x32);
+        }
+        self.reward_growths_outside_x32 = reward_growths_outside_x32;
+
         self.liquidity_net
     }
 
@@ -154,6 +193,7 @@ impl TickState {
         self.tick_cumulative_outside = 0;
         self.seconds_per_liquidity_outside_x32 = 0;
         self.seconds_outside = 0;
+        self.reward_growths_outside_x32 = [0; REWARD_NUM];
     }
 
     pub fn is_clear(self) -> bool {
@@ -164,6 +204,7 @@ impl TickState {
             && self.tick_cumulative_outside == 0
             && self.seconds_per_liquidity_outside_x32 == 0
             && self.seconds_outside == 0
+            && { self.reward_growths_outside_x32 } == [0; REWARD_NUM]
     }
 }
 
@@ -220,6 +261,42 @@ pub fn get_fee_growth_inside(
     (fee_growth_inside_0_x32, fee_growth_inside_1_x32)
 }
 
+/// Retrieves the all time reward growth of each reward slot, per unit of liquidity, inside a
+/// position's tick boundaries. Computed as fee growth inside, wrapping on overflow
+///
+/// # Arguments
+///
+/// * `tick_lower` - The lower tick boundary of the position
+/// * `tick_upper` - The upper tick boundary of the position
+/// * `tick_current` - The current tick
+/// * `reward_growths_global_x32` - The all-time reward growth of each reward slot, per unit of liquidity
+///
+pub fn get_reward_growths_inside(
+    tick_lower: &TickState,
+    tick_upper: &TickState,
+    tick_current: i32,
+    reward_growths_global_x32: [u64; REWARD_NUM],
+) -> [u64; REWARD_NUM] {
+    let mut reward_growths_inside_x32 = [0; REWARD_NUM];
+    for (i, reward_growth_inside_x32) in reward_growths_inside_x32.iter_mut().enumerate() {
+        let reward_growth_global_x32 = reward_growths_global_x32[i];
+        let reward_growth_below_x32 = if tick_current >= tick_lower.tick {
+            tick_lower.reward_growths_outside_x32[i]
+        } else {
+            reward_growth_global_x32.wrapping_sub(tick_lower.reward_growths_outside_x32[i])
+        };
+        let reward_growth_above_x32 = if tick_current < tick_upper.tick {
+            tick_upper.reward_growths_outside_x32[i]
+        } else {
+            reward_growth_global_x32.wrapping_sub(tick_upper.reward_growths_outside_x32[i])
+        };
+        *reward_growth_inside_x32 = reward_growth_global_x32
+            .wrapping_sub(reward_growth_below_x32)
+            .wrapping_sub(reward_growth_above_x32);
+    }
+    reward_growths_inside_x32
+}
+
 /// Derives max liquidity per tick from given tick spacing
 ///
 /// # Arguments
@@ -241,6 +318,16 @@ pub fn tick_spacing_to_max_liquidity_per_tick(tick_spacing: i32) -> u64 {
 mod test {
     use super::*;
 
+    #[test]
+    fn appends_fields_after_the_legacy_layout() {
+        let tick_state = TickState::default();
+        let start = std::ptr::addr_of!(tick_state) as usize;
+        assert_eq!(
+            std::ptr::addr_of!(tick_state.payer) as usize - start,
+            LEGACY_TICK_STATE_LEN
+        );
+    }
+
     mod tick_spacing_to_max_liquidity_per_tick {
         use super::*;
 
@@ -341,6 +428,8 @@ mod test {
                 tick_cumulative_outside: 0,
                 seconds_per_liquidity_outside_x32: 0,
                 seconds_outside: 0,
+                payer: Pubkey::default(),
+                reward_growths_outside_x32: [0; REWARD_NUM],
             };
             assert_eq!(
                 get_fee_growth_inside(&tick_lower, &tick_upper, 0, 15, 15),
@@ -360,6 +449,8 @@ mod test {
                 tick_cumulative_outside: 0,
                 seconds_per_liquidity_outside_x32: 0,
                 seconds_outside: 0,
+                payer: Pubkey::default(),
+                reward_growths_outside_x32: [0; REWARD_NUM],
             };
             let mut tick_upper = TickState::default();
             tick_upper.tick = 2;
@@ -381,6 +472,8 @@ mod test {
                 tick_cumulative_outside: 0,
                 seconds_per_liquidity_outside_x32: 0,
                 seconds_outside: 0,
+                payer: Pubkey::default(),
+                reward_growths_outside_x32: [0; REWARD_NUM],
             };
             let tick_upper = TickState {
                 bump: 0,
@@ -392,6 +485,8 @@ mod test {
                 tick_cumulative_outside: 0,
                 seconds_per_liquidity_outside_x32: 0,
                 seconds_outside: 0,
+                payer: Pubkey::default(),
+                reward_growths_outside_x32: [0; REWARD_NUM],
             };
             assert_eq!(
                 get_fee_growth_inside(&tick_lower, &tick_upper, 0, 15, 15),
@@ -412,6 +507,8 @@ mod test {
                 tick_cumulative_outside: 0,
                 seconds_per_liquidity_outside_x32: 0,
                 seconds_outside: 0,
+                payer: Pubkey::default(),
+                reward_growths_outside_x32: [0; REWARD_NUM],
             };
             let tick_upper = TickState {
                 bump: 0,
@@ -423,6 +520,8 @@ mod test {
                 tick_cumulative_outside: 0,
                 seconds_per_liquidity_outside_x32: 0,
                 seconds_outside: 0,
+                payer: Pubkey::default(),
+                reward_growths_outside_x32: [0; REWARD_NUM],
             };
             assert_eq!(
                 get_fee_growth_inside(&tick_lower, &tick_upper, 0, 15, 15),
@@ -431,52 +530,120 @@ mod test {
         }
     }
 
+    mod get_reward_growths_inside {
+        use super::*;
+
+        #[test]
+        fn subtracts_growth_outside_the_range() {
+            let tick_lower = TickState {
+                tick: -2,
+                reward_growths_outside_x32: [2, 0, 0],
+                ..Default::default()
+            };
+            let tick_upper = TickState {
+                tick: 2,
+                reward_growths_outside_x32: [3, 0, 0],
+                ..Default::default()
+            };
+
+            // inside, below and above the range
+            assert_eq!(
+                get_reward_growths_inside(&tick_lower, &tick_upper, 0, [15, 15, 0]),
+                [10, 15, 0]
+            );
+            assert_eq!(
+                get_reward_growths_inside(&tick_lower, &tick_upper, -4, [15, 15, 0]),
+                [u64::MAX, 0, 0]
+            );
+            assert_eq!(
+                get_reward_growths_inside(&tick_lower, &tick_upper, 4, [15, 15, 0]),
+                [1, 0, 0]
+            );
+        }
+
+        #[test]
+        fn wraps_on_overflow() {
+            let tick_lower = TickState {
+                tick: -2,
+                reward_growths_outside_x32: [u64::MAX - 3, 0, 0],
+                ..Default::default()
+            };
+            let tick_upper = TickState {
+                tick: 2,
+                reward_growths_outside_x32: [3, 0, 0],
+                ..Default::default()
+            };
+            assert_eq!(
+                get_reward_growths_inside(&tick_lower, &tick_upper, 0, [15, 0, 0]),
+                [16, 0, 0]
+            );
+        }
+    }
+
     mod update {
         use super::*;
 
         #[test]
         fn flips_from_zero_to_non_zero() {
             let mut tick = TickState::default();
-            assert!(tick.update(0, 1, 0, 0, 0, 0, 0, false, 3).unwrap());
+            assert!(tick
+                .update(0, 1, &GlobalGrowths::default(), false, 3)
+                .unwrap());
         }
 
         #[test]
         fn does_not_flip_from_nonzero_to_greater_nonzero() {
             let mut tick = TickState::default();
-            tick.update(0, 1, 0, 0, 0, 0, 0, false, 3).unwrap();
-            assert!(!tick.update(0, 1, 0, 0, 0, 0, 0, false, 3).unwrap());
+            tick.update(0, 1, &GlobalGrowths::default(), false, 3)
+                .unwrap();
+            assert!(!tick
+                .update(0, 1, &GlobalGrowths::default(), false, 3)
+                .unwrap());
         }
 
         #[test]
         fn flips_from_nonzero_to_zero() {
             let mut tick = TickState::default();
-            tick.update(0, 1, 0, 0, 0, 0, 0, false, 3).unwrap();
-            assert!(tick.update(0, -1, 0, 0, 0, 0, 0, false, 3).unwrap());
+            tick.update(0, 1, &GlobalGrowths::default(), false, 3)
+                .unwrap();
+            assert!(tick
+                .update(0, -1, &GlobalGrowths::default(), false, 3)
+                .unwrap());
         }
 
         #[test]
         fn does_not_flip_from_nonzero_to_lesser_nonzero() {
             let mut tick = TickState::default();
-            tick.update(0, 2, 0, 0, 0, 0, 0, false, 3).unwrap();
-            assert!(!tick.update(0, -1, 0, 0, 0, 0, 0, false, 3).unwrap());
+            tick.update(0, 2, &GlobalGrowths::default(), false, 3)
+                .unwrap();
+            assert!(!tick
+                .update(0, -1, &GlobalGrowths::default(), false, 3)
+                .unwrap());
         }
 
         #[test]
         #[should_panic(expected = "LO")]
         fn reverts_if_total_liquidity_gross_is_greater_than_max() {
             let mut tick = TickState::default();
-            tick.update(0, 2, 0, 0, 0, 0, 0, false, 3).unwrap();
-            tick.update(0, 2, 0, 0, 0, 0, 0, false, 3).unwrap();
-            tick.update(0, 1, 0, 0, 0, 0, 0, false, 3).unwrap();
+            tick.update(0, 2, &GlobalGrowths::default(), false, 3)
+                .unwrap();
+            tick.update(0, 2, &GlobalGrowths::default(), false, 3)
+                .unwrap();
+            tick.update(0, 1, &GlobalGrowths::default(), false, 3)
+                .unwrap();
         }
 
         #[test]
         fn nets_the_liquidity_based_on_upper_flag() {
             let mut tick = TickState::default();
-            tick.update(0, 2, 0, 0, 0, 0, 0, false, 3).unwrap();
-            tick.update(0, 1, 0, 0, 0, 0, 0, true, 10).unwrap();
-            tick.update(0, 3, 0, 0, 0, 0, 0, true, 10).unwrap();
-            tick.update(0, 1, 0, 0, 0, 0, 0, false, 10).unwrap();
+            tick.update(0, 2, &GlobalGrowths::default(), false, 3)
+                .unwrap();
+            tick.update(0, 1, &GlobalGrowths::default(), true, 10)
+                .unwrap();
+            tick.update(0, 3, &GlobalGrowths::default(), true, 10)
+                .unwrap();
+            tick.update(0, 1, &GlobalGrowths::default(), false, 10)
+                .unwrap();
 
             assert!(tick.liquidity_gross == 2 + 1 + 3 + 1);
             assert!(tick.liquidity_net == 2 - 1 - 3 + 1);
@@ -486,36 +653,66 @@ mod test {
         #[should_panic]
         fn reverts_on_overflow_liquidity_gross() {
             let mut tick = TickState::default();
-            tick.update(0, (u64::MAX / 2 - 1) as i64, 0, 0, 0, 0, 0, false, u64::MAX)
-                .unwrap();
-            tick.update(0, (u64::MAX / 2 - 1) as i64, 0, 0, 0, 0, 0, false, u64::MAX)
-                .unwrap();
+            tick.update(
+                0,
+                (u64::MAX / 2 - 1) as i64,
+                &GlobalGrowths::default(),
+                false,
+                u64::MAX,
+            )
+            .unwrap();
+            tick.update(
+                0,
+                (u64::MAX / 2 - 1) as i64,
+                &GlobalGrowths::default(),
+                false,
+                u64::MAX,
+            )
+            .unwrap();
         }
 
         #[test]
         fn assume_all_growth_happens_below_ticks_lte_current_tick() {
             let mut tick = TickState::default();
             tick.tick = 1;
-            tick.update(1, 1, 1, 2, 3, 4, 5, false, u64::MAX).unwrap();
+            let globals = GlobalGrowths {
+                fee_growth_global_0_x32: 1,
+                fee_growth_global_1_x32: 2,
+                seconds_per_liquidity_cumulative_x32: 3,
+                tick_cumulative: 4,
+                time: 5,
+                reward_growths_global_x32: [6, 7, 8],
+            };
+            tick.update(1, 1, &globals, false, u64::MAX).unwrap();
 
             assert!(tick.fee_growth_outside_0_x32 == 1);
             assert!(tick.fee_growth_outside_1_x32 == 2);
             assert!(tick.seconds_per_liquidity_outside_x32 == 3);
             assert!(tick.tick_cumulative_outside == 4);
             assert!(tick.seconds_outside == 5);
+            assert!({ tick.reward_growths_outside_x32 } == [6, 7, 8]);
         }
 
         #[test]
         fn does_not_set_any_growth_fields_for_ticks_gt_current_tick() {
             let mut tick = TickState::default();
             tick.tick = 2;
-            tick.update(1, 1, 1, 2, 3, 4, 5, false, u64::MAX).unwrap();
+            let globals = GlobalGrowths {
+                fee_growth_global_0_x32: 1,
+                fee_growth_global_1_x32: 2,
+                seconds_per_liquidity_cumulative_x32: 3,
+                tick_cumulative: 4,
+                time: 5,
+                reward_growths_global_x32: [6, 7, 8],
+            };
+            tick.update(1, 1, &globals, false, u64::MAX).unwrap();
 
             assert!(tick.fee_growth_outside_0_x32 == 0);
             assert!(tick.fee_growth_outside_1_x32 == 0);
             assert!(tick.seconds_per_liquidity_outside_x32 == 0);
             assert!(tick.tick_cumulative_outside == 0);
             assert!(tick.seconds_outside == 0);
+            assert!({ tick.reward_growths_outside_x32 } == [0; REWARD_NUM]);
         }
     }
 
@@ -534,6 +731,8 @@ mod test {
                 tick_cumulative_outside: 6,
                 seconds_per_liquidity_outside_x32: 5,
                 seconds_outside: 7,
+                payer: Pubkey::default(),
+                reward_growths_outside_x32: [0; REWARD_NUM],
             };
             tick.clear();
             assert!(tick.bump == 255);
@@ -562,14 +761,17 @@ mod test {
                 tick_cumulative_outside: 6,
                 seconds_per_liquidity_outside_x32: 5,
                 seconds_outside: 7,
+                payer: Pubkey::default(),
+                reward_growths_outside_x32: [1, 2, 3],
             };
-            tick.cross(7, 9, 8, 15, 10);
+            tick.cross(7, 9, 8, 15, 10, [5, 2, 1]);
 
             assert!(tick.fee_growth_outside_0_x32 == 6);
             assert!(tick.fee_growth_outside_1_x32 == 7);
             assert!(tick.seconds_per_liquidity_outside_x32 == 3);
             assert!(tick.tick_cumulative_outside == 9);
             assert!(tick.seconds_outside == 3);
+            assert!({ tick.reward_growths_outside_x32 } == [4, 0, u64::MAX - 1]);
         }
 
         #[test]
@@ -584,15 +786,18 @@ mod test {
                 tick_cumulative_outside: 6,
                 seconds_per_liquidity_outside_x32: 5,
                 seconds_outside: 7,
+                payer: Pubkey::default(),
+                reward_growths_outside_x32: [1, 2, 3],
             };
-            tick.cross(7, 9, 8, 15, 10);
-            tick.cross(7, 9, 8, 15, 10);
+            tick.cross(7, 9, 8, 15, 10, [5, 2, 1]);
+            tick.cross(7, 9, 8, 15, 10, [5, 2, 1]);
 
             assert!(tick.fee_growth_outside_0_x32 == 1);
             assert!(tick.fee_growth_outside_1_x32 == 2);
             assert!(tick.seconds_per_liquidity_outside_x32 == 5);
             assert!(tick.tick_cumulative_outside == 6);
             assert!(tick.seconds_outside == 7);
+            assert!({ tick.reward_growths_outside_x32 } == [1, 2, 3]);
         }
     }
 }
@@ -12,6 +12,10 @@ use std::ops::BitXor;
 /// Seed to derive account address and signature
 pub const BITMAP_SEED: &str = "b";
 
+/// Size of bitmap accounts created before rent payers were recorded, without the discriminator.
+/// These end after `word`, and are grown by `migrate_bitmap_account`
+pub const LEGACY_TICK_BITMAP_STATE_LEN: usize = 35;
+
 /// Stores info for a single bitmap word.
 /// Each word represents 256 packed tick initialized boolean values.
 ///
@@ -32,6 +36,9 @@ pub struct TickBitmapState {
 
     /// Packed initialized state
     pub word: [u64; 4],
+
+    /// Paid rent for the account, and is refunded when the empty bitmap is closed
+    pub payer: Pubkey,
 }
 
 /// The position in the mapping where the initialized bit for a tick lives
@@ -133,6 +140,12 @@ impl TickBitmapState {
         }
     }
 
+    /// Whether no tick in the word is initialized, so that the account can be closed
+    pub fn is_clear(self) -> bool {
+        let word = self.word;
+        word == [0; 4]
+    }
+
     /// Whether the tick at given bit position is initialized
     #[cfg(test)]
     fn is_initialized(self, bit_pos: u8) -> bool {
@@ -153,6 +166,16 @@ impl TickBitmapState {
 mod tests {
     use super::*;
 
+    #[test]
+    fn appends_fields_after_the_legacy_layout() {
+        let bitmap_state = TickBitmapState::default();
+        let start = std::ptr::addr_of!(bitmap_state) as usize;
+        assert_eq!(
+            std::ptr::addr_of!(bitmap_state.payer) as usize - start,
+            LEGACY_TICK_BITMAP_STATE_LEN
+        );
+    }
+
     mod is_initialized {
         use super::*;
 
@@ -203,6 +226,30 @@ mod tests {
         }
     }
 
+    mod is_clear {
+        use super::*;
+
+        #[test]
+        fn is_true_at_first() {
+            assert!(TickBitmapState::default().is_clear());
+        }
+
+        #[test]
+        fn is_false_with_any_tick_initialized() {
+            let mut tick_bitmap = TickBitmapState::default();
+            tick_bitmap.flip_bit(255);
+            assert!(!tick_bitmap.is_clear());
+        }
+
+        #[test]
+        fn is_true_once_ticks_are_flipped_back() {
+            let mut tick_bitmap = TickBitmapState::default();
+            tick_bitmap.init_bits(&[0, 64, 200]);
+            tick_bitmap.init_bits(&[0, 64, 200]);
+            assert!(tick_bitmap.is_clear());
+        }
+    }
+
     mod next_initialized_bit_within_one_word {
         use super::*;
 
@@ -0,0 +1,308 @@
+use crate::error::ErrorCode;
+use crate::states::factory::{
+    FactoryState, FeeDiscountTier, Role, FEE_DISCOUNT_TIER_NUM, MAX_FEE_DISCOUNT, MAX_FEE_PROTOCOL,
+};
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::hash::hash;
+
+/// Seed to derive account address and signature
+pub const TIMELOCK_SEED: &str = "tl";
+
+/// The longest delay the timelock can be set to, 30 days
+pub const MAX_TIMELOCK_DELAY: u32 = 30 * 24 * 60 * 60;
+
+/// The shortest delay the timelock can be set to, and the delay of new factories. 2 days,
+/// shortened to 2 seconds by the `localnet` feature so that tests can wait it out
+#[cfg(not(feature = "localnet"))]
+pub const MIN_TIMELOCK_DELAY: u32 = 2 * 24 * 60 * 60;
+#[cfg(feature = "localnet")]
+pub const MIN_TIMELOCK_DELAY: u32 = 2;
+
+/// An admin instruction queued behind the timelock, along with its arguments
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AdminAction {
+    /// `enable_fee_amount`, queued by the fee tier admin
+    EnableFeeAmount { fee: u32, tick_spacing: u16 },
+
+    /// `set_fee_protocol`, queued by the protocol fee admin
+    SetFeeProtocol { fee_protocol: u32 },
+
+    /// `propose_owner`, queued by the owner
+    ProposeOwner { new_owner: Pubkey },
+
+    /// `set_timelock_delay`, queued by the owner
+    SetTimelockDelay { delay: u32 },
+
+    /// `set_pool_fee_protocol`, queued by the protocol fee admin
+    SetPoolFeeProtocol {
+        pool: Pubkey,
+        fee_protocol_0: u32,
+        fee_protocol_1: u32,
+    },
+
+    /// `set_role`, queued by the owner
+    SetRole { role: Role, new_holder: Pubkey },
+
+    /// `set_fee_discounts`, queued by the protocol fee admin
+    SetFeeDiscounts {
+        locker: Pubkey,
+        fee_discount_tiers: [FeeDiscountTier; FEE_DISCOUNT_TIER_NUM],
+    },
+}
+
+impl AdminAction {
+    /// Returns the seed of the timelock account queuing this action, a hash of the
+    /// instruction and its arguments
+    pub fn seed(&self) -> [u8; 32] {
+        hash(&self.try_to_vec().unwrap()).to_bytes()
+    }
+
+    /// Returns the address allowed to queue this action
+    ///
+    /// # Arguments
+    ///
+    /// * `factory_state` - The factory holding the owner and roles
+    ///
+    pub fn admin(&self, factory_state: &FactoryState) -> Pubkey {
+        match self {
+            AdminAction::EnableFeeAmount { .. } => factory_state.fee_tier_admin,
+            AdminAction::SetFeeProtocol { .. }
+            | AdminAction::SetPoolFeeProtocol { .. }
+            | AdminAction::SetFeeDiscounts { .. } => factory_state.protocol_fee_admin,
+            AdminAction::ProposeOwner { .. }
+            | AdminAction::SetTimelockDelay { .. }
+            | AdminAction::SetRole { .. } => factory_state.owner,
+        }
+    }
+
+    /// Checks the arguments of the action, so that actions which would fail once executed
+    /// cannot be queued
+    pub fn validate(&self) -> Result<()> {
+        match *self {
+            AdminAction::EnableFeeAmount { fee, tick_spacing } => {
+                require!(fee < 1_000_000, ErrorCode::InvalidAdminAction); // 100%
+                require!(
+                    tick_spacing > 0 && tick_spacing < 16384,
+                    ErrorCode::InvalidAdminAction
+                );
+            }
+            AdminAction::SetFeeProtocol { fee_protocol } => {
+                require!(
+                    fee_protocol <= MAX_FEE_PROTOCOL,
+                    ErrorCode::InvalidAdminAction
+                );
+            }
+            AdminAction::SetTimelockDelay { delay } => {
+                require!(
+                    (MIN_TIMELOCK_DELAY..=MAX_TIMELOCK_DELAY).contains(&delay),
+                    ErrorCode::InvalidAdminAction
+                );
+            }
+            AdminAction::SetPoolFeeProtocol {
+                fee_protocol_0,
+                fee_protocol_1,
+                ..
+            } => {
+                require!(
+                    fee_protocol_0 <= MAX_FEE_PROTOCOL && fee_protocol_1 <= MAX_FEE_PROTOCOL,
+                    ErrorCode::InvalidAdminAction
+                );
+            }
+            AdminAction::SetFeeDiscounts {
+                fee_discount_tiers, ..
+            } => {
+                require!(
+                    fee_discount_tiers
+                        .iter()
+                        .all(|tier| tier.discount <= MAX_FEE_DISCOUNT),
+                    ErrorCode::InvalidAdminAction
+                );
+            }
+            AdminAction::ProposeOwner { .. } | AdminAction::SetRole { .. } => {}
+        }
+        Ok(())
+    }
+}
+
+/// An admin action queued behind the timelock. Anyone can execute the action once its
+/// ETA has passed, and the owner or the pause guardian can cancel it before.
+///
+/// PDA of `[TIMELOCK_SEED, AdminAction::seed()]`
+///
+#[account(zero_copy)]
+#[derive(Default)]
+#[repr(packed)]
+pub struct TimelockState {
+    /// Bump to identify PDA
+    pub bump: u8,
+
+    /// The time from which the action can be executed
+    pub eta: i64,
+
+    /// Paid rent for the account, and is refunded when the action is executed or canceled
+    pub payer: Pubkey,
+}
+
+/// Emitted when an admin action is queued behind the timelock
+#[event]
+pub struct AdminActionQueued {
+    /// The queued action
+    pub action: AdminAction,
+
+    /// The time from which the action can be executed
+    pub eta: i64,
+}
+
+/// Emitted when a queued admin action is executed
+#[event]
+pub struct AdminActionExecuted {
+    /// The executed action
+    pub action: AdminAction,
+}
+
+/// Emitted when a queued admin action is canceled
+#[event]
+pub struct AdminActionCanceled {
+    /// The canceled action
+    pub action: AdminAction,
+}
+
+/// Emitted when the timelock delay is changed
+#[event]
+pub struct TimelockDelayChanged {
+    /// The delay before the change, in seconds
+    pub delay_old: u32,
+
+    /// The delay after the change, in seconds
+    pub delay: u32,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn seeds_differ_by_instruction_and_arguments() {
+        let enable_fee_amount = AdminAction::EnableFeeAmount {
+            fee: 500,
+            tick_spacing: 10,
+        };
+        assert_eq!(enable_fee_amount.seed(), enable_fee_amount.seed());
+        assert_ne!(
+            enable_fee_amount.seed(),
+            AdminAction::EnableFeeAmount {
+                fee: 500,
+                tick_spacing: 60,
+            }
+            .seed()
+        );
+        assert_ne!(
+            AdminAction::SetFeeProtocol { fee_protocol: 4 }.seed(),
+            AdminAction::SetTimelockDelay { delay: 4 }.seed()
+        );
+    }
+
+    #[test]
+    fn is_queued_by_the_holder_of_the_role() {
+        let factory_state = FactoryState {
+            owner: Pubkey::new_unique(),
+            fee_tier_admin: Pubkey::new_unique(),
+            protocol_fee_admin: Pubkey::new_unique(),
+            ..Default::default()
+        };
+        assert_eq!(
+            AdminAction::EnableFeeAmount {
+                fee: 500,
+                tick_spacing: 10
+            }
+            .admin(&factory_state),
+            factory_state.fee_tier_admin
+        );
+        assert_eq!(
+            AdminAction::SetFeeProtocol { fee_protocol: 4 }.admin(&factory_state),
+            factory_state.protocol_fee_admin
+        );
+        assert_eq!(
+            AdminAction::ProposeOwner {
+                new_owner: Pubkey::new_unique()
+            }
+            .admin(&factory_state),
+            factory_state.owner
+        );
+        assert_eq!(
+            AdminAction::SetPoolFeeProtocol {
+                pool: Pubkey::new_unique(),
+                fee_protocol_0: 0,
+                fee_protocol_1: 0,
+            }
+            .admin(&factory_state),
+            factory_state.protocol_fee_admin
+        );
+        assert_eq!(
+            AdminAction::SetRole {
+                role: Role::FeeCollector,
+                new_holder: Pubkey::new_unique()
+            }
+            .admin(&factory_state),
+            factory_state.owner
+        );
+    }
+
+    #[test]
+    fn rejects_out_of_bounds_arguments_at_queue_time() {
+        let enable_fee_amount =
+            |fee, tick_spacing| AdminAction::EnableFeeAmount { fee, tick_spacing };
+        assert!(enable_fee_amount(500, 10).validate().is_ok());
+        assert!(enable_fee_amount(1_000_000, 10).validate().is_err());
+        assert!(enable_fee_amount(500, 0).validate().is_err());
+        assert!(enable_fee_amount(500, 16384).validate().is_err());
+
+        assert!(AdminAction::SetFeeProtocol {
+            fee_protocol: 150_000
+        }
+        .validate()
+        .is_ok());
+        assert!(AdminAction::SetFeeProtocol {
+            fee_protocol: MAX_FEE_PROTOCOL + 1
+        }
+        .validate()
+        .is_err());
+
+        let set_pool_fee_protocol =
+            |fee_protocol_0, fee_protocol_1| AdminAction::SetPoolFeeProtocol {
+                pool: Pubkey::new_unique(),
+                fee_protocol_0,
+                fee_protocol_1,
+            };
+        assert!(set_pool_fee_protocol(MAX_FEE_PROTOCOL, 0)
+            .validate()
+            .is_ok());
+        assert!(set_pool_fee_protocol(MAX_FEE_PROTOCOL + 1, 0)
+            .validate()
+            .is_err());
+        assert!(set_pool_fee_protocol(0, MAX_FEE_PROTOCOL + 1)
+            .validate()
+            .is_err());
+
+        assert!(AdminAction::SetTimelockDelay {
+            delay: MIN_TIMELOCK_DELAY - 1
+        }
+        .validate()
+        .is_err());
+        assert!(AdminAction::SetTimelockDelay {
+            delay: MAX_TIMELOCK_DELAY + 1
+        }
+        .validate()
+        .is_err());
+
+        let mut fee_discount_tiers = [FeeDiscountTier::default(); FEE_DISCOUNT_TIER_NUM];
+        fee_discount_tiers[0].discount = MAX_FEE_DISCOUNT;
+        let set_fee_discounts = |fee_discount_tiers| AdminAction::SetFeeDiscounts {
+            locker: Pubkey::new_unique(),
+            fee_discount_tiers,
+        };
+        assert!(set_fee_discounts(fee_discount_tiers).validate().is_ok());
+        fee_discount_tiers[3].discount = MAX_FEE_DISCOUNT + 1;
+        assert!(set_fee_discounts(fee_discount_tiers).validate().is_err());
+    }
+}
@@ -1,5 +1,11 @@
+use crate::states::pool::REWARD_NUM;
+use crate::states::position::{PositionRewardInfo, PositionState};
 use anchor_lang::prelude::*;
 
+/// Size of tokenized position accounts created before rewards were added, without the
+/// discriminator. These end after `tokens_owed_1`, and are grown by `migrate_tokenized_position`
+pub const LEGACY_TOKENIZED_POSITION_STATE_LEN: usize = 113;
+
 /// Position wrapped as an SPL non-fungible token
 ///
 /// PDA of `[POSITION_SEED, mint_address]`
@@ -37,6 +43,55 @@ pub struct TokenizedPositionState {
 
     /// How many uncollected token_0 are owed to the position, as of the last computation
     pub tokens_owed_1: u64,
+
+    /// The reward growth of the aggregate position and the uncollected rewards owed to the
+    /// position, for each reward slot of the pool
+    pub reward_infos: [PositionRewardInfo; REWARD_NUM],
+}
+
+impl TokenizedPositionState {
+    /// Credits rewards earned since the last action on the position, as of the reward growth
+    /// snapshots of the aggregate position. Must be called before the liquidity changes
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The tokenized position to update
+    /// * `core_position` - The core position holding the liquidity, updated in the same transaction
+    ///
+    pub fn update_rewards(&mut self, core_position: &PositionState) {
+        let liquidity = self.liquidity;
+        for (i, reward_info) in self.reward_infos.iter_mut().enumerate() {
+            reward_info.update(
+                liquidity,
+                core_position.reward_infos[i].growth_inside_last_x32,
+            );
+        }
+    }
+}
+
+/// Arguments of the `mint_tokenized_position` instruction
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct MintTokenizedPositionParams {
+    /// The lower tick of the position
+    pub tick_lower: i32,
+
+    /// The upper tick of the position
+    pub tick_upper: i32,
+
+    /// Desired amount of token_0 to be spent
+    pub amount_0_desired: u64,
+
+    /// Desired amount of token_1 to be spent
+    pub amount_1_desired: u64,
+
+    /// The minimum amount of token_0 to spend, which serves as a slippage check
+    pub amount_0_min: u64,
+
+    /// The minimum amount of token_1 to spend, which serves as a slippage check
+    pub amount_1_min: u64,
+
+    /// The time by which the transaction must be included to effect the change
+    pub deadline: i64,
 }
 
 /// Emitted when liquidity is increased for a position NFT.
@@ -94,3 +149,35 @@ pub struct CollectTokenizedEvent {
     /// The amount of token_1 owed to the position that was collected
     pub amount_1: u64,
 }
+
+/// Emitted when rewards are collected for a position NFT
+#[event]
+pub struct CollectTokenizedRewardEvent {
+    /// The ID of the token for which rewards were collected
+    #[index]
+    pub token_id: Pubkey,
+
+    /// The slot of the collected reward
+    pub reward_index: u8,
+
+    /// The token account that received the collected rewards
+    pub recipient_wallet: Pubkey,
+
+    /// The amount of rewards owed to the position that was collected
+    pub amount: u64,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn appends_fields_after_the_legacy_layout() {
+        let tokenized_position = TokenizedPositionState::default();
+        let start = std::ptr::addr_of!(tokenized_position) as usize;
+        assert_eq!(
+            std::ptr::addr_of!(tokenized_position.reward_infos) as usize - start,
+            LEGACY_TOKENIZED_POSITION_STATE_LEN
+        );
+    }
+}
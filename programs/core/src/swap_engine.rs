@@ -0,0 +1,753 @@
+//! Swap engine
+//! Steps through the price range of a swap, one initialized tick at a time
+//!
+//! The engine does not read accounts itself. Bitmaps and ticks are sourced from a `TickProvider`,
+//! and oracle accumulators from an `ObservationProvider`. The `swap` instruction plugs in
+//! providers backed by remaining accounts, while quoters and simulators can use in-memory state.
+//!
+use crate::error::ErrorCode;
+use crate::libraries::full_math::MulDiv;
+use crate::libraries::{fixed_point_32, liquidity_math, swap_math, tick_math};
+use crate::states::factory::FEE_PROTOCOL_DENOMINATOR;
+use crate::states::oracle::ObservationState;
+use crate::states::pool::{PoolState, REWARD_NUM};
+use crate::states::tick::TickState;
+use crate::states::tick_bitmap::{self, NextBit, Position, TickBitmapState, BITMAP_SEED};
+use anchor_lang::prelude::*;
+use std::convert::TryFrom;
+use std::ops::Neg;
+
+pub struct SwapCache {
+    // the swap fee in hundredths of a bip, after any discount of the trader
+    pub fee: u32,
+    // the protocol fee for the input token, in hundredths of a bip
+    pub fee_protocol: u32,
+    // liquidity at the beginning of the swap
+    pub liquidity_start: u64,
+    // the timestamp of the current block
+    pub block_timestamp: u32,
+    // the current value of the tick accumulator, computed only if we cross an initialized tick
+    pub tick_cumulative: i64,
+    // the current value of seconds per liquidity accumulator, computed only if we cross an initialized tick
+    pub seconds_per_liquidity_cumulative_x32: u64,
+    // whether we've computed and cached the above two accumulators
+    pub computed_latest_observation: bool,
+    // the reward growth of each reward slot, accrued up to the current block
+    pub reward_growths_global_x32: [u64; REWARD_NUM],
+}
+
+// the top level state of the swap, the results of which are recorded in storage at the end
+#[derive(Debug)]
+pub struct SwapState {
+    // the amount remaining to be swapped in/out of the input/output asset
+    pub amount_specified_remaining: i64,
+    // the amount already swapped out/in of the output/input asset
+    pub amount_calculated: i64,
+    // current sqrt(price)
+    pub sqrt_price_x32: u64,
+    // the tick associated with the current price
+    pub tick: i32,
+    // the global fee growth of the input token
+    pub fee_growth_global_x32: u64,
+    // amount of input token paid as protocol fee
+    pub protocol_fee: u64,
+    // the current liquidity in range
+    pub liquidity: u64,
+}
+
+#[derive(Default)]
+struct StepComputations {
+    // the price at the beginning of the step
+    sqrt_price_start_x32: u64,
+    // the next tick to swap to from the current tick in the swap direction
+    tick_next: i32,
+    // whether tick_next is initialized or not
+    initialized: bool,
+    // sqrt(price) for the next tick (1/0)
+    sqrt_price_next_x32: u64,
+    // how much is being swapped in in this step
+    amount_in: u64,
+    // how much is being swapped out
+    amount_out: u64,
+    // how much fee is being paid in
+    fee_amount: u64,
+}
+
+/// Source of the bitmaps and initialized ticks read by a swap
+pub trait TickProvider {
+    /// Returns the bitmap at a word position, or None if the bitmap is not initialized
+    ///
+    /// # Arguments
+    ///
+    /// * `word_pos` - The word position of the bitmap
+    ///
+    fn bitmap(&mut self, word_pos: i16) -> Result<Option<TickBitmapState>>;
+
+    /// Transitions to an initialized tick as needed by price movement, and returns
+    /// its liquidity_net
+    ///
+    /// # Arguments
+    ///
+    /// * `tick` - The initialized tick being crossed
+    /// * `fee_growth_global_0_x32` - The all-time global fee growth, per unit of liquidity, in token_0
+    /// * `fee_growth_global_1_x32` - The all-time global fee growth, per unit of liquidity, in token_1
+    /// * `cache` - Holds the oracle accumulators, reward growths and timestamp to write outside the tick
+    ///
+    fn cross(
+        &mut self,
+        tick: i32,
+        fee_growth_global_0_x32: u64,
+        fee_growth_global_1_x32: u64,
+        cache: &SwapCache,
+    ) -> Result<i64>;
+}
+
+/// Source of the oracle accumulators written to crossed ticks
+pub trait ObservationProvider {
+    /// Returns the tick and seconds per liquidity accumulators as of the given time
+    ///
+    /// # Arguments
+    ///
+    /// * `time` - The current block timestamp
+    /// * `tick` - The active tick
+    /// * `liquidity` - The total in-range liquidity
+    ///
+    fn observe_latest(&self, time: u32, tick: i32, liquidity: u64) -> (i64, u64);
+}
+
+impl ObservationProvider for ObservationState {
+    fn observe_latest(&self, time: u32, tick: i32, liquidity: u64) -> (i64, u64) {
+        ObservationState::observe_latest(*self, time, tick, liquidity)
+    }
+}
+
+/// Observation provider for dry runs, where crossed ticks are read but not written
+pub struct NoObservation;
+
+impl ObservationProvider for NoObservation {
+    fn observe_latest(&self, _time: u32, _tick: i32, _liquidity: u64) -> (i64, u64) {
+        (0, 0)
+    }
+}
+
+/// Tick provider reading bitmap and tick accounts from the remaining accounts of an instruction
+pub struct AccountTickProvider<'p, 'a, 'b, 'info> {
+    /// The pool being swapped in, used to validate account addresses
+    pool: &'p PoolState,
+
+    /// Bitmap and tick accounts, in the order they are consumed. Accounts for a wider price
+    /// range than the swap covers are skipped
+    remaining_accounts: &'a mut std::slice::Iter<'b, AccountInfo<'info>>,
+
+    /// Whether crossed tick accounts are written. Dry runs only read them
+    cross_ticks: bool,
+}
+
+impl<'p, 'a, 'b, 'info> AccountTickProvider<'p, 'a, 'b, 'info> {
+    /// Creates a tick provider over remaining accounts
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - The pool being swapped in
+    /// * `remaining_accounts` - Bitmap and tick accounts, in the order they are consumed
+    /// * `cross_ticks` - Whether to write crossed tick accounts. Pass false for a dry run
+    ///
+    pub fn new(
+        pool: &'p PoolState,
+        remaining_accounts: &'a mut std::slice::Iter<'b, AccountInfo<'info>>,
+        cross_ticks: bool,
+    ) -> Self {
+        AccountTickProvider {
+            pool,
+            remaining_accounts,
+            cross_ticks,
+        }
+    }
+}
+
+impl<'p, 'a, 'b, 'info> TickProvider for AccountTickProvider<'p, 'a, 'b, 'info> {
+    fn bitmap(&mut self, word_pos: i16) -> Result<Option<TickBitmapState>> {
+        let bitmap_address = Pubkey::find_program_address(
+            &[
+                BITMAP_SEED.as_bytes(),
+                self.pool.token_0.as_ref(),
+                self.pool.token_1.as_ref(),
+                &self.pool.fee.to_be_bytes(),
+                &word_pos.to_be_bytes(),
+            ],
+            &crate::id(),
+        )
+        .0;
+        // ensure this is a valid PDA, even if account is not initialized. Accounts listed for
+        // a wider price range than the swap covers are skipped
+        let bitmap_account = self
+            .remaining_accounts
+            .find(|account| account.key() == bitmap_address)
+            .ok_or(anchor_lang::error::ErrorCode::AccountNotEnoughKeys)?;
+
+        // read from bitmap if account is initialized, else use default values for next initialized bit.
+        // Closed bitmaps fail to load, and so read as uninitialized
+        if let Ok(bitmap_loader) = AccountLoader::<TickBitmapState>::try_from(bitmap_account) {
+            let bitmap_state = bitmap_loader.load()?;
+            Ok(Some(*bitmap_state))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn cross(
+        &mut self,
+        tick: i32,
+        fee_growth_global_0_x32: u64,
+        fee_growth_global_1_x32: u64,
+        cache: &SwapCache,
+    ) -> Result<i64> {
+        // skip accounts listed for a wider price range, up to the account of this tick
+        let tick_loader = loop {
+            let account = self.remaining_accounts.next().ok_or(ErrorCode::TNI)?;
+            if let Ok(tick_loader) = AccountLoader::<TickState>::try_from(account) {
+                if tick_loader.load()?.tick == tick {
+                    break tick_loader;
+                }
+            }
+        };
+        if self.cross_ticks {
+            let mut tick_state = tick_loader.load_mut()?;
+            self.pool
+                .validate_tick_address(&tick_loader.key(), tick_state.bump, tick)?;
+            Ok(tick_state.cross(
+                fee_growth_global_0_x32,
+                fee_growth_global_1_x32,
+                cache.seconds_per_liquidity_cumulative_x32,
+                cache.tick_cumulative,
+                cache.block_timestamp,
+                cache.reward_growths_global_x32,
+            ))
+        } else {
+            // dry runs read the tick without crossing it
+            let tick_state = tick_loader.load()?;
+            self.pool
+                .validate_tick_address(&tick_loader.key(), tick_state.bump, tick)?;
+            Ok(tick_state.liquidity_net)
+        }
+    }
+}
+
+/// Steps through the price range of a swap, one initialized tick at a time, until the specified
+/// amount is used up or the price limit is reached
+///
+/// Token transfers and pool state writes are left to the caller.
+///
+/// Returns the number of initialized ticks crossed
+///
+/// # Arguments
+///
+/// * `pool` - The pool being swapped in, as of the start of the swap
+/// * `cache` - Values fixed over the course of the swap
+/// * `state` - The state of the swap, updated in place
+/// * `tick_provider` - Source of the bitmaps and initialized ticks
+/// * `observation_provider` - Source of the oracle accumulators, read once if a tick is crossed
+/// * `zero_for_one` - The direction of the swap, true for token_0 to token_1
+/// * `sqrt_price_limit_x32` - The Q32.32 sqrt price √P limit
+///
+pub fn swap_steps<T: TickProvider, O: ObservationProvider>(
+    pool: &PoolState,
+    cache: &mut SwapCache,
+    state: &mut SwapState,
+    tick_provider: &mut T,
+    observation_provider: &O,
+    zero_for_one: bool,
+    sqrt_price_limit_x32: u64,
+) -> Result<u32> {
+    let exact_input = state.amount_specified_remaining > 0;
+    let mut ticks_crossed = 0;
+
+    // cache for the current bitmap account. Cache is cleared on bitmap transitions
+    let mut bitmap_cache: Option<TickBitmapState> = None;
+
+    // continue swapping as long as we haven't used the entire input/output and haven't
+    // reached the price limit
+    while state.amount_specified_remaining != 0 && state.sqrt_price_x32 != sqrt_price_limit_x32 {
+        let mut step = StepComputations {
+            sqrt_price_start_x32: state.sqrt_price_x32,
+            ..Default::default()
+        };
+
+        let mut compressed = state.tick / pool.tick_spacing as i32;
+
+        // state.tick is the starting tick for the transition
+        if state.tick < 0 && state.tick % pool.tick_spacing as i32 != 0 {
+            compressed -= 1; // round towards negative infinity
+        }
+        // The current tick is not considered in greater than or equal to (lte = false, i.e one for zero) case
+        if !zero_for_one {
+            compressed += 1;
+        }
+
+        let Position { word_pos, bit_pos } = tick_bitmap::position(compressed);
+
+        // load the next bitmap if cache is empty (first loop instance), or if we have
+        // crossed out of this bitmap. The cache is cleared if the bitmap is uninitialized, this way
+        // default uninitialized values will be returned for the next bit
+        if bitmap_cache.is_none() || bitmap_cache.unwrap().word_pos != word_pos {
+            bitmap_cache = tick_provider.bitmap(word_pos)?;
+        }
+
+        // default values for the next initialized bit if the bitmap is not initialized
+        let next_initialized_bit = if let Some(bitmap) = bitmap_cache {
+            bitmap.next_initialized_bit(bit_pos, zero_for_one)
+        } else {
+            NextBit {
+                next: if zero_for_one { 0 } else { 255 },
+                initialized: false,
+            }
+        };
+
+        step.tick_next = (((word_pos as i32) << 8) + next_initialized_bit.next as i32)
+            * pool.tick_spacing as i32; // convert relative to absolute
+        step.initialized = next_initialized_bit.initialized;
+
+        // ensure that we do not overshoot the min/max tick, as the tick bitmap is not aware of these bounds
+        step.tick_next = step
+            .tick_next
+            .clamp(tick_math::MIN_TICK, tick_math::MAX_TICK);
+
+        step.sqrt_price_next_x32 = tick_math::get_sqrt_ratio_at_tick(step.tick_next)?;
+
+        let target_price = if (zero_for_one && step.sqrt_price_next_x32 < sqrt_price_limit_x32)
+            || (!zero_for_one && step.sqrt_price_next_x32 > sqrt_price_limit_x32)
+        {
+            sqrt_price_limit_x32
+        } else {
+            step.sqrt_price_next_x32
+        };
+        let swap_step = swap_math::compute_swap_step(
+            state.sqrt_price_x32,
+            target_price,
+            state.liquidity,
+            state.amount_specified_remaining,
+            cache.fee,
+        );
+        state.sqrt_price_x32 = swap_step.sqrt_ratio_next_x32;
+        step.amount_in = swap_step.amount_in;
+        step.amount_out = swap_step.amount_out;
+        step.fee_amount = swap_step.fee_amount;
+
+        if exact_input {
+            state.amount_specified_remaining -=
+                i64::try_from(step.amount_in + step.fee_amount).unwrap();
+            state.amount_calculated = state
+                .amount_calculated
+                .checked_sub(i64::try_from(step.amount_out).unwrap())
+                .unwrap();
+        } else {
+            state.amount_specified_remaining += i64::try_from(step.amount_out).unwrap();
+            state.amount_calculated = state
+                .amount_calculated
+                .checked_add(i64::try_from(step.amount_in + step.fee_amount).unwrap())
+                .unwrap();
+        }
+
+        // if the protocol fee is on, calculate how much is owed, decrement fee_amount, and increment protocol_fee
+        if cache.fee_protocol > 0 {
+            // rounded down, leaving the remainder to liquidity providers
+            let delta = step
+                .fee_amount
+                .mul_div_floor(cache.fee_protocol.into(), FEE_PROTOCOL_DENOMINATOR.into())
+                .unwrap();
+            step.fee_amount -= delta;
+            state.protocol_fee += delta;
+        }
+
+        // update global fee tracker
+        if state.liquidity > 0 {
+            state.fee_growth_global_x32 += step
+                .fee_amount
+                .mul_div_floor(fixed_point_32::Q32, state.liquidity)
+                .unwrap();
+        }
+
+        // shift tick if we reached the next price
+        if state.sqrt_price_x32 == step.sqrt_price_next_x32 {
+            // if the tick is initialized, run the tick transition
+            if step.initialized {
+                // check for the placeholder value for the oracle observation, which we replace with the
+                // actual value the first time the swap crosses an initialized tick
+                if !cache.computed_latest_observation {
+                    let new_observation = observation_provider.observe_latest(
+                        cache.block_timestamp,
+                        pool.tick,
+                        pool.liquidity,
+                    );
+                    cache.tick_cumulative = new_observation.0;
+                    cache.seconds_per_liquidity_cumulative_x32 = new_observation.1;
+                    cache.computed_latest_observation = true;
+                }
+
+                let mut liquidity_net = tick_provider.cross(
+                    step.tick_next,
+                    if zero_for_one {
+                        state.fee_growth_global_x32
+                    } else {
+                        pool.fee_growth_global_0_x32
+                    },
+                    if zero_for_one {
+                        pool.fee_growth_global_1_x32
+                    } else {
+                        state.fee_growth_global_x32
+                    },
+                    cache,
+                )?;
+                ticks_crossed += 1;
+
+                // if we're moving leftward, we interpret liquidity_net as the opposite sign
+                // safe because liquidity_net cannot be i64::MIN
+                if zero_for_one {
+                    liquidity_net = liquidity_net.neg();
+                }
+
+                state.liquidity = liquidity_math::add_delta(state.liquidity, liquidity_net)?;
+            }
+
+            state.tick = if zero_for_one {
+                step.tick_next - 1
+            } else {
+                step.tick_next
+            };
+        } else if state.sqrt_price_x32 != step.sqrt_price_start_x32 {
+            // recompute unless we're on a lower tick boundary (i.e. already transitioned ticks), and haven't moved
+            state.tick = tick_math::get_tick_at_sqrt_ratio(state.sqrt_price_x32)?;
+        }
+    }
+
+    Ok(ticks_crossed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::error::ErrorCode;
+    use std::cell::Cell;
+    use std::collections::HashMap;
+
+    const LIQUIDITY: u64 = 1 << 32;
+
+    /// Returns the name of the program error
+    fn error_name(error: Error) -> String {
+        match error {
+            Error::AnchorError(error) => error.error_name,
+            Error::ProgramError(error) => error.to_string(),
+        }
+    }
+
+    /// In-memory ticks and bitmaps, which records the bitmaps read and ticks crossed
+    #[derive(Default)]
+    struct MemoryTickProvider {
+        bitmaps: HashMap<i16, TickBitmapState>,
+        ticks: HashMap<i32, TickState>,
+        bitmaps_read: Vec<i16>,
+        ticks_crossed: Vec<i32>,
+    }
+
+    impl MemoryTickProvider {
+        fn add_tick(&mut self, tick: i32, tick_spacing: u16, liquidity_net: i64) {
+            let Position { word_pos, bit_pos } = tick_bitmap::position(tick / tick_spacing as i32);
+            self.bitmaps
+                .entry(word_pos)
+                .or_insert(TickBitmapState {
+                    word_pos,
+                    ..Default::default()
+                })
+                .flip_bit(bit_pos);
+            self.ticks.insert(
+                tick,
+                TickState {
+                    tick,
+                    liquidity_net,
+                    liquidity_gross: liquidity_net.unsigned_abs(),
+                    ..Default::default()
+                },
+            );
+        }
+    }
+
+    impl TickProvider for MemoryTickProvider {
+        fn bitmap(&mut self, word_pos: i16) -> Result<Option<TickBitmapState>> {
+            self.bitmaps_read.push(word_pos);
+            Ok(self.bitmaps.get(&word_pos).copied())
+        }
+
+        fn cross(
+            &mut self,
+            tick: i32,
+            fee_growth_global_0_x32: u64,
+            fee_growth_global_1_x32: u64,
+            cache: &SwapCache,
+        ) -> Result<i64> {
+            self.ticks_crossed.push(tick);
+            let tick_state = self.ticks.get_mut(&tick).ok_or(ErrorCode::TNI)?;
+            Ok(tick_state.cross(
+                fee_growth_global_0_x32,
+                fee_growth_global_1_x32,
+                cache.seconds_per_liquidity_cumulative_x32,
+                cache.tick_cumulative,
+                cache.block_timestamp,
+                cache.reward_growths_global_x32,
+            ))
+        }
+    }
+
+    /// Returns fixed accumulators, counting the number of reads
+    #[derive(Default)]
+    struct MemoryObservationProvider {
+        reads: Cell<u32>,
+    }
+
+    impl ObservationProvider for MemoryObservationProvider {
+        fn observe_latest(&self, _time: u32, _tick: i32, _liquidity: u64) -> (i64, u64) {
+            self.reads.set(self.reads.get() + 1);
+            (100, 200)
+        }
+    }
+
+    /// A pool at tick 5 with spacing 10, and a position between ticks -10 and 10
+    fn setup() -> (PoolState, MemoryTickProvider) {
+        let pool = PoolState {
+            fee: 500,
+            tick_spacing: 10,
+            tick: 5,
+            sqrt_price_x32: tick_math::get_sqrt_ratio_at_tick(5).unwrap(),
+            liquidity: LIQUIDITY,
+            ..Default::default()
+        };
+        let mut tick_provider = MemoryTickProvider::default();
+        tick_provider.add_tick(-10, 10, LIQUIDITY as i64);
+        tick_provider.add_tick(10, 10, -(LIQUIDITY as i64));
+        (pool, tick_provider)
+    }
+
+    /// Runs the swap engine over the pool, returning the final state and ticks crossed
+    fn run(
+        pool: &PoolState,
+        tick_provider: &mut MemoryTickProvider,
+        observation_provider: &MemoryObservationProvider,
+        fee_protocol: u32,
+        amount_specified: i64,
+        zero_for_one: bool,
+        sqrt_price_limit_x32: u64,
+    ) -> Result<(SwapState, u32)> {
+        let mut cache = SwapCache {
+            fee: pool.fee,
+            fee_protocol,
+            liquidity_start: pool.liquidity,
+            block_timestamp: 1400,
+            tick_cumulative: 0,
+            seconds_per_liquidity_cumulative_x32: 0,
+            computed_latest_observation: false,
+            reward_growths_global_x32: [0; REWARD_NUM],
+        };
+        let mut state = SwapState {
+            amount_specified_remaining: amount_specified,
+            amount_calculated: 0,
+            sqrt_price_x32: pool.sqrt_price_x32,
+            tick: pool.tick,
+            fee_growth_global_x32: 0,
+            protocol_fee: 0,
+            liquidity: pool.liquidity,
+        };
+        let ticks_crossed = swap_steps(
+            pool,
+            &mut cache,
+            &mut state,
+            tick_provider,
+            observation_provider,
+            zero_for_one,
+            sqrt_price_limit_x32,
+        )?;
+        Ok((state, ticks_crossed))
+    }
+
+    #[test]
+    fn exact_input_within_a_tick_is_a_single_step() {
+        let (pool, mut tick_provider) = setup();
+        let observation_provider = MemoryObservationProvider::default();
+        let (state, ticks_crossed) = run(
+            &pool,
+            &mut tick_provider,
+            &observation_provider,
+            0,
+            1_000,
+            true,
+            tick_math::MIN_SQRT_RATIO + 1,
+        )
+        .unwrap();
+
+        let step = swap_math::compute_swap_step(
+            pool.sqrt_price_x32,
+            tick_math::get_sqrt_ratio_at_tick(0).unwrap(),
+            LIQUIDITY,
+            1_000,
+            500,
+        );
+        assert_eq!(state.amount_specified_remaining, 0);
+        assert_eq!(state.amount_calculated, -(step.amount_out as i64));
+        assert_eq!(state.sqrt_price_x32, step.sqrt_ratio_next_x32);
+        assert_eq!(
+            state.fee_growth_global_x32,
+            step.fee_amount
+                .mul_div_floor(fixed_point_32::Q32, LIQUIDITY)
+                .unwrap()
+        );
+        assert_eq!(ticks_crossed, 0);
+        assert_eq!(tick_provider.bitmaps_read, vec![0]);
+        assert_eq!(observation_provider.reads.get(), 0);
+    }
+
+    #[test]
+    fn exact_output_uses_up_the_specified_amount() {
+        let (pool, mut tick_provider) = setup();
+        let (state, ticks_crossed) = run(
+            &pool,
+            &mut tick_provider,
+            &MemoryObservationProvider::default(),
+            0,
+            -1_000,
+            false,
+            tick_math::MAX_SQRT_RATIO - 1,
+        )
+        .unwrap();
+
+        assert_eq!(state.amount_specified_remaining, 0);
+        assert!(state.amount_calculated > 1_000);
+        assert!(state.sqrt_price_x32 > pool.sqrt_price_x32);
+        assert_eq!(ticks_crossed, 0);
+    }
+
+    #[test]
+    fn stops_at_the_price_limit() {
+        let (pool, mut tick_provider) = setup();
+        let limit = tick_math::get_sqrt_ratio_at_tick(8).unwrap();
+        let (state, _) = run(
+            &pool,
+            &mut tick_provider,
+            &MemoryObservationProvider::default(),
+            0,
+            i64::MAX,
+            false,
+            limit,
+        )
+        .unwrap();
+
+        assert_eq!(state.sqrt_price_x32, limit);
+        assert_eq!(state.tick, 8);
+        assert!(state.amount_specified_remaining > 0);
+    }
+
+    #[test]
+    fn crosses_initialized_ticks_and_updates_liquidity() {
+        let (pool, mut tick_provider) = setup();
+        let observation_provider = MemoryObservationProvider::default();
+        let limit = tick_math::get_sqrt_ratio_at_tick(-3000).unwrap();
+        let (state, ticks_crossed) = run(
+            &pool,
+            &mut tick_provider,
+            &observation_provider,
+            0,
+            i64::MAX,
+            true,
+            limit,
+        )
+        .unwrap();
+
+        // moving leftward across the lower tick removes the position's liquidity
+        assert_eq!(ticks_crossed, 1);
+        assert_eq!(tick_provider.ticks_crossed, vec![-10]);
+        assert_eq!(state.liquidity, 0);
+        assert_eq!(state.tick, -3000);
+        // the uninitialized bitmap -2 is read on the way to the limit
+        assert_eq!(tick_provider.bitmaps_read, vec![0, -1, -2]);
+
+        // accumulators are read once and written outside the crossed tick
+        assert_eq!(observation_provider.reads.get(), 1);
+        let tick_state = tick_provider.ticks[&-10];
+        assert_eq!({ tick_state.tick_cumulative_outside }, 100);
+        assert_eq!({ tick_state.seconds_per_liquidity_outside_x32 }, 200);
+        assert_eq!({ tick_state.seconds_outside }, 1400);
+        assert_eq!(
+            { tick_state.fee_growth_outside_0_x32 },
+            state.fee_growth_global_x32
+        );
+    }
+
+    #[test]
+    fn reads_the_observation_once_for_several_crossings() {
+        let (pool, mut tick_provider) = setup();
+        // upper tick of a second position below the first one
+        tick_provider.add_tick(-20, 10, -(LIQUIDITY as i64));
+        let observation_provider = MemoryObservationProvider::default();
+        let (_, ticks_crossed) = run(
+            &pool,
+            &mut tick_provider,
+            &observation_provider,
+            0,
+            i64::MAX,
+            true,
+            tick_math::get_sqrt_ratio_at_tick(-30).unwrap(),
+        )
+        .unwrap();
+
+        assert_eq!(ticks_crossed, 2);
+        assert_eq!(tick_provider.ticks_crossed, vec![-10, -20]);
+        assert_eq!(observation_provider.reads.get(), 1);
+    }
+
+    #[test]
+    fn takes_the_protocol_fee_out_of_the_swap_fee() {
+        let (pool, mut tick_provider) = setup();
+        let (without_protocol_fee, _) = run(
+            &pool,
+            &mut tick_provider,
+            &MemoryObservationProvider::default(),
+            0,
+            1_000_000,
+            true,
+            tick_math::MIN_SQRT_RATIO + 1,
+        )
+        .unwrap();
+        let (with_protocol_fee, _) = run(
+            &pool,
+            &mut tick_provider,
+            &MemoryObservationProvider::default(),
+            250_000,
+            1_000_000,
+            true,
+            tick_math::MIN_SQRT_RATIO + 1,
+        )
+        .unwrap();
+
+        assert!(with_protocol_fee.protocol_fee > 0);
+        assert_eq!(
+            with_protocol_fee.amount_calculated,
+            without_protocol_fee.amount_calculated
+        );
+        assert!(
+            with_protocol_fee.fee_growth_global_x32 < without_protocol_fee.fee_growth_global_x32
+        );
+    }
+
+    #[test]
+    fn propagates_tick_provider_errors() {
+        let (pool, mut tick_provider) = setup();
+        tick_provider.ticks.remove(&-10);
+        let result = run(
+            &pool,
+            &mut tick_provider,
+            &MemoryObservationProvider::default(),
+            0,
+            i64::MAX,
+            true,
+            tick_math::get_sqrt_ratio_at_tick(-3000).unwrap(),
+        );
+        assert_eq!(error_name(result.unwrap_err()), "TNI");
+    }
+}
@@ -0,0 +1,542 @@
+//! Off-chain swap quoter for Cykura pools
+//!
+//! Runs the swap engine of the program over account data fetched by the client. A quote matches
+//! the amounts of an on-chain swap against the same account state, and lists the remaining
+//! accounts the swap reads, in order.
+//!
+use anchor_lang::prelude::*;
+use anchor_lang::AccountDeserialize;
+use cyclos_core::error::ErrorCode;
+use cyclos_core::libraries::tick_math;
+use cyclos_core::states::escrow::Escrow;
+use cyclos_core::states::factory::FactoryState;
+use cyclos_core::states::oracle::{ObservationState, OBSERVATION_SEED};
+use cyclos_core::states::pool::PoolState;
+use cyclos_core::states::tick::{TickState, TICK_SEED};
+use cyclos_core::states::tick_bitmap::{TickBitmapState, BITMAP_SEED};
+use cyclos_core::swap_engine::{swap_steps, SwapCache, SwapState, TickProvider};
+use std::collections::HashMap;
+use std::ops::Neg;
+
+/// The result of a quoted swap
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SwapQuote {
+    /// The amount of input token paid, including the fee
+    pub amount_in: u64,
+
+    /// The amount of output token received
+    pub amount_out: u64,
+
+    /// The sqrt(price) of the pool after the swap, as a Q32.32
+    pub sqrt_price_x32_after: u64,
+
+    /// The tick of the pool after the swap
+    pub tick_after: i32,
+
+    /// The in-range liquidity of the pool after the swap
+    pub liquidity_after: u64,
+
+    /// The number of initialized ticks crossed
+    pub ticks_crossed: u32,
+
+    /// Remaining accounts for the swap instruction, in the order they are read
+    pub remaining_accounts: Vec<Pubkey>,
+}
+
+/// A pool along with the accounts read by its swaps
+pub struct Quoter {
+    /// The pool to quote swaps for
+    pub pool: PoolState,
+
+    /// The observation at `pool.observation_index`
+    pub latest_observation: ObservationState,
+
+    /// Initialized bitmap accounts of the pool, keyed by word position
+    pub bitmaps: HashMap<i16, TickBitmapState>,
+
+    /// Initialized tick accounts of the pool, keyed by tick
+    pub ticks: HashMap<i32, TickState>,
+
+    /// The swap fee charged, in hundredths of a bip. The pool fee unless discounted with
+    /// `apply_fee_discount`
+    pub fee: u32,
+}
+
+impl Quoter {
+    /// Creates a quoter for a pool. Bitmap and tick accounts must be added before quoting
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - The pool to quote swaps for
+    /// * `latest_observation` - The observation at `pool.observation_index`
+    ///
+    pub fn new(pool: PoolState, latest_observation: ObservationState) -> Self {
+        Quoter {
+            fee: pool.fee,
+            pool,
+            latest_observation,
+            bitmaps: HashMap::new(),
+            ticks: HashMap::new(),
+        }
+    }
+
+    /// Discounts the swap fee for a trader locking governance tokens, as the swap instruction
+    /// does when passed the escrow of the trader
+    ///
+    /// # Arguments
+    ///
+    /// * `factory_state` - The factory holding the locker and discount tiers
+    /// * `escrow` - The escrow of the trader
+    /// * `now` - Expected block timestamp, used to tell the time left before the tokens unlock
+    ///
+    pub fn apply_fee_discount(&mut self, factory_state: &FactoryState, escrow: &Escrow, now: i64) {
+        self.fee = factory_state.discounted_fee(self.pool.fee, Some(escrow), now);
+    }
+
+    /// Creates a quoter from raw pool and observation account data
+    ///
+    /// # Arguments
+    ///
+    /// * `pool_data` - Data of the pool account, including the discriminator
+    /// * `observation_data` - Data of the observation account at `pool.observation_index`
+    ///
+    pub fn try_from_account_data(pool_data: &[u8], observation_data: &[u8]) -> Result<Self> {
+        Ok(Quoter::new(
+            PoolState::try_deserialize(&mut &pool_data[..])?,
+            ObservationState::try_deserialize(&mut &observation_data[..])?,
+        ))
+    }
+
+    /// Adds a bitmap account from raw account data
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Data of a bitmap account of this pool, including the discriminator
+    ///
+    pub fn add_bitmap_account(&mut self, data: &[u8]) -> Result<()> {
+        let bitmap = TickBitmapState::try_deserialize(&mut &data[..])?;
+        self.bitmaps.insert(bitmap.word_pos, bitmap);
+        Ok(())
+    }
+
+    /// Adds a tick account from raw account data
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Data of a tick account of this pool, including the discriminator
+    ///
+    pub fn add_tick_account(&mut self, data: &[u8]) -> Result<()> {
+        let tick = TickState::try_deserialize(&mut &data[..])?;
+        self.ticks.insert(tick.tick, tick);
+        Ok(())
+    }
+
+    /// Returns the address of the bitmap account at a word position
+    ///
+    /// # Arguments
+    ///
+    /// * `word_pos` - The word position of the bitmap
+    ///
+    pub fn bitmap_address(&self, word_pos: i16) -> Pubkey {
+        Pubkey::find_program_address(
+            &[
+                BITMAP_SEED.as_bytes(),
+                self.pool.token_0.as_ref(),
+                self.pool.token_1.as_ref(),
+                &self.pool.fee.to_be_bytes(),
+                &word_pos.to_be_bytes(),
+            ],
+            &cyclos_core::id(),
+        )
+        .0
+    }
+
+    /// Returns the address of the account for a tick
+    ///
+    /// # Arguments
+    ///
+    /// * `tick` - The tick
+    ///
+    pub fn tick_address(&self, tick: i32) -> Pubkey {
+        Pubkey::find_program_address(
+            &[
+                TICK_SEED.as_bytes(),
+                self.pool.token_0.as_ref(),
+                self.pool.token_1.as_ref(),
+                &self.pool.fee.to_be_bytes(),
+                &tick.to_be_bytes(),
+            ],
+            &cyclos_core::id(),
+        )
+        .0
+    }
+
+    /// Returns the address of the observation account at an index of the oracle array
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the observation
+    ///
+    pub fn observation_address(&self, index: u16) -> Pubkey {
+        Pubkey::find_program_address(
+            &[
+                OBSERVATION_SEED.as_bytes(),
+                self.pool.token_0.as_ref(),
+                self.pool.token_1.as_ref(),
+                &self.pool.fee.to_be_bytes(),
+                &index.to_be_bytes(),
+            ],
+            &cyclos_core::id(),
+        )
+        .0
+    }
+
+    /// Quotes a swap, following the same steps as the `swap` instruction
+    ///
+    /// # Arguments
+    ///
+    /// * `zero_for_one` - The direction of the swap, true for token_0 to token_1
+    /// * `amount_specified` - Exact input amount if positive, or exact output amount if negative
+    /// * `sqrt_price_limit_x32` - The Q32.32 sqrt price √P limit, or 0 to swap without a limit
+    /// * `block_timestamp` - Expected block timestamp, used to tell if the next observation is written
+    ///
+    pub fn quote(
+        &self,
+        zero_for_one: bool,
+        amount_specified: i64,
+        sqrt_price_limit_x32: u64,
+        block_timestamp: u32,
+    ) -> Result<SwapQuote> {
+        require!(amount_specified != 0, ErrorCode::AS);
+
+        let pool = &self.pool;
+        let sqrt_price_limit_x32 = if sqrt_price_limit_x32 == 0 {
+            if zero_for_one {
+                tick_math::MIN_SQRT_RATIO + 1
+            } else {
+                tick_math::MAX_SQRT_RATIO - 1
+            }
+        } else {
+            sqrt_price_limit_x32
+        };
+        require!(
+            if zero_for_one {
+                sqrt_price_limit_x32 < pool.sqrt_price_x32
+                    && sqrt_price_limit_x32 > tick_math::MIN_SQRT_RATIO
+            } else {
+                sqrt_price_limit_x32 > pool.sqrt_price_x32
+                    && sqrt_price_limit_x32 < tick_math::MAX_SQRT_RATIO
+            },
+            ErrorCode::SPL
+        );
+
+        let mut cache = SwapCache {
+            fee: self.fee,
+            // the protocol fee is taken out of the swap fee, so it does not change swap amounts
+            fee_protocol: 0,
+            liquidity_start: pool.liquidity,
+            block_timestamp,
+            tick_cumulative: 0,
+            seconds_per_liquidity_cumulative_x32: 0,
+            computed_latest_observation: false,
+            reward_growths_global_x32: pool.reward_growths_global_x32(),
+        };
+        let mut state = SwapState {
+            amount_specified_remaining: amount_specified,
+            amount_calculated: 0,
+            sqrt_price_x32: pool.sqrt_price_x32,
+            tick: pool.tick,
+            fee_growth_global_x32: 0,
+            protocol_fee: 0,
+            liquidity: pool.liquidity,
+        };
+        let mut tick_provider = QuoterTickProvider {
+            quoter: self,
+            remaining_accounts: Vec::new(),
+        };
+        let ticks_crossed = swap_steps(
+            pool,
+            &mut cache,
+            &mut state,
+            &mut tick_provider,
+            &self.latest_observation,
+            zero_for_one,
+            sqrt_price_limit_x32,
+        )?;
+        let mut remaining_accounts = tick_provider.remaining_accounts;
+
+        // a swap moving the tick in a new oracle partition writes the next observation
+        if state.tick != pool.tick
+            && block_timestamp / 14 > self.latest_observation.block_timestamp / 14
+        {
+            remaining_accounts.push(self.observation_address(pool.next_observation_index()));
+        }
+
+        let amount_specified_used = amount_specified - state.amount_specified_remaining;
+        let (amount_in, amount_out) = if amount_specified > 0 {
+            (amount_specified_used, state.amount_calculated.neg())
+        } else {
+            (state.amount_calculated, amount_specified_used.neg())
+        };
+
+        Ok(SwapQuote {
+            amount_in: amount_in as u64,
+            amount_out: amount_out as u64,
+            sqrt_price_x32_after: state.sqrt_price_x32,
+            tick_after: state.tick,
+            liquidity_after: state.liquidity,
+            ticks_crossed,
+            remaining_accounts,
+        })
+    }
+}
+
+/// Serves the bitmaps and ticks held by a quoter, recording the accounts read by the swap
+struct QuoterTickProvider<'a> {
+    quoter: &'a Quoter,
+    remaining_accounts: Vec<Pubkey>,
+}
+
+impl<'a> TickProvider for QuoterTickProvider<'a> {
+    fn bitmap(&mut self, word_pos: i16) -> Result<Option<TickBitmapState>> {
+        self.remaining_accounts
+            .push(self.quoter.bitmap_address(word_pos));
+        Ok(self.quoter.bitmaps.get(&word_pos).copied())
+    }
+
+    fn cross(
+        &mut self,
+        tick: i32,
+        _fee_growth_global_0_x32: u64,
+        _fee_growth_global_1_x32: u64,
+        _cache: &SwapCache,
+    ) -> Result<i64> {
+        self.remaining_accounts.push(self.quoter.tick_address(tick));
+        Ok(self
+            .quoter
+            .ticks
+            .get(&tick)
+            .ok_or(ErrorCode::TNI)?
+            .liquidity_net)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use anchor_lang::Discriminator;
+    use cyclos_core::libraries::swap_math;
+
+    const LIQUIDITY: u64 = 1 << 32;
+
+    /// A pool at tick 5 with spacing 10, and a position between ticks -10 and 10
+    fn quoter() -> Quoter {
+        let pool = PoolState {
+            token_0: Pubkey::new_from_array([1; 32]),
+            token_1: Pubkey::new_from_array([2; 32]),
+            fee: 500,
+            tick_spacing: 10,
+            tick: 5,
+            sqrt_price_x32: tick_math::get_sqrt_ratio_at_tick(5).unwrap(),
+            liquidity: LIQUIDITY,
+            observation_cardinality: 2,
+            observation_cardinality_next: 2,
+            ..Default::default()
+        };
+        let latest_observation = ObservationState {
+            block_timestamp: 1400,
+            initialized: true,
+            ..Default::default()
+        };
+        let mut quoter = Quoter::new(pool, latest_observation);
+
+        // tick -10 is bit 255 of word -1, tick 10 is bit 1 of word 0
+        let mut lower_bitmap = TickBitmapState {
+            word_pos: -1,
+            ..Default::default()
+        };
+        lower_bitmap.flip_bit(255);
+        quoter.bitmaps.insert(-1, lower_bitmap);
+        let mut upper_bitmap = TickBitmapState::default();
+        upper_bitmap.flip_bit(1);
+        quoter.bitmaps.insert(0, upper_bitmap);
+
+        for (tick, liquidity_net) in [(-10, LIQUIDITY as i64), (10, -(LIQUIDITY as i64))] {
+            let tick_state = TickState {
+                tick,
+                liquidity_net,
+                liquidity_gross: LIQUIDITY,
+                ..Default::default()
+            };
+            quoter.ticks.insert(tick, tick_state);
+        }
+        quoter
+    }
+
+    #[test]
+    fn fails_for_zero_amount() {
+        assert!(quoter().quote(true, 0, 0, 1400).is_err());
+    }
+
+    #[test]
+    fn fails_for_limit_on_the_wrong_side_of_the_price() {
+        let quoter = quoter();
+        let sqrt_price_x32 = quoter.pool.sqrt_price_x32;
+        assert!(quoter.quote(true, 1_000, sqrt_price_x32 + 1, 1400).is_err());
+        assert!(quoter
+            .quote(false, 1_000, sqrt_price_x32 - 1, 1400)
+            .is_err());
+    }
+
+    #[test]
+    fn exact_input_within_a_tick_matches_a_single_step() {
+        let quoter = quoter();
+        let quote = quoter.quote(true, 1_000, 0, 1400).unwrap();
+
+        let step = swap_math::compute_swap_step(
+            quoter.pool.sqrt_price_x32,
+            tick_math::get_sqrt_ratio_at_tick(0).unwrap(),
+            LIQUIDITY,
+            1_000,
+            500,
+        );
+        assert_eq!(quote.amount_in, 1_000);
+        assert_eq!(step.amount_in + step.fee_amount, 1_000);
+        assert_eq!(quote.amount_out, step.amount_out);
+        assert_eq!(quote.sqrt_price_x32_after, step.sqrt_ratio_next_x32);
+        assert_eq!(quote.ticks_crossed, 0);
+        assert_eq!(quote.liquidity_after, LIQUIDITY);
+        assert_eq!(quote.remaining_accounts, vec![quoter.bitmap_address(0)]);
+    }
+
+    #[test]
+    fn charges_the_discounted_fee() {
+        let mut quoter = quoter();
+        let locker = Pubkey::new_unique();
+        let mut factory_state = FactoryState {
+            locker,
+            ..Default::default()
+        };
+        factory_state.fee_discount_tiers[0].discount = 500_000;
+        let escrow = Escrow {
+            locker,
+            escrow_ends_at: 2_000,
+            ..Default::default()
+        };
+        quoter.apply_fee_discount(&factory_state, &escrow, 1_000);
+        assert_eq!(quoter.fee, 250);
+
+        let quote = quoter.quote(true, 1_000, 0, 1400).unwrap();
+        let step = swap_math::compute_swap_step(
+            quoter.pool.sqrt_price_x32,
+            tick_math::get_sqrt_ratio_at_tick(0).unwrap(),
+            LIQUIDITY,
+            1_000,
+            250,
+        );
+        assert_eq!(quote.amount_out, step.amount_out);
+    }
+
+    #[test]
+    fn exact_output_receives_the_requested_amount() {
+        let quote = quoter().quote(false, -1_000, 0, 1400).unwrap();
+        assert_eq!(quote.amount_out, 1_000);
+        assert!(quote.amount_in > 1_000);
+        assert_eq!(quote.ticks_crossed, 0);
+    }
+
+    #[test]
+    fn crosses_initialized_ticks_and_lists_their_accounts() {
+        let quoter = quoter();
+        let limit = tick_math::get_sqrt_ratio_at_tick(20).unwrap();
+        let quote = quoter.quote(false, i64::MAX, limit, 1400).unwrap();
+
+        // liquidity runs out past tick 10, so the swap stops at the price limit
+        assert_eq!(quote.sqrt_price_x32_after, limit);
+        assert_eq!(quote.tick_after, 20);
+        assert_eq!(quote.ticks_crossed, 1);
+        assert_eq!(quote.liquidity_after, 0);
+        assert_eq!(
+            quote.remaining_accounts,
+            vec![quoter.bitmap_address(0), quoter.tick_address(10)]
+        );
+    }
+
+    #[test]
+    fn lists_uninitialized_bitmaps_on_the_way() {
+        let quoter = quoter();
+        let limit = tick_math::get_sqrt_ratio_at_tick(-3000).unwrap();
+        let quote = quoter.quote(true, i64::MAX, limit, 1400).unwrap();
+
+        assert_eq!(quote.tick_after, -3000);
+        assert_eq!(quote.ticks_crossed, 1);
+        assert_eq!(
+            quote.remaining_accounts,
+            vec![
+                quoter.bitmap_address(0),
+                quoter.bitmap_address(-1),
+                quoter.tick_address(-10),
+                quoter.bitmap_address(-2),
+            ]
+        );
+    }
+
+    #[test]
+    fn adds_the_next_observation_when_writing_to_a_new_partition() {
+        let quoter = quoter();
+        let limit = tick_math::get_sqrt_ratio_at_tick(8).unwrap();
+
+        // same 14 second partition as the latest observation
+        let quote = quoter.quote(false, i64::MAX, limit, 1405).unwrap();
+        assert_eq!(quote.remaining_accounts, vec![quoter.bitmap_address(0)]);
+
+        let quote = quoter.quote(false, i64::MAX, limit, 1414).unwrap();
+        assert_eq!(
+            quote.remaining_accounts,
+            vec![quoter.bitmap_address(0), quoter.observation_address(1)]
+        );
+    }
+
+    #[test]
+    fn reads_account_data() {
+        let quoter = quoter();
+        let serialize = |discriminator: [u8; 8], bytes: &[u8]| {
+            let mut data = discriminator.to_vec();
+            data.extend_from_slice(bytes);
+            data
+        };
+        let pool_data = serialize(
+            PoolState::discriminator(),
+            anchor_lang::__private::bytemuck::bytes_of(&quoter.pool),
+        );
+        let observation_data = serialize(
+            ObservationState::discriminator(),
+            anchor_lang::__private::bytemuck::bytes_of(&quoter.latest_observation),
+        );
+
+        let mut from_data = Quoter::try_from_account_data(&pool_data, &observation_data).unwrap();
+        for bitmap in quoter.bitmaps.values() {
+            from_data
+                .add_bitmap_account(&serialize(
+                    TickBitmapState::discriminator(),
+                    anchor_lang::__private::bytemuck::bytes_of(bitmap),
+                ))
+                .unwrap();
+        }
+        for tick in quoter.ticks.values() {
+            from_data
+                .add_tick_account(&serialize(
+                    TickState::discriminator(),
+                    anchor_lang::__private::bytemuck::bytes_of(tick),
+                ))
+                .unwrap();
+        }
+        assert!(from_data.add_tick_account(&pool_data).is_err());
+
+        let limit = tick_math::get_sqrt_ratio_at_tick(-3000).unwrap();
+        assert_eq!(
+            from_data.quote(true, i64::MAX, limit, 1400).unwrap(),
+            quoter.quote(true, i64::MAX, limit, 1400).unwrap()
+        );
+    }
+}
@@ -43,6 +43,8 @@ async function main() {
     coreProgram.programId
   );
 
+  const [factoryState, factoryStateBump] = await web3.PublicKey.findProgramAddress([], coreProgram.programId)
+
   const [initialObservationStateA, initialObservationBumpA] = await web3.PublicKey.findProgramAddress(
     [
       OBSERVATION_SEED,
@@ -75,6 +77,7 @@ async function main() {
       token0: usdtMint,
       token1: usdcMint,
       feeState,
+      factoryState,
       poolState: poolAState,
       initialObservationState: initialObservationStateA,
       vault0: vaultA0,
@@ -0,0 +1,1026 @@
+//! In-memory pool simulator for Cykura pools
+//!
+//! Replays swaps and liquidity changes against a model of the accounts of a pool, with no
+//! validator involved. Swaps run on the swap engine of the program, while positions, ticks,
+//! bitmaps and oracle observations are updated with the state functions of the program.
+//! `Simulator::report` values each position at the end of a run.
+//!
+mod report;
+
+pub use report::{value_in_token_1, PositionReport};
+
+use anchor_lang::prelude::*;
+use cyclos_core::error::ErrorCode;
+use cyclos_core::libraries::{liquidity_math, sqrt_price_math, tick_math};
+use cyclos_core::states::oracle::{self, ObservationState};
+use cyclos_core::states::pool::PoolState;
+use cyclos_core::states::position::PositionState;
+use cyclos_core::states::tick::{self, GlobalGrowths, TickState};
+use cyclos_core::states::tick_bitmap::{self, Position, TickBitmapState};
+use cyclos_core::swap_engine::{swap_steps, SwapCache, SwapState, TickProvider};
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::ops::Neg;
+
+/// Identifies a position by its owner and tick boundaries, like the seeds of a position account
+pub type PositionKey = (Pubkey, i32, i32);
+
+/// A position, along with the token flows needed to value it
+#[derive(Clone, Copy, Default)]
+pub struct SimulatedPosition {
+    /// The position as stored by the program
+    pub state: PositionState,
+
+    /// Total token_0 deposited by mints
+    pub deposited_0: u64,
+
+    /// Total token_1 deposited by mints
+    pub deposited_1: u64,
+
+    /// Value of the deposits in token_1, at the price of each mint
+    pub cost: u128,
+
+    /// Total token_0 principal removed by burns, which is credited to tokens owed
+    pub withdrawn_0: u64,
+
+    /// Total token_1 principal removed by burns, which is credited to tokens owed
+    pub withdrawn_1: u64,
+
+    /// Total token_0 collected
+    pub collected_0: u64,
+
+    /// Total token_1 collected
+    pub collected_1: u64,
+}
+
+/// An action replayed against the pool
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    /// Adds liquidity to a position
+    Mint {
+        owner: Pubkey,
+        tick_lower: i32,
+        tick_upper: i32,
+        liquidity: u64,
+    },
+
+    /// Removes liquidity from a position, crediting the tokens to the position
+    Burn {
+        owner: Pubkey,
+        tick_lower: i32,
+        tick_upper: i32,
+        liquidity: u64,
+    },
+
+    /// Collects tokens owed to a position
+    Collect {
+        owner: Pubkey,
+        tick_lower: i32,
+        tick_upper: i32,
+        amount_0_requested: u64,
+        amount_1_requested: u64,
+    },
+
+    /// Swaps against the pool. Exact input if the amount is positive, exact output if negative.
+    /// A price limit of 0 swaps without a limit
+    Swap {
+        zero_for_one: bool,
+        amount_specified: i64,
+        sqrt_price_limit_x32: u64,
+    },
+}
+
+/// An action, and the block timestamp at which it happens
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Event {
+    /// The block timestamp of the action
+    pub time: u32,
+
+    /// The action to replay
+    pub action: Action,
+}
+
+/// A model of a pool and the accounts it owns
+pub struct Simulator {
+    /// The pool being simulated
+    pub pool: PoolState,
+
+    /// Initialized ticks of the pool
+    pub ticks: BTreeMap<i32, TickState>,
+
+    /// Bitmaps of the pool, keyed by word position
+    pub bitmaps: HashMap<i16, TickBitmapState>,
+
+    /// Positions of the pool
+    pub positions: BTreeMap<PositionKey, SimulatedPosition>,
+
+    /// The oracle array, of length `pool.observation_cardinality_next`
+    pub observations: Vec<ObservationState>,
+
+    /// The current block timestamp
+    pub time: u32,
+
+    /// The token_0 balance of the pool
+    pub balance_0: u64,
+
+    /// The token_1 balance of the pool
+    pub balance_1: u64,
+
+    /// The maximum liquidity allocation for a single tick
+    max_liquidity_per_tick: u64,
+}
+
+impl Simulator {
+    /// Creates and initializes a pool, as in `create_and_init_pool`
+    ///
+    /// # Arguments
+    ///
+    /// * `fee` - The fee of the pool, in hundredths of a bip
+    /// * `tick_spacing` - The tick spacing of the pool
+    /// * `sqrt_price_x32` - The initial sqrt(price) of the pool, as a Q32.32
+    /// * `time` - The block timestamp at which the pool is created
+    ///
+    pub fn new(fee: u32, tick_spacing: u16, sqrt_price_x32: u64, time: u32) -> Result<Self> {
+        let tick = tick_math::get_tick_at_sqrt_ratio(sqrt_price_x32)?;
+        Ok(Simulator {
+            pool: PoolState {
+                fee,
+                tick_spacing,
+                sqrt_price_x32,
+                tick,
+                observation_cardinality: 1,
+                observation_cardinality_next: 1,
+                unlocked: true,
+                ..Default::default()
+            },
+            ticks: BTreeMap::new(),
+            bitmaps: HashMap::new(),
+            positions: BTreeMap::new(),
+            observations: vec![ObservationState {
+                block_timestamp: time,
+                initialized: true,
+                ..Default::default()
+            }],
+            time,
+            balance_0: 0,
+            balance_1: 0,
+            max_liquidity_per_tick: tick::tick_spacing_to_max_liquidity_per_tick(
+                tick_spacing as i32,
+            ),
+        })
+    }
+
+    /// Moves the block timestamp forward
+    ///
+    /// # Arguments
+    ///
+    /// * `time` - The new block timestamp, not less than the current one
+    ///
+    pub fn set_time(&mut self, time: u32) {
+        assert!(time >= self.time, "time cannot move backwards");
+        self.time = time;
+    }
+
+    /// Grows the oracle array, as in `increase_observation_cardinality_next`
+    ///
+    /// # Arguments
+    ///
+    /// * `observation_cardinality_next` - The desired minimum length of the oracle array
+    ///
+    pub fn increase_observation_cardinality_next(&mut self, observation_cardinality_next: u16) {
+        for index in self.pool.observation_cardinality_next..observation_cardinality_next {
+            // placeholder timestamp, as written by the program
+            self.observations.push(ObservationState {
+                index,
+                block_timestamp: 1,
+                ..Default::default()
+            });
+        }
+        self.pool.observation_cardinality_next = self
+            .pool
+            .observation_cardinality_next
+            .max(observation_cardinality_next);
+    }
+
+    /// Adds liquidity to a position. Returns the amounts of token_0 and token_1 paid
+    ///
+    /// # Arguments
+    ///
+    /// * `owner` - The owner of the position
+    /// * `tick_lower` - The lower tick of the position
+    /// * `tick_upper` - The upper tick of the position
+    /// * `liquidity` - The liquidity to add
+    ///
+    pub fn mint(
+        &mut self,
+        owner: Pubkey,
+        tick_lower: i32,
+        tick_upper: i32,
+        liquidity: u64,
+    ) -> Result<(u64, u64)> {
+        let key = (owner, tick_lower, tick_upper);
+        let (amount_0, amount_1) = self.modify_position(key, i64::try_from(liquidity).unwrap())?;
+        let (amount_0, amount_1) = (amount_0 as u64, amount_1 as u64);
+
+        self.balance_0 += amount_0;
+        self.balance_1 += amount_1;
+        let sqrt_price_x32 = self.pool.sqrt_price_x32;
+        let position = self.positions.get_mut(&key).unwrap();
+        position.deposited_0 += amount_0;
+        position.deposited_1 += amount_1;
+        position.cost += value_in_token_1(amount_0 as u128, amount_1 as u128, sqrt_price_x32);
+
+        Ok((amount_0, amount_1))
+    }
+
+    /// Removes liquidity from a position, crediting the tokens to the position.
+    /// Returns the amounts of token_0 and token_1 credited
+    ///
+    /// # Arguments
+    ///
+    /// * `owner` - The owner of the position
+    /// * `tick_lower` - The lower tick of the position
+    /// * `tick_upper` - The upper tick of the position
+    /// * `liquidity` - The liquidity to remove
+    ///
+    pub fn burn(
+        &mut self,
+        owner: Pubkey,
+        tick_lower: i32,
+        tick_upper: i32,
+        liquidity: u64,
+    ) -> Result<(u64, u64)> {
+        let key = (owner, tick_lower, tick_upper);
+        let (amount_0, amount_1) = self.modify_position(key, -i64::try_from(liquidity).unwrap())?;
+        let (amount_0, amount_1) = (amount_0.neg() as u64, amount_1.neg() as u64);
+
+        let position = self.positions.get_mut(&key).unwrap();
+        position.state.tokens_owed_0 += amount_0;
+        position.state.tokens_owed_1 += amount_1;
+        position.withdrawn_0 += amount_0;
+        position.withdrawn_1 += amount_1;
+
+        Ok((amount_0, amount_1))
+    }
+
+    /// Collects tokens owed to a position. Returns the amounts of token_0 and token_1 collected
+    ///
+    /// # Arguments
+    ///
+    /// * `owner` - The owner of the position
+    /// * `tick_lower` - The lower tick of the position
+    /// * `tick_upper` - The upper tick of the position
+    /// * `amount_0_requested` - How much token_0 should be withdrawn from the fees owed
+    /// * `amount_1_requested` - How much token_1 should be withdrawn from the fees owed
+    ///
+    pub fn collect(
+        &mut self,
+        owner: Pubkey,
+        tick_lower: i32,
+        tick_upper: i32,
+        amount_0_requested: u64,
+        amount_1_requested: u64,
+    ) -> Result<(u64, u64)> {
+        let position = self
+            .positions
+            .get_mut(&(owner, tick_lower, tick_upper))
+            .ok_or(ErrorCode::NP)?;
+
+        let amount_0 = amount_0_requested.min(position.state.tokens_owed_0);
+        let amount_1 = amount_1_requested.min(position.state.tokens_owed_1);
+        position.state.tokens_owed_0 -= amount_0;
+        position.state.tokens_owed_1 -= amount_1;
+        position.collected_0 += amount_0;
+        position.collected_1 += amount_1;
+        self.balance_0 -= amount_0;
+        self.balance_1 -= amount_1;
+
+        Ok((amount_0, amount_1))
+    }
+
+    /// Swaps token_0 for token_1, or token_1 for token_0. Returns the deltas of the pool
+    /// balances of token_0 and token_1, positive for tokens paid to the pool
+    ///
+    /// # Arguments
+    ///
+    /// * `zero_for_one` - The direction of the swap, true for token_0 to token_1
+    /// * `amount_specified` - Exact input amount if positive, or exact output amount if negative
+    /// * `sqrt_price_limit_x32` - The Q32.32 sqrt price √P limit, or 0 to swap without a limit
+    ///
+    pub fn swap(
+        &mut self,
+        zero_for_one: bool,
+        amount_specified: i64,
+        sqrt_price_limit_x32: u64,
+    ) -> Result<(i64, i64)> {
+        require!(amount_specified != 0, ErrorCode::AS);
+
+        let pool = self.pool;
+        let sqrt_price_limit_x32 = if sqrt_price_limit_x32 == 0 {
+            if zero_for_one {
+                tick_math::MIN_SQRT_RATIO + 1
+            } else {
+                tick_math::MAX_SQRT_RATIO - 1
+            }
+        } else {
+            sqrt_price_limit_x32
+        };
+        require!(
+            if zero_for_one {
+                sqrt_price_limit_x32 < pool.sqrt_price_x32
+                    && sqrt_price_limit_x32 > tick_math::MIN_SQRT_RATIO
+            } else {
+                sqrt_price_limit_x32 > pool.sqrt_price_x32
+                    && sqrt_price_limit_x32 < tick_math::MAX_SQRT_RATIO
+            },
+            ErrorCode::SPL
+        );
+
+        let mut cache = SwapCache {
+            fee: pool.fee,
+            fee_protocol: if zero_for_one {
+                pool.fee_protocol_0
+            } else {
+                pool.fee_protocol_1
+            },
+            liquidity_start: pool.liquidity,
+            block_timestamp: self.time,
+            tick_cumulative: 0,
+            seconds_per_liquidity_cumulative_x32: 0,
+            computed_latest_observation: false,
+            reward_growths_global_x32: pool.reward_growths_global_x32(),
+        };
+        let mut state = SwapState {
+            amount_specified_remaining: amount_specified,
+            amount_calculated: 0,
+            sqrt_price_x32: pool.sqrt_price_x32,
+            tick: pool.tick,
+            fee_growth_global_x32: if zero_for_one {
+                pool.fee_growth_global_0_x32
+            } else {
+                pool.fee_growth_global_1_x32
+            },
+            protocol_fee: 0,
+            liquidity: pool.liquidity,
+        };
+
+        let latest_observation = self.observations[pool.observation_index as usize];
+        let mut tick_provider = SimulatorTickProvider {
+            ticks: &mut self.ticks,
+            bitmaps: &self.bitmaps,
+            crossed: Vec::new(),
+        };
+        let result = swap_steps(
+            &pool,
+            &mut cache,
+            &mut state,
+            &mut tick_provider,
+            &latest_observation,
+            zero_for_one,
+            sqrt_price_limit_x32,
+        );
+        if result.is_err() {
+            // revert crossed ticks, like a failed transaction
+            for (tick, tick_state) in tick_provider.crossed.into_iter().rev() {
+                self.ticks.insert(tick, tick_state);
+            }
+        }
+        result?;
+
+        // write an oracle entry if the tick changes
+        if state.tick != pool.tick {
+            self.write_observation(pool.tick, cache.liquidity_start);
+            self.pool.tick = state.tick;
+        }
+        self.pool.sqrt_price_x32 = state.sqrt_price_x32;
+        self.pool.liquidity = state.liquidity;
+
+        if zero_for_one {
+            self.pool.fee_growth_global_0_x32 = state.fee_growth_global_x32;
+            self.pool.protocol_fees_token_0 += state.protocol_fee;
+        } else {
+            self.pool.fee_growth_global_1_x32 = state.fee_growth_global_x32;
+            self.pool.protocol_fees_token_1 += state.protocol_fee;
+        }
+
+        let exact_input = amount_specified > 0;
+        let (amount_0, amount_1) = if zero_for_one == exact_input {
+            (
+                amount_specified - state.amount_specified_remaining,
+                state.amount_calculated,
+            )
+        } else {
+            (
+                state.amount_calculated,
+                amount_specified - state.amount_specified_remaining,
+            )
+        };
+        self.balance_0 = liquidity_math::add_delta(self.balance_0, amount_0)?;
+        self.balance_1 = liquidity_math::add_delta(self.balance_1, amount_1)?;
+
+        Ok((amount_0, amount_1))
+    }
+
+    /// Returns the tick and seconds per liquidity cumulatives as of each `seconds_ago`,
+    /// as in the `observe` instruction
+    ///
+    /// # Arguments
+    ///
+    /// * `seconds_agos` - From how long ago each cumulative value should be returned
+    ///
+    pub fn observe(&self, seconds_agos: &[u32]) -> Result<(Vec<i64>, Vec<u64>)> {
+        oracle::observe(
+            &self.observations,
+            self.time,
+            seconds_agos,
+            self.pool.tick,
+            self.pool.observation_index,
+            self.pool.liquidity,
+            self.pool.observation_cardinality,
+        )
+    }
+
+    /// Moves to the time of an event and replays its action
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The event to replay
+    ///
+    pub fn apply(&mut self, event: &Event) -> Result<()> {
+        self.set_time(event.time);
+        match event.action {
+            Action::Mint {
+                owner,
+                tick_lower,
+                tick_upper,
+                liquidity,
+            } => {
+                self.mint(owner, tick_lower, tick_upper, liquidity)?;
+            }
+            Action::Burn {
+                owner,
+                tick_lower,
+                tick_upper,
+                liquidity,
+            } => {
+                self.burn(owner, tick_lower, tick_upper, liquidity)?;
+            }
+            Action::Collect {
+                owner,
+                tick_lower,
+                tick_upper,
+                amount_0_requested,
+                amount_1_requested,
+            } => {
+                self.collect(
+                    owner,
+                    tick_lower,
+                    tick_upper,
+                    amount_0_requested,
+                    amount_1_requested,
+                )?;
+            }
+            Action::Swap {
+                zero_for_one,
+                amount_specified,
+                sqrt_price_limit_x32,
+            } => {
+                self.swap(zero_for_one, amount_specified, sqrt_price_limit_x32)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Replays events in order, stopping at the first failed action
+    ///
+    /// # Arguments
+    ///
+    /// * `events` - The events to replay, ordered by time
+    ///
+    pub fn replay(&mut self, events: &[Event]) -> Result<()> {
+        for event in events {
+            self.apply(event)?;
+        }
+        Ok(())
+    }
+
+    /// Values every position at the current price
+    pub fn report(&self) -> Result<Vec<PositionReport>> {
+        self.positions
+            .iter()
+            .map(|(key, position)| report::report_position(&self.pool, &self.ticks, key, position))
+            .collect()
+    }
+
+    /// Credits or debits liquidity to a position, as in `_modify_position`. Returns the amounts
+    /// of token_0 and token_1 owed to the pool, negative if the pool should pay the owner
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The owner and tick boundaries of the position
+    /// * `liquidity_delta` - The change in liquidity
+    ///
+    fn modify_position(&mut self, key: PositionKey, liquidity_delta: i64) -> Result<(i64, i64)> {
+        let (_, tick_lower, tick_upper) = key;
+        cyclos_core::check_ticks(tick_lower, tick_upper)?;
+        cyclos_core::check_tick(tick_lower, self.pool.tick_spacing)?;
+        cyclos_core::check_tick(tick_upper, self.pool.tick_spacing)?;
+
+        self.update_position(key, liquidity_delta)?;
+
+        let sqrt_ratio_lower_x32 = tick_math::get_sqrt_ratio_at_tick(tick_lower)?;
+        let sqrt_ratio_upper_x32 = tick_math::get_sqrt_ratio_at_tick(tick_upper)?;
+        let mut amount_0 = 0;
+        let mut amount_1 = 0;
+        if liquidity_delta != 0 {
+            if self.pool.tick < tick_lower {
+                amount_0 = sqrt_price_math::get_amount_0_delta_signed(
+                    sqrt_ratio_lower_x32,
+                    sqrt_ratio_upper_x32,
+                    liquidity_delta,
+                );
+            } else if self.pool.tick < tick_upper {
+                self.write_observation(self.pool.tick, self.pool.liquidity);
+
+                amount_0 = sqrt_price_math::get_amount_0_delta_signed(
+                    self.pool.sqrt_price_x32,
+                    sqrt_ratio_upper_x32,
+                    liquidity_delta,
+                );
+                amount_1 = sqrt_price_math::get_amount_1_delta_signed(
+                    sqrt_ratio_lower_x32,
+                    self.pool.sqrt_price_x32,
+                    liquidity_delta,
+                );
+                self.pool.liquidity =
+                    liquidity_math::add_delta(self.pool.liquidity, liquidity_delta)?;
+            } else {
+                amount_1 = sqrt_price_math::get_amount_1_delta_signed(
+                    sqrt_ratio_lower_x32,
+                    sqrt_ratio_upper_x32,
+                    liquidity_delta,
+                );
+            }
+        }
+
+        Ok((amount_0, amount_1))
+    }
+
+    /// Updates a position and its ticks with the given liquidity delta, as in `_update_position`.
+    /// Nothing is written if the update fails
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The owner and tick boundaries of the position
+    /// * `liquidity_delta` - The change in liquidity
+    ///
+    fn update_position(&mut self, key: PositionKey, liquidity_delta: i64) -> Result<()> {
+        let (_, tick_lower, tick_upper) = key;
+        let pool = self.pool;
+        let load_tick = |tick: i32| {
+            self.ticks.get(&tick).copied().unwrap_or(TickState {
+                tick,
+                ..Default::default()
+            })
+        };
+        let mut tick_lower_state = load_tick(tick_lower);
+        let mut tick_upper_state = load_tick(tick_upper);
+        let mut position = self.positions.get(&key).copied().unwrap_or_default();
+
+        let mut flipped_lower = false;
+        let mut flipped_upper = false;
+        if liquidity_delta != 0 {
+            let (tick_cumulative, seconds_per_liquidity_cumulative_x32) = self.observations
+                [pool.observation_index as usize]
+                .observe_latest(self.time, pool.tick, pool.liquidity);
+
+            let globals = GlobalGrowths {
+                fee_growth_global_0_x32: pool.fee_growth_global_0_x32,
+                fee_growth_global_1_x32: pool.fee_growth_global_1_x32,
+                seconds_per_liquidity_cumulative_x32,
+                tick_cumulative,
+                time: self.time,
+                reward_growths_global_x32: pool.reward_growths_global_x32(),
+            };
+
+            flipped_lower = tick_lower_state.update(
+                pool.tick,
+                liquidity_delta,
+                &globals,
+                false,
+                self.max_liquidity_per_tick,
+            )?;
+            flipped_upper = tick_upper_state.update(
+                pool.tick,
+                liquidity_delta,
+                &globals,
+                true,
+                self.max_liquidity_per_tick,
+            )?;
+        }
+
+        let (fee_growth_inside_0_x32, fee_growth_inside_1_x32) = tick::get_fee_growth_inside(
+            &tick_lower_state,
+            &tick_upper_state,
+            pool.tick,
+            pool.fee_growth_global_0_x32,
+            pool.fee_growth_global_1_x32,
+        );
+        let reward_growths_inside_x32 = tick::get_reward_growths_inside(
+            &tick_lower_state,
+            &tick_upper_state,
+            pool.tick,
+            pool.reward_growths_global_x32(),
+        );
+        position.state.update(
+            liquidity_delta,
+            fee_growth_inside_0_x32,
+            fee_growth_inside_1_x32,
+            reward_growths_inside_x32,
+        )?;
+
+        self.positions.insert(key, position);
+        for (tick, tick_state, flipped) in [
+            (tick_lower, tick_lower_state, flipped_lower),
+            (tick_upper, tick_upper_state, flipped_upper),
+        ] {
+            if flipped {
+                self.flip_tick(tick);
+            }
+            // ticks are un-initialized on flip if liquidity_delta is negative
+            if flipped && liquidity_delta < 0 {
+                self.ticks.remove(&tick);
+            } else {
+                self.ticks.insert(tick, tick_state);
+            }
+        }
+        Ok(())
+    }
+
+    /// Flips the initialized state of a tick in its bitmap
+    ///
+    /// # Arguments
+    ///
+    /// * `tick` - The tick to flip
+    ///
+    fn flip_tick(&mut self, tick: i32) {
+        let Position { word_pos, bit_pos } =
+            tick_bitmap::position(tick / self.pool.tick_spacing as i32);
+        self.bitmaps
+            .entry(word_pos)
+            .or_insert(TickBitmapState {
+                word_pos,
+                ..Default::default()
+            })
+            .flip_bit(bit_pos);
+    }
+
+    /// Writes an oracle observation, at most once per 14 second partition like the program.
+    /// Writes within the partition of the latest observation overwrite it
+    ///
+    /// # Arguments
+    ///
+    /// * `tick` - The active tick before the change
+    /// * `liquidity` - The in-range liquidity before the change
+    ///
+    fn write_observation(&mut self, tick: i32, liquidity: u64) {
+        let index = self.pool.observation_index;
+        let last = self.observations[index as usize];
+        if last.block_timestamp == self.time {
+            return;
+        }
+
+        if self.time / 14 > last.block_timestamp / 14 {
+            let cardinality = if self.pool.observation_cardinality_next
+                > self.pool.observation_cardinality
+                && index == self.pool.observation_cardinality - 1
+            {
+                self.pool.observation_cardinality_next
+            } else {
+                self.pool.observation_cardinality
+            };
+            let index_next = (index + 1) % cardinality;
+            self.observations[index_next as usize] = ObservationState {
+                index: index_next,
+                ..last.transform(self.time, tick, liquidity)
+            };
+            self.pool.observation_index = index_next;
+            self.pool.observation_cardinality = cardinality;
+        } else {
+            self.observations[index as usize] = last.transform(self.time, tick, liquidity);
+        }
+    }
+}
+
+/// Crosses the ticks held by a simulator, keeping their prior state to revert failed swaps
+struct SimulatorTickProvider<'a> {
+    ticks: &'a mut BTreeMap<i32, TickState>,
+    bitmaps: &'a HashMap<i16, TickBitmapState>,
+    crossed: Vec<(i32, TickState)>,
+}
+
+impl<'a> TickProvider for SimulatorTickProvider<'a> {
+    fn bitmap(&mut self, word_pos: i16) -> Result<Option<TickBitmapState>> {
+        Ok(self.bitmaps.get(&word_pos).copied())
+    }
+
+    fn cross(
+        &mut self,
+        tick: i32,
+        fee_growth_global_0_x32: u64,
+        fee_growth_global_1_x32: u64,
+        cache: &SwapCache,
+    ) -> Result<i64> {
+        let tick_state = self.ticks.get_mut(&tick).ok_or(ErrorCode::TNI)?;
+        self.crossed.push((tick, *tick_state));
+        Ok(tick_state.cross(
+            fee_growth_global_0_x32,
+            fee_growth_global_1_x32,
+            cache.seconds_per_liquidity_cumulative_x32,
+            cache.tick_cumulative,
+            cache.block_timestamp,
+            cache.reward_growths_global_x32,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const LP: Pubkey = Pubkey::new_from_array([1; 32]);
+    const OTHER_LP: Pubkey = Pubkey::new_from_array([2; 32]);
+    const LIQUIDITY: u64 = 1 << 32;
+
+    /// A 0.05% pool at price 1 with tick spacing 10
+    fn simulator() -> Simulator {
+        Simulator::new(500, 10, 1 << 32, 1_000).unwrap()
+    }
+
+    /// Returns the name of the program error
+    fn error_name(error: Error) -> String {
+        match error {
+            Error::AnchorError(error) => error.error_name,
+            Error::ProgramError(error) => error.to_string(),
+        }
+    }
+
+    #[test]
+    fn mints_both_tokens_in_range_and_one_token_out_of_range() {
+        let mut simulator = simulator();
+
+        let (amount_0, amount_1) = simulator.mint(LP, -100, 100, LIQUIDITY).unwrap();
+        assert!(amount_0 > 0 && amount_1 > 0);
+        assert_eq!({ simulator.pool.liquidity }, LIQUIDITY);
+
+        let (amount_0, amount_1) = simulator.mint(LP, 100, 200, LIQUIDITY).unwrap();
+        assert!(amount_0 > 0);
+        assert_eq!(amount_1, 0);
+
+        let (amount_0, amount_1) = simulator.mint(LP, -200, -100, LIQUIDITY).unwrap();
+        assert_eq!(amount_0, 0);
+        assert!(amount_1 > 0);
+        assert_eq!({ simulator.pool.liquidity }, LIQUIDITY);
+        assert_eq!(simulator.ticks.len(), 4);
+    }
+
+    #[test]
+    fn fails_for_invalid_ticks() {
+        let mut simulator = simulator();
+        assert_eq!(
+            error_name(simulator.mint(LP, 100, -100, LIQUIDITY).unwrap_err()),
+            "TLU"
+        );
+        assert_eq!(
+            error_name(simulator.mint(LP, -105, 100, LIQUIDITY).unwrap_err()),
+            "TMS"
+        );
+        assert!(simulator.positions.is_empty());
+    }
+
+    #[test]
+    fn swaps_update_the_pool_and_balances() {
+        let mut simulator = simulator();
+        simulator.mint(LP, -100, 100, LIQUIDITY).unwrap();
+        let (balance_0, balance_1) = (simulator.balance_0, simulator.balance_1);
+
+        let (amount_0, amount_1) = simulator.swap(true, 1_000, 0).unwrap();
+        assert_eq!(amount_0, 1_000);
+        assert!(amount_1 < 0);
+        assert_eq!(simulator.balance_0, balance_0 + 1_000);
+        assert_eq!(simulator.balance_1 as i64, balance_1 as i64 + amount_1);
+        assert!(simulator.pool.sqrt_price_x32 < 1 << 32);
+        assert!(simulator.pool.fee_growth_global_0_x32 > 0);
+
+        // exact output
+        let (amount_0, amount_1) = simulator.swap(false, -1_000, 0).unwrap();
+        assert_eq!(amount_0, -1_000);
+        assert!(amount_1 > 1_000);
+    }
+
+    #[test]
+    fn crosses_ticks_to_activate_liquidity() {
+        let mut simulator = simulator();
+        simulator.mint(LP, 100, 200, LIQUIDITY).unwrap();
+        assert_eq!({ simulator.pool.liquidity }, 0);
+
+        let limit = tick_math::get_sqrt_ratio_at_tick(150).unwrap();
+        simulator.swap(false, i64::MAX, limit).unwrap();
+        assert_eq!({ simulator.pool.tick }, 150);
+        assert_eq!({ simulator.pool.liquidity }, LIQUIDITY);
+
+        simulator.swap(false, i64::MAX, 0).unwrap();
+        assert!(simulator.pool.tick >= 200);
+        assert_eq!({ simulator.pool.liquidity }, 0);
+    }
+
+    #[test]
+    fn reverts_crossed_ticks_if_a_swap_fails() {
+        let mut simulator = simulator();
+        simulator.mint(LP, -100, 100, LIQUIDITY).unwrap();
+        simulator.mint(OTHER_LP, -200, -100, LIQUIDITY).unwrap();
+        let tick_before = simulator.ticks[&-100];
+
+        // tick -200 is flagged in the bitmap but missing
+        simulator.ticks.remove(&-200);
+        assert_eq!(
+            error_name(simulator.swap(true, i64::MAX, 0).unwrap_err()),
+            "TNI"
+        );
+        assert_eq!({ simulator.pool.tick }, 0);
+        assert_eq!({ simulator.ticks[&-100].fee_growth_outside_0_x32 }, {
+            tick_before.fee_growth_outside_0_x32
+        });
+    }
+
+    #[test]
+    fn credits_fees_to_in_range_positions() {
+        let mut simulator = simulator();
+        simulator.mint(LP, -100, 100, LIQUIDITY).unwrap();
+        simulator.mint(OTHER_LP, 100, 200, LIQUIDITY).unwrap();
+
+        let amount_in = 1_000_000;
+        simulator.swap(true, amount_in, 0).unwrap();
+        let fee = amount_in as u64 * 500 / 1_000_000;
+
+        let reports = simulator.report().unwrap();
+        let in_range = reports.iter().find(|report| report.owner == LP).unwrap();
+        let out_of_range = reports
+            .iter()
+            .find(|report| report.owner == OTHER_LP)
+            .unwrap();
+        // fee growth is rounded down
+        assert!(in_range.fees_0 <= fee && in_range.fees_0 >= fee - 1);
+        assert_eq!(in_range.fees_1, 0);
+        assert_eq!(out_of_range.fees_0, 0);
+        assert_eq!(out_of_range.fees_1, 0);
+    }
+
+    #[test]
+    fn takes_the_protocol_fee_out_of_lp_fees() {
+        let mut simulator = simulator();
+        simulator.pool.fee_protocol_0 = 150_000;
+        simulator.mint(LP, -100, 100, LIQUIDITY).unwrap();
+        simulator.swap(true, 1_000_000, 0).unwrap();
+
+        // 15% of the swap fee, rounded down
+        assert_eq!({ simulator.pool.protocol_fees_token_0 }, 75);
+        let report = &simulator.report().unwrap()[0];
+        assert!(report.fees_0 <= 500 - 75);
+    }
+
+    #[test]
+    fn burns_and_collects_principal_and_fees() {
+        let mut simulator = simulator();
+        simulator.mint(LP, -100, 100, LIQUIDITY).unwrap();
+        simulator.swap(true, 1_000_000, 0).unwrap();
+        simulator.swap(false, 1_000_000, 0).unwrap();
+
+        let (withdrawn_0, withdrawn_1) = simulator.burn(LP, -100, 100, LIQUIDITY).unwrap();
+        assert_eq!({ simulator.pool.liquidity }, 0);
+        // the position was the only one on its ticks
+        assert!(simulator.ticks.is_empty());
+        assert!(simulator
+            .bitmaps
+            .values()
+            .all(|bitmap| { bitmap.word } == [0; 4]));
+
+        let (collected_0, collected_1) = simulator
+            .collect(LP, -100, 100, u64::MAX, u64::MAX)
+            .unwrap();
+        assert!(collected_0 > withdrawn_0 && collected_1 > withdrawn_1);
+        // only rounding dust is left in the pool
+        assert!(simulator.balance_0 <= 2 && simulator.balance_1 <= 2);
+
+        let report = &simulator.report().unwrap()[0];
+        assert_eq!(report.liquidity, 0);
+        assert_eq!(report.fees_0, collected_0 - withdrawn_0);
+        assert_eq!(report.fees_1, collected_1 - withdrawn_1);
+    }
+
+    #[test]
+    fn reports_divergence_loss_when_the_price_moves() {
+        let mut simulator = simulator();
+        simulator.mint(LP, -1000, 1000, LIQUIDITY).unwrap();
+        let limit = tick_math::get_sqrt_ratio_at_tick(-500).unwrap();
+        simulator.swap(true, i64::MAX, limit).unwrap();
+
+        let report = &simulator.report().unwrap()[0];
+        assert!(report.divergence_loss > 0);
+        assert!(report.value < report.hold_value);
+        assert_eq!(report.pnl, report.value as i128 - report.cost as i128);
+    }
+
+    #[test]
+    fn reports_fees_as_pnl_when_the_price_returns() {
+        let mut simulator = simulator();
+        simulator.mint(LP, -1000, 1000, LIQUIDITY).unwrap();
+        simulator.swap(true, 1_000_000, 0).unwrap();
+        simulator
+            .swap(false, i64::MAX, simulator.pool.sqrt_price_x32 + 1)
+            .unwrap();
+        simulator.swap(false, i64::MAX, 1 << 32).unwrap();
+
+        let report = &simulator.report().unwrap()[0];
+        let fees_value = value_in_token_1(report.fees_0 as u128, report.fees_1 as u128, 1 << 32);
+        assert!(fees_value > 0);
+        // principal rounds down against the position
+        assert!(report.divergence_loss >= 0 && report.divergence_loss <= 2);
+        assert!((report.pnl - fees_value as i128).abs() <= 2);
+    }
+
+    #[test]
+    fn observes_tick_cumulatives() {
+        let mut simulator = simulator();
+        simulator.increase_observation_cardinality_next(4);
+        simulator.mint(LP, -1000, 1000, LIQUIDITY).unwrap();
+
+        let limit = tick_math::get_sqrt_ratio_at_tick(-100).unwrap();
+        simulator.set_time(1_014);
+        simulator.swap(true, i64::MAX, limit).unwrap();
+        assert_eq!({ simulator.pool.observation_index }, 1);
+        assert_eq!({ simulator.pool.observation_cardinality }, 4);
+
+        simulator.set_time(1_114);
+        let (tick_cumulatives, _) = simulator.observe(&[100, 0]).unwrap();
+        assert_eq!(tick_cumulatives[1] - tick_cumulatives[0], -100 * 100);
+    }
+
+    #[test]
+    fn replays_events() {
+        let events = [
+            Event {
+                time: 1_000,
+                action: Action::Mint {
+                    owner: LP,
+                    tick_lower: -100,
+                    tick_upper: 100,
+                    liquidity: LIQUIDITY,
+                },
+            },
+            Event {
+                time: 1_020,
+                action: Action::Swap {
+                    zero_for_one: true,
+                    amount_specified: 1_000_000,
+                    sqrt_price_limit_x32: 0,
+                },
+            },
+            Event {
+                time: 1_040,
+                action: Action::Burn {
+                    owner: LP,
+                    tick_lower: -100,
+                    tick_upper: 100,
+                    liquidity: LIQUIDITY / 2,
+                },
+            },
+            Event {
+                time: 1_060,
+                action: Action::Collect {
+                    owner: LP,
+                    tick_lower: -100,
+                    tick_upper: 100,
+                    amount_0_requested: u64::MAX,
+                    amount_1_requested: u64::MAX,
+                },
+            },
+        ];
+        let mut replayed = simulator();
+        replayed.replay(&events).unwrap();
+
+        let mut direct = simulator();
+        direct.mint(LP, -100, 100, LIQUIDITY).unwrap();
+        direct.set_time(1_020);
+        direct.swap(true, 1_000_000, 0).unwrap();
+        direct.set_time(1_040);
+        direct.burn(LP, -100, 100, LIQUIDITY / 2).unwrap();
+        direct.set_time(1_060);
+        direct.collect(LP, -100, 100, u64::MAX, u64::MAX).unwrap();
+
+        assert_eq!(replayed.time, 1_060);
+        assert_eq!(replayed.balance_0, direct.balance_0);
+        assert_eq!(replayed.balance_1, direct.balance_1);
+        assert_eq!(replayed.report().unwrap(), direct.report().unwrap());
+    }
+}
@@ -0,0 +1,155 @@
+//! End of run valuation of simulated positions
+//!
+use crate::{PositionKey, SimulatedPosition};
+use anchor_lang::prelude::*;
+use cyclos_core::libraries::big_num::U128;
+use cyclos_core::libraries::full_math::MulDiv;
+use cyclos_core::libraries::{liquidity_amounts, tick_math};
+use cyclos_core::states::pool::PoolState;
+use cyclos_core::states::tick::{self, TickState};
+use std::collections::BTreeMap;
+
+/// The outcome of a position over a run, valued in token_1 at the final pool price
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PositionReport {
+    /// The owner of the position
+    pub owner: Pubkey,
+
+    /// The lower tick of the position
+    pub tick_lower: i32,
+
+    /// The upper tick of the position
+    pub tick_upper: i32,
+
+    /// The liquidity left in the position
+    pub liquidity: u64,
+
+    /// The token_0 principal of the remaining liquidity, at the final price
+    pub amount_0: u64,
+
+    /// The token_1 principal of the remaining liquidity, at the final price
+    pub amount_1: u64,
+
+    /// All swap fees earned in token_0, collected or not
+    pub fees_0: u64,
+
+    /// All swap fees earned in token_1, collected or not
+    pub fees_1: u64,
+
+    /// Value of the principal, fees, and tokens withdrawn by burns
+    pub value: u128,
+
+    /// Value of the deposited tokens, had they been held instead
+    pub hold_value: u128,
+
+    /// Value of the deposited tokens at the price of each mint
+    pub cost: u128,
+
+    /// `value` less `cost`
+    pub pnl: i128,
+
+    /// `hold_value` less `value` without fees. Positive if providing liquidity did worse
+    /// than holding, before fees
+    pub divergence_loss: i128,
+}
+
+/// Values a token amount pair in token_1
+///
+/// # Arguments
+///
+/// * `amount_0` - Amount of token_0
+/// * `amount_1` - Amount of token_1
+/// * `sqrt_price_x32` - The sqrt(price) used to convert token_0, as a Q32.32
+///
+pub fn value_in_token_1(amount_0: u128, amount_1: u128, sqrt_price_x32: u64) -> u128 {
+    // sqrt_price_x32 is below 2^49, so the square fits in 128 bits
+    let price_x64 = U128::from(sqrt_price_x32) * U128::from(sqrt_price_x32);
+    let value_0 = price_x64
+        .mul_div_floor(U128::from(amount_0), U128::from(1) << 64)
+        .unwrap();
+    value_0.as_u128() + amount_1
+}
+
+/// Values a position at the current state of the pool
+///
+/// # Arguments
+///
+/// * `pool` - The pool at the end of the run
+/// * `ticks` - Initialized ticks of the pool
+/// * `key` - The owner and tick boundaries of the position
+/// * `position` - The position to value
+///
+pub(crate) fn report_position(
+    pool: &PoolState,
+    ticks: &BTreeMap<i32, TickState>,
+    key: &PositionKey,
+    position: &SimulatedPosition,
+) -> Result<PositionReport> {
+    let (owner, tick_lower, tick_upper) = *key;
+    let sqrt_ratio_lower_x32 = tick_math::get_sqrt_ratio_at_tick(tick_lower)?;
+    let sqrt_ratio_upper_x32 = tick_math::get_sqrt_ratio_at_tick(tick_upper)?;
+
+    // poke a copy of the position to credit the fees earned since its last update
+    let mut state = position.state;
+    if state.liquidity > 0 {
+        let (fee_growth_inside_0_x32, fee_growth_inside_1_x32) = tick::get_fee_growth_inside(
+            &ticks[&tick_lower],
+            &ticks[&tick_upper],
+            pool.tick,
+            pool.fee_growth_global_0_x32,
+            pool.fee_growth_global_1_x32,
+        );
+        let reward_growths_inside_x32 = tick::get_reward_growths_inside(
+            &ticks[&tick_lower],
+            &ticks[&tick_upper],
+            pool.tick,
+            pool.reward_growths_global_x32(),
+        );
+        state.update(
+            0,
+            fee_growth_inside_0_x32,
+            fee_growth_inside_1_x32,
+            reward_growths_inside_x32,
+        )?;
+    }
+
+    let (amount_0, amount_1) = liquidity_amounts::get_amounts_for_liquidity(
+        pool.sqrt_price_x32,
+        sqrt_ratio_lower_x32,
+        sqrt_ratio_upper_x32,
+        state.liquidity,
+    );
+
+    // tokens owed hold the principal of burns besides fees
+    let fees_0 = state.tokens_owed_0 + position.collected_0 - position.withdrawn_0;
+    let fees_1 = state.tokens_owed_1 + position.collected_1 - position.withdrawn_1;
+
+    let principal_value = value_in_token_1(
+        amount_0 as u128 + position.withdrawn_0 as u128,
+        amount_1 as u128 + position.withdrawn_1 as u128,
+        pool.sqrt_price_x32,
+    );
+    let fees_value = value_in_token_1(fees_0 as u128, fees_1 as u128, pool.sqrt_price_x32);
+    let value = principal_value + fees_value;
+    let hold_value = value_in_token_1(
+        position.deposited_0 as u128,
+        position.deposited_1 as u128,
+        pool.sqrt_price_x32,
+    );
+
+    Ok(PositionReport {
+        owner,
+        tick_lower,
+        tick_upper,
+        liquidity: state.liquidity,
+        amount_0,
+        amount_1,
+        fees_0,
+        fees_1,
+        value,
+        hold_value,
+        cost: position.cost,
+        pnl: value as i128 - position.cost as i128,
+        divergence_loss: hold_value as i128 - principal_value as i128,
+    })
+}
@@ -21,14 +21,35 @@ chai.use(chaiAsPromised)
 
 import { CyclosCore } from '../target/types/cyclos_core'
 import {
+  CLAIM_STATUS_SEED,
+  DEPOSIT_SEED,
+  DISTRIBUTOR_SEED,
+  INCENTIVE_SEED,
   MaxU64,
   MAX_SQRT_RATIO,
   MAX_TICK,
   MIN_SQRT_RATIO,
   MIN_TICK,
+  PAUSE_ALL,
+  PAUSE_MINT,
+  PAUSE_SWAP,
+  REWARD_VAULT_SEED,
+  STAKE_SEED,
+  TIMELOCK_SEED,
 } from './utils'
 import SolanaTickDataProvider from './SolanaTickDataProvider'
 import { Transaction } from '@solana/web3.js'
+import { GokiSDK, SmartWalletWrapper } from '@gokiprotocol/client'
+import { SolanaProvider, TransactionEnvelope } from '@saberhq/solana-contrib'
+import {
+  findGovernorAddress,
+  findLockerAddress,
+  GovernorWrapper,
+  LockerWrapper,
+  TribecaSDK,
+  VoteSide,
+} from '@tribecahq/tribeca-sdk'
+import { createHash } from 'crypto'
 import JSBI from 'jsbi'
 
 console.log('starting test')
@@ -61,6 +82,39 @@ describe('cyclos-core', async () => {
   );
   console.log("Fee", feeState.toString(), feeStateBump)
 
+  // Admin actions are queued at a PDA of the hash of the action and its arguments
+  async function findTimelockAddress(action: any) {
+    const actionHash = createHash('sha256')
+      .update(coreProgram.coder.types.encode('AdminAction', action))
+      .digest()
+    const [timelockState] = await PublicKey.findProgramAddress(
+      [TIMELOCK_SEED, actionHash],
+      coreProgram.programId
+    )
+    return timelockState
+  }
+
+  function sleep(seconds: number) {
+    return new Promise(r => setTimeout(r, seconds * 1000))
+  }
+
+  // Queues an admin action, and by default waits out the timelock delay
+  async function queueAdminAction(action: any, admin?: web3.Keypair, wait = true) {
+    await coreProgram.rpc.queueAdminAction(action, {
+      accounts: {
+        admin: admin ? admin.publicKey : owner,
+        payer: owner,
+        factoryState,
+        timelockState: await findTimelockAddress(action),
+        systemProgram: SystemProgram.programId,
+      }, signers: admin ? [admin] : []
+    })
+    if (wait) {
+      const { timelockDelay } = await coreProgram.account.factoryState.fetch(factoryState)
+      await sleep(timelockDelay + 1)
+    }
+  }
+
   const mintAuthority = new Keypair()
 
   // Tokens constituting the pool
@@ -146,6 +200,9 @@ describe('cyclos-core', async () => {
   let feeRecipientWallet0: web3.PublicKey
   let feeRecipientWallet1: web3.PublicKey
 
+  let rewardVaultA: web3.PublicKey
+  let rewardRecipientWallet: web3.PublicKey
+
   const initialPriceX32 = new BN(4297115210)
   const initialTick = 10
 
@@ -347,7 +404,8 @@ describe('cyclos-core', async () => {
       const factoryStateData = await coreProgram.account.factoryState.fetch(factoryState)
       assert.equal(factoryStateData.bump, factoryStateBump)
       assert(factoryStateData.owner.equals(owner))
-      assert.equal(factoryStateData.feeProtocol, 3)
+      assert.equal(factoryStateData.defaultFeeProtocol, 333_333)
+      assert.isAbove(factoryStateData.timelockDelay, 0)
     });
 
     it('Trying to re-initialize factory fails', async () => {
@@ -361,46 +419,78 @@ describe('cyclos-core', async () => {
     });
   })
 
-  describe('#set_owner', () => {
+  describe('#propose_owner', () => {
     const newOwner = new Keypair()
+    const action = { proposeOwner: { newOwner: newOwner.publicKey } }
+
+    it('cannot be queued by addresses that are not owner', async () => {
+      await expect(queueAdminAction(action, notOwner)).to.be.rejectedWith(Error)
+    })
 
-    it('fails if owner does not sign', async () => {
-      const tx = coreProgram.transaction.setOwner({
+    it('fails if not queued', async () => {
+      await expect(coreProgram.rpc.proposeOwner({
         accounts: {
-          owner,
           newOwner: newOwner.publicKey,
           factoryState,
+          timelockState: await findTimelockAddress(action),
+          timelockPayer: owner,
         }
+      })).to.be.rejectedWith(Error)
+    })
+
+    it('proposes the new owner and emits an event', async function () {
+      await queueAdminAction(action)
+
+      let listener: number
+      let [_event, _slot] = await new Promise(async (resolve, _reject) => {
+        listener = coreProgram.addEventListener("OwnerProposed", (event, slot) => {
+          assert((event.owner as web3.PublicKey).equals(owner))
+          assert((event.pendingOwner as web3.PublicKey).equals(newOwner.publicKey))
+
+          resolve([event, slot]);
+        });
+
+        coreProgram.rpc.proposeOwner({
+          accounts: {
+            newOwner: newOwner.publicKey,
+            factoryState,
+            timelockState: await findTimelockAddress(action),
+            timelockPayer: owner,
+          }
+        });
       });
-      tx.recentBlockhash = (await connection.getRecentBlockhash()).blockhash
+      await coreProgram.removeEventListener(listener);
 
-      await expect(connection.sendTransaction(tx, [])).to.be.rejectedWith(Error)
+      const factoryStateData = await coreProgram.account.factoryState.fetch(factoryState)
+      assert(factoryStateData.owner.equals(owner))
+      assert(factoryStateData.pendingOwner.equals(newOwner.publicKey))
+      assert.isNull(await connection.getAccountInfo(await findTimelockAddress(action)))
     })
+  })
 
-    it('fails if caller is not owner', async () => {
-      const tx = coreProgram.transaction.setOwner({
+  describe('#accept_owner', () => {
+    const newOwner = new Keypair()
+
+    it('fails if not signed by the proposed owner', async () => {
+      const action = { proposeOwner: { newOwner: newOwner.publicKey } }
+      await queueAdminAction(action)
+      await coreProgram.rpc.proposeOwner({
         accounts: {
-          owner,
           newOwner: newOwner.publicKey,
           factoryState,
+          timelockState: await findTimelockAddress(action),
+          timelockPayer: owner,
         }
-      });
-      tx.recentBlockhash = (await connection.getRecentBlockhash()).blockhash
-
-      await expect(connection.sendTransaction(tx, [notOwner])).to.be.rejectedWith(Error)
-    })
+      })
 
-    it('fails if correct signer but incorrect owner field', async () => {
-      await expect(coreProgram.rpc.setOwner({
+      await expect(coreProgram.rpc.acceptOwner({
         accounts: {
-          owner: notOwner.publicKey,
-          newOwner: newOwner.publicKey,
+          pendingOwner: notOwner.publicKey,
           factoryState,
-        }
+        }, signers: [notOwner]
       })).to.be.rejectedWith(Error)
     })
 
-    // Test for event and updated owner value
     it('updates owner and emits an event', async function () {
       let listener: number
       let [_event, _slot] = await new Promise((resolve, _reject) => {
@@ -411,99 +501,160 @@ describe('cyclos-core', async () => {
           resolve([event, slot]);
         });
 
-        coreProgram.rpc.setOwner({
+        coreProgram.rpc.acceptOwner({
           accounts: {
-            owner,
-            newOwner: newOwner.publicKey,
+            pendingOwner: newOwner.publicKey,
             factoryState,
-          }
+          }, signers: [newOwner]
         });
       });
       await coreProgram.removeEventListener(listener);
 
       const factoryStateData = await coreProgram.account.factoryState.fetch(factoryState)
       assert(factoryStateData.owner.equals(newOwner.publicKey))
+      assert(factoryStateData.pendingOwner.equals(new PublicKey(0)))
     })
 
-    it('reverts to original owner when signed by the new owner', async () => {
-      await coreProgram.rpc.setOwner({
+    it('reverts to original owner when proposed by the new owner', async () => {
+      const action = { proposeOwner: { newOwner: owner } }
+      await queueAdminAction(action, newOwner)
+      await coreProgram.rpc.proposeOwner({
         accounts: {
-          owner: newOwner.publicKey,
           newOwner: owner,
           factoryState,
-        }, signers: [newOwner]
+          timelockState: await findTimelockAddress(action),
+          timelockPayer: newOwner.publicKey,
+        }
+      });
+      await coreProgram.rpc.acceptOwner({
+        accounts: {
+          pendingOwner: owner,
+          factoryState,
+        }
       });
       const factoryStateData = await coreProgram.account.factoryState.fetch(factoryState)
       assert(factoryStateData.owner.equals(owner))
     })
   })
 
-  describe('#enable_fee_amount', () => {
-    it('fails if PDA seeds do not match', async () => {
-      await expect(coreProgram.rpc.enableFeeAmount(fee + 1, tickSpacing, {
+  describe('#set_role', () => {
+    async function setRole(role: any, newHolder: web3.PublicKey) {
+      await coreProgram.rpc.setRole(role, {
         accounts: {
-          owner,
+          newHolder,
           factoryState,
-          feeState,
-          systemProgram: SystemProgram.programId,
+          timelockState: await findTimelockAddress({ setRole: { role, newHolder } }),
+          timelockPayer: owner,
         }
-      })).to.be.rejectedWith(Error)
+      })
+    }
+
+    it('gives every role to the owner on initialization', async () => {
+      const factoryStateData = await coreProgram.account.factoryState.fetch(factoryState)
+      assert(factoryStateData.feeTierAdmin.equals(owner))
+      assert(factoryStateData.protocolFeeAdmin.equals(owner))
+      assert(factoryStateData.feeCollector.equals(owner))
+      assert(factoryStateData.pauseGuardian.equals(owner))
     })
 
-    it('fails if caller is not owner', async () => {
-      const tx = coreProgram.transaction.enableFeeAmount(fee, tickSpacing, {
+    it('cannot be queued by addresses that are not owner', async () => {
+      await expect(queueAdminAction(
+        { setRole: { role: { feeCollector: {} }, newHolder: notOwner.publicKey } },
+        notOwner
+      )).to.be.rejectedWith(Error)
+    })
+
+    it('fails if not queued', async () => {
+      await expect(setRole({ feeCollector: {} }, notOwner.publicKey)).to.be.rejectedWith(Error)
+    })
+
+    it('hands a role over and emits an event', async () => {
+      await queueAdminAction({ setRole: { role: { feeCollector: {} }, newHolder: notOwner.publicKey } })
+
+      let listener: number
+      let [_event, _slot] = await new Promise((resolve, _reject) => {
+        listener = coreProgram.addEventListener("RoleChanged", (event, slot) => {
+          assert.deepEqual(event.role, { feeCollector: {} })
+          assert((event.oldHolder as web3.PublicKey).equals(owner))
+          assert((event.newHolder as web3.PublicKey).equals(notOwner.publicKey))
+
+          resolve([event, slot]);
+        });
+
+        setRole({ feeCollector: {} }, notOwner.publicKey)
+      });
+      await coreProgram.removeEventListener(listener);
+
+      const factoryStateData = await coreProgram.account.factoryState.fetch(factoryState)
+      assert(factoryStateData.feeCollector.equals(notOwner.publicKey))
+      assert(factoryStateData.owner.equals(owner))
+    })
+
+    it('does not let a role holder hand over the protocol', async () => {
+      await expect(queueAdminAction(
+        { proposeOwner: { newOwner: notOwner.publicKey } },
+        notOwner
+      )).to.be.rejectedWith(Error)
+    })
+
+    it('hands the role back to the owner', async () => {
+      await queueAdminAction({ setRole: { role: { feeCollector: {} }, newHolder: owner } })
+      await setRole({ feeCollector: {} }, owner)
+      const factoryStateData = await coreProgram.account.factoryState.fetch(factoryState)
+      assert(factoryStateData.feeCollector.equals(owner))
+    })
+  })
+
+  describe('#enable_fee_amount', () => {
+    async function enableFeeAmount(fee: number, tickSpacing: number, feeState: web3.PublicKey) {
+      await coreProgram.rpc.enableFeeAmount(fee, tickSpacing, {
         accounts: {
-          owner: notOwner.publicKey,
-          factoryState,
+          payer: owner,
+          timelockState: await findTimelockAddress({ enableFeeAmount: { fee, tickSpacing } }),
+          timelockPayer: owner,
           feeState,
           systemProgram: SystemProgram.programId,
-        }, signers: [notOwner]
+        }
       })
-      tx.recentBlockhash = (await connection.getRecentBlockhash()).blockhash
+    }
 
-      await expect(connection.sendTransaction(tx, [notOwner])).to.be.rejectedWith(Error)
+    it('fails if PDA seeds do not match', async () => {
+      await queueAdminAction({ enableFeeAmount: { fee: fee + 1, tickSpacing } })
+      await expect(enableFeeAmount(fee + 1, tickSpacing, feeState)).to.be.rejectedWith(Error)
     })
 
-    it('fails if fee is too great', async () => {
-      const highFee = 1_000_000
-      const [highFeeState, highFeeStateBump] = await PublicKey.findProgramAddress(
-        [FEE_SEED, u32ToSeed(highFee)],
-        coreProgram.programId
-      );
+    it('cannot be queued by addresses that are not the fee tier admin', async () => {
+      await expect(queueAdminAction(
+        { enableFeeAmount: { fee, tickSpacing } },
+        notOwner
+      )).to.be.rejectedWith(Error)
+    })
 
-      await expect(coreProgram.rpc.enableFeeAmount(highFee, tickSpacing, {
-        accounts: {
-          owner,
-          factoryState,
-          feeState: highFeeState,
-          systemProgram: SystemProgram.programId,
-        }
-      })).to.be.rejectedWith(Error)
+    it('fails if not queued', async () => {
+      await expect(enableFeeAmount(fee, tickSpacing, feeState)).to.be.rejectedWith(Error)
     })
 
-    it('fails if tick spacing is too small', async () => {
-      await expect(coreProgram.rpc.enableFeeAmount(fee, 0, {
-        accounts: {
-          owner,
-          factoryState,
-          feeState: feeState,
-          systemProgram: SystemProgram.programId,
-        }
-      })).to.be.rejectedWith(Error)
+    it('cannot be queued if fee is too great', async () => {
+      await expect(queueAdminAction(
+        { enableFeeAmount: { fee: 1_000_000, tickSpacing } }
+      )).to.be.rejectedWith(Error)
     })
 
-    it('fails if tick spacing is too large', async () => {
-      await expect(coreProgram.rpc.enableFeeAmount(fee, 16384, {
-        accounts: {
-          owner,
-          factoryState,
-          feeState: feeState,
-          systemProgram: SystemProgram.programId,
-        }
-      })).to.be.rejectedWith(Error)
+    it('cannot be queued if tick spacing is too small', async () => {
+      await expect(queueAdminAction(
+        { enableFeeAmount: { fee, tickSpacing: 0 } }
+      )).to.be.rejectedWith(Error)
+    })
+
+    it('cannot be queued if tick spacing is too large', async () => {
+      await expect(queueAdminAction(
+        { enableFeeAmount: { fee, tickSpacing: 16384 } }
+      )).to.be.rejectedWith(Error)
     })
 
     it('sets the fee amount and emits an event', async () => {
+      await queueAdminAction({ enableFeeAmount: { fee, tickSpacing } })
+
       let listener: number
       let [_event, _slot] = await new Promise((resolve, _reject) => {
         listener = coreProgram.addEventListener("FeeAmountEnabled", (event, slot) => {
@@ -513,14 +664,7 @@ describe('cyclos-core', async () => {
           resolve([event, slot]);
         });
 
-        coreProgram.rpc.enableFeeAmount(fee, tickSpacing, {
-          accounts: {
-            owner,
-            factoryState,
-            feeState,
-            systemProgram: SystemProgram.programId,
-          }
-        })
+        enableFeeAmount(fee, tickSpacing, feeState)
       });
       await coreProgram.removeEventListener(listener);
 
@@ -532,25 +676,13 @@ describe('cyclos-core', async () => {
     })
 
     it('fails if already initialized', async () => {
-      await expect(coreProgram.rpc.enableFeeAmount(feeStateBump, fee, tickSpacing, {
-        accounts: {
-          owner,
-          factoryState,
-          feeState,
-          systemProgram: SystemProgram.programId,
-        }
-      })).to.be.rejectedWith(Error)
+      await queueAdminAction({ enableFeeAmount: { fee, tickSpacing } })
+      await expect(enableFeeAmount(fee, tickSpacing, feeState)).to.be.rejectedWith(Error)
     })
 
     it('cannot change spacing of a fee tier', async () => {
-      await expect(coreProgram.rpc.enableFeeAmount(feeStateBump, fee, tickSpacing + 1, {
-        accounts: {
-          owner,
-          factoryState,
-          feeState,
-          systemProgram: SystemProgram.programId,
-        }
-      })).to.be.rejectedWith(Error)
+      await queueAdminAction({ enableFeeAmount: { fee, tickSpacing: tickSpacing + 1 } })
+      await expect(enableFeeAmount(fee, tickSpacing + 1, feeState)).to.be.rejectedWith(Error)
     })
   })
 
@@ -586,6 +718,7 @@ describe('cyclos-core', async () => {
           token0: token1.publicKey,
           token1: token0.publicKey,
           feeState,
+          factoryState,
           poolState: poolAState,
           initialObservationState: initialObservationStateA,
           systemProgram: SystemProgram.programId,
@@ -602,6 +735,7 @@ describe('cyclos-core', async () => {
           token0: token0.publicKey,
           token1: token0.publicKey,
           feeState,
+          factoryState,
           poolState: poolAState,
           initialObservationState: initialObservationStateA,
           systemProgram: SystemProgram.programId,
@@ -622,6 +756,7 @@ describe('cyclos-core', async () => {
           token0: token0.publicKey,
           token1: token0.publicKey,
           feeState: uninitializedFeeState,
+          factoryState,
           poolState: poolAState,
           initialObservationState: initialObservationStateA,
           systemProgram: SystemProgram.programId,
@@ -637,6 +772,7 @@ describe('cyclos-core', async () => {
           token0: token0.publicKey,
           token1: token1.publicKey,
           feeState,
+          factoryState,
           poolState: poolAState,
           initialObservationState: initialObservationStateA,
           systemProgram: SystemProgram.programId,
@@ -651,6 +787,7 @@ describe('cyclos-core', async () => {
           token0: token0.publicKey,
           token1: token1.publicKey,
           feeState,
+          factoryState,
           poolState: poolAState,
           initialObservationState: initialObservationStateA,
           systemProgram: SystemProgram.programId,
@@ -667,6 +804,7 @@ describe('cyclos-core', async () => {
           token0: token0.publicKey,
           token1: token1.publicKey,
           feeState,
+          factoryState,
           poolState: poolAState,
           initialObservationState: initialObservationStateA,
           systemProgram: SystemProgram.programId,
@@ -681,6 +819,7 @@ describe('cyclos-core', async () => {
           token0: token0.publicKey,
           token1: token1.publicKey,
           feeState,
+          factoryState,
           poolState: poolAState,
           initialObservationState: initialObservationStateA,
           systemProgram: SystemProgram.programId,
@@ -710,6 +849,7 @@ describe('cyclos-core', async () => {
             token0: token0.publicKey,
             token1: token1.publicKey,
             feeState,
+            factoryState,
             poolState: poolAState,
             initialObservationState: initialObservationStateA,
             systemProgram: SystemProgram.programId,
@@ -757,6 +897,7 @@ describe('cyclos-core', async () => {
           token0: token0.publicKey,
           token1: token1.publicKey,
           feeState,
+          factoryState,
           poolState: poolAState,
           initialObservationState: initialObservationStateA,
           systemProgram: SystemProgram.programId,
@@ -1021,103 +1162,333 @@ describe('cyclos-core', async () => {
 
   })
 
-  describe('#set_fee_protocol', () => {
-    it('cannot be changed by addresses that are not owner', async () => {
-      await expect(coreProgram.rpc.setFeeProtocol(6, {
+  describe('#observe', () => {
+    it('fails if observation accounts are not passed', async () => {
+      await expect(coreProgram.rpc.observe([0], {
         accounts: {
-          owner: notOwner.publicKey,
-          factoryState,
-        }, signers: [notOwner]
+          poolState: poolAState,
+        }
       })).to.be.rejectedWith(Error)
     })
 
-    it('cannot be changed out of bounds', async () => {
-      await expect(coreProgram.rpc.setFeeProtocol(1, {
+    it('fails if the observation account belongs to another pool', async () => {
+      await expect(coreProgram.rpc.observe([0], {
         accounts: {
-          owner,
-          factoryState,
-        }
+          poolState: poolAState,
+        }, remainingAccounts: [{
+          pubkey: initialObservationStateB,
+          isSigner: false,
+          isWritable: false
+        }]
       })).to.be.rejectedWith(Error)
+    })
 
-      await expect(coreProgram.rpc.setFeeProtocol(11, {
+    it('fails if the target is older than the oldest observation', async () => {
+      await expect(coreProgram.rpc.observe([1_000_000], {
         accounts: {
-          owner,
-          factoryState,
-        }
+          poolState: poolAState,
+        }, remainingAccounts: [{
+          pubkey: initialObservationStateA,
+          isSigner: false,
+          isWritable: false
+        }]
       })).to.be.rejectedWith(Error)
     })
 
-    it('can be changed by owner', async () => {
-      // let listener: number
-      // let [_event, _slot] = await new Promise((resolve, _reject) => {
-      //   listener = coreProgram.addEventListener("SetFeeProtocolEvent", (event, slot) => {
-      //     assert.equal(event.feeProtocolOld, 3)
-      //     assert.equal(event.feeProtocol, 6)
-
-      //     resolve([event, slot]);
-      //   });
-
-      //   coreProgram.rpc.setFeeProtocol(6, {
-      //     accounts: {
-      //       owner,
-      //       factoryState,
-      //     }
-      //   })
-      // })
-      // await coreProgram.removeEventListener(listener)
+    it('returns cumulatives through return data', async () => {
+      const { raw } = await coreProgram.simulate.observe([0], {
+        accounts: {
+          poolState: poolAState,
+        }, remainingAccounts: [{
+          pubkey: initialObservationStateA,
+          isSigner: false,
+          isWritable: false
+        }]
+      })
+      const prefix = `Program return: ${coreProgram.programId.toString()} `
+      const returnLog = raw.find((log) => log.startsWith(prefix))
+      const returnData = Buffer.from(returnLog.slice(prefix.length), 'base64')
+
+      // borsh (Vec<i64>, Vec<u64>) with a single element each
+      assert.equal(returnData.readUInt32LE(0), 1)
+      const tickCumulative = new BN(returnData.slice(4, 12), 'le').fromTwos(64)
+      assert.equal(returnData.readUInt32LE(12), 1)
+      const secondsPerLiquidityCumulativeX32 = new BN(returnData.slice(16, 24), 'le')
+
+      // no liquidity has been added, so the pool accrues at its initial tick
+      const { blockTimestamp } = await coreProgram.account.observationState.fetch(initialObservationStateA)
+      assert(tickCumulative.gten(0))
+      assert(secondsPerLiquidityCumulativeX32.gten(0))
+      assert(blockTimestamp > 0)
+    })
+  })
 
-      await coreProgram.rpc.setFeeProtocol(6, {
+  describe('#set_fee_protocol', () => {
+    async function setFeeProtocol(feeProtocol: number) {
+      await coreProgram.rpc.setFeeProtocol(feeProtocol, {
         accounts: {
-          owner,
           factoryState,
+          timelockState: await findTimelockAddress({ setFeeProtocol: { feeProtocol } }),
+          timelockPayer: owner,
         }
       })
+    }
+
+    it('cannot be queued by addresses that are not the protocol fee admin', async () => {
+      await expect(queueAdminAction(
+        { setFeeProtocol: { feeProtocol: 150_000 } },
+        notOwner
+      )).to.be.rejectedWith(Error)
+    })
+
+    it('cannot be queued out of bounds', async () => {
+      await expect(queueAdminAction({ setFeeProtocol: { feeProtocol: 500_001 } })).to.be.rejectedWith(Error)
+    })
+
+    it('can be changed once queued', async () => {
+      await queueAdminAction({ setFeeProtocol: { feeProtocol: 150_000 } })
+      await setFeeProtocol(150_000)
 
       const factoryStateData = await coreProgram.account.factoryState.fetch(factoryState)
-      assert.equal(factoryStateData.feeProtocol, 6)
+      assert.equal(factoryStateData.defaultFeeProtocol, 150_000)
     })
   })
 
-  describe('#collect_protocol', () => {
-    it('creates token accounts for recipient', async () => {
-      feeRecipientWallet0 = await token0.createAssociatedTokenAccount(protocolFeeRecipient.publicKey)
-      feeRecipientWallet1 = await token1.createAssociatedTokenAccount(protocolFeeRecipient.publicKey)
+  describe('#set_pool_fee_protocol', () => {
+    it('is set to the factory protocol fee on pool creation', async () => {
+      const poolStateData = await coreProgram.account.poolState.fetch(poolAState)
+      // 1/3 of the swap fees, in hundredths of a bip
+      assert.equal(poolStateData.feeProtocol0, 333_333)
+      assert.equal(poolStateData.feeProtocol1, 333_333)
     })
 
-    it('fails if caller is not owner', async () => {
-      await expect(coreProgram.rpc.collectProtocol(MaxU64, MaxU64, {
+    async function setPoolFeeProtocol(feeProtocol0: number, feeProtocol1: number) {
+      await coreProgram.rpc.setPoolFeeProtocol(feeProtocol0, feeProtocol1, {
         accounts: {
-          owner: notOwner,
-          factoryState,
           poolState: poolAState,
-          vault0: vaultA0,
-          vault1: vaultA1,
-          recipientWallet0: feeRecipientWallet0,
-          recipientWallet1: feeRecipientWallet1,
-          tokenProgram: TOKEN_PROGRAM_ID,
+          timelockState: await findTimelockAddress({
+            setPoolFeeProtocol: { pool: poolAState, feeProtocol0, feeProtocol1 }
+          }),
+          timelockPayer: owner,
         }
-      })).to.be.rejectedWith(Error)
+      })
+    }
+
+    it('cannot be queued by addresses that are not the protocol fee admin', async () => {
+      await expect(queueAdminAction(
+        { setPoolFeeProtocol: { pool: poolAState, feeProtocol0: 150_000, feeProtocol1: 150_000 } },
+        notOwner
+      )).to.be.rejectedWith(Error)
     })
 
-    it('fails if vault 0 address is not valid', async () => {
-      await expect(coreProgram.rpc.collectProtocol(MaxU64, MaxU64, {
+    it('fails if not queued', async () => {
+      await expect(setPoolFeeProtocol(150_000, 150_000)).to.be.rejectedWith(Error)
+    })
+
+    it('cannot be queued out of bounds', async () => {
+      await expect(queueAdminAction(
+        { setPoolFeeProtocol: { pool: poolAState, feeProtocol0: 500_001, feeProtocol1: 150_000 } }
+      )).to.be.rejectedWith(Error)
+      await expect(queueAdminAction(
+        { setPoolFeeProtocol: { pool: poolAState, feeProtocol0: 150_000, feeProtocol1: 500_001 } }
+      )).to.be.rejectedWith(Error)
+    })
+
+    it('can be changed for each token once queued', async () => {
+      await queueAdminAction({ setPoolFeeProtocol: { pool: poolAState, feeProtocol0: 150_000, feeProtocol1: 0 } })
+      await setPoolFeeProtocol(150_000, 0)
+
+      let poolStateData = await coreProgram.account.poolState.fetch(poolAState)
+      assert.equal(poolStateData.feeProtocol0, 150_000)
+      assert.equal(poolStateData.feeProtocol1, 0)
+
+      await queueAdminAction({ setPoolFeeProtocol: { pool: poolAState, feeProtocol0: 150_000, feeProtocol1: 150_000 } })
+      await setPoolFeeProtocol(150_000, 150_000)
+      poolStateData = await coreProgram.account.poolState.fetch(poolAState)
+      assert.equal(poolStateData.feeProtocol1, 150_000)
+    })
+  })
+
+  describe('#set_fee_discounts', () => {
+    const locker = new web3.Keypair().publicKey
+    const tiers = [
+      { minAmount: new BN(1_000), minDuration: 0, discount: 100_000 },
+      { minAmount: new BN(10_000), minDuration: 86_400, discount: 250_000 },
+      { minAmount: new BN(0), minDuration: 0, discount: 0 },
+      { minAmount: new BN(0), minDuration: 0, discount: 0 },
+    ]
+
+    async function setFeeDiscounts(locker: web3.PublicKey) {
+      await coreProgram.rpc.setFeeDiscounts(locker, tiers, {
         accounts: {
-          owner: notOwner,
           factoryState,
-          poolState: poolAState,
-          vault0: new Keypair().publicKey,
-          vault1: vaultA1,
-          recipientWallet0: feeRecipientWallet0,
-          recipientWallet1: feeRecipientWallet1,
-          tokenProgram: TOKEN_PROGRAM_ID,
+          timelockState: await findTimelockAddress({ setFeeDiscounts: { locker, feeDiscountTiers: tiers } }),
+          timelockPayer: owner,
+        }
+      })
+    }
+
+    it('cannot be queued by addresses that are not the protocol fee admin', async () => {
+      await expect(queueAdminAction(
+        { setFeeDiscounts: { locker, feeDiscountTiers: tiers } },
+        notOwner
+      )).to.be.rejectedWith(Error)
+    })
+
+    it('cannot discount more than half of the fee', async () => {
+      await expect(queueAdminAction({
+        setFeeDiscounts: {
+          locker,
+          feeDiscountTiers: [
+            ...tiers.slice(0, 3),
+            { minAmount: new BN(100_000), minDuration: 0, discount: 500_001 },
+          ]
         }
       })).to.be.rejectedWith(Error)
     })
 
-    it('fails if vault 1 address is not valid', async () => {
-      await expect(coreProgram.rpc.collectProtocol(MaxU64, MaxU64, {
-        accounts: {
-          owner: notOwner,
+    it('can be changed once queued', async () => {
+      await queueAdminAction({ setFeeDiscounts: { locker, feeDiscountTiers: tiers } })
+      await setFeeDiscounts(locker)
+      let factoryStateData = await coreProgram.account.factoryState.fetch(factoryState)
+      assert.isTrue(factoryStateData.locker.equals(locker))
+      assert.equal(factoryStateData.feeDiscountTiers[1].discount, 250_000)
+
+      // turn discounts off for the swaps below
+      await queueAdminAction({ setFeeDiscounts: { locker: web3.PublicKey.default, feeDiscountTiers: tiers } })
+      await setFeeDiscounts(web3.PublicKey.default)
+      factoryStateData = await coreProgram.account.factoryState.fetch(factoryState)
+      assert.isTrue(factoryStateData.locker.equals(web3.PublicKey.default))
+    })
+  })
+
+  describe('#set_pause_state', () => {
+    it('cannot be changed by addresses that are not the pause guardian', async () => {
+      await expect(coreProgram.rpc.setPauseState(PAUSE_ALL, {
+        accounts: {
+          pauseGuardian: notOwner.publicKey,
+          factoryState,
+        }, signers: [notOwner]
+      })).to.be.rejectedWith(Error)
+    })
+
+    it('cannot be set to unknown operations', async () => {
+      await expect(coreProgram.rpc.setPauseState(4, {
+        accounts: {
+          pauseGuardian: owner,
+          factoryState,
+        }
+      })).to.be.rejectedWith(Error)
+    })
+
+    it('pauses and unpauses every pool and emits an event', async () => {
+      let listener: number
+      let [_event, _slot] = await new Promise((resolve, _reject) => {
+        listener = coreProgram.addEventListener("PauseStateChanged", (event, slot) => {
+          assert((event.poolState as web3.PublicKey).equals(new PublicKey(0)))
+          assert.equal(event.pausedOld, 0)
+          assert.equal(event.paused, PAUSE_MINT)
+
+          resolve([event, slot]);
+        });
+
+        coreProgram.rpc.setPauseState(PAUSE_MINT, {
+          accounts: {
+            pauseGuardian: owner,
+            factoryState,
+          }
+        })
+      });
+      await coreProgram.removeEventListener(listener);
+
+      let factoryStateData = await coreProgram.account.factoryState.fetch(factoryState)
+      assert.equal(factoryStateData.paused, PAUSE_MINT)
+
+      await coreProgram.rpc.setPauseState(0, {
+        accounts: {
+          pauseGuardian: owner,
+          factoryState,
+        }
+      })
+      factoryStateData = await coreProgram.account.factoryState.fetch(factoryState)
+      assert.equal(factoryStateData.paused, 0)
+    })
+  })
+
+  describe('#set_pool_pause_state', () => {
+    it('cannot be changed by addresses that are not the pause guardian', async () => {
+      await expect(coreProgram.rpc.setPoolPauseState(PAUSE_SWAP, {
+        accounts: {
+          pauseGuardian: notOwner.publicKey,
+          factoryState,
+          poolState: poolAState,
+        }, signers: [notOwner]
+      })).to.be.rejectedWith(Error)
+    })
+
+    it('pauses and unpauses a pool', async () => {
+      await coreProgram.rpc.setPoolPauseState(PAUSE_SWAP, {
+        accounts: {
+          pauseGuardian: owner,
+          factoryState,
+          poolState: poolAState,
+        }
+      })
+      let poolStateData = await coreProgram.account.poolState.fetch(poolAState)
+      assert.equal(poolStateData.paused, PAUSE_SWAP)
+
+      await coreProgram.rpc.setPoolPauseState(0, {
+        accounts: {
+          pauseGuardian: owner,
+          factoryState,
+          poolState: poolAState,
+        }
+      })
+      poolStateData = await coreProgram.account.poolState.fetch(poolAState)
+      assert.equal(poolStateData.paused, 0)
+    })
+  })
+
+  describe('#collect_protocol', () => {
+    it('creates token accounts for recipient', async () => {
+      feeRecipientWallet0 = await token0.createAssociatedTokenAccount(protocolFeeRecipient.publicKey)
+      feeRecipientWallet1 = await token1.createAssociatedTokenAccount(protocolFeeRecipient.publicKey)
+    })
+
+    it('fails if caller is not owner', async () => {
+      await expect(coreProgram.rpc.collectProtocol(MaxU64, MaxU64, {
+        accounts: {
+          feeCollector: notOwner,
+          factoryState,
+          poolState: poolAState,
+          vault0: vaultA0,
+          vault1: vaultA1,
+          recipientWallet0: feeRecipientWallet0,
+          recipientWallet1: feeRecipientWallet1,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        }
+      })).to.be.rejectedWith(Error)
+    })
+
+    it('fails if vault 0 address is not valid', async () => {
+      await expect(coreProgram.rpc.collectProtocol(MaxU64, MaxU64, {
+        accounts: {
+          feeCollector: notOwner,
+          factoryState,
+          poolState: poolAState,
+          vault0: new Keypair().publicKey,
+          vault1: vaultA1,
+          recipientWallet0: feeRecipientWallet0,
+          recipientWallet1: feeRecipientWallet1,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        }
+      })).to.be.rejectedWith(Error)
+    })
+
+    it('fails if vault 1 address is not valid', async () => {
+      await expect(coreProgram.rpc.collectProtocol(MaxU64, MaxU64, {
+        accounts: {
+          feeCollector: notOwner,
           factoryState,
           poolState: poolAState,
           vault0: vaultA0,
@@ -1143,7 +1514,7 @@ describe('cyclos-core', async () => {
 
         coreProgram.rpc.collectProtocol(MaxU64, MaxU64, {
           accounts: {
-            owner,
+            feeCollector: owner,
             factoryState,
             poolState: poolAState,
             vault0: vaultA0,
@@ -1170,6 +1541,178 @@ describe('cyclos-core', async () => {
 
   })
 
+  describe('#initialize_reward', () => {
+    it('derive reward vault address', async () => {
+      [rewardVaultA] = await PublicKey.findProgramAddress(
+        [REWARD_VAULT_SEED, poolAState.toBuffer(), token2.publicKey.toBuffer()],
+        coreProgram.programId
+      )
+    })
+
+    it('fails if caller is not owner', async () => {
+      await expect(coreProgram.rpc.initializeReward(0, {
+        accounts: {
+          owner: notOwner.publicKey,
+          payer: owner,
+          factoryState,
+          poolState: poolAState,
+          rewardMint: token2.publicKey,
+          rewardVault: rewardVaultA,
+          rewardAuthority: owner,
+          systemProgram: SystemProgram.programId,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          rent: web3.SYSVAR_RENT_PUBKEY,
+        }, signers: [notOwner]
+      })).to.be.rejectedWith(Error)
+    })
+
+    it('fails if the reward index is out of range', async () => {
+      await expect(coreProgram.rpc.initializeReward(3, {
+        accounts: {
+          owner,
+          payer: owner,
+          factoryState,
+          poolState: poolAState,
+          rewardMint: token2.publicKey,
+          rewardVault: rewardVaultA,
+          rewardAuthority: owner,
+          systemProgram: SystemProgram.programId,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          rent: web3.SYSVAR_RENT_PUBKEY,
+        }
+      })).to.be.rejectedWith(Error)
+    })
+
+    it('fills a reward slot and emits an event', async () => {
+      let listener: number
+      let [_event, _slot] = await new Promise((resolve, _reject) => {
+        listener = coreProgram.addEventListener("RewardInitialized", (event, slot) => {
+          assert((event.poolState as web3.PublicKey).equals(poolAState))
+          assert.equal(event.rewardIndex, 0)
+          assert((event.mint as web3.PublicKey).equals(token2.publicKey))
+          assert((event.vault as web3.PublicKey).equals(rewardVaultA))
+          assert((event.authority as web3.PublicKey).equals(owner))
+
+          resolve([event, slot]);
+        });
+
+        coreProgram.rpc.initializeReward(0, {
+          accounts: {
+            owner,
+            payer: owner,
+            factoryState,
+            poolState: poolAState,
+            rewardMint: token2.publicKey,
+            rewardVault: rewardVaultA,
+            rewardAuthority: owner,
+            systemProgram: SystemProgram.programId,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            rent: web3.SYSVAR_RENT_PUBKEY,
+          }
+        })
+      });
+      await coreProgram.removeEventListener(listener);
+
+      const poolStateData = await coreProgram.account.poolState.fetch(poolAState)
+      const rewardInfo = poolStateData.rewardInfos[0]
+      assert((rewardInfo.mint as web3.PublicKey).equals(token2.publicKey))
+      assert((rewardInfo.vault as web3.PublicKey).equals(rewardVaultA))
+      assert((rewardInfo.authority as web3.PublicKey).equals(owner))
+      assert(rewardInfo.emissionsPerSecondX32.eqn(0))
+
+      const vaultInfo = await token2.getAccountInfo(rewardVaultA)
+      assert(vaultInfo.owner.equals(poolAState))
+    })
+
+    it('fails if the slot is already initialized', async () => {
+      const otherMint = await Token.createMint(
+        connection,
+        mintAuthority,
+        mintAuthority.publicKey,
+        null,
+        8,
+        TOKEN_PROGRAM_ID
+      )
+      const [otherVault] = await PublicKey.findProgramAddress(
+        [REWARD_VAULT_SEED, poolAState.toBuffer(), otherMint.publicKey.toBuffer()],
+        coreProgram.programId
+      )
+      await expect(coreProgram.rpc.initializeReward(0, {
+        accounts: {
+          owner,
+          payer: owner,
+          factoryState,
+          poolState: poolAState,
+          rewardMint: otherMint.publicKey,
+          rewardVault: otherVault,
+          rewardAuthority: owner,
+          systemProgram: SystemProgram.programId,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          rent: web3.SYSVAR_RENT_PUBKEY,
+        }
+      })).to.be.rejectedWith(Error)
+    })
+  })
+
+  describe('#set_reward_emissions', () => {
+    it('fails if caller is not the reward authority', async () => {
+      await expect(coreProgram.rpc.setRewardEmissions(0, new BN(1).shln(32), {
+        accounts: {
+          rewardAuthority: notOwner.publicKey,
+          poolState: poolAState,
+        }, signers: [notOwner]
+      })).to.be.rejectedWith(Error)
+    })
+
+    it('fails if the slot is not initialized', async () => {
+      await expect(coreProgram.rpc.setRewardEmissions(1, new BN(1).shln(32), {
+        accounts: {
+          rewardAuthority: owner,
+          poolState: poolAState,
+        }
+      })).to.be.rejectedWith(Error)
+    })
+
+    it('sets the emission rate and emits an event', async () => {
+      // fund the vault
+      const fundTx = new web3.Transaction()
+      fundTx.recentBlockhash = (await connection.getRecentBlockhash()).blockhash
+      fundTx.add(Token.createTransferInstruction(
+        TOKEN_PROGRAM_ID,
+        minterWallet2,
+        rewardVaultA,
+        owner,
+        [],
+        1_000_000
+      ))
+      await anchor.getProvider().send(fundTx)
+
+      const emissionsPerSecondX32 = new BN(1).shln(32)
+      let listener: number
+      let [_event, _slot] = await new Promise((resolve, _reject) => {
+        listener = coreProgram.addEventListener("SetRewardEmissionsEvent", (event, slot) => {
+          assert((event.poolState as web3.PublicKey).equals(poolAState))
+          assert.equal(event.rewardIndex, 0)
+          assert((event.emissionsPerSecondX32Old as BN).eqn(0))
+          assert((event.emissionsPerSecondX32 as BN).eq(emissionsPerSecondX32))
+
+          resolve([event, slot]);
+        });
+
+        coreProgram.rpc.setRewardEmissions(0, emissionsPerSecondX32, {
+          accounts: {
+            rewardAuthority: owner,
+            poolState: poolAState,
+          }
+        })
+      });
+      await coreProgram.removeEventListener(listener);
+
+      const poolStateData = await coreProgram.account.poolState.fetch(poolAState)
+      assert(poolStateData.rewardInfos[0].emissionsPerSecondX32.eq(emissionsPerSecondX32))
+    })
+  })
+
   it('find program accounts addresses for position creation', async () => {
     [tickLowerAState, tickLowerAStateBump] = await PublicKey.findProgramAddress([
       TICK_SEED,
@@ -1556,13 +2099,18 @@ describe('cyclos-core', async () => {
       // connection.slot
       const deadline = new BN(Date.now() / 1000 - 10_000)
 
-      await expect(coreProgram.rpc.mintTokenizedPosition(amount0Desired,
+      await expect(coreProgram.rpc.mintTokenizedPosition({
+        tickLower,
+        tickUpper,
+        amount0Desired,
         amount1Desired,
-        amount0Minimum,
-        amount1Minimum,
-        deadline, {
+        amount0Min: amount0Minimum,
+        amount1Min: amount1Minimum,
+        deadline,
+      }, {
         accounts: {
           minter: owner,
+          payer: owner,
           recipient: owner,
           factoryState,
           nftMint: nftMintAKeypair.publicKey,
@@ -1600,13 +2148,18 @@ describe('cyclos-core', async () => {
 
       console.log('word upper', wordPosUpper)
       console.log('word upper bytes', u16ToSeed(wordPosUpper))
-      await coreProgram.rpc.mintTokenizedPosition(amount0Desired,
+      await coreProgram.rpc.mintTokenizedPosition({
+        tickLower,
+        tickUpper,
+        amount0Desired,
         amount1Desired,
-        amount0Minimum,
-        amount1Minimum,
-        deadline, {
+        amount0Min: amount0Minimum,
+        amount1Min: amount1Minimum,
+        deadline,
+      }, {
         accounts: {
           minter: owner,
+          payer: owner,
           recipient: owner,
           factoryState,
           nftMint: nftMintAKeypair.publicKey,
@@ -1835,6 +2388,7 @@ describe('cyclos-core', async () => {
           tokenizedPositionState: tokenizedPositionAState,
           coreProgram: coreProgram.programId,
           tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
         },
         remainingAccounts: [{
           pubkey: nextObservationAState,
@@ -1913,6 +2467,7 @@ describe('cyclos-core', async () => {
           tokenizedPositionState: tokenizedPositionAState,
           coreProgram: coreProgram.programId,
           tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
         },
         remainingAccounts: [{
           pubkey: nextObservationAState,
@@ -2642,33 +3197,199 @@ describe('cyclos-core', async () => {
     })
   })
 
-  describe('#exact_input_single', () => {
-    // before swapping, current tick = 10 and price = 4297115210
-    // active ticks are 0 and 10
-    // entire liquidity is in token_1
-
-    const deadline = new BN(Date.now() / 1000 + 1_000_000)
+  describe('#collect_reward', () => {
+    it('creates a token account for the recipient', async () => {
+      rewardRecipientWallet = await token2.createAssociatedTokenAccount(protocolFeeRecipient.publicKey)
+    })
 
-    it('fails if limit price is greater than current pool price', async () => {
-      const amountIn = new BN(100_000)
-      const amountOutMinimum = new BN(0)
-      const sqrtPriceLimitX32 = new BN(4297115220)
+    it('fails if the signer does not own the core position', async () => {
+      await expect(coreProgram.rpc.collectReward(0, MaxU64, {
+        accounts: {
+          owner,
+          poolState: poolAState,
+          tickLowerState: tickLowerAState,
+          tickUpperState: tickUpperAState,
+          positionState: corePositionAState,
+          rewardVault: rewardVaultA,
+          recipientWallet: rewardRecipientWallet,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        }
+      })).to.be.rejectedWith(Error)
+    })
+  })
 
-      await expect(coreProgram.rpc.exactInputSingle(
-        deadline,
-        // true,
-        amountIn,
-        amountOutMinimum,
-        sqrtPriceLimitX32,
-        {
-          accounts: {
-            signer: owner,
-            factoryState,
-            poolState: poolAState,
-            inputTokenAccount: minterWallet0,
-            outputTokenAccount: minterWallet1,
-            inputVault: vaultA0,
-            outputVault: vaultA1,
+  describe('#collect_reward_from_tokenized', () => {
+    it('fails if signer is not the owner or a delegated authority', async () => {
+      const tx = coreProgram.transaction.collectRewardFromTokenized(0, MaxU64, {
+        accounts: {
+          ownerOrDelegate: notOwner.publicKey,
+          nftAccount: positionANftAccount,
+          tokenizedPositionState: tokenizedPositionAState,
+          factoryState,
+          poolState: poolAState,
+          corePositionState: corePositionAState,
+          tickLowerState: tickLowerAState,
+          tickUpperState: tickUpperAState,
+          bitmapLowerState: bitmapLowerAState,
+          bitmapUpperState: bitmapUpperAState,
+          lastObservationState: lastObservationAState,
+          rewardVault: rewardVaultA,
+          recipientWallet: rewardRecipientWallet,
+          coreProgram: coreProgram.programId,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        },
+        remainingAccounts: [{
+          pubkey: nextObservationAState,
+          isSigner: false,
+          isWritable: true
+        }],
+      })
+      await expect(connection.sendTransaction(tx, [notOwner])).to.be.rejectedWith(Error)
+    })
+
+    it('fails if the reward vault does not belong to the slot', async () => {
+      await expect(coreProgram.rpc.collectRewardFromTokenized(0, MaxU64, {
+        accounts: {
+          ownerOrDelegate: owner,
+          nftAccount: positionANftAccount,
+          tokenizedPositionState: tokenizedPositionAState,
+          factoryState,
+          poolState: poolAState,
+          corePositionState: corePositionAState,
+          tickLowerState: tickLowerAState,
+          tickUpperState: tickUpperAState,
+          bitmapLowerState: bitmapLowerAState,
+          bitmapUpperState: bitmapUpperAState,
+          lastObservationState: lastObservationAState,
+          rewardVault: vaultA0,
+          recipientWallet: feeRecipientWallet0,
+          coreProgram: coreProgram.programId,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        },
+        remainingAccounts: [{
+          pubkey: nextObservationAState,
+          isSigner: false,
+          isWritable: true
+        }],
+      })).to.be.rejectedWith(Error)
+    })
+
+    it('collects the rewards owed to the position and emits an event', async () => {
+      let listener: number
+      let [event, _slot]: [any, number] = await new Promise((resolve, _reject) => {
+        listener = coreProgram.addEventListener("CollectTokenizedRewardEvent", (event, slot) => {
+          assert((event.tokenId as web3.PublicKey).equals(nftMintAKeypair.publicKey))
+          assert.equal(event.rewardIndex, 0)
+          assert((event.recipientWallet as web3.PublicKey).equals(rewardRecipientWallet))
+
+          resolve([event, slot]);
+        });
+
+        coreProgram.rpc.collectRewardFromTokenized(0, MaxU64, {
+          accounts: {
+            ownerOrDelegate: owner,
+            nftAccount: positionANftAccount,
+            tokenizedPositionState: tokenizedPositionAState,
+            factoryState,
+            poolState: poolAState,
+            corePositionState: corePositionAState,
+            tickLowerState: tickLowerAState,
+            tickUpperState: tickUpperAState,
+            bitmapLowerState: bitmapLowerAState,
+            bitmapUpperState: bitmapUpperAState,
+            lastObservationState: lastObservationAState,
+            rewardVault: rewardVaultA,
+            recipientWallet: rewardRecipientWallet,
+            coreProgram: coreProgram.programId,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          },
+          remainingAccounts: [{
+            pubkey: nextObservationAState,
+            isSigner: false,
+            isWritable: true
+          }],
+        })
+      })
+      await coreProgram.removeEventListener(listener)
+
+      const tokenizedPositionData = await coreProgram.account.tokenizedPositionState.fetch(tokenizedPositionAState)
+      assert(tokenizedPositionData.rewardInfos[0].rewardAmountOwed.eqn(0))
+
+      const recipientWalletInfo = await token2.getAccountInfo(rewardRecipientWallet)
+      assert(recipientWalletInfo.amount.eq(event.amount))
+      const vaultInfo = await token2.getAccountInfo(rewardVaultA)
+      assert(vaultInfo.amount.eq(new BN(1_000_000).sub(event.amount)))
+    })
+  })
+
+  describe('#snapshot_cumulatives_inside', () => {
+    it('fails if tick lower is not less than tick upper', async () => {
+      await expect(coreProgram.rpc.snapshotCumulativesInside({
+        accounts: {
+          poolState: poolAState,
+          tickLowerState: tickUpperAState,
+          tickUpperState: tickLowerAState,
+          latestObservationState: lastObservationAState,
+        }
+      })).to.be.rejectedWith(Error)
+    })
+
+    it('fails if the tick accounts belong to another pool', async () => {
+      await expect(coreProgram.rpc.snapshotCumulativesInside({
+        accounts: {
+          poolState: poolAState,
+          tickLowerState: tickLowerBState,
+          tickUpperState: tickUpperBState,
+          latestObservationState: lastObservationAState,
+        }
+      })).to.be.rejectedWith(Error)
+    })
+
+    it('returns the snapshot through return data', async () => {
+      const { raw } = await coreProgram.simulate.snapshotCumulativesInside({
+        accounts: {
+          poolState: poolAState,
+          tickLowerState: tickLowerAState,
+          tickUpperState: tickUpperAState,
+          latestObservationState: lastObservationAState,
+        }
+      })
+      const prefix = `Program return: ${coreProgram.programId.toString()} `
+      const returnLog = raw.find((log) => log.startsWith(prefix))
+      const returnData = Buffer.from(returnLog.slice(prefix.length), 'base64')
+
+      // borsh SnapshotCumulative { i64, u64, u32 }
+      assert.equal(returnData.length, 20)
+    })
+  })
+
+  describe('#exact_input_single', () => {
+    // before swapping, current tick = 10 and price = 4297115210
+    // active ticks are 0 and 10
+    // entire liquidity is in token_1
+
+    const deadline = new BN(Date.now() / 1000 + 1_000_000)
+
+    it('fails if limit price is greater than current pool price', async () => {
+      const amountIn = new BN(100_000)
+      const amountOutMinimum = new BN(0)
+      const sqrtPriceLimitX32 = new BN(4297115220)
+
+      await expect(coreProgram.rpc.exactInputSingle(
+        deadline,
+        // true,
+        amountIn,
+        amountOutMinimum,
+        sqrtPriceLimitX32,
+        {
+          accounts: {
+            signer: owner,
+            factoryState,
+            poolState: poolAState,
+            inputTokenAccount: minterWallet0,
+            outputTokenAccount: minterWallet1,
+            inputVault: vaultA0,
+            outputVault: vaultA1,
             lastObservationState: lastObservationAState,
             // nextObservationState: nextObservationAState,
             coreProgram: coreProgram.programId,
@@ -2840,6 +3561,90 @@ describe('cyclos-core', async () => {
 
       uniPoolA = expectedNewPool
     })
+
+    it('skips bitmap accounts listed beyond the range crossed', async () => {
+      const {
+        tick,
+        observationIndex,
+        observationCardinalityNext
+      } = await coreProgram.account.poolState.fetch(poolAState)
+
+      lastObservationAState = (await PublicKey.findProgramAddress(
+        [
+          OBSERVATION_SEED,
+          token0.publicKey.toBuffer(),
+          token1.publicKey.toBuffer(),
+          u32ToSeed(fee),
+          u16ToSeed(observationIndex)
+        ],
+        coreProgram.programId
+      ))[0]
+
+      nextObservationAState = (await PublicKey.findProgramAddress(
+        [
+          OBSERVATION_SEED,
+          token0.publicKey.toBuffer(),
+          token1.publicKey.toBuffer(),
+          u32ToSeed(fee),
+          u16ToSeed((observationIndex + 1) % observationCardinalityNext)
+        ],
+        coreProgram.programId
+      ))[0]
+
+      // a bitmap further in the swap direction, as listed by a resolver with a price margin
+      const marginBitmapState = (await PublicKey.findProgramAddress([
+        BITMAP_SEED,
+        token0.publicKey.toBuffer(),
+        token1.publicKey.toBuffer(),
+        u32ToSeed(fee),
+        u16ToSeed((Math.floor(tick / tickSpacing) >> 8) - 2),
+      ],
+        coreProgram.programId
+      ))[0]
+
+      const amountIn = new BN(1_000)
+      const [expectedAmountOut, expectedNewPool, bitmapAndTickAccounts] = await uniPoolA.getOutputAmount(
+        CurrencyAmount.fromRawAmount(uniToken0, amountIn.toNumber())
+      )
+
+      await coreProgram.rpc.exactInputSingle(
+        deadline,
+        amountIn,
+        new BN(0),
+        new BN(0),
+        {
+          accounts: {
+            signer: owner,
+            factoryState,
+            poolState: poolAState,
+            inputTokenAccount: minterWallet0,
+            outputTokenAccount: minterWallet1,
+            inputVault: vaultA0,
+            outputVault: vaultA1,
+            lastObservationState: lastObservationAState,
+            coreProgram: coreProgram.programId,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          },
+          remainingAccounts: [
+            ...bitmapAndTickAccounts,
+            {
+              pubkey: marginBitmapState,
+              isSigner: false,
+              isWritable: false
+            },
+            {
+              pubkey: nextObservationAState,
+              isSigner: false,
+              isWritable: true
+            },
+          ],
+        }
+      )
+      const poolStateDataAfter = await coreProgram.account.poolState.fetch(poolAState)
+      assert.equal(poolStateDataAfter.sqrtPriceX32.toString(), expectedNewPool.sqrtRatioX32.toString())
+
+      uniPoolA = expectedNewPool
+    })
   })
 
   describe('#exact_input', () => {
@@ -2939,6 +3744,7 @@ describe('cyclos-core', async () => {
           token0: token1.publicKey,
           token1: token2.publicKey,
           feeState,
+          factoryState,
           poolState: poolBState,
           initialObservationState: initialObservationStateB,
           systemProgram: SystemProgram.programId,
@@ -2974,58 +3780,19 @@ describe('cyclos-core', async () => {
         coreProgram.programId
       ))[0]
 
-      // create tick and bitmap accounts
-      // can't combine with createTokenizedPosition due to size limit
-
-      const tx = new web3.Transaction()
-      tx.recentBlockhash = (await connection.getRecentBlockhash()).blockhash
-      tx.instructions = [
-        coreProgram.instruction.initTickAccount(tickLower, {
-          accounts: {
-            signer: owner,
-            poolState: poolBState,
-            tickState: tickLowerBState,
-            systemProgram: SystemProgram.programId,
-          }
-        }),
-        coreProgram.instruction.initTickAccount(tickUpper, {
-          accounts: {
-            signer: owner,
-            poolState: poolBState,
-            tickState: tickUpperBState,
-            systemProgram: SystemProgram.programId,
-          }
-        }),
-        coreProgram.instruction.initBitmapAccount(wordPosLower, {
-          accounts: {
-            signer: owner,
-            poolState: poolBState,
-            bitmapState: bitmapLowerBState,
-            systemProgram: SystemProgram.programId,
-          }
-        }),
-        coreProgram.instruction.initPositionAccount({
-          accounts: {
-            signer: owner,
-            recipient: factoryState,
-            poolState: poolBState,
-            tickLowerState: tickLowerBState,
-            tickUpperState: tickUpperBState,
-            positionState: corePositionBState,
-            systemProgram: SystemProgram.programId,
-          }
-        })
-      ]
-      await anchor.getProvider().send(tx)
-
       console.log('creating tokenized position')
-      await coreProgram.rpc.mintTokenizedPosition(amount0Desired,
+      await coreProgram.rpc.mintTokenizedPosition({
+        tickLower,
+        tickUpper,
+        amount0Desired,
         amount1Desired,
-        new BN(0),
-        new BN(0),
-        deadline, {
+        amount0Min: new BN(0),
+        amount1Min: new BN(0),
+        deadline,
+      }, {
         accounts: {
           minter: owner,
+          payer: owner,
           recipient: owner,
           factoryState,
           nftMint: nftMintBKeypair.publicKey,
@@ -3056,6 +3823,17 @@ describe('cyclos-core', async () => {
         }],
         signers: [nftMintBKeypair],
       })
+
+      // tick, bitmap and position accounts are created by the mint
+      const tickLowerData = await coreProgram.account.tickState.fetch(tickLowerBState)
+      assert.equal(tickLowerData.tick, tickLower)
+      assert.equal(tickLowerData.bump, tickLowerBStateBump)
+      const tickUpperData = await coreProgram.account.tickState.fetch(tickUpperBState)
+      assert.equal(tickUpperData.tick, tickUpper)
+      const bitmapData = await coreProgram.account.tickBitmapState.fetch(bitmapLowerBState)
+      assert.equal(bitmapData.wordPos, wordPosLower)
+      const corePositionData = await coreProgram.account.positionState.fetch(corePositionBState)
+      assert.equal(corePositionData.bump, corePositionBBump)
     })
 
     it('perform a two pool swap', async () => {
@@ -3238,96 +4016,1409 @@ describe('cyclos-core', async () => {
     })
   })
 
-  describe('Completely close position and deallocate ticks', () => {
-    it('update observation accounts', async () => {
-      const {
-        observationIndex,
-        observationCardinalityNext
-      } = await coreProgram.account.poolState.fetch(poolAState)
+  describe('#exact_output_single', () => {
+    const deadline = new BN(Date.now() / 1000 + 10_000)
 
-      const { blockTimestamp: lastBlockTime } = await coreProgram.account.observationState.fetch(lastObservationAState)
+    it('fails if past deadline', async () => {
+      const amountOut = new BN(1_000)
+      const amountInMaximum = MaxU64
+      const sqrtPriceLimitX32 = new BN(0)
 
-      const slot = await connection.getSlot()
-      const blockTimestamp = await connection.getBlockTime(slot)
+      await expect(coreProgram.rpc.exactOutputSingle(
+        new BN(Date.now() / 1000 - 100_000),
+        amountOut,
+        amountInMaximum,
+        sqrtPriceLimitX32,
+        {
+          accounts: {
+            signer: owner,
+            factoryState,
+            poolState: poolAState,
+            inputTokenAccount: minterWallet0,
+            outputTokenAccount: minterWallet1,
+            inputVault: vaultA0,
+            outputVault: vaultA1,
+            lastObservationState: lastObservationAState,
+            coreProgram: coreProgram.programId,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          }
+        }
+      )).to.be.rejectedWith(Error)
+    })
 
-      // If current observation account will expire in 3 seconds, we sleep for this time
-      // before recalculating the observation states
-      if (Math.floor(lastBlockTime / 14) == Math.floor(blockTimestamp / 14) && lastBlockTime % 14 >= 11) {
-        await new Promise(r => setTimeout(r, 3000))
-      }
-      if (Math.floor(lastBlockTime / 14) > Math.floor(blockTimestamp / 14)) {
-        lastObservationAState = (await PublicKey.findProgramAddress(
-          [
-            OBSERVATION_SEED,
-            token0.publicKey.toBuffer(),
-            token1.publicKey.toBuffer(),
-            u32ToSeed(fee),
-            u16ToSeed(observationIndex)
-          ],
-          coreProgram.programId
-        ))[0]
+    it('fails if required input exceeds amount_in_maximum', async () => {
+      const amountOut = new BN(1_000)
+      const amountInMaximum = new BN(0)
+      const sqrtPriceLimitX32 = new BN(0)
 
-        nextObservationAState = (await PublicKey.findProgramAddress(
-          [
-            OBSERVATION_SEED,
-            token0.publicKey.toBuffer(),
-            token1.publicKey.toBuffer(),
-            u32ToSeed(fee),
-            u16ToSeed((observationIndex + 1) % observationCardinalityNext)
-          ],
-          coreProgram.programId
-        ))[0]
-      }
+      await expect(coreProgram.rpc.exactOutputSingle(
+        deadline,
+        amountOut,
+        amountInMaximum,
+        sqrtPriceLimitX32,
+        {
+          accounts: {
+            signer: owner,
+            factoryState,
+            poolState: poolAState,
+            inputTokenAccount: minterWallet0,
+            outputTokenAccount: minterWallet1,
+            inputVault: vaultA0,
+            outputVault: vaultA1,
+            lastObservationState: lastObservationAState,
+            coreProgram: coreProgram.programId,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          }, remainingAccounts: [{
+            pubkey: bitmapLowerAState,
+            isSigner: false,
+            isWritable: true
+          }, {
+            pubkey: nextObservationAState,
+            isSigner: false,
+            isWritable: true
+          }]
+        }
+      )).to.be.rejectedWith(Error)
     })
 
-    it('burn entire of the position liquidity as owner', async () => {
-      const { liquidity } = await coreProgram.account.tokenizedPositionState.fetch(tokenizedPositionAState)
-      console.log('liquidity in position', liquidity)
-      const deadline = new BN(Date.now() / 1000 + 10_000)
+    it('receives exactly the output amount in a zero for one swap', async () => {
+      const amountOut = new BN(1_000)
+      const amountInMaximum = MaxU64
+      const sqrtPriceLimitX32 = new BN(0)
 
-      const tx = new Transaction()
-      tx.instructions = [
-        coreProgram.instruction.decreaseLiquidity(
-          liquidity,
-          new BN(0),
-          new BN(0),
-          deadline, {
+      const outputBalanceBefore = (await token1.getAccountInfo(minterWallet1)).amount
+      await coreProgram.rpc.exactOutputSingle(
+        deadline,
+        amountOut,
+        amountInMaximum,
+        sqrtPriceLimitX32,
+        {
           accounts: {
-            ownerOrDelegate: owner,
-            nftAccount: positionANftAccount,
-            tokenizedPositionState: tokenizedPositionAState,
+            signer: owner,
             factoryState,
             poolState: poolAState,
-            corePositionState: corePositionAState,
-            tickLowerState: tickLowerAState,
-            tickUpperState: tickUpperAState,
-            bitmapLowerState: bitmapLowerAState,
-            bitmapUpperState: bitmapUpperAState,
+            inputTokenAccount: minterWallet0,
+            outputTokenAccount: minterWallet1,
+            inputVault: vaultA0,
+            outputVault: vaultA1,
             lastObservationState: lastObservationAState,
-            coreProgram: coreProgram.programId
-          },
-          remainingAccounts: [{
+            coreProgram: coreProgram.programId,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          }, remainingAccounts: [{
+            pubkey: bitmapLowerAState,
+            isSigner: false,
+            isWritable: true
+          }, {
             pubkey: nextObservationAState,
             isSigner: false,
             isWritable: true
-          }],
+          }]
         }
-        ),
-        coreProgram.instruction.closeTickAccount({
+      )
+      const outputBalanceAfter = (await token1.getAccountInfo(minterWallet1)).amount
+      assert(outputBalanceAfter.sub(outputBalanceBefore).eq(amountOut))
+    })
+  })
+
+  describe('#quote_exact_input_single', () => {
+    it('fails if amount in is zero', async () => {
+      await expect(coreProgram.simulate.quoteExactInputSingle(
+        new BN(0),
+        new BN(0),
+        {
           accounts: {
-            recipient: owner,
-            tickState: tickLowerAState,
+            factoryState,
+            poolState: poolAState,
+            inputVault: vaultA0,
           }
-        }),
-        coreProgram.instruction.closeTickAccount({
+        }
+      )).to.be.rejectedWith(Error)
+    })
+
+    it('quotes without modifying the pool', async () => {
+      const poolBefore = await coreProgram.account.poolState.fetch(poolAState)
+      const { raw } = await coreProgram.simulate.quoteExactInputSingle(
+        new BN(1_000),
+        new BN(0),
+        {
           accounts: {
-            recipient: owner,
-            tickState: tickUpperAState,
-          }
-        })
-      ]
-      tx.recentBlockhash = (await connection.getRecentBlockhash()).blockhash
+            factoryState,
+            poolState: poolAState,
+            inputVault: vaultA0,
+          }, remainingAccounts: [{
+            pubkey: bitmapLowerAState,
+            isSigner: false,
+            isWritable: false
+          }]
+        }
+      )
+      const prefix = `Program return: ${coreProgram.programId.toString()} `
+      const returnLog = raw.find((log) => log.startsWith(prefix))
+      const returnData = Buffer.from(returnLog.slice(prefix.length), 'base64')
+
+      // borsh (amount_in: u64, amount_out: u64, sqrt_price_after: u64, ticks_crossed: u32)
+      assert.equal(returnData.length, 28)
+      assert(new BN(returnData.slice(0, 8), 'le').eqn(1_000))
+      assert(new BN(returnData.slice(8, 16), 'le').gtn(0))
+
+      const poolAfter = await coreProgram.account.poolState.fetch(poolAState)
+      assert(poolAfter.sqrtPriceX32.eq(poolBefore.sqrtPriceX32))
+    })
+  })
+
+  describe('#flash', () => {
+    const amount0 = new BN(1_000)
+    const amount1 = new BN(1_000)
+
+    function flashIx(amount0: BN, amount1: BN) {
+      return coreProgram.instruction.flash(amount0, amount1, {
+        accounts: {
+          signer: owner,
+          factoryState,
+          poolState: poolAState,
+          vault0: vaultA0,
+          vault1: vaultA1,
+          recipientWallet0: minterWallet0,
+          recipientWallet1: minterWallet1,
+          instructionsSysvar: web3.SYSVAR_INSTRUCTIONS_PUBKEY,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        }
+      })
+    }
+
+    function repayFlashIx(amount0: BN, amount1: BN) {
+      return coreProgram.instruction.repayFlash(amount0, amount1, {
+        accounts: {
+          signer: owner,
+          factoryState,
+          poolState: poolAState,
+          vault0: vaultA0,
+          vault1: vaultA1,
+          tokenAccount0: minterWallet0,
+          tokenAccount1: minterWallet1,
+          instructionsSysvar: web3.SYSVAR_INSTRUCTIONS_PUBKEY,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        }
+      })
+    }
+
+    it('fails if not repaid in the same transaction', async () => {
+      const tx = new Transaction().add(flashIx(amount0, amount1))
+      await expect(anchor.getProvider().send(tx)).to.be.rejectedWith(Error)
+    })
+
+    it('fails if the repaid amounts do not match the borrowed amounts', async () => {
+      const tx = new Transaction().add(
+        flashIx(amount0, amount1),
+        repayFlashIx(amount0, new BN(0))
+      )
+      await expect(anchor.getProvider().send(tx)).to.be.rejectedWith(Error)
+    })
+
+    it('fails if repaid without a flash loan', async () => {
+      const tx = new Transaction().add(repayFlashIx(amount0, amount1))
+      await expect(anchor.getProvider().send(tx)).to.be.rejectedWith(Error)
+    })
+
+    it('borrows and repays with fee in the same transaction', async () => {
+      const {
+        feeGrowthGlobal0X32: feeGrowthGlobal0Before,
+        feeGrowthGlobal1X32: feeGrowthGlobal1Before,
+      } = await coreProgram.account.poolState.fetch(poolAState)
+      const vault0Before = (await token0.getAccountInfo(vaultA0)).amount
+      const vault1Before = (await token1.getAccountInfo(vaultA1)).amount
+
+      const tx = new Transaction().add(
+        flashIx(amount0, amount1),
+        repayFlashIx(amount0, amount1)
+      )
       await anchor.getProvider().send(tx)
+
+      // 1000 * 500 / 1_000_000 rounded up
+      const vault0After = (await token0.getAccountInfo(vaultA0)).amount
+      const vault1After = (await token1.getAccountInfo(vaultA1)).amount
+      assert(vault0After.sub(vault0Before).eqn(1))
+      assert(vault1After.sub(vault1Before).eqn(1))
+
+      const {
+        feeGrowthGlobal0X32,
+        feeGrowthGlobal1X32,
+        unlocked,
+        flashAmount0,
+        flashAmount1,
+      } = await coreProgram.account.poolState.fetch(poolAState)
+      assert(feeGrowthGlobal0X32.gte(feeGrowthGlobal0Before))
+      assert(feeGrowthGlobal1X32.gte(feeGrowthGlobal1Before))
+      assert(unlocked)
+      // the loan is cleared once repaid
+      assert(flashAmount0.eqn(0))
+      assert(flashAmount1.eqn(0))
+    })
+
+    it('fails while swaps are paused for the pool', async () => {
+      await coreProgram.rpc.setPoolPauseState(PAUSE_SWAP, {
+        accounts: {
+          pauseGuardian: owner,
+          factoryState,
+          poolState: poolAState,
+        }
+      })
+      const tx = new Transaction().add(
+        flashIx(amount0, amount1),
+        repayFlashIx(amount0, amount1)
+      )
+      await expect(anchor.getProvider().send(tx)).to.be.rejectedWith(Error)
+
+      await coreProgram.rpc.setPoolPauseState(0, {
+        accounts: {
+          pauseGuardian: owner,
+          factoryState,
+          poolState: poolAState,
+        }
+      })
+    })
+
+    it('fails while everything is paused globally', async () => {
+      await coreProgram.rpc.setPauseState(PAUSE_ALL, {
+        accounts: {
+          pauseGuardian: owner,
+          factoryState,
+        }
+      })
+      const tx = new Transaction().add(
+        flashIx(amount0, amount1),
+        repayFlashIx(amount0, amount1)
+      )
+      await expect(anchor.getProvider().send(tx)).to.be.rejectedWith(Error)
+
+      await coreProgram.rpc.setPauseState(0, {
+        accounts: {
+          pauseGuardian: owner,
+          factoryState,
+        }
+      })
+    })
+  })
+
+  describe('#staker', () => {
+    const reward = new BN(1_000_000)
+    let startTime: number
+    let endTime: number
+    let incentiveState: web3.PublicKey
+    let incentiveVault: web3.PublicKey
+    let depositState: web3.PublicKey
+    let depositVault: web3.PublicKey
+    let stakeState: web3.PublicKey
+
+    async function blockTime() {
+      return await connection.getBlockTime(await connection.getSlot())
+    }
+
+    async function waitUntil(time: number) {
+      while (await blockTime() < time) {
+        await new Promise(r => setTimeout(r, 1000))
+      }
+    }
+
+    function stakeAccounts() {
+      return {
+        incentiveState,
+        depositState,
+        stakeState,
+        poolState: poolBState,
+        tickLowerState: tickLowerBState,
+        tickUpperState: tickUpperBState,
+        latestObservationState: latestObservationBState,
+      }
+    }
+
+    it('derive incentive and deposit accounts', async () => {
+      const { observationIndex } = await coreProgram.account.poolState.fetch(poolBState)
+      latestObservationBState = (await PublicKey.findProgramAddress(
+        [
+          OBSERVATION_SEED,
+          token1.publicKey.toBuffer(),
+          token2.publicKey.toBuffer(),
+          u32ToSeed(fee),
+          u16ToSeed(observationIndex)
+        ],
+        coreProgram.programId
+      ))[0]
+
+      startTime = await blockTime() + 5
+      endTime = startTime + 5
+      incentiveState = (await PublicKey.findProgramAddress(
+        [
+          INCENTIVE_SEED,
+          token2.publicKey.toBuffer(),
+          poolBState.toBuffer(),
+          u32ToSeed(startTime),
+          u32ToSeed(endTime),
+          owner.toBuffer()
+        ],
+        coreProgram.programId
+      ))[0]
+      incentiveVault = await Token.getAssociatedTokenAddress(
+        ASSOCIATED_TOKEN_PROGRAM_ID,
+        TOKEN_PROGRAM_ID,
+        token2.publicKey,
+        incentiveState,
+        true
+      )
+
+      depositState = (await PublicKey.findProgramAddress(
+        [DEPOSIT_SEED, nftMintBKeypair.publicKey.toBuffer()],
+        coreProgram.programId
+      ))[0]
+      depositVault = await Token.getAssociatedTokenAddress(
+        ASSOCIATED_TOKEN_PROGRAM_ID,
+        TOKEN_PROGRAM_ID,
+        nftMintBKeypair.publicKey,
+        depositState,
+        true
+      )
+      stakeState = (await PublicKey.findProgramAddress(
+        [STAKE_SEED, incentiveState.toBuffer(), nftMintBKeypair.publicKey.toBuffer()],
+        coreProgram.programId
+      ))[0]
+    })
+
+    it('fails to create an incentive ending before it starts', async () => {
+      const [invalidIncentiveState] = await PublicKey.findProgramAddress(
+        [
+          INCENTIVE_SEED,
+          token2.publicKey.toBuffer(),
+          poolBState.toBuffer(),
+          u32ToSeed(startTime),
+          u32ToSeed(startTime),
+          owner.toBuffer()
+        ],
+        coreProgram.programId
+      )
+      await expect(coreProgram.rpc.createIncentive(startTime, startTime, reward, {
+        accounts: {
+          creator: owner,
+          poolState: poolBState,
+          rewardMint: token2.publicKey,
+          refundee: owner,
+          incentiveState: invalidIncentiveState,
+          rewardVault: await Token.getAssociatedTokenAddress(
+            ASSOCIATED_TOKEN_PROGRAM_ID,
+            TOKEN_PROGRAM_ID,
+            token2.publicKey,
+            invalidIncentiveState,
+            true
+          ),
+          funderWallet: minterWallet2,
+          rent: web3.SYSVAR_RENT_PUBKEY,
+          systemProgram: SystemProgram.programId,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        }
+      })).to.be.rejectedWith(Error)
+    })
+
+    it('creates an incentive funded by the creator', async () => {
+      let listener: number
+      let [_event, _slot] = await new Promise((resolve, _reject) => {
+        listener = coreProgram.addEventListener("IncentiveCreated", (event, slot) => {
+          assert((event.rewardToken as web3.PublicKey).equals(token2.publicKey))
+          assert((event.poolState as web3.PublicKey).equals(poolBState))
+          assert.equal(event.startTime, startTime)
+          assert.equal(event.endTime, endTime)
+          assert((event.refundee as web3.PublicKey).equals(owner))
+          assert((event.reward as BN).eq(reward))
+
+          resolve([event, slot]);
+        });
+
+        coreProgram.rpc.createIncentive(startTime, endTime, reward, {
+          accounts: {
+            creator: owner,
+            poolState: poolBState,
+            rewardMint: token2.publicKey,
+            refundee: owner,
+            incentiveState,
+            rewardVault: incentiveVault,
+            funderWallet: minterWallet2,
+            rent: web3.SYSVAR_RENT_PUBKEY,
+            systemProgram: SystemProgram.programId,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          }
+        })
+      })
+      await coreProgram.removeEventListener(listener)
+
+      const incentiveData = await coreProgram.account.incentiveState.fetch(incentiveState)
+      assert(incentiveData.vault.equals(incentiveVault))
+      assert(incentiveData.totalRewardUnclaimed.eq(reward))
+      const vaultInfo = await token2.getAccountInfo(incentiveVault)
+      assert(vaultInfo.amount.eq(reward))
+    })
+
+    it('fails to deposit an NFT not held by the signer', async () => {
+      await expect(coreProgram.rpc.depositToken({
+        accounts: {
+          owner: notOwner.publicKey,
+          nftAccount: positionBNftAccount,
+          nftMint: nftMintBKeypair.publicKey,
+          tokenizedPositionState: tokenizedPositionBState,
+          depositState,
+          depositVault,
+          rent: web3.SYSVAR_RENT_PUBKEY,
+          systemProgram: SystemProgram.programId,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        }, signers: [notOwner]
+      })).to.be.rejectedWith(Error)
+    })
+
+    it('deposits the NFT', async () => {
+      await coreProgram.rpc.depositToken({
+        accounts: {
+          owner,
+          nftAccount: positionBNftAccount,
+          nftMint: nftMintBKeypair.publicKey,
+          tokenizedPositionState: tokenizedPositionBState,
+          depositState,
+          depositVault,
+          rent: web3.SYSVAR_RENT_PUBKEY,
+          systemProgram: SystemProgram.programId,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        }
+      })
+
+      const depositData = await coreProgram.account.depositState.fetch(depositState)
+      assert(depositData.owner.equals(owner))
+      assert(depositData.poolId.equals(poolBState))
+      assert.equal(depositData.numberOfStakes, 0)
+      const nftAccountInfo = await connection.getTokenAccountBalance(depositVault)
+      assert.equal(nftAccountInfo.value.amount, '1')
+    })
+
+    it('fails to stake before the incentive starts', async () => {
+      await expect(coreProgram.rpc.stakeToken({
+        accounts: {
+          owner,
+          tokenizedPositionState: tokenizedPositionBState,
+          systemProgram: SystemProgram.programId,
+          ...stakeAccounts(),
+        }
+      })).to.be.rejectedWith(Error)
+    })
+
+    it('stakes the NFT once the incentive starts', async () => {
+      await waitUntil(startTime)
+      await coreProgram.rpc.stakeToken({
+        accounts: {
+          owner,
+          tokenizedPositionState: tokenizedPositionBState,
+          systemProgram: SystemProgram.programId,
+          ...stakeAccounts(),
+        }
+      })
+
+      const { liquidity } = await coreProgram.account.tokenizedPositionState.fetch(tokenizedPositionBState)
+      const stakeData = await coreProgram.account.stakeState.fetch(stakeState)
+      assert(stakeData.liquidity.eq(liquidity))
+      const incentiveData = await coreProgram.account.incentiveState.fetch(incentiveState)
+      assert.equal(incentiveData.numberOfStakes, 1)
+      const depositData = await coreProgram.account.depositState.fetch(depositState)
+      assert.equal(depositData.numberOfStakes, 1)
+    })
+
+    it('fails to withdraw a staked NFT', async () => {
+      await expect(coreProgram.rpc.withdrawToken({
+        accounts: {
+          owner,
+          depositState,
+          depositVault,
+          recipientWallet: positionBNftAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        }
+      })).to.be.rejectedWith(Error)
+    })
+
+    it('fails to unstake for the owner before the incentive ends', async () => {
+      await expect(coreProgram.rpc.unstakeToken({
+        accounts: {
+          signer: notOwner.publicKey,
+          owner,
+          rewardVault: incentiveVault,
+          recipientWallet: minterWallet2,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          ...stakeAccounts(),
+        }, signers: [notOwner]
+      })).to.be.rejectedWith(Error)
+    })
+
+    it('claims rewards while staked', async () => {
+      const balanceBefore = (await token2.getAccountInfo(minterWallet2)).amount
+      await coreProgram.rpc.claimReward({
+        accounts: {
+          owner,
+          rewardVault: incentiveVault,
+          recipientWallet: minterWallet2,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          ...stakeAccounts(),
+        }
+      })
+
+      const { totalRewardUnclaimed } = await coreProgram.account.incentiveState.fetch(incentiveState)
+      const balanceAfter = (await token2.getAccountInfo(minterWallet2)).amount
+      assert(balanceAfter.sub(balanceBefore).eq(reward.sub(totalRewardUnclaimed)))
+    })
+
+    it('fails to end the incentive before its end time', async () => {
+      await expect(coreProgram.rpc.endIncentive({
+        accounts: {
+          incentiveState,
+          rewardVault: incentiveVault,
+          refundeeWallet: minterWallet2,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        }
+      })).to.be.rejectedWith(Error)
+    })
+
+    it('lets anyone unstake once the incentive ends', async () => {
+      await waitUntil(endTime)
+      await coreProgram.rpc.unstakeToken({
+        accounts: {
+          signer: notOwner.publicKey,
+          owner,
+          rewardVault: incentiveVault,
+          recipientWallet: minterWallet2,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          ...stakeAccounts(),
+        }, signers: [notOwner]
+      })
+
+      assert.isNull(await connection.getAccountInfo(stakeState))
+      const incentiveData = await coreProgram.account.incentiveState.fetch(incentiveState)
+      assert.equal(incentiveData.numberOfStakes, 0)
+      const depositData = await coreProgram.account.depositState.fetch(depositState)
+      assert.equal(depositData.numberOfStakes, 0)
+    })
+
+    it('ends the incentive and refunds the remaining rewards', async () => {
+      const { totalRewardUnclaimed } = await coreProgram.account.incentiveState.fetch(incentiveState)
+      const balanceBefore = (await token2.getAccountInfo(minterWallet2)).amount
+      await coreProgram.rpc.endIncentive({
+        accounts: {
+          incentiveState,
+          rewardVault: incentiveVault,
+          refundeeWallet: minterWallet2,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        }
+      })
+
+      const balanceAfter = (await token2.getAccountInfo(minterWallet2)).amount
+      assert(balanceAfter.sub(balanceBefore).eq(totalRewardUnclaimed))
+      const vaultInfo = await token2.getAccountInfo(incentiveVault)
+      assert(vaultInfo.amount.eqn(0))
+    })
+
+    it('withdraws the NFT and closes the deposit', async () => {
+      await coreProgram.rpc.withdrawToken({
+        accounts: {
+          owner,
+          depositState,
+          depositVault,
+          recipientWallet: positionBNftAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        }
+      })
+
+      assert.isNull(await connection.getAccountInfo(depositState))
+      assert.isNull(await connection.getAccountInfo(depositVault))
+      const nftAccountInfo = await connection.getTokenAccountBalance(positionBNftAccount)
+      assert.equal(nftAccountInfo.value.amount, '1')
+    })
+  })
+
+  describe('#distributor', () => {
+    const distributionIndex = 0
+    const ownerAmount = new BN(1_000)
+    const otherAmount = new BN(2_000)
+    const otherClaimant = new Keypair().publicKey
+    let distributorState: web3.PublicKey
+    let distributorVault: web3.PublicKey
+    let root: Buffer
+    let ownerProof: Buffer[]
+
+    // Leaves and nodes are hashed as by the distributor of the program
+    function leaf(index: number, claimant: web3.PublicKey, amount: BN) {
+      return createHash('sha256')
+        .update(new BN(index).toArrayLike(Buffer, 'le', 8))
+        .update(claimant.toBuffer())
+        .update(amount.toArrayLike(Buffer, 'le', 8))
+        .digest()
+    }
+
+    function hashPair(a: Buffer, b: Buffer) {
+      const [first, second] = Buffer.compare(a, b) <= 0 ? [a, b] : [b, a]
+      return createHash('sha256').update(first).update(second).digest()
+    }
+
+    async function findClaimStatusAddress(index: number) {
+      return (await PublicKey.findProgramAddress(
+        [CLAIM_STATUS_SEED, distributorState.toBuffer(), new BN(index).toArrayLike(Buffer, 'be', 8)],
+        coreProgram.programId
+      ))[0]
+    }
+
+    function claimAccounts(claimStatusState: web3.PublicKey) {
+      return {
+        claimant: owner,
+        distributorState,
+        claimStatusState,
+        vault: distributorVault,
+        recipientWallet: minterWallet2,
+        systemProgram: SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      }
+    }
+
+    it('derive distributor accounts and the tree of claims', async () => {
+      distributorState = (await PublicKey.findProgramAddress(
+        [
+          DISTRIBUTOR_SEED,
+          poolBState.toBuffer(),
+          token2.publicKey.toBuffer(),
+          u32ToSeed(distributionIndex)
+        ],
+        coreProgram.programId
+      ))[0]
+      distributorVault = await Token.getAssociatedTokenAddress(
+        ASSOCIATED_TOKEN_PROGRAM_ID,
+        TOKEN_PROGRAM_ID,
+        token2.publicKey,
+        distributorState,
+        true
+      )
+
+      const ownerLeaf = leaf(0, owner, ownerAmount)
+      const otherLeaf = leaf(1, otherClaimant, otherAmount)
+      root = hashPair(ownerLeaf, otherLeaf)
+      ownerProof = [otherLeaf]
+    })
+
+    it('fails if not called by the owner', async () => {
+      await expect(coreProgram.rpc.newDistributor(
+        distributionIndex,
+        [...root],
+        ownerAmount.add(otherAmount),
+        {
+          accounts: {
+            owner: notOwner.publicKey,
+            payer: owner,
+            factoryState,
+            poolState: poolBState,
+            rewardMint: token2.publicKey,
+            distributorState,
+            vault: distributorVault,
+            rent: web3.SYSVAR_RENT_PUBKEY,
+            systemProgram: SystemProgram.programId,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          }, signers: [notOwner]
+        }
+      )).to.be.rejectedWith(Error)
+    })
+
+    it('creates a distributor and funds its vault', async () => {
+      await coreProgram.rpc.newDistributor(
+        distributionIndex,
+        [...root],
+        ownerAmount.add(otherAmount),
+        {
+          accounts: {
+            owner,
+            payer: owner,
+            factoryState,
+            poolState: poolBState,
+            rewardMint: token2.publicKey,
+            distributorState,
+            vault: distributorVault,
+            rent: web3.SYSVAR_RENT_PUBKEY,
+            systemProgram: SystemProgram.programId,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          }
+        }
+      )
+      await token2.mintTo(distributorVault, mintAuthority, [], ownerAmount.add(otherAmount).toNumber())
+
+      const distributorData = await coreProgram.account.distributorState.fetch(distributorState)
+      assert(distributorData.poolId.equals(poolBState))
+      assert(distributorData.vault.equals(distributorVault))
+      assert.deepEqual(Buffer.from(distributorData.root), root)
+      assert(distributorData.maxTotalClaim.eq(ownerAmount.add(otherAmount)))
+    })
+
+    it('fails to claim more than the leaf', async () => {
+      await expect(coreProgram.rpc.claim(
+        new BN(0),
+        ownerAmount.addn(1),
+        ownerProof.map(node => [...node]),
+        { accounts: claimAccounts(await findClaimStatusAddress(0)) }
+      )).to.be.rejectedWith(Error)
+    })
+
+    it('fails to claim the leaf of another claimant', async () => {
+      await expect(coreProgram.rpc.claim(
+        new BN(1),
+        otherAmount,
+        [[...leaf(0, owner, ownerAmount)]],
+        { accounts: claimAccounts(await findClaimStatusAddress(1)) }
+      )).to.be.rejectedWith(Error)
+    })
+
+    it('claims with a valid proof', async () => {
+      const claimStatusState = await findClaimStatusAddress(0)
+      const balanceBefore = (await token2.getAccountInfo(minterWallet2)).amount
+      await coreProgram.rpc.claim(
+        new BN(0),
+        ownerAmount,
+        ownerProof.map(node => [...node]),
+        { accounts: claimAccounts(claimStatusState) }
+      )
+
+      const balanceAfter = (await token2.getAccountInfo(minterWallet2)).amount
+      assert(balanceAfter.sub(balanceBefore).eq(ownerAmount))
+      const claimStatusData = await coreProgram.account.claimStatusState.fetch(claimStatusState)
+      assert(claimStatusData.claimant.equals(owner))
+      assert(claimStatusData.amount.eq(ownerAmount))
+      const distributorData = await coreProgram.account.distributorState.fetch(distributorState)
+      assert(distributorData.totalAmountClaimed.eq(ownerAmount))
+      assert(distributorData.numNodesClaimed.eqn(1))
+    })
+
+    it('fails to claim a leaf twice', async () => {
+      await expect(coreProgram.rpc.claim(
+        new BN(0),
+        ownerAmount,
+        ownerProof.map(node => [...node]),
+        { accounts: claimAccounts(await findClaimStatusAddress(0)) }
+      )).to.be.rejectedWith(Error)
+    })
+  })
+
+  describe('Completely close position and deallocate ticks', () => {
+    it('update observation accounts', async () => {
+      const {
+        observationIndex,
+        observationCardinalityNext
+      } = await coreProgram.account.poolState.fetch(poolAState)
+
+      const { blockTimestamp: lastBlockTime } = await coreProgram.account.observationState.fetch(lastObservationAState)
+
+      const slot = await connection.getSlot()
+      const blockTimestamp = await connection.getBlockTime(slot)
+
+      // If current observation account will expire in 3 seconds, we sleep for this time
+      // before recalculating the observation states
+      if (Math.floor(lastBlockTime / 14) == Math.floor(blockTimestamp / 14) && lastBlockTime % 14 >= 11) {
+        await new Promise(r => setTimeout(r, 3000))
+      }
+      if (Math.floor(lastBlockTime / 14) > Math.floor(blockTimestamp / 14)) {
+        lastObservationAState = (await PublicKey.findProgramAddress(
+          [
+            OBSERVATION_SEED,
+            token0.publicKey.toBuffer(),
+            token1.publicKey.toBuffer(),
+            u32ToSeed(fee),
+            u16ToSeed(observationIndex)
+          ],
+          coreProgram.programId
+        ))[0]
+
+        nextObservationAState = (await PublicKey.findProgramAddress(
+          [
+            OBSERVATION_SEED,
+            token0.publicKey.toBuffer(),
+            token1.publicKey.toBuffer(),
+            u32ToSeed(fee),
+            u16ToSeed((observationIndex + 1) % observationCardinalityNext)
+          ],
+          coreProgram.programId
+        ))[0]
+      }
+    })
+
+    it('burn entire of the position liquidity as owner', async () => {
+      const { liquidity } = await coreProgram.account.tokenizedPositionState.fetch(tokenizedPositionAState)
+      console.log('liquidity in position', liquidity)
+      const deadline = new BN(Date.now() / 1000 + 10_000)
+
+      const tx = new Transaction()
+      tx.instructions = [
+        coreProgram.instruction.decreaseLiquidity(
+          liquidity,
+          new BN(0),
+          new BN(0),
+          deadline, {
+          accounts: {
+            ownerOrDelegate: owner,
+            nftAccount: positionANftAccount,
+            tokenizedPositionState: tokenizedPositionAState,
+            factoryState,
+            poolState: poolAState,
+            corePositionState: corePositionAState,
+            tickLowerState: tickLowerAState,
+            tickUpperState: tickUpperAState,
+            bitmapLowerState: bitmapLowerAState,
+            bitmapUpperState: bitmapUpperAState,
+            lastObservationState: lastObservationAState,
+            coreProgram: coreProgram.programId
+          },
+          remainingAccounts: [{
+            pubkey: nextObservationAState,
+            isSigner: false,
+            isWritable: true
+          }],
+        }
+        ),
+      ]
+      tx.recentBlockhash = (await connection.getRecentBlockhash()).blockhash
+      await anchor.getProvider().send(tx)
+    })
+
+    it('fails to burn the NFT while tokens are owed', async () => {
+      await expect(coreProgram.rpc.burnTokenizedPosition({
+        accounts: {
+          owner,
+          nftAccount: positionANftAccount,
+          nftMint: nftMintAKeypair.publicKey,
+          factoryState,
+          tokenizedPositionState: tokenizedPositionAState,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        }
+      })).to.be.rejectedWith(Error)
+    })
+
+    it('collect all tokens owed to the position', async () => {
+      const { tokensOwed0, tokensOwed1 } = await coreProgram.account.tokenizedPositionState.fetch(tokenizedPositionAState)
+      await coreProgram.rpc.collectFromTokenized(tokensOwed0, tokensOwed1, {
+        accounts: {
+          ownerOrDelegate: owner,
+          nftAccount: positionANftAccount,
+          tokenizedPositionState: tokenizedPositionAState,
+          factoryState,
+          poolState: poolAState,
+          corePositionState: corePositionAState,
+          tickLowerState: tickLowerAState,
+          tickUpperState: tickUpperAState,
+          bitmapLowerState: bitmapLowerAState,
+          bitmapUpperState: bitmapUpperAState,
+          lastObservationState: lastObservationAState,
+          coreProgram: coreProgram.programId,
+          vault0: vaultA0,
+          vault1: vaultA1,
+          recipientWallet0: minterWallet0,
+          recipientWallet1: minterWallet1,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        },
+        remainingAccounts: [{
+          pubkey: nextObservationAState,
+          isSigner: false,
+          isWritable: true
+        }],
+      })
+
+      const tokenizedPositionData = await coreProgram.account.tokenizedPositionState.fetch(tokenizedPositionAState)
+      assert(tokenizedPositionData.tokensOwed0.eqn(0))
+      assert(tokenizedPositionData.tokensOwed1.eqn(0))
+    })
+
+    it('collect all rewards owed to the position', async () => {
+      await coreProgram.rpc.collectRewardFromTokenized(0, MaxU64, {
+        accounts: {
+          ownerOrDelegate: owner,
+          nftAccount: positionANftAccount,
+          tokenizedPositionState: tokenizedPositionAState,
+          factoryState,
+          poolState: poolAState,
+          corePositionState: corePositionAState,
+          tickLowerState: tickLowerAState,
+          tickUpperState: tickUpperAState,
+          bitmapLowerState: bitmapLowerAState,
+          bitmapUpperState: bitmapUpperAState,
+          lastObservationState: lastObservationAState,
+          rewardVault: rewardVaultA,
+          recipientWallet: rewardRecipientWallet,
+          coreProgram: coreProgram.programId,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        },
+        remainingAccounts: [{
+          pubkey: nextObservationAState,
+          isSigner: false,
+          isWritable: true
+        }],
+      })
+
+      const tokenizedPositionData = await coreProgram.account.tokenizedPositionState.fetch(tokenizedPositionAState)
+      assert(tokenizedPositionData.rewardInfos[0].rewardAmountOwed.eqn(0))
+    })
+
+    it('burn the NFT and close the tokenized position', async () => {
+      const balanceBefore = await connection.getBalance(owner)
+
+      await coreProgram.rpc.burnTokenizedPosition({
+        accounts: {
+          owner,
+          nftAccount: positionANftAccount,
+          nftMint: nftMintAKeypair.publicKey,
+          factoryState,
+          tokenizedPositionState: tokenizedPositionAState,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        }
+      })
+
+      assert.isNull(await connection.getAccountInfo(tokenizedPositionAState))
+      assert.isNull(await connection.getAccountInfo(positionANftAccount))
+      const nftSupply = (await connection.getTokenSupply(nftMintAKeypair.publicKey)).value.amount
+      assert.equal(nftSupply, '0')
+      const nftMintInfo = await new Token(
+        connection,
+        nftMintAKeypair.publicKey,
+        TOKEN_PROGRAM_ID,
+        new Keypair()
+      ).getMintInfo()
+      assert.isNull(nftMintInfo.mintAuthority)
+      assert.isAbove(await connection.getBalance(owner), balanceBefore)
+    })
+
+    it('fails to refund tick rent to an account other than the payer', async () => {
+      await expect(coreProgram.rpc.closeTickAccount({
+        accounts: {
+          recipient: Keypair.generate().publicKey,
+          tickState: tickLowerAState,
+        }
+      })).to.be.rejectedWith(Error)
+    })
+
+    it('deallocate the lower tick', async () => {
+      await coreProgram.rpc.closeTickAccount({
+        accounts: {
+          recipient: owner,
+          tickState: tickLowerAState,
+        }
+      })
+      assert.isNull(await connection.getAccountInfo(tickLowerAState))
+    })
+
+    it('sweep cleared ticks and skip ticks holding liquidity', async () => {
+      await coreProgram.rpc.sweepTickAccounts({
+        accounts: {},
+        remainingAccounts: [{
+          pubkey: tickUpperAState,
+          isSigner: false,
+          isWritable: true
+        }, {
+          pubkey: owner,
+          isSigner: false,
+          isWritable: true
+        }, {
+          pubkey: tickLowerBState,
+          isSigner: false,
+          isWritable: true
+        }, {
+          pubkey: owner,
+          isSigner: false,
+          isWritable: true
+        }],
+      })
+      assert.isNull(await connection.getAccountInfo(tickUpperAState))
+
+      const tickLowerBData = await coreProgram.account.tickState.fetch(tickLowerBState)
+      assert(tickLowerBData.payer.equals(owner))
+    })
+  })
+
+  describe('#close_bitmap_account', () => {
+    const emptyWordPos = 5
+    let emptyBitmapBState: web3.PublicKey
+
+    it('fails if the bitmap has initialized ticks', async () => {
+      await expect(coreProgram.rpc.closeBitmapAccount({
+        accounts: {
+          bitmapState: bitmapLowerBState,
+          recipient: owner,
+        }
+      })).to.be.rejectedWith(Error)
+    })
+
+    it('closes a bitmap with no initialized tick', async () => {
+      [emptyBitmapBState] = await PublicKey.findProgramAddress([
+        BITMAP_SEED,
+        token1.publicKey.toBuffer(),
+        token2.publicKey.toBuffer(),
+        u32ToSeed(fee),
+        u16ToSeed(emptyWordPos),
+      ],
+        coreProgram.programId
+      )
+      await coreProgram.rpc.initBitmapAccount(emptyWordPos, {
+        accounts: {
+          signer: owner,
+          poolState: poolBState,
+          bitmapState: emptyBitmapBState,
+          systemProgram: SystemProgram.programId,
+        }
+      })
+
+      await coreProgram.rpc.closeBitmapAccount({
+        accounts: {
+          bitmapState: emptyBitmapBState,
+          recipient: owner,
+        }
+      })
+      assert.isNull(await connection.getAccountInfo(emptyBitmapBState))
+    })
+  })
+
+  describe('#close_position_account', () => {
+    let emptyPositionBState: web3.PublicKey
+
+    it('fails if the position holds liquidity', async () => {
+      await expect(coreProgram.rpc.closePositionAccount({
+        accounts: {
+          positionState: corePositionBState,
+          recipient: owner,
+        }
+      })).to.be.rejectedWith(Error)
+    })
+
+    it('closes a position with no liquidity and no tokens owed', async () => {
+      [emptyPositionBState] = await PublicKey.findProgramAddress([
+        POSITION_SEED,
+        token1.publicKey.toBuffer(),
+        token2.publicKey.toBuffer(),
+        u32ToSeed(fee),
+        owner.toBuffer(),
+        u32ToSeed(tickLower),
+        u32ToSeed(tickUpper)
+      ],
+        coreProgram.programId
+      )
+      await coreProgram.rpc.initPositionAccount({
+        accounts: {
+          signer: owner,
+          recipient: owner,
+          poolState: poolBState,
+          tickLowerState: tickLowerBState,
+          tickUpperState: tickUpperBState,
+          positionState: emptyPositionBState,
+          systemProgram: SystemProgram.programId,
+        }
+      })
+
+      await coreProgram.rpc.closePositionAccount({
+        accounts: {
+          positionState: emptyPositionBState,
+          recipient: owner,
+        }
+      })
+      assert.isNull(await connection.getAccountInfo(emptyPositionBState))
+    })
+  })
+
+  describe('#timelock', () => {
+    const delay = 60
+
+    it('cannot be queued below the minimum delay', async () => {
+      await expect(queueAdminAction({ setTimelockDelay: { delay: 0 } })).to.be.rejectedWith(Error)
+    })
+
+    it('sets the delay through the timelock', async () => {
+      const action = { setTimelockDelay: { delay } }
+      await queueAdminAction(action)
+      await coreProgram.rpc.setTimelockDelay(delay, {
+        accounts: {
+          factoryState,
+          timelockState: await findTimelockAddress(action),
+          timelockPayer: owner,
+        }
+      })
+
+      const factoryStateData = await coreProgram.account.factoryState.fetch(factoryState)
+      assert.equal(factoryStateData.timelockDelay, delay)
+    })
+
+    it('queues an action with an ETA after the delay', async () => {
+      const action = { setFeeProtocol: { feeProtocol: 200_000 } }
+      await queueAdminAction(action, undefined, false)
+
+      const { eta } = await coreProgram.account.timelockState.fetch(await findTimelockAddress(action))
+      assert(eta.gtn(Date.now() / 1000 + delay - 10))
+    })
+
+    it('fails to execute before the ETA', async () => {
+      await expect(coreProgram.rpc.setFeeProtocol(200_000, {
+        accounts: {
+          factoryState,
+          timelockState: await findTimelockAddress({ setFeeProtocol: { feeProtocol: 200_000 } }),
+          timelockPayer: owner,
+        }
+      })).to.be.rejectedWith(Error)
+    })
+
+    it('cannot be canceled by addresses that are not owner or pause guardian', async () => {
+      const action = { setFeeProtocol: { feeProtocol: 200_000 } }
+      await expect(coreProgram.rpc.cancelAdminAction(action, {
+        accounts: {
+          signer: notOwner.publicKey,
+          factoryState,
+          timelockState: await findTimelockAddress(action),
+          timelockPayer: owner,
+        }, signers: [notOwner]
+      })).to.be.rejectedWith(Error)
+    })
+
+    it('cancels a queued action and emits an event', async () => {
+      const action = { setFeeProtocol: { feeProtocol: 200_000 } }
+      const timelockState = await findTimelockAddress(action)
+
+      let listener: number
+      let [_event, _slot] = await new Promise((resolve, _reject) => {
+        listener = coreProgram.addEventListener("AdminActionCanceled", (event, slot) => {
+          assert.deepEqual(event.action, action)
+
+          resolve([event, slot]);
+        });
+
+        coreProgram.rpc.cancelAdminAction(action, {
+          accounts: {
+            signer: owner,
+            factoryState,
+            timelockState,
+            timelockPayer: owner,
+          }
+        })
+      });
+      await coreProgram.removeEventListener(listener);
+
+      assert.isNull(await connection.getAccountInfo(timelockState))
+    })
+  })
+
+  describe('#governance', () => {
+    // The factory is owned by a Goki smart wallet whose transactions are queued by a
+    // Tribeca governor. The test wallet sits on the executive council, executing them.
+    const base = new Keypair()
+    const feeTier = { fee: 3000, tickSpacing: 60 }
+    const feeProtocol = 125_000
+    let smartWallet: web3.PublicKey
+    let smartWalletWrapper: SmartWalletWrapper
+    let governorWrapper: GovernorWrapper
+    let lockerWrapper: LockerWrapper
+
+    const solanaProvider = SolanaProvider.init({
+      connection,
+      wallet: anchor.getProvider().wallet,
+      opts: {},
+    })
+    const tribecaSdk = TribecaSDK.load({ provider: solanaProvider })
+
+    async function send(...envelopes: TransactionEnvelope[]) {
+      for (const envelope of envelopes) {
+        await anchor.getProvider().send(envelope.build(), envelope.signers)
+      }
+    }
+
+    // Passes a proposal through the governor and executes it from the smart wallet
+    async function passProposal(instructions: web3.TransactionInstruction[]) {
+      const { proposal, index, tx: createProposalTx } = await governorWrapper.createProposal({
+        instructions,
+      })
+      await send(createProposalTx)
+
+      await sleep(2)
+      await send(await lockerWrapper.activateProposal({ proposal }))
+      await send(...await lockerWrapper.castVotes({ proposal, side: VoteSide.For }))
+
+      const { votingEndsAt } = await tribecaSdk.govern.program.account.proposal.fetch(proposal)
+      while (Date.now() / 1000 <= votingEndsAt.toNumber() + 1) {
+        await sleep(1)
+      }
+      await send(await governorWrapper.queueProposal({ index }))
+
+      const { queuedTransaction } = await tribecaSdk.govern.program.account.proposal.fetch(proposal)
+      await send(await smartWalletWrapper.executeTransaction({
+        transactionKey: queuedTransaction,
+        owner,
+      }))
+    }
+
+    async function queueAdminActionIx(action: any) {
+      return coreProgram.instruction.queueAdminAction(action, {
+        accounts: {
+          admin: smartWallet,
+          payer: owner,
+          factoryState,
+          timelockState: await findTimelockAddress(action),
+          systemProgram: SystemProgram.programId,
+        }
+      })
+    }
+
+    it('sets up a governor, its smart wallet and a locker', async () => {
+      const [governorKey] = await findGovernorAddress(base.publicKey)
+      const [lockerKey] = await findLockerAddress(base.publicKey)
+
+      const gokiSdk = GokiSDK.load({ provider: solanaProvider })
+      const { smartWalletWrapper: wrapper, tx: createSmartWalletTx } = await gokiSdk.newSmartWallet({
+        numOwners: 2,
+        owners: [governorKey, owner],
+        threshold: new BN(1),
+        base,
+      })
+      await send(createSmartWalletTx)
+      smartWalletWrapper = wrapper
+      smartWallet = wrapper.key
+
+      const govToken = await Token.createMint(
+        connection,
+        mintAuthority,
+        mintAuthority.publicKey,
+        null,
+        6,
+        TOKEN_PROGRAM_ID
+      )
+      const govTokenWallet = await govToken.createAssociatedTokenAccount(owner)
+      await govToken.mintTo(govTokenWallet, mintAuthority, [], 1_000_000)
+
+      const { wrapper: governor, tx: createGovernorTx } = await tribecaSdk.govern.createGovernor({
+        electorate: lockerKey,
+        smartWallet,
+        baseKP: base,
+        governanceParameters: {
+          votingDelay: new BN(1),
+          votingPeriod: new BN(5),
+          quorumVotes: new BN(1),
+          timelockDelaySeconds: new BN(0),
+        },
+      })
+      await send(createGovernorTx)
+      governorWrapper = governor
+
+      const { tx: createLockerTx } = await tribecaSdk.createLocker({
+        baseKP: base,
+        governor: governorKey,
+        govTokenMint: govToken.publicKey,
+        minStakeDuration: new BN(1),
+        proposalActivationMinVotes: new BN(1),
+      })
+      await send(createLockerTx)
+      lockerWrapper = await LockerWrapper.load(tribecaSdk, lockerKey, governorKey)
+
+      await send(await lockerWrapper.lockTokens({
+        amount: new BN(1_000_000),
+        duration: new BN(365 * 24 * 60 * 60),
+      }))
+    })
+
+    it('proposes the smart wallet as owner', async () => {
+      const action = { proposeOwner: { newOwner: smartWallet } }
+      await queueAdminAction(action)
+
+      await coreProgram.rpc.proposeOwner({
+        accounts: {
+          newOwner: smartWallet,
+          factoryState,
+          timelockState: await findTimelockAddress(action),
+          timelockPayer: owner,
+        }
+      })
+      const factoryStateData = await coreProgram.account.factoryState.fetch(factoryState)
+      assert(factoryStateData.pendingOwner.equals(smartWallet))
+    })
+
+    it('passes a proposal to accept ownership and queue handing over the admin roles', async () => {
+      await passProposal([
+        coreProgram.instruction.acceptOwner({
+          accounts: {
+            pendingOwner: smartWallet,
+            factoryState,
+          }
+        }),
+        await queueAdminActionIx({ setRole: { role: { feeTierAdmin: {} }, newHolder: smartWallet } }),
+        await queueAdminActionIx({ setRole: { role: { protocolFeeAdmin: {} }, newHolder: smartWallet } }),
+      ])
+      const { timelockDelay } = await coreProgram.account.factoryState.fetch(factoryState)
+      await sleep(timelockDelay + 1)
+
+      for (const role of [{ feeTierAdmin: {} }, { protocolFeeAdmin: {} }]) {
+        await coreProgram.rpc.setRole(role, {
+          accounts: {
+            newHolder: smartWallet,
+            factoryState,
+            timelockState: await findTimelockAddress({ setRole: { role, newHolder: smartWallet } }),
+            timelockPayer: owner,
+          }
+        })
+      }
+
+      const factoryStateData = await coreProgram.account.factoryState.fetch(factoryState)
+      assert(factoryStateData.owner.equals(smartWallet))
+      assert(factoryStateData.feeTierAdmin.equals(smartWallet))
+      assert(factoryStateData.protocolFeeAdmin.equals(smartWallet))
+    })
+
+    it('passes a proposal to queue a fee tier and protocol fee', async () => {
+      const enableFeeAmount = { enableFeeAmount: feeTier }
+      const setFeeProtocol = { setFeeProtocol: { feeProtocol } }
+      await passProposal([
+        await queueAdminActionIx(enableFeeAmount),
+        await queueAdminActionIx(setFeeProtocol),
+      ])
+
+      const timelockStateData = await coreProgram.account.timelockState.fetch(
+        await findTimelockAddress(enableFeeAmount)
+      )
+      assert(timelockStateData.payer.equals(owner))
+    })
+
+    it('no longer accepts admin instructions signed by the previous owner', async () => {
+      await expect(queueAdminAction(
+        { setRole: { role: { feeCollector: {} }, newHolder: owner } }
+      )).to.be.rejectedWith(Error)
+      await expect(queueAdminAction({ setFeeProtocol: { feeProtocol: 100_000 } })).to.be.rejectedWith(Error)
+    })
+
+    it('executes the queued fee tier and protocol fee once past the timelock', async () => {
+      const { timelockDelay } = await coreProgram.account.factoryState.fetch(factoryState)
+      await sleep(timelockDelay + 1)
+
+      const [feeTierState] = await PublicKey.findProgramAddress(
+        [FEE_SEED, u32ToSeed(feeTier.fee)],
+        coreProgram.programId
+      )
+      await coreProgram.rpc.enableFeeAmount(feeTier.fee, feeTier.tickSpacing, {
+        accounts: {
+          payer: owner,
+          timelockState: await findTimelockAddress({ enableFeeAmount: feeTier }),
+          timelockPayer: owner,
+          feeState: feeTierState,
+          systemProgram: SystemProgram.programId,
+        }
+      })
+      await coreProgram.rpc.setFeeProtocol(feeProtocol, {
+        accounts: {
+          factoryState,
+          timelockState: await findTimelockAddress({ setFeeProtocol: { feeProtocol } }),
+          timelockPayer: owner,
+        }
+      })
+
+      const feeStateData = await coreProgram.account.feeState.fetch(feeTierState)
+      assert.equal(feeStateData.fee, feeTier.fee)
+      assert.equal(feeStateData.tickSpacing, feeTier.tickSpacing)
+      const factoryStateData = await coreProgram.account.factoryState.fetch(factoryState)
+      assert.equal(factoryStateData.defaultFeeProtocol, feeProtocol)
     })
   })
 })
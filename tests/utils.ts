@@ -7,3 +7,18 @@ export const MIN_TICK = -221818
 export const MAX_TICK = 221818
 
 export const MaxU64 = new BN(2).pow(new BN(64)).subn(1)
+
+export const PAUSE_SWAP = 1
+export const PAUSE_MINT = 2
+export const PAUSE_ALL = PAUSE_SWAP | PAUSE_MINT
+
+export const TIMELOCK_SEED = Buffer.from('tl')
+
+export const REWARD_VAULT_SEED = Buffer.from('rv')
+
+export const INCENTIVE_SEED = Buffer.from('in')
+export const DEPOSIT_SEED = Buffer.from('dp')
+export const STAKE_SEED = Buffer.from('sk')
+
+export const DISTRIBUTOR_SEED = Buffer.from('md')
+export const CLAIM_STATUS_SEED = Buffer.from('cs')